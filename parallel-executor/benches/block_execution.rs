@@ -0,0 +1,186 @@
+//! End-to-end benchmark of the parallel application of a batch of extrinsics.
+//!
+//! Batches of transfers of the substrate test runtime are applied on top of the genesis state,
+//! once every sender is funded, either one after the other by the `LocalCallExecutor`, as the
+//! nodes import blocks today, or in parallel with Block-STM by the `ParallelLocalCallExecutor`
+//! with an increasing number of workers, including the validation and the commit of the
+//! extrinsics and the application of their changes.
+//!
+//! Every transfer is sent by an account of its own. A given ratio of them are made to the same
+//! account, so that they conflict with each other, and the others to accounts of their own. The
+//! largest batches take a while, run a subset with a filter, e.g.:
+//!
+//! ```text
+//! cargo bench -p parallel-executor --bench block_execution -- "1000_txns"
+//! ```
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use codec::Encode;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use parallel_executor::extrinsic::APPLY_EXTRINSIC_METHOD;
+use parallel_executor::ParallelLocalCallExecutor;
+use sc_client_api::execution_extensions::ExecutionExtensions;
+use sc_client_api::CallExecutor;
+use sc_service::ClientConfig;
+use sp_blockchain::HeaderBackend;
+use sp_core::traits::CallContext;
+use sp_core::{sr25519, Pair};
+use sp_keyring::AccountKeyring;
+use sp_runtime::traits::BlakeTwo256;
+use sp_state_machine::OverlayedChanges;
+use substrate_test_runtime_client::runtime::{Block, Extrinsic, Hash, Transfer};
+use substrate_test_runtime_client::{DefaultTestClientBuilderExt, TestClientBuilder, TestClientBuilderExt};
+
+/// Numbers of transfers of the batches.
+const NUM_TXNS: [usize; 3] = [1_000, 10_000, 100_000];
+
+/// Ratios of the transfers made to the same account.
+const CONFLICT_RATIOS: [f64; 3] = [0.0, 0.1, 0.5];
+
+/// Numbers of workers applying the batches in parallel.
+const CONCURRENCY_LEVELS: [usize; 4] = [2, 4, 8, 16];
+
+/// Amount sent to every sender beforehand.
+const FUNDING: u64 = 1_000_000;
+
+/// Batch of transfers, and the changes funding their senders it is applied on top of.
+struct Workload {
+    funded: OverlayedChanges<BlakeTwo256>,
+    extrinsics: Vec<Extrinsic>,
+}
+
+/// Whether the `txn_idx`-th transfer is made to the shared account, so that a `conflict_ratio` of
+/// the transfers, evenly spread, are.
+fn is_conflicting(txn_idx: usize, conflict_ratio: f64) -> bool {
+    ((txn_idx + 1) as f64 * conflict_ratio).floor() > (txn_idx as f64 * conflict_ratio).floor()
+}
+
+fn account(path: &str) -> sr25519::Pair {
+    sr25519::Pair::from_string(path, None).expect("The derivation path is valid")
+}
+
+/// Applies `extrinsics` one after the other on top of `changes` with the `LocalCallExecutor`.
+fn apply_sequentially(
+    executor: &impl CallExecutor<Block>,
+    at_hash: Hash,
+    extrinsics: &[Extrinsic],
+    changes: &RefCell<OverlayedChanges<BlakeTwo256>>,
+) {
+    for xt in extrinsics {
+        executor
+            .contextual_call(
+                at_hash,
+                APPLY_EXTRINSIC_METHOD,
+                &xt.encode(),
+                changes,
+                &None,
+                CallContext::Offchain,
+                &RefCell::default(),
+            )
+            .expect("The transfer is applied");
+    }
+}
+
+fn workload(executor: &impl CallExecutor<Block>, at_hash: Hash, num_txns: usize, conflict_ratio: f64) -> Workload {
+    let senders: Vec<_> = (0..num_txns).map(|txn_idx| account(&format!("//sender/{txn_idx}"))).collect();
+    let funding: Vec<_> = senders
+        .iter()
+        .enumerate()
+        .map(|(nonce, sender)| {
+            Transfer {
+                from: AccountKeyring::Alice.into(),
+                to: sender.public().into(),
+                amount: FUNDING,
+                nonce: nonce as u64,
+            }
+            .into_unchecked_extrinsic()
+        })
+        .collect();
+    let funded = RefCell::default();
+    apply_sequentially(executor, at_hash, &funding, &funded);
+
+    let extrinsics = senders
+        .into_iter()
+        .enumerate()
+        .map(|(txn_idx, sender)| {
+            let to = if is_conflicting(txn_idx, conflict_ratio) {
+                AccountKeyring::Bob.into()
+            } else {
+                account(&format!("//recipient/{txn_idx}")).public().into()
+            };
+            Transfer { from: sender, to, amount: FUNDING / 2, nonce: 0 }.into_unchecked_extrinsic()
+        })
+        .collect();
+    Workload { funded: funded.into_inner(), extrinsics }
+}
+
+fn benchmark_block_execution(c: &mut Criterion) {
+    let builder = TestClientBuilder::new();
+    let backend = builder.backend();
+    let client = builder.build();
+    let genesis_hash = client.info().genesis_hash;
+
+    let executor = substrate_test_runtime_client::new_native_or_wasm_executor();
+    let new_executor = |concurrency_level| {
+        ParallelLocalCallExecutor::new(
+            backend.clone(),
+            executor.clone(),
+            ClientConfig::default(),
+            ExecutionExtensions::new(None, Arc::new(executor.clone())),
+            concurrency_level,
+        )
+        .expect("The executor is created")
+        // The test runtime does not declare the batch method.
+        .with_legacy_runtimes()
+    };
+    let sequential_executor = new_executor(1);
+    let parallel_executors: Vec<_> = CONCURRENCY_LEVELS.into_iter().map(new_executor).collect();
+
+    for num_txns in NUM_TXNS {
+        for conflict_ratio in CONFLICT_RATIOS {
+            let workload = workload(&sequential_executor.executor, genesis_hash, num_txns, conflict_ratio);
+            let mut group =
+                c.benchmark_group(format!("block_execution/{num_txns}_txns/{}%_conflicts", conflict_ratio * 100.0));
+            group.sample_size(10);
+            group.throughput(Throughput::Elements(num_txns as u64));
+
+            group.bench_function("sequential", |b| {
+                b.iter_batched(
+                    || RefCell::new(workload.funded.clone()),
+                    |changes| {
+                        apply_sequentially(&sequential_executor.executor, genesis_hash, &workload.extrinsics, &changes)
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+            for (concurrency_level, executor) in CONCURRENCY_LEVELS.into_iter().zip(&parallel_executors) {
+                group.bench_with_input(BenchmarkId::new("parallel", concurrency_level), &workload, |b, workload| {
+                    b.iter_batched(
+                        || RefCell::new(workload.funded.clone()),
+                        |changes| {
+                            let results = executor
+                                .apply_extrinsics_parallel(
+                                    genesis_hash,
+                                    &workload.extrinsics,
+                                    &changes,
+                                    &None,
+                                    CallContext::Offchain,
+                                    &RefCell::default(),
+                                    None,
+                                )
+                                .expect("The batch is applied");
+                            assert_eq!(results.len(), workload.extrinsics.len());
+                        },
+                        BatchSize::PerIteration,
+                    )
+                });
+            }
+            group.finish();
+        }
+    }
+}
+
+criterion_group!(benches, benchmark_block_execution);
+criterion_main!(benches);
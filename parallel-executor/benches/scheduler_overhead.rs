@@ -0,0 +1,125 @@
+//! Floor cost of the block executor itself: blocks of independent transactions that do nothing
+//! are executed, so that the time measured is spent fetching the tasks from the scheduler,
+//! transitioning the statuses of the transactions, validating their empty reads, and recording
+//! and committing their outputs.
+//!
+//! The sequential execution of the same blocks is measured as a baseline, the difference being
+//! the overhead of the parallel execution per transaction.
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use parallel_executor::executor::BlockExecutor;
+use parallel_executor::scheduler::TxnIndex;
+use parallel_executor::task::{
+    ExecutionCancelled, ExecutionPanic, ExecutionStatus, ExecutorTask, Transaction, TransactionOutput, WorkerId,
+    WriteSet,
+};
+use parallel_executor::view::{LatestView, StateView};
+use rayon::ThreadPoolBuilder;
+use sp_weights::Weight;
+
+/// Numbers of transactions of the blocks.
+const NUM_TXNS: [usize; 3] = [100, 1_000, 10_000];
+
+/// Numbers of workers executing the blocks in parallel.
+const CONCURRENCY_LEVELS: [usize; 4] = [2, 4, 8, 16];
+
+/// Transaction that neither reads nor writes.
+struct NoopTransaction;
+
+impl Transaction for NoopTransaction {
+    type Key = u32;
+    type Value = u64;
+}
+
+#[derive(Debug)]
+struct NoopOutput;
+
+impl TransactionOutput for NoopOutput {
+    type Txn = NoopTransaction;
+
+    fn get_writes(&self) -> WriteSet<NoopTransaction> {
+        Vec::new()
+    }
+
+    fn weight(&self) -> Weight {
+        Weight::zero()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum NoopError {
+    Panic,
+    Cancelled,
+}
+
+impl From<ExecutionPanic> for NoopError {
+    fn from(_: ExecutionPanic) -> Self {
+        NoopError::Panic
+    }
+}
+
+impl From<ExecutionCancelled> for NoopError {
+    fn from(_: ExecutionCancelled) -> Self {
+        NoopError::Cancelled
+    }
+}
+
+struct NoopTask;
+
+impl ExecutorTask for NoopTask {
+    type Txn = NoopTransaction;
+    type Output = NoopOutput;
+    type Error = NoopError;
+    type Argument = ();
+
+    fn init(_args: (), _worker_id: WorkerId) -> Self {
+        NoopTask
+    }
+
+    fn execute_transaction<S: StateView<NoopTransaction>>(
+        &self,
+        _view: &LatestView<NoopTransaction, S>,
+        _txn: &NoopTransaction,
+        _txn_idx: TxnIndex,
+    ) -> ExecutionStatus<NoopOutput, NoopError> {
+        ExecutionStatus::Success(NoopOutput)
+    }
+}
+
+struct EmptyState;
+
+impl StateView<NoopTransaction> for EmptyState {
+    fn get_state_value(&self, _key: &u32) -> Arc<u64> {
+        Arc::new(0)
+    }
+}
+
+type NoopExecutor = BlockExecutor<NoopTransaction, NoopTask, EmptyState>;
+
+fn benchmark_scheduler_overhead(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scheduler_overhead");
+    for num_txns in NUM_TXNS {
+        let block: Vec<_> = (0..num_txns).map(|_| NoopTransaction).collect();
+        group.throughput(Throughput::Elements(num_txns as u64));
+
+        let executor = NoopExecutor::new(1, None);
+        group.bench_with_input(BenchmarkId::new("sequential", num_txns), &block, |b, block| {
+            b.iter(|| executor.execute_transactions_sequential((), block, &EmptyState, None).unwrap())
+        });
+        for concurrency_level in CONCURRENCY_LEVELS {
+            let pool = ThreadPoolBuilder::new().num_threads(concurrency_level).build().unwrap();
+            let executor = NoopExecutor::new(concurrency_level, None).with_thread_pool(Arc::new(pool));
+            group.bench_with_input(
+                BenchmarkId::new(format!("parallel_{concurrency_level}_workers"), num_txns),
+                &block,
+                |b, block| b.iter(|| executor.execute_transactions_parallel((), block, &EmptyState, None).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_scheduler_overhead);
+criterion_main!(benches);
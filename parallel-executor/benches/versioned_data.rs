@@ -0,0 +1,158 @@
+//! Microbenchmarks of the data structures shared by the workers of the block executor, to catch
+//! their regressions before they show up in block times:
+//!
+//! - `fetch_data` by several threads at once, of the same key or of keys spread over the map,
+//! - `write` of the write sets of a block, followed by `mark_estimate` of all of them, as when
+//!   every incarnation is aborted,
+//! - `validate_data_reads` of read sets of increasing sizes, the first time and once the reads were
+//!   validated and a single key was updated since.
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use parallel_executor::captured_reads::{CapturedReads, DataRead};
+use parallel_executor::extrinsic::Extrinsic;
+use parallel_executor::scheduler::TxnIndex;
+use parallel_executor::versioned_data::VersionedData;
+use parallel_executor::view::StateView;
+
+type Key = Vec<u8>;
+type Value = Option<Vec<u8>>;
+
+/// Number of transactions of the block written to the map.
+const NUM_TXNS: TxnIndex = 1_000;
+
+/// Fetches performed by every thread.
+const NUM_FETCHES: usize = 10_000;
+
+/// Base state in which no key exists.
+struct EmptyState;
+
+impl StateView<Extrinsic> for EmptyState {
+    fn get_state_value(&self, _key: &Key) -> Arc<Value> {
+        Arc::new(None)
+    }
+}
+
+fn key(idx: usize) -> Key {
+    (idx as u64).to_be_bytes().to_vec()
+}
+
+fn value(txn_idx: TxnIndex) -> Value {
+    Some(txn_idx.to_le_bytes().to_vec())
+}
+
+/// Map in which every transaction of the block wrote to the `txn_idx % num_keys`-th key.
+fn populated_map(num_keys: usize) -> VersionedData<Key, Value> {
+    let data_map = VersionedData::new();
+    for txn_idx in 0..NUM_TXNS {
+        data_map.write(key(txn_idx as usize % num_keys), (txn_idx, 0), value(txn_idx));
+    }
+    data_map
+}
+
+fn benchmark_fetch_data(c: &mut Criterion) {
+    let mut group = c.benchmark_group("versioned_data/fetch_data");
+    // A single hot key, and keys the threads hardly contend on.
+    for num_keys in [1, 1_024] {
+        let data_map = populated_map(num_keys);
+        for num_threads in [1, 2, 4, 8] {
+            group.throughput(Throughput::Elements((num_threads * NUM_FETCHES) as u64));
+            group.bench_function(BenchmarkId::new(format!("{num_keys}_keys"), num_threads), |b| {
+                b.iter(|| {
+                    std::thread::scope(|s| {
+                        for thread_idx in 0..num_threads {
+                            let data_map = &data_map;
+                            s.spawn(move || {
+                                for fetch_idx in 0..NUM_FETCHES {
+                                    let reader_idx =
+                                        ((thread_idx * NUM_FETCHES + fetch_idx) % NUM_TXNS as usize) as TxnIndex;
+                                    let _ = criterion::black_box(
+                                        data_map.fetch_data(&key(fetch_idx % num_keys), reader_idx + 1),
+                                    );
+                                }
+                            });
+                        }
+                    })
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn benchmark_write_and_mark_estimate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("versioned_data/write_and_mark_estimate");
+    for writes_per_txn in [1, 10, 100] {
+        group.throughput(Throughput::Elements(NUM_TXNS as u64 * writes_per_txn as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(writes_per_txn), &writes_per_txn, |b, writes_per_txn| {
+            b.iter_batched(
+                VersionedData::<Key, Value>::new,
+                |data_map| {
+                    for txn_idx in 0..NUM_TXNS {
+                        for write_idx in 0..*writes_per_txn {
+                            data_map.write(key(txn_idx as usize + write_idx), (txn_idx, 0), value(txn_idx));
+                        }
+                    }
+                    for txn_idx in 0..NUM_TXNS {
+                        for write_idx in 0..*writes_per_txn {
+                            data_map.mark_estimate(&key(txn_idx as usize + write_idx), txn_idx);
+                        }
+                    }
+                    data_map
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Map in which the first transaction wrote to `num_reads` keys, and the reads of them by the
+/// second transaction.
+fn read_set(num_reads: usize) -> (VersionedData<Key, Value>, CapturedReads<Extrinsic>) {
+    let data_map = VersionedData::new();
+    let mut reads = CapturedReads::default();
+    for read_idx in 0..num_reads {
+        data_map.write(key(read_idx), (0, 0), value(0));
+        reads.capture_read(key(read_idx), DataRead::Versioned((0, 0), Arc::new(value(0))));
+    }
+    (data_map, reads)
+}
+
+fn benchmark_validate_data_reads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("captured_reads/validate_data_reads");
+    for num_reads in [1, 10, 100, 1_000, 10_000] {
+        group.throughput(Throughput::Elements(num_reads as u64));
+        group.bench_with_input(BenchmarkId::new("first", num_reads), &num_reads, |b, num_reads| {
+            b.iter_batched(
+                || read_set(*num_reads),
+                |(data_map, reads)| {
+                    assert!(reads.validate_data_reads(&data_map, &EmptyState, 1));
+                    (data_map, reads)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("after_update", num_reads), &num_reads, |b, num_reads| {
+            b.iter_batched(
+                || {
+                    let (data_map, reads) = read_set(*num_reads);
+                    assert!(reads.validate_data_reads(&data_map, &EmptyState, 1));
+                    // A higher transaction updated a key that was not read.
+                    data_map.write(key(*num_reads), (2, 0), value(2));
+                    (data_map, reads)
+                },
+                |(data_map, reads)| {
+                    assert!(reads.validate_data_reads(&data_map, &EmptyState, 1));
+                    (data_map, reads)
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_fetch_data, benchmark_write_and_mark_estimate, benchmark_validate_data_reads);
+criterion_main!(benches);
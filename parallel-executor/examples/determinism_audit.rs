@@ -0,0 +1,87 @@
+//! Audits the determinism of the parallel application of a block of the substrate test runtime,
+//! on top of its genesis state: the block is applied sequentially, then with 2, 4 and 8 workers,
+//! and the first difference of every parallel run is printed.
+//!
+//! ```text
+//! cargo run -p parallel-executor --example determinism_audit -- [block.scale]
+//! ```
+//!
+//! The block is read from a file holding its SCALE encoded `Vec` of extrinsics, or made of
+//! conflicting transfers if none is given. Chain teams audit their own blocks by building the
+//! executor with the client of their node instead.
+
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use codec::Decode;
+use parallel_executor::determinism::AUDIT_CONCURRENCY_LEVELS;
+use parallel_executor::ParallelLocalCallExecutor;
+use sc_client_api::execution_extensions::ExecutionExtensions;
+use sc_service::ClientConfig;
+use sp_blockchain::HeaderBackend;
+use sp_core::storage::StateVersion;
+use sp_keyring::AccountKeyring;
+use substrate_test_runtime_client::runtime::{Extrinsic, Transfer};
+use substrate_test_runtime_client::{DefaultTestClientBuilderExt, TestClientBuilder, TestClientBuilderExt};
+
+fn transfer(from: AccountKeyring, to: AccountKeyring, amount: u64, nonce: u64) -> Extrinsic {
+    Transfer { from: from.into(), to: to.into(), amount, nonce }.into_unchecked_extrinsic()
+}
+
+fn sample_block() -> Vec<Extrinsic> {
+    vec![
+        transfer(AccountKeyring::Alice, AccountKeyring::Bob, 69, 0),
+        transfer(AccountKeyring::Bob, AccountKeyring::Charlie, 42, 0),
+        transfer(AccountKeyring::Charlie, AccountKeyring::Dave, 7, 0),
+        transfer(AccountKeyring::Alice, AccountKeyring::Eve, 1, 1),
+        transfer(AccountKeyring::Ferdie, AccountKeyring::Alice, 3, 0),
+        transfer(AccountKeyring::Alice, AccountKeyring::Bob, 5, 1),
+    ]
+}
+
+fn main() -> ExitCode {
+    let extrinsics = match std::env::args().nth(1) {
+        Some(path) => {
+            let encoded = std::fs::read(&path).unwrap_or_else(|err| panic!("Failed to read {path}: {err}"));
+            Vec::<Extrinsic>::decode(&mut &encoded[..]).unwrap_or_else(|err| panic!("Invalid block {path}: {err}"))
+        }
+        None => sample_block(),
+    };
+
+    let builder = TestClientBuilder::new();
+    let backend = builder.backend();
+    let client = builder.build();
+    let genesis_hash = client.info().genesis_hash;
+
+    let executor = substrate_test_runtime_client::new_native_or_wasm_executor();
+    let parallel_executor = ParallelLocalCallExecutor::new(
+        backend,
+        executor.clone(),
+        ClientConfig::default(),
+        ExecutionExtensions::new(None, Arc::new(executor)),
+        AUDIT_CONCURRENCY_LEVELS.into_iter().max().unwrap_or(1),
+    )
+    .expect("The executor is created")
+    // The test runtime does not declare the batch method.
+    .with_legacy_runtimes();
+
+    let report = parallel_executor
+        .audit_determinism(genesis_hash, &extrinsics, &AUDIT_CONCURRENCY_LEVELS, StateVersion::V1)
+        .expect("The block is applied");
+
+    println!(
+        "Sequential: {} extrinsics applied, {} keys changed, storage root {:?}",
+        report.sequential.results.len(),
+        report.sequential.changes.len(),
+        report.sequential.storage_root,
+    );
+    for run in &report.parallel {
+        let outcome = match report.divergences.iter().find(|(level, _)| *level == run.concurrency_level) {
+            Some((_, divergence)) => format!("diverged: {divergence}"),
+            None => "identical".to_owned(),
+        };
+        println!("{} workers: {outcome}", run.concurrency_level);
+    }
+
+    if report.is_deterministic() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
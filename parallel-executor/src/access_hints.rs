@@ -0,0 +1,226 @@
+//! Access hints: the keys that the extrinsics of a batch are predicted to access, decoded from the
+//! extrinsics of a FRAME runtime before they are executed.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use codec::{Compact, Decode, Encode};
+use sp_core::crypto::AccountId32;
+use sp_core::hashing::{blake2_128, twox_128, twox_64};
+use sp_runtime::MultiAddress;
+use sp_state_machine::StorageKey;
+
+use crate::conflict_oracle::{ConflictOracle, KeySet};
+use crate::extrinsic::Extrinsic;
+
+/// Keys a transaction is predicted to access.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct AccessHint<K> {
+    /// Keys predicted to be read, and not written.
+    pub reads: Vec<K>,
+    /// Keys predicted to be written.
+    pub writes: Vec<K>,
+}
+
+impl<K> Default for AccessHint<K> {
+    fn default() -> Self {
+        Self { reads: Vec::new(), writes: Vec::new() }
+    }
+}
+
+/// Predicts the keys accessed by the call of a signed extrinsic, given its signer and its
+/// SCALE encoded arguments.
+pub type CallHint = fn(signer: &AccountId32, args: &[u8]) -> AccessHint<StorageKey>;
+
+/// Returns the keys declared by the submitter of an extrinsic in a transaction extension of its
+/// `Extra`, e.g. an optional access list, if any. The extrinsic must access no other key: keys
+/// read are declared as read or written, and keys written as written.
+pub type AccessList<Extra> = fn(extra: &Extra) -> Option<AccessHint<StorageKey>>;
+
+/// Version of the extrinsic format decoded by the [`AccessHintProvider`].
+const EXTRINSIC_FORMAT_VERSION: u8 = 4;
+
+/// Bit of the version byte set for signed extrinsics.
+const SIGNED_EXTRINSIC_BIT: u8 = 0b1000_0000;
+
+/// Decodes the signed extrinsics of a FRAME runtime to predict the keys they access: the account
+/// of their signer, which pays the fees and whose nonce is incremented, and the keys of the calls
+/// the provider knows about, by pallet and call indices. The extrinsics of a signer are chained,
+/// see [`ConflictOracle::sender`].
+///
+/// The extrinsics must be `UncheckedExtrinsic`s of version 4 signed by a `MultiAddress` of the
+/// `AccountIndex`, with a `Signature` and a `Extra` of the runtime. Others, like the unsigned
+/// extrinsics, are not predicted to access any key. The predictions are partial, as every
+/// extrinsic accesses keys of the block, e.g. its weight, unless the submitter declared the keys,
+/// see [`with_access_lists`](Self::with_access_lists).
+pub struct AccessHintProvider<AccountIndex, Signature, Extra> {
+    calls: HashMap<(u8, u8), CallHint>,
+    access_list: Option<AccessList<Extra>>,
+    phantom: PhantomData<(AccountIndex, Signature, Extra)>,
+}
+
+impl<AccountIndex, Signature, Extra> Default for AccessHintProvider<AccountIndex, Signature, Extra> {
+    fn default() -> Self {
+        Self { calls: HashMap::new(), access_list: None, phantom: PhantomData }
+    }
+}
+
+impl<AccountIndex, Signature, Extra> AccessHintProvider<AccountIndex, Signature, Extra>
+where
+    MultiAddress<AccountId32, AccountIndex>: Decode,
+    Signature: Decode,
+    Extra: Decode,
+{
+    /// Creates a provider that only knows about the account of the signers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Predicts the keys accessed by the call `call_index` of the pallet `pallet_index` with
+    /// `hint`.
+    pub fn with_call(mut self, pallet_index: u8, call_index: u8, hint: CallHint) -> Self {
+        self.calls.insert((pallet_index, call_index), hint);
+        self
+    }
+
+    /// Predicts the keys accessed by the transfers of `pallet_balances` at `pallet_index`, whose
+    /// balances are stored in `System::Account`.
+    pub fn with_balances(self, pallet_index: u8) -> Self {
+        [0, 3, 4]
+            .into_iter()
+            .fold(self, |provider, call_index| provider.with_call(pallet_index, call_index, balances_transfer))
+    }
+
+    /// Predicts the keys accessed by the bonding calls of `pallet_staking` at `pallet_index`,
+    /// named `Staking` in the runtime.
+    pub fn with_staking(self, pallet_index: u8) -> Self {
+        let provider = self.with_call(pallet_index, 0, staking_bond);
+        provider.with_call(pallet_index, 1, staking_bond_extra).with_call(pallet_index, 2, staking_bond_extra)
+    }
+
+    /// Takes the keys declared by the submitters in a transaction extension into account with
+    /// `access_list`, which returns the keys declared in the `Extra` of an extrinsic, if any. The
+    /// declared keys are predicted exhaustively, see
+    /// [`SchedulerPolicy::Conservative`](crate::executor::SchedulerPolicy::Conservative).
+    pub fn with_access_lists(mut self, access_list: AccessList<Extra>) -> Self {
+        self.access_list = Some(access_list);
+        self
+    }
+
+    /// Returns the keys the encoded extrinsic is predicted to access, and whether the prediction
+    /// is exhaustive.
+    pub fn hint(&self, encoded: &[u8]) -> (AccessHint<StorageKey>, bool) {
+        let Some((signer, extra, call)) = self.decode_signed(encoded) else {
+            return (AccessHint::default(), false);
+        };
+        if let Some(declared) = self.access_list.and_then(|access_list| access_list(&extra)) {
+            return (declared, true);
+        }
+
+        let mut hint = match call {
+            [pallet_index, call_index, args @ ..] => match self.calls.get(&(*pallet_index, *call_index)) {
+                Some(call_hint) => call_hint(&signer, args),
+                None => AccessHint::default(),
+            },
+            _ => AccessHint::default(),
+        };
+        hint.writes.push(system_account(&signer));
+        (hint, false)
+    }
+
+    /// Returns the signer, the extra and the encoded call of a signed extrinsic.
+    fn decode_signed<'a>(&self, mut encoded: &'a [u8]) -> Option<(AccountId32, Extra, &'a [u8])> {
+        let input = &mut encoded;
+        let signer = self.decode_signer(input)?;
+        Signature::decode(input).ok()?;
+        let extra = Extra::decode(input).ok()?;
+        Some((signer, extra, encoded))
+    }
+
+    /// Decodes the signer of a signed extrinsic, leaving `input` at its signature.
+    fn decode_signer(&self, input: &mut &[u8]) -> Option<AccountId32> {
+        Compact::<u32>::decode(input).ok()?;
+        if u8::decode(input).ok()? != EXTRINSIC_FORMAT_VERSION | SIGNED_EXTRINSIC_BIT {
+            return None;
+        }
+        match MultiAddress::<AccountId32, AccountIndex>::decode(input).ok()? {
+            MultiAddress::Id(signer) => Some(signer),
+            _ => None,
+        }
+    }
+}
+
+/// `transfer_allow_death`, `transfer_keep_alive` and `transfer_all`, which start with the
+/// destination.
+fn balances_transfer(_signer: &AccountId32, mut args: &[u8]) -> AccessHint<StorageKey> {
+    let writes = match MultiAddress::<AccountId32, ()>::decode(&mut args) {
+        Ok(MultiAddress::Id(dest)) => vec![system_account(&dest)],
+        _ => Vec::new(),
+    };
+    AccessHint { reads: Vec::new(), writes }
+}
+
+/// `bond`, creating the ledger of the signer.
+fn staking_bond(stash: &AccountId32, _args: &[u8]) -> AccessHint<StorageKey> {
+    AccessHint {
+        reads: Vec::new(),
+        writes: vec![
+            storage_key(b"Staking", b"Bonded", &twox_64_concat(stash)),
+            storage_key(b"Staking", b"Ledger", &blake2_128_concat(stash)),
+            storage_key(b"Staking", b"Payee", &twox_64_concat(stash)),
+        ],
+    }
+}
+
+/// `bond_extra` and `unbond`, updating the ledger of the signer.
+fn staking_bond_extra(stash: &AccountId32, _args: &[u8]) -> AccessHint<StorageKey> {
+    AccessHint {
+        reads: vec![storage_key(b"Staking", b"Bonded", &twox_64_concat(stash))],
+        writes: vec![storage_key(b"Staking", b"Ledger", &blake2_128_concat(stash))],
+    }
+}
+
+fn system_account(account: &AccountId32) -> StorageKey {
+    storage_key(b"System", b"Account", &blake2_128_concat(account))
+}
+
+pub(crate) fn storage_key(pallet: &[u8], storage: &[u8], hashed_key: &[u8]) -> StorageKey {
+    [&twox_128(pallet)[..], &twox_128(storage)[..], hashed_key].concat()
+}
+
+pub(crate) fn blake2_128_concat(key: &impl Encode) -> Vec<u8> {
+    let encoded = key.encode();
+    [&blake2_128(&encoded)[..], &encoded[..]].concat()
+}
+
+fn twox_64_concat(key: &impl Encode) -> Vec<u8> {
+    let encoded = key.encode();
+    [&twox_64(&encoded)[..], &encoded[..]].concat()
+}
+
+impl<AccountIndex, Signature, Extra> ConflictOracle<Extrinsic> for AccessHintProvider<AccountIndex, Signature, Extra>
+where
+    AccountIndex: Send + Sync,
+    MultiAddress<AccountId32, AccountIndex>: Decode,
+    Signature: Decode + Send + Sync,
+    Extra: Decode + Send + Sync,
+{
+    fn predict_reads(&self, extrinsic: &Extrinsic) -> KeySet<StorageKey> {
+        self.predict(extrinsic).0
+    }
+
+    fn predict_writes(&self, extrinsic: &Extrinsic) -> KeySet<StorageKey> {
+        self.predict(extrinsic).1
+    }
+
+    fn predict(&self, extrinsic: &Extrinsic) -> (KeySet<StorageKey>, KeySet<StorageKey>) {
+        match self.hint(extrinsic.encoded()) {
+            (AccessHint { reads, writes }, true) => (KeySet::Exhaustive(reads), KeySet::Exhaustive(writes)),
+            (AccessHint { reads, writes }, false) => (KeySet::Partial(reads), KeySet::Partial(writes)),
+        }
+    }
+
+    fn sender(&self, extrinsic: &Extrinsic) -> Option<Vec<u8>> {
+        self.decode_signer(&mut extrinsic.encoded()).map(|signer| signer.encode())
+    }
+}
@@ -0,0 +1,31 @@
+//! Reports of the keys accessed by an extrinsic, observed while applying it.
+//!
+//! The [`AccessHintProvider`](crate::access_hints::AccessHintProvider) and the conflict models of
+//! the static analyzer predict the keys accessed by the calls of a runtime. An offline profiling
+//! run applies sample extrinsics with [`APPLY_EXTRINSIC_WITH_ACCESS_REPORT_METHOD`] instead of
+//! `apply_extrinsic`, and learns the keys every call actually reads and writes from the
+//! [`AccessReport`]s.
+//!
+//! As for the batches, the method only exists on the host side: it is intercepted by the
+//! [`ParallelLocalCallExecutor`](crate::ParallelLocalCallExecutor), which applies the extrinsic
+//! in a worker tracking its accesses.
+
+use codec::{Decode, Encode};
+use sp_runtime::ApplyExtrinsicResult;
+use sp_state_machine::StorageKey;
+
+/// Runtime method applying a single extrinsic, given as argument as for `apply_extrinsic`, and
+/// returning its SCALE encoded [`AccessReport`].
+pub const APPLY_EXTRINSIC_WITH_ACCESS_REPORT_METHOD: &str = "BlockBuilder_apply_extrinsic_with_access_report";
+
+/// Result of an extrinsic, along with the keys it accessed.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct AccessReport {
+    /// Result of the extrinsic, as returned by `apply_extrinsic`.
+    pub result: ApplyExtrinsicResult,
+    /// Keys read by the extrinsic from the state before it, sorted. The keys it only read after
+    /// writing them are not included.
+    pub reads: Vec<StorageKey>,
+    /// Keys written by the extrinsic, sorted, except for the events of the block.
+    pub writes: Vec<StorageKey>,
+}
@@ -0,0 +1,95 @@
+//! Statistics of the backend reads of the batches applied in parallel, and pinning of hot values.
+//!
+//! The reads of the workers missing the multi-version data are served by the
+//! [`SharedReadCache`](crate::read_cache::SharedReadCache) of the batch, or else by the trie
+//! backend, which finds the value in the shared trie cache of the client database or reads it
+//! from the disk. A [`BackendCache`] given the shared trie cache of the client tells the reads
+//! apart, and holds the values of the keys pinned by the node in memory for the whole batch,
+//! whether the shared trie cache evicts them meanwhile or not.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use sp_core::Hasher;
+use sp_state_machine::StorageKey;
+use sp_trie::cache::{SharedTrieCache, ValueCacheKey};
+
+use crate::sync_wrapper::RwLock;
+
+/// Number of base values read by the batches applied in parallel, by where they were found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackendReadStats {
+    /// Values read by another worker of the batch, or pinned, before.
+    pub read_cache: u64,
+    /// Values found in the shared trie cache of the client database.
+    pub trie_cache: u64,
+    /// Values read from the disk, or from a backend whose shared trie cache is not known.
+    pub disk: u64,
+}
+
+/// Shared trie cache of the client database, if known, and the keys pinned for every batch.
+pub struct BackendCache<H: Hasher> {
+    maybe_shared_cache: Option<SharedTrieCache<H>>,
+    pinned_keys: RwLock<HashSet<StorageKey>>,
+    read_cache: AtomicU64,
+    trie_cache: AtomicU64,
+    disk: AtomicU64,
+}
+
+impl<H: Hasher> BackendCache<H> {
+    /// Creates the cache of a backend using `maybe_shared_cache`, the shared trie cache of the
+    /// client database, if any.
+    pub fn new(maybe_shared_cache: Option<SharedTrieCache<H>>) -> Self {
+        Self {
+            maybe_shared_cache,
+            pinned_keys: RwLock::new(HashSet::new()),
+            read_cache: AtomicU64::new(0),
+            trie_cache: AtomicU64::new(0),
+            disk: AtomicU64::new(0),
+        }
+    }
+
+    /// Pins the values of `keys`, e.g. the hot keys of the previous blocks: they are read before
+    /// every batch is executed, and held in memory until it is applied.
+    pub fn pin(&self, keys: impl IntoIterator<Item = StorageKey>) {
+        self.pinned_keys.write().extend(keys);
+    }
+
+    /// Unpins the values of all the keys.
+    pub fn unpin_all(&self) {
+        self.pinned_keys.write().clear();
+    }
+
+    /// Returns the keys whose values are pinned.
+    pub fn pinned_keys(&self) -> Vec<StorageKey> {
+        self.pinned_keys.read().iter().cloned().collect()
+    }
+
+    /// Returns the number of base values read so far, by where they were found. The reads of a
+    /// batch are the difference between the statistics taken before and after it.
+    pub fn stats(&self) -> BackendReadStats {
+        BackendReadStats {
+            read_cache: self.read_cache.load(Ordering::Relaxed),
+            trie_cache: self.trie_cache.load(Ordering::Relaxed),
+            disk: self.disk.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Records a base value read from the read cache of the batch.
+    pub(crate) fn record_cached_read(&self) {
+        self.read_cache.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a base value about to be read from the backend at `storage_root`, looking it up in
+    /// the shared trie cache beforehand.
+    pub(crate) fn record_backend_read(&self, storage_root: &H::Out, key: &[u8]) {
+        let cached = self.maybe_shared_cache.as_ref().is_some_and(|shared_cache| {
+            shared_cache.peek_value_by_hash(ValueCacheKey::hash_data(key, storage_root), storage_root, key).is_some()
+        });
+        if cached {
+            self.trie_cache.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.disk.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
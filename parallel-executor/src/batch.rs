@@ -0,0 +1,78 @@
+//! Lazy splitting of an encoded batch into its extrinsics.
+//!
+//! The extrinsics of a batch are only delimited by their encoding. Rather than splitting the whole
+//! batch before scheduling it, the batch is split as far as the extrinsics requested so far, so
+//! that the workers start executing the first extrinsics while the following ones are not
+//! delimited yet.
+
+use std::fmt;
+use std::ops::Range;
+
+use codec::{Compact, Decode};
+
+use crate::scheduler::TxnIndex;
+use crate::sync_wrapper::Mutex;
+
+/// SCALE encoded `Vec` of extrinsics, split on demand.
+pub struct LazyBatch {
+    encoded: Vec<u8>,
+    len: TxnIndex,
+    /// Skips the encoding of an extrinsic at the start of the input.
+    skip: fn(&mut &[u8]) -> Result<(), codec::Error>,
+    /// Ranges of the encoded extrinsics delimited so far, in order.
+    ranges: Mutex<Vec<Range<usize>>>,
+}
+
+impl LazyBatch {
+    /// Reads the number of extrinsics of type `X` in `encoded`, without splitting them yet.
+    pub fn new<X: Decode>(encoded: Vec<u8>) -> Result<Self, codec::Error> {
+        let input = &mut &encoded[..];
+        let Compact(len) = Compact::<u32>::decode(input)?;
+        // Every extrinsic is encoded in one byte at least.
+        if len as usize > input.len() {
+            return Err("Batch shorter than its number of extrinsics".into());
+        }
+        let start = encoded.len() - input.len();
+        Ok(Self { len, skip: skip::<X>, ranges: Mutex::new(vec![start..start]), encoded })
+    }
+
+    /// Number of extrinsics of the batch.
+    pub fn len(&self) -> TxnIndex {
+        self.len
+    }
+
+    /// Whether the batch has no extrinsic.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the encoding of the extrinsic `txn_idx`, delimiting the extrinsics up to it if they
+    /// were not yet.
+    pub fn get(&self, txn_idx: TxnIndex) -> Result<&[u8], codec::Error> {
+        if txn_idx >= self.len {
+            return Err("Extrinsic index out of the batch".into());
+        }
+        let range = {
+            let mut ranges = self.ranges.lock();
+            // The first range is the empty one before the first extrinsic.
+            while ranges.len() <= txn_idx as usize + 1 {
+                let start = ranges.last().expect("Starts with an empty range").end;
+                let input = &mut &self.encoded[start..];
+                (self.skip)(input)?;
+                ranges.push(start..self.encoded.len() - input.len());
+            }
+            ranges[txn_idx as usize + 1].clone()
+        };
+        Ok(&self.encoded[range])
+    }
+}
+
+fn skip<X: Decode>(input: &mut &[u8]) -> Result<(), codec::Error> {
+    X::skip(input)
+}
+
+impl fmt::Debug for LazyBatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyBatch").field("len", &self.len).field("size", &self.encoded.len()).finish()
+    }
+}
@@ -0,0 +1,344 @@
+//! Incremental application of the extrinsics of a block, in several parallel batches.
+//!
+//! A proposer pulling the extrinsics out of the transaction pool does not know the whole content
+//! of its block up front. Rather than waiting for it, the proposer pushes the extrinsics in
+//! batches as they stream out of the pool: every batch is applied in parallel on top of the
+//! changes of the batches pushed before it, i.e. of the state committed so far.
+//!
+//! The inherents, e.g. the timestamp or the validation data of a parachain, come first in the
+//! block in a strict order. They are applied sequentially before the first batch, so that their
+//! writes are the base values the extrinsics of the batches are executed on.
+//!
+//! The same goes for the processing of the messages of a parachain, e.g. the XCM messages enqueued
+//! by its validation data inherent and serviced by `pallet-message-queue` in `on_initialize`: its
+//! state is cross-cutting, every extrinsic touching the queues would conflict with the others.
+//! The initialization of the block and the inherents form a sequential prologue whose writes are
+//! base values, read by the extrinsics of the batches as is, not versioned, so that they consume
+//! them the same way whatever their schedule. The extrinsics the runtime requires after the others,
+//! e.g. servicing the rest of the queues, form a sequential epilogue applied on top of the changes
+//! of the last batch, see [`BatchPusher::apply_epilogue`].
+//!
+//! The weight and the size of the block are estimated as the batches are applied, so that the
+//! proposer can stop pulling extrinsics out of the pool once the block is full, without
+//! finalizing it first. Once the block is built, the [`ProposalOutcome`] tells the transaction
+//! pool which of the extrinsics pushed were included, which were invalid and must be pruned, and
+//! which were skipped and must be retried in a later block.
+//!
+//! The block of a parachain must also fit in the budget of its proof of validity, and apply the
+//! validation data inherent before any extrinsic, see [`collator`](crate::collator).
+
+use std::cell::RefCell;
+use std::time::Instant;
+
+use codec::{Compact, Encode};
+use sc_client_api::backend;
+use sc_executor::RuntimeVersionOf;
+use sp_api::ProofRecorder;
+use sp_core::traits::{CallContext, CodeExecutor};
+use sp_externalities::Extensions;
+use sp_runtime::traits::{Block as BlockT, Hash as HashT, HashingFor};
+use sp_runtime::transaction_validity::TransactionValidityError;
+use sp_runtime::ApplyExtrinsicResult;
+use sp_state_machine::{OverlayedChanges, StorageKey};
+use sp_weights::Weight;
+
+use crate::collator::validation_data_key;
+use crate::extrinsic::Extrinsic;
+use crate::limit_processor::{block_weight, BLOCK_WEIGHT};
+use crate::{ParallelLocalCallExecutor, LOG_TARGET};
+
+/// What became of the extrinsics pushed to a [`BatchPusher`], inherents and epilogue excluded,
+/// identified by their hash as in the transaction pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProposalOutcome<Hash> {
+    /// Extrinsics included in the block, in order, whether their dispatch succeeded or not.
+    pub included: Vec<Hash>,
+    /// Extrinsics that cannot be included, along with the reason, to be pruned from the pool.
+    pub invalid: Vec<(Hash, TransactionValidityError)>,
+    /// Extrinsics that were not attempted because of the deadline, or that exhausted the
+    /// resources of the block, to be retried in a later block.
+    pub skipped: Vec<Hash>,
+}
+
+impl<Hash> Default for ProposalOutcome<Hash> {
+    fn default() -> Self {
+        Self { included: Vec::new(), invalid: Vec::new(), skipped: Vec::new() }
+    }
+}
+
+/// Block being built at `at_hash` from batches of extrinsics pushed one after the other, see
+/// [`ParallelLocalCallExecutor::batch_pusher`].
+pub struct BatchPusher<'a, Block: BlockT, B, E> {
+    // Applies the batches as part of the block built by the node.
+    executor: ParallelLocalCallExecutor<Block, B, E>,
+    at_hash: Block::Hash,
+    changes: &'a RefCell<OverlayedChanges<HashingFor<Block>>>,
+    recorder: &'a Option<ProofRecorder<Block>>,
+    call_context: CallContext,
+    extensions: &'a RefCell<Extensions>,
+    maybe_deadline: Option<Instant>,
+    // Size the estimated size of the block must not exceed, if any.
+    maybe_size_limit: Option<usize>,
+    // Key written by the validation data inherent of a parachain, if the block must apply it.
+    maybe_validation_data_key: Option<StorageKey>,
+    // Number of extrinsics applied by the batches pushed so far, inherents included.
+    num_applied: usize,
+    // Number and encoded size of the extrinsics included so far, inherents included.
+    num_included: usize,
+    extrinsics_size: usize,
+    // What became of the extrinsics pushed so far, inherents excluded.
+    outcome: ProposalOutcome<Block::Hash>,
+    // Number of batches pushed so far, after the inherents.
+    num_batches: usize,
+    // Whether the epilogue was applied, so that no batch is pushed anymore.
+    epilogue_applied: bool,
+    // Whether a batch was cut short by the deadline, the size limit or the block weight limit, so
+    // that no extrinsic is applied anymore.
+    deadline_reached: bool,
+}
+
+impl<'a, Block, B, E> BatchPusher<'a, Block, B, E>
+where
+    B: backend::Backend<Block>,
+    E: CodeExecutor + RuntimeVersionOf + Clone + 'static,
+    Block: BlockT,
+{
+    pub(crate) fn new(
+        executor: ParallelLocalCallExecutor<Block, B, E>,
+        at_hash: Block::Hash,
+        changes: &'a RefCell<OverlayedChanges<HashingFor<Block>>>,
+        recorder: &'a Option<ProofRecorder<Block>>,
+        call_context: CallContext,
+        extensions: &'a RefCell<Extensions>,
+    ) -> Self {
+        Self {
+            executor,
+            at_hash,
+            changes,
+            recorder,
+            call_context,
+            extensions,
+            maybe_deadline: None,
+            maybe_size_limit: None,
+            maybe_validation_data_key: None,
+            num_applied: 0,
+            num_included: 0,
+            extrinsics_size: 0,
+            outcome: ProposalOutcome::default(),
+            num_batches: 0,
+            epilogue_applied: false,
+            deadline_reached: false,
+        }
+    }
+
+    /// Stops applying the extrinsics pushed once `deadline` is reached, e.g. the end of the slot
+    /// of the proposer.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.maybe_deadline = Some(deadline);
+        self
+    }
+
+    /// Stops applying the extrinsics pushed once the [estimated size](Self::estimated_block_size)
+    /// of the block reaches `limit`, e.g. the PoV budget of a parachain with some headroom. The
+    /// extrinsics of a batch that would not fit are skipped, as the `BlockBuilder` of the node
+    /// does; the storage proof growing as the batch is applied is only accounted afterwards.
+    pub fn with_size_limit(mut self, limit: usize) -> Self {
+        self.maybe_size_limit = Some(limit);
+        self
+    }
+
+    /// Refuses to push batches of extrinsics until the validation data inherent of the parachain
+    /// is applied, i.e. until it wrote `ValidationData` in the pallet named `pallet_name`, see
+    /// [`PARACHAIN_SYSTEM_PALLET`](crate::collator::PARACHAIN_SYSTEM_PALLET).
+    pub fn with_validation_data(mut self, pallet_name: &[u8]) -> Self {
+        self.maybe_validation_data_key = Some(validation_data_key(pallet_name));
+        self
+    }
+
+    /// Applies the `inherents` one after the other, in order, before any batch is pushed. Their
+    /// results are returned. The inherents are applied whether the deadline is reached or not,
+    /// since the block is invalid without them.
+    pub fn apply_inherents(
+        &mut self,
+        inherents: &[Block::Extrinsic],
+    ) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        if self.num_batches > 0 || self.epilogue_applied {
+            return Err(sp_blockchain::Error::Application(
+                "Inherents applied after a batch of extrinsics or the epilogue".into(),
+            ));
+        }
+        tracing::debug!(target: LOG_TARGET, num_inherents = inherents.len(), "Applying inherents");
+        self.apply_sequential(inherents)
+    }
+
+    /// Applies the `extrinsics` of the epilogue of the block one after the other, in order, on top
+    /// of the changes of the batches pushed so far, and returns their results. No batch is pushed
+    /// after the epilogue. As the inherents, the extrinsics of the epilogue are applied whether
+    /// the deadline is reached or not.
+    pub fn apply_epilogue(
+        &mut self,
+        extrinsics: &[Block::Extrinsic],
+    ) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        if self.epilogue_applied {
+            return Err(sp_blockchain::Error::Application("Epilogue of the block applied twice".into()));
+        }
+        self.check_validation_data()?;
+        tracing::debug!(target: LOG_TARGET, txn_idx = self.num_applied, num_txns = extrinsics.len(), "Applying epilogue");
+        self.epilogue_applied = true;
+        self.apply_sequential(extrinsics)
+    }
+
+    /// Applies `extrinsics` one after the other on top of the changes of the block so far, and
+    /// includes the ones that are applied.
+    fn apply_sequential(
+        &mut self,
+        extrinsics: &[Block::Extrinsic],
+    ) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        let block: Vec<_> = extrinsics.iter().map(|xt| Extrinsic::new(xt.encode())).collect();
+        let results = self.executor.apply_extrinsics_sequential(
+            self.at_hash,
+            &block,
+            self.changes,
+            self.recorder,
+            self.call_context,
+            self.extensions,
+            None,
+        )?;
+        self.num_applied += results.len();
+        for (xt, _) in block.iter().zip(&results).filter(|(_, result)| result.is_ok()) {
+            self.include(xt);
+        }
+        Ok(results)
+    }
+
+    /// Applies `extrinsics` in parallel on top of the changes of the batches pushed so far, and
+    /// returns the result of every extrinsic applied.
+    ///
+    /// Once the deadline is reached, or the block weight limit, see
+    /// [`ParallelLocalCallExecutor::with_block_weight_limit`], the extrinsics following the last
+    /// one applied are not attempted, nor are the ones of the next batches: the proposer puts
+    /// them back in the pool.
+    pub fn batch_push(&mut self, extrinsics: &[Block::Extrinsic]) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        let block: Vec<_> = extrinsics.iter().map(|xt| Extrinsic::new(xt.encode())).collect();
+        self.batch_push_encoded(&block)
+    }
+
+    /// Applies the already encoded extrinsics of `block` as [`batch_push`](Self::batch_push)
+    /// does.
+    pub fn batch_push_encoded(&mut self, block: &[Extrinsic]) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        if self.epilogue_applied {
+            return Err(sp_blockchain::Error::Application("Batch of extrinsics pushed after the epilogue".into()));
+        }
+        if self.num_batches == 0 {
+            self.check_validation_data()?;
+        }
+        if self.is_finished() {
+            self.outcome.skipped.extend(block.iter().map(Self::hash));
+            return Ok(Vec::new());
+        }
+        let block = &block[..self.num_fitting(block)];
+        tracing::debug!(target: LOG_TARGET, txn_idx = self.num_applied, num_txns = block.len(), "Pushing batch");
+        let results = self.executor.apply_encoded_extrinsics_parallel(
+            self.at_hash,
+            block,
+            self.changes,
+            self.recorder,
+            self.call_context,
+            self.extensions,
+            self.maybe_deadline,
+        )?;
+        self.num_batches += 1;
+        self.num_applied += results.len();
+        self.deadline_reached |= results.len() < block.len();
+        for (xt, result) in block.iter().zip(&results) {
+            match result {
+                Ok(_) => {
+                    self.include(xt);
+                    self.outcome.included.push(Self::hash(xt));
+                }
+                Err(err) if err.exhausted_resources() => self.outcome.skipped.push(Self::hash(xt)),
+                Err(err) => self.outcome.invalid.push((Self::hash(xt), *err)),
+            }
+        }
+        self.outcome.skipped.extend(block[results.len()..].iter().map(Self::hash));
+        Ok(results)
+    }
+
+    /// Number of extrinsics applied so far, inherents included.
+    pub fn num_applied(&self) -> usize {
+        self.num_applied
+    }
+
+    /// Whether the deadline, the size limit or the block weight limit is reached, so that the
+    /// extrinsics pushed are not applied anymore.
+    pub fn is_finished(&self) -> bool {
+        self.deadline_reached
+            || self.maybe_deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            || self.maybe_size_limit.is_some_and(|limit| self.estimated_block_size() >= limit)
+    }
+
+    /// Weight consumed by the block so far, as accounted by the runtime in `System::BlockWeight`
+    /// for all the dispatch classes. Zero if the runtime did not account any weight yet.
+    pub fn estimated_block_weight(&self) -> Weight {
+        let changes = self.changes.borrow();
+        block_weight(changes.storage(&BLOCK_WEIGHT).flatten())
+    }
+
+    /// What became of the extrinsics pushed so far, inherents and epilogue excluded.
+    pub fn outcome(&self) -> &ProposalOutcome<Block::Hash> {
+        &self.outcome
+    }
+
+    /// Encoded size of the extrinsics included so far, inherents included, plus the size of the
+    /// storage proof recorded so far, if any. The header of the block is not accounted, as in the
+    /// estimate of the `BlockBuilder` of the node.
+    pub fn estimated_block_size(&self) -> usize {
+        let proof_size = self.recorder.as_ref().map_or(0, |recorder| recorder.estimate_encoded_size());
+        Compact(self.num_included as u32).encoded_size() + self.extrinsics_size + proof_size
+    }
+
+    /// Number of the first extrinsics of `block` fitting in the size limit, if any, on top of the
+    /// estimated size of the block.
+    fn num_fitting(&mut self, block: &[Extrinsic]) -> usize {
+        let Some(limit) = self.maybe_size_limit else {
+            return block.len();
+        };
+        let mut size = self.estimated_block_size();
+        let num_fitting = block
+            .iter()
+            .take_while(|xt| {
+                size += xt.encoded().len();
+                size <= limit
+            })
+            .count();
+        if num_fitting < block.len() {
+            tracing::debug!(target: LOG_TARGET, num_fitting, num_txns = block.len(), limit, "Batch exceeds the size limit");
+            // The extrinsics that do not fit are skipped, as are the ones of the next batches.
+            self.deadline_reached = true;
+            self.outcome.skipped.extend(block[num_fitting..].iter().map(Self::hash));
+        }
+        num_fitting
+    }
+
+    /// Fails if the block must apply the validation data inherent of a parachain and did not.
+    fn check_validation_data(&self) -> sp_blockchain::Result<()> {
+        let Some(key) = &self.maybe_validation_data_key else {
+            return Ok(());
+        };
+        match self.changes.borrow().storage(key) {
+            Some(Some(_)) => Ok(()),
+            _ => Err(sp_blockchain::Error::Application("Validation data inherent not applied".into())),
+        }
+    }
+
+    /// Accounts for the size of `xt`, included in the block.
+    fn include(&mut self, xt: &Extrinsic) {
+        self.num_included += 1;
+        self.extrinsics_size += xt.encoded().len();
+    }
+
+    /// Hash of `xt` in the transaction pool.
+    fn hash(xt: &Extrinsic) -> Block::Hash {
+        HashingFor::<Block>::hash(xt.encoded())
+    }
+}
@@ -0,0 +1,48 @@
+//! Re-execution of historical blocks, to measure the speedup of Block-STM on the actual load of a
+//! chain.
+//!
+//! Every block is applied twice on top of the state of its parent, once initialized: sequentially
+//! with the `LocalCallExecutor`, as imported by the nodes today, then in parallel with Block-STM,
+//! whatever the version of the runtime. The changes are discarded. See the `parallel-bench`
+//! binary, built with the `bench` feature, to re-execute a range of blocks of a database.
+
+use std::time::Duration;
+
+use sp_state_machine::StorageKey;
+
+use crate::executor::ExecutionStats;
+
+/// Runtime method initializing a block, given its header as argument.
+pub const INITIALIZE_BLOCK_METHOD: &str = "Core_initialize_block";
+
+/// Outcome of the re-execution of a block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockBenchmark {
+    /// Number of extrinsics of the block, including the inherents.
+    pub num_extrinsics: usize,
+    /// Time spent applying the extrinsics sequentially.
+    pub sequential: Duration,
+    /// Time spent applying the extrinsics in parallel, including the ones applied sequentially
+    /// after an extrinsic changing the runtime code.
+    pub parallel: Duration,
+    /// How the extrinsics were executed in parallel, `None` if the block was applied sequentially
+    /// because an extrinsic did something not supported in parallel.
+    pub maybe_stats: Option<ExecutionStats<StorageKey>>,
+}
+
+impl BlockBenchmark {
+    /// Ratio of the sequential time to the parallel one.
+    pub fn speedup(&self) -> f64 {
+        self.sequential.as_secs_f64() / self.parallel.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Number of incarnations aborted by a failed validation.
+    pub fn aborts(&self) -> u32 {
+        self.maybe_stats.as_ref().map_or(0, |stats| stats.scheduler.validation_failures)
+    }
+
+    /// The `limit` keys that aborted the most incarnations, along with the number of aborts.
+    pub fn hot_keys(&self, limit: usize) -> &[(StorageKey, u32)] {
+        self.maybe_stats.as_ref().map_or(&[], |stats| &stats.aborts_by_key[..limit.min(stats.aborts_by_key.len())])
+    }
+}
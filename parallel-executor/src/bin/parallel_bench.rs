@@ -0,0 +1,188 @@
+//! Re-executes a range of historical blocks of a chain with Block-STM, and reports the speedup
+//! of every block over its sequential application, the number of aborted incarnations and the
+//! keys that aborted the most.
+//!
+//! The blocks are read from the database of an archive node, or a backup of it, so that the state
+//! of their parents is available:
+//!
+//! ```text
+//! cargo run --release -p parallel-executor --features bench --bin parallel-bench -- \
+//!     --db <path> [--paritydb] --from <number> --to <number> [--workers <number>] [--hot-keys <number>]
+//! ```
+//!
+//! The blocks are decoded with opaque extrinsics and 32-bit block numbers hashed with BLAKE2-256,
+//! as most chains built with Substrate, and executed by the runtime stored on chain.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parallel_executor::bench::BlockBenchmark;
+use parallel_executor::ParallelLocalCallExecutor;
+use sc_client_api::execution_extensions::ExecutionExtensions;
+use sc_client_api::Backend as _;
+use sc_client_db::{BlocksPruning, DatabaseSettings, DatabaseSource};
+use sc_executor::WasmExecutor;
+use sc_service::ClientConfig;
+use sp_blockchain::{Backend as _, HeaderBackend};
+use sp_core::hexdisplay::HexDisplay;
+use sp_runtime::generic::{self, DigestItem};
+use sp_runtime::traits::{BlakeTwo256, Header as _};
+use sp_runtime::OpaqueExtrinsic;
+
+type Header = generic::Header<u32, BlakeTwo256>;
+type Block = generic::Block<Header, OpaqueExtrinsic>;
+
+const USAGE: &str = "Usage: parallel-bench --db <path> [--paritydb] --from <number> --to <number> [--workers \
+                     <number>] [--hot-keys <number>]";
+
+/// Arguments of the command line.
+struct Args {
+    db: PathBuf,
+    paritydb: bool,
+    from: u32,
+    to: u32,
+    workers: usize,
+    hot_keys: usize,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut maybe_db = None;
+        let mut paritydb = false;
+        let (mut maybe_from, mut maybe_to) = (None, None);
+        let mut workers = std::thread::available_parallelism().map_or(4, |workers| workers.get());
+        let mut hot_keys = 3;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value of {arg}"));
+            match arg.as_str() {
+                "--db" => maybe_db = Some(PathBuf::from(value()?)),
+                "--paritydb" => paritydb = true,
+                "--from" => maybe_from = Some(parse_number(&value()?)?),
+                "--to" => maybe_to = Some(parse_number(&value()?)?),
+                "--workers" => workers = parse_number(&value()?)?,
+                "--hot-keys" => hot_keys = parse_number(&value()?)?,
+                _ => return Err(format!("Unknown argument {arg}")),
+            }
+        }
+        let (Some(db), Some(from), Some(to)) = (maybe_db, maybe_from, maybe_to) else {
+            return Err("Missing --db, --from or --to".to_owned());
+        };
+        Ok(Self { db, paritydb, from, to, workers, hot_keys })
+    }
+}
+
+fn parse_number<N: std::str::FromStr>(value: &str) -> Result<N, String> {
+    value.parse().map_err(|_| format!("Invalid number {value}"))
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let source = if args.paritydb {
+        DatabaseSource::ParityDb { path: args.db.clone() }
+    } else {
+        DatabaseSource::RocksDb { path: args.db.clone(), cache_size: 1024 }
+    };
+    let settings = DatabaseSettings {
+        trie_cache_maximum_size: Some(256 * 1024 * 1024),
+        // The pruning mode the database was created with.
+        state_pruning: None,
+        source,
+        blocks_pruning: BlocksPruning::KeepAll,
+        metrics_registry: None,
+    };
+    let backend = Arc::new(
+        sc_client_db::Backend::<Block>::new(settings, 0)
+            .map_err(|err| format!("Failed to open the database {}: {err}", args.db.display()))?,
+    );
+
+    let executor = WasmExecutor::<sp_io::SubstrateHostFunctions>::builder()
+        // The host functions specific to the chain are only resolved if called.
+        .with_allow_missing_host_functions(true)
+        .build();
+    let parallel_executor = ParallelLocalCallExecutor::new(
+        backend.clone(),
+        executor.clone(),
+        ClientConfig::default(),
+        ExecutionExtensions::new(None, Arc::new(executor)),
+        args.workers,
+    )
+    .map_err(|err| format!("Failed to create the executor: {err}"))?
+    .with_legacy_runtimes();
+
+    println!("block\textrinsics\tsequential\tparallel\tspeedup\taborts\thot keys");
+    let (mut sequential, mut parallel) = (Duration::ZERO, Duration::ZERO);
+    for number in args.from..=args.to {
+        let (header, extrinsics) = read_block(backend.blockchain(), number)?;
+        let benchmark = parallel_executor
+            .benchmark_block(&header, &extrinsics)
+            .map_err(|err| format!("Failed to re-execute block {number}: {err}"))?;
+        println!("{number}\t{}", format_benchmark(&benchmark, args.hot_keys));
+        sequential += benchmark.sequential;
+        parallel += benchmark.parallel;
+    }
+    println!(
+        "Blocks {}..={}: {sequential:?} sequentially, {parallel:?} in parallel with {} workers, speedup {:.2}",
+        args.from,
+        args.to,
+        args.workers,
+        sequential.as_secs_f64() / parallel.as_secs_f64().max(f64::EPSILON),
+    );
+    Ok(())
+}
+
+/// Reads the header of the block `number`, without its seal, and its extrinsics.
+fn read_block(
+    blockchain: &sc_client_db::BlockchainDb<Block>,
+    number: u32,
+) -> Result<(Header, Vec<OpaqueExtrinsic>), String> {
+    let read_error = |err: sp_blockchain::Error| format!("Failed to read block {number}: {err}");
+    let missing = || format!("Block {number} is not in the database");
+    let hash = blockchain.hash(number).map_err(read_error)?.ok_or_else(missing)?;
+    let mut header = blockchain.header(hash).map_err(read_error)?.ok_or_else(missing)?;
+    let extrinsics = blockchain.body(hash).map_err(read_error)?.ok_or_else(missing)?;
+    // The block is initialized with its header as authored, before it was sealed.
+    if let Some(DigestItem::Seal(..)) = header.digest().logs().last() {
+        header.digest_mut().pop();
+    }
+    Ok((header, extrinsics))
+}
+
+fn format_benchmark(benchmark: &BlockBenchmark, hot_keys: usize) -> String {
+    let hot_keys: Vec<_> = benchmark
+        .hot_keys(hot_keys)
+        .iter()
+        .map(|(key, aborts)| format!("0x{}:{aborts}", HexDisplay::from(key)))
+        .collect();
+    let aborts = match benchmark.maybe_stats {
+        Some(_) => benchmark.aborts().to_string(),
+        None => "sequential".to_owned(),
+    };
+    format!(
+        "{}\t{:?}\t{:?}\t{:.2}\t{aborts}\t{}",
+        benchmark.num_extrinsics,
+        benchmark.sequential,
+        benchmark.parallel,
+        benchmark.speedup(),
+        hot_keys.join(","),
+    )
+}
@@ -0,0 +1,46 @@
+//! Compact approximation of a set of keys, telling cheaply that a key is definitely not in it.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Number of bits set for every key.
+const NUM_HASHES: u64 = 3;
+
+/// Number of bits of the filter per key, for a false positive rate of about 3%.
+const BITS_PER_KEY: usize = 8;
+
+/// Bloom filter of a set of keys.
+#[derive(Debug)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+    /// Number of bits of the filter minus one, a power of two minus one.
+    mask: u64,
+}
+
+impl BloomFilter {
+    /// Creates the filter of `keys`.
+    pub(crate) fn new<'k, K: Hash + 'k>(keys: impl ExactSizeIterator<Item = &'k K>) -> Self {
+        let num_bits = (keys.len() * BITS_PER_KEY).next_power_of_two().max(64);
+        let mut filter = Self { bits: vec![0; num_bits / 64], mask: num_bits as u64 - 1 };
+        for key in keys {
+            for bit in filter.bits_of(key) {
+                filter.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+        filter
+    }
+
+    /// Whether `key` may be in the set. `false` if it is definitely not.
+    pub(crate) fn may_contain<K: Hash>(&self, key: &K) -> bool {
+        self.bits_of(key).all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Returns the bits of `key`, derived from a single hash by double hashing.
+    fn bits_of<K: Hash>(&self, key: &K) -> impl Iterator<Item = u64> + '_ {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash & u32::MAX as u64, (hash >> 32) | 1);
+        (0..NUM_HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) & self.mask)
+    }
+}
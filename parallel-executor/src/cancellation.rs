@@ -0,0 +1,56 @@
+//! Cancellation of the block executions in flight, e.g. when the node shuts down or the proposer
+//! abandons its slot for a better fork.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use sp_core::traits::SpawnNamed;
+
+/// Shared flag cancelling the executions it is given to, and the ones of its children.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    maybe_parent: Option<CancellationToken>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token cancelled along with this one, which can also be cancelled on its own,
+    /// e.g. for the proposal of a single slot while the token of the node is cancelled on
+    /// shutdown.
+    pub fn child(&self) -> Self {
+        Self(Arc::new(Inner { cancelled: AtomicBool::new(false), maybe_parent: Some(self.clone()) }))
+    }
+
+    /// Cancels the executions given this token or one of its children.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Whether this token or one of its ancestors was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Acquire) || self.0.maybe_parent.as_ref().is_some_and(Self::is_cancelled)
+    }
+
+    /// Cancels the token once `exit` completes, e.g. the exit future of the service, polled by a
+    /// task of `spawner`.
+    pub fn cancel_on(&self, spawner: &dyn SpawnNamed, exit: impl Future<Output = ()> + Send + 'static) {
+        let token = self.clone();
+        spawner.spawn(
+            "parallel-executor-cancellation",
+            Some("parallel-executor"),
+            Box::pin(async move {
+                exit.await;
+                token.cancel();
+            }),
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! Reads performed by an incarnation, validated against the [`VersionedData`] after execution.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use once_cell::sync::OnceCell;
+
+use crate::bloom::BloomFilter;
+use crate::scheduler::{TxnIndex, Version};
+use crate::task::Transaction;
+use crate::versioned_data::{MVDataError, VersionedData};
+use crate::view::StateView;
+
+/// What a transaction reads of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadKind {
+    /// The value of the key.
+    Value,
+    /// Only whether the key exists, e.g. to check that a storage map contains it.
+    Exists,
+}
+
+/// A value read by a transaction.
+#[derive(Debug)]
+pub enum DataRead<V> {
+    /// The value was written by the given version of a lower transaction of the block.
+    Versioned(Version, Arc<V>),
+    /// No lower transaction wrote to the key, the value was read from the base state.
+    Storage(Arc<V>),
+    /// Only the existence of the key was read. It stays valid as long as the key exists, or not,
+    /// whichever transaction wrote its value.
+    Exists(bool),
+}
+
+impl<V> DataRead<V> {
+    /// The value that was read, `None` if only its existence was.
+    pub fn value(&self) -> Option<&Arc<V>> {
+        match self {
+            DataRead::Versioned(_, value) | DataRead::Storage(value) => Some(value),
+            DataRead::Exists(_) => None,
+        }
+    }
+
+    /// What was read of the key.
+    pub fn kind(&self) -> ReadKind {
+        match self {
+            DataRead::Versioned(..) | DataRead::Storage(_) => ReadKind::Value,
+            DataRead::Exists(_) => ReadKind::Exists,
+        }
+    }
+}
+
+/// The reads performed by an incarnation. Every key is captured once, the first time it is read,
+/// and later reads of the same key by the incarnation are served from the captured value so that
+/// the incarnation observes a consistent state.
+#[derive(Debug)]
+pub struct CapturedReads<T: Transaction> {
+    data_reads: HashMap<T::Key, DataRead<T::Value>>,
+    /// Key whose value was read after its existence, and disagreed with it. The incarnation
+    /// observed an inconsistent state, its reads can never be valid.
+    maybe_inconsistent_read: Option<T::Key>,
+    /// Number of updates of the [`VersionedData`] logged before the last successful validation,
+    /// plus one, or 0 if the reads were never validated. Only the keys updated since are checked
+    /// by the next validation.
+    validated_updates: AtomicUsize,
+    /// Filter of the keys read, built by the first validation, to skip cheaply the updated keys
+    /// that were definitely not read.
+    read_filter: OnceCell<BloomFilter>,
+}
+
+impl<T: Transaction> CapturedReads<T> {
+    /// Records the first read of `key`, or the read of its value after its existence only.
+    pub fn capture_read(&mut self, key: T::Key, read: DataRead<T::Value>) {
+        if let (Some(DataRead::Exists(exists)), Some(value)) = (self.data_reads.get(&key), read.value()) {
+            if T::exists(value) != *exists && self.maybe_inconsistent_read.is_none() {
+                self.maybe_inconsistent_read = Some(key.clone());
+            }
+        }
+        self.data_reads.insert(key, read);
+    }
+
+    /// Whether the captured `read` observed `key` to exist.
+    pub fn exists(read: &DataRead<T::Value>) -> bool {
+        match read {
+            DataRead::Versioned(_, value) | DataRead::Storage(value) => T::exists(value),
+            DataRead::Exists(exists) => *exists,
+        }
+    }
+
+    /// Returns the captured read of `key`, if any.
+    pub fn get(&self, key: &T::Key) -> Option<&DataRead<T::Value>> {
+        self.data_reads.get(key)
+    }
+
+    /// Returns the keys read by the incarnation.
+    pub fn keys(&self) -> impl Iterator<Item = &T::Key> {
+        self.data_reads.keys()
+    }
+
+    /// Returns the lower transactions the incarnation read a value written by, possibly more than
+    /// once.
+    pub fn dependencies(&self) -> impl Iterator<Item = TxnIndex> + '_ {
+        self.versioned_reads().map(|(_, txn_idx)| txn_idx)
+    }
+
+    /// Returns the keys whose value the incarnation read from a lower transaction, along with the
+    /// index of that transaction.
+    pub fn versioned_reads(&self) -> impl Iterator<Item = (&T::Key, TxnIndex)> {
+        self.data_reads.iter().filter_map(|(key, read)| match read {
+            DataRead::Versioned((txn_idx, _), _) => Some((key, *txn_idx)),
+            DataRead::Storage(_) | DataRead::Exists(_) => None,
+        })
+    }
+
+    /// Checks that every captured read would still observe the same value, i.e. that the
+    /// incarnation read a consistent snapshot of the state.
+    pub fn validate_data_reads<S: StateView<T>>(
+        &self,
+        data_map: &VersionedData<T::Key, T::Value>,
+        base_view: &S,
+        idx_to_validate: TxnIndex,
+    ) -> bool {
+        self.invalid_read(data_map, base_view, idx_to_validate).is_none()
+    }
+
+    /// Whether the incarnation only read values of the base state, of keys that no transaction
+    /// wrote to so far. Such reads cannot have been invalidated, and are not validated again.
+    pub fn is_validation_exempt(&self, data_map: &VersionedData<T::Key, T::Value>) -> bool {
+        self.data_reads
+            .iter()
+            .all(|(key, read)| matches!(read, DataRead::Storage(_)) && data_map.max_writer(key).is_none())
+    }
+
+    /// Returns a key whose captured read would no longer observe the same value, if any.
+    ///
+    /// After a successful validation, only the keys updated in the [`VersionedData`] since are
+    /// checked again, unless they outnumber the captured reads. The existence reads of keys no
+    /// lower transaction wrote to are checked against the base state.
+    pub fn invalid_read<S: StateView<T>>(
+        &self,
+        data_map: &VersionedData<T::Key, T::Value>,
+        base_view: &S,
+        idx_to_validate: TxnIndex,
+    ) -> Option<&T::Key> {
+        if self.maybe_inconsistent_read.is_some() {
+            return self.maybe_inconsistent_read.as_ref();
+        }
+
+        let num_updates = data_map.num_updates();
+        let invalid_read = match self.validated_updates.load(Ordering::Acquire).checked_sub(1) {
+            Some(validated_updates) if num_updates - validated_updates < self.data_reads.len() => data_map
+                .keys_updated_since(validated_updates)
+                .iter()
+                .filter(|key| self.read_filter().may_contain(key))
+                .filter_map(|key| self.data_reads.get_key_value(key))
+                .find_map(|(key, read)| {
+                    (!Self::is_valid(data_map, base_view, key, read, idx_to_validate)).then_some(key)
+                }),
+            _ => self.data_reads.iter().find_map(|(key, read)| {
+                (!Self::is_valid(data_map, base_view, key, read, idx_to_validate)).then_some(key)
+            }),
+        };
+
+        if invalid_read.is_none() {
+            self.validated_updates.fetch_max(num_updates + 1, Ordering::AcqRel);
+        }
+        invalid_read
+    }
+
+    fn read_filter(&self) -> &BloomFilter {
+        self.read_filter.get_or_init(|| BloomFilter::new(self.data_reads.keys()))
+    }
+
+    /// Whether the captured `read` of `key` would still observe the same value.
+    fn is_valid<S: StateView<T>>(
+        data_map: &VersionedData<T::Key, T::Value>,
+        base_view: &S,
+        key: &T::Key,
+        read: &DataRead<T::Value>,
+        idx_to_validate: TxnIndex,
+    ) -> bool {
+        match (data_map.fetch_data(key, idx_to_validate), read) {
+            (Ok((version, _)), DataRead::Versioned(read_version, _)) => version == *read_version,
+            (Err(MVDataError::NotFound), DataRead::Storage(_)) => true,
+            (Ok((_, value)), DataRead::Exists(exists)) => T::exists(&value) == *exists,
+            (Err(MVDataError::NotFound), DataRead::Exists(exists)) => {
+                T::exists(&base_view.get_state_value(key)) == *exists
+            }
+            // The value was written by a different transaction (or incarnation), or the key now
+            // depends on an aborted transaction.
+            _ => false,
+        }
+    }
+}
+
+impl<T: Transaction> Default for CapturedReads<T> {
+    fn default() -> Self {
+        Self {
+            data_reads: HashMap::new(),
+            maybe_inconsistent_read: None,
+            validated_updates: AtomicUsize::new(0),
+            read_filter: OnceCell::new(),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Child tries of the contracts as coarse-grained conflict units.
+//!
+//! `pallet-contracts` and `pallet-revive` store the state of every contract in a child trie of
+//! its own, under keys derived from the storage of the contract, which no oracle can predict. The
+//! accesses to the child tries are not tracked key by key: by default, an extrinsic accessing one
+//! is applied sequentially, see [`ChildTriePolicy::Sequential`].
+//!
+//! With [`ChildTriePolicy::Locked`], every child trie is locked as a whole instead: the changes
+//! made to a child trie by the extrinsics of the batch are versioned under a key of the top trie
+//! of its own, its [`lock_key`], whose value is every change made to the child trie so far. An
+//! extrinsic reads the lock of the child trie on its first access to it, and writes the lock again
+//! with its own changes, so that the extrinsics calling the same contract conflict with one
+//! another, while the ones calling different contracts are still applied in parallel. The locks
+//! never reach the state: once the batch is applied, the last changes of every child trie are
+//! written to it, see [`child_changes`].
+
+use std::collections::BTreeMap;
+
+use codec::{Decode, Encode};
+use sp_core::storage::ChildInfo;
+use sp_core::Hasher;
+use sp_state_machine::{OverlayedChanges, StorageKey, StorageValue};
+
+/// Prefix of the locks of the child tries. The runtime can not write the keys under the prefix of
+/// the child tries in the top trie, so that the locks never collide with its writes.
+pub const CHILD_TRIE_LOCK_PREFIX: &[u8] = b":child_storage:parallel:";
+
+/// How the accesses of the extrinsics of a batch to the child tries are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChildTriePolicy {
+    /// The child tries are not supported in parallel: the batch is applied sequentially instead.
+    #[default]
+    Sequential,
+    /// Every child trie is a conflict unit of its own, see [`lock_key`]. The iteration over the
+    /// keys of a child trie, its removal and its root are still not supported in parallel.
+    Locked,
+}
+
+/// Changes made to a child trie, by key.
+pub type ChildTrieChanges = BTreeMap<StorageKey, Option<StorageValue>>;
+
+/// Key of the lock of the child trie `child_info` in the top trie.
+pub fn lock_key(child_info: &ChildInfo) -> StorageKey {
+    [CHILD_TRIE_LOCK_PREFIX, child_info.storage_key()].concat()
+}
+
+/// Child trie locked by `key`, if it is the key of a lock.
+pub fn locked_child_info(key: &[u8]) -> Option<ChildInfo> {
+    key.strip_prefix(CHILD_TRIE_LOCK_PREFIX).map(ChildInfo::new_default)
+}
+
+/// Whether `key` is the key of the lock of a child trie.
+pub fn is_lock_key(key: &[u8]) -> bool {
+    key.starts_with(CHILD_TRIE_LOCK_PREFIX)
+}
+
+/// Decodes the changes made to a child trie from the value of its lock, none if the lock was not
+/// written yet.
+pub fn decode_lock(value: Option<&[u8]>) -> ChildTrieChanges {
+    value.and_then(|mut value| ChildTrieChanges::decode(&mut value).ok()).unwrap_or_default()
+}
+
+/// Values of the locks of the child tries changed in `changes`, e.g. by the previous batches of
+/// the block, on top of which the extrinsics of the batch read the child tries.
+pub fn base_locks<H: Hasher>(changes: &OverlayedChanges<H>) -> Vec<(StorageKey, Option<StorageValue>)> {
+    changes
+        .children()
+        .map(|(child_changes, child_info)| {
+            let child_changes: ChildTrieChanges =
+                child_changes.map(|(key, value)| (key.clone(), value.value().cloned())).collect();
+            (lock_key(child_info), Some(child_changes.encode()))
+        })
+        .collect()
+}
+
+/// Changes of the child trie locked by `key`, of value `value`, to be written to the state, if
+/// `key` is the key of a lock.
+pub fn child_changes(key: &[u8], value: Option<&[u8]>) -> Option<(ChildInfo, ChildTrieChanges)> {
+    locked_child_info(key).map(|child_info| (child_info, decode_lock(value)))
+}
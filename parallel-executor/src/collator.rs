@@ -0,0 +1,52 @@
+//! Blocks of a parachain authored by its collator with the parallel executor.
+//!
+//! The collator of a parachain builds its block in `collate` as any proposer does, pushing the
+//! extrinsics of the pool in batches to a [`BatchPusher`](crate::batch_push::BatchPusher), with
+//! the storage proof recorded. The block is only valid on the relay chain along with its proof of
+//! validity (PoV), which sets three constraints on the way it is built:
+//!
+//! - The first inherent of the block, `ParachainSystem::set_validation_data`, puts the validation
+//!   data of the relay chain in the state, which the runtime reads, e.g. to check the messages from
+//!   the relay chain. It is applied with the other inherents before the first batch, see
+//!   [`BatchPusher::with_validation_data`](crate::batch_push::BatchPusher::with_validation_data),
+//!   which refuses to push a batch until it is.
+//! - The PoV, i.e. the block and the storage proof of its execution, can not exceed the budget set
+//!   by the relay chain, see
+//!   [`BatchPusher::with_size_limit`](crate::batch_push::BatchPusher::with_size_limit).
+//! - The storage proof recorded is sent compacted in the PoV, see [`compact_proof`]. The workers
+//!   record their reads in the recorder of the block as the sequential execution does, so that the
+//!   proof of a block applied in parallel is checked by the relay chain as any other.
+
+use codec::Encode;
+use sp_api::ProofRecorder;
+use sp_core::hashing::twox_128;
+use sp_runtime::traits::{Block as BlockT, HashingFor};
+use sp_state_machine::StorageKey;
+use sp_trie::CompactProof;
+
+/// Name of the `cumulus-pallet-parachain-system` pallet in the runtimes of most parachains.
+pub const PARACHAIN_SYSTEM_PALLET: &[u8] = b"ParachainSystem";
+
+/// Key of `ValidationData` of the `cumulus-pallet-parachain-system` pallet named `pallet_name` in
+/// the runtime, set by the validation data inherent of every block.
+pub fn validation_data_key(pallet_name: &[u8]) -> StorageKey {
+    [twox_128(pallet_name), twox_128(b"ValidationData")].concat()
+}
+
+/// Compacts the storage proof recorded by `recorder` while building a block on top of the state
+/// `parent_state_root`, to be sent in the PoV of the block.
+pub fn compact_proof<Block: BlockT>(
+    recorder: &ProofRecorder<Block>,
+    parent_state_root: Block::Hash,
+) -> sp_blockchain::Result<CompactProof> {
+    recorder
+        .to_storage_proof()
+        .into_compact_proof::<HashingFor<Block>>(parent_state_root)
+        .map_err(|err| sp_blockchain::Error::Application(format!("Compacting the storage proof: {err:?}").into()))
+}
+
+/// Encoded size of the PoV made of `block`, encoded, and of the compacted `proof` of its
+/// execution, to be checked against the budget of the relay chain.
+pub fn pov_size(encoded_block: &[u8], proof: &CompactProof) -> usize {
+    encoded_block.len() + proof.encoded_size()
+}
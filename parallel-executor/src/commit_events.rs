@@ -0,0 +1,125 @@
+//! Stream of the transactions of a block as they are committed.
+//!
+//! Block-STM commits the transactions in order, as soon as they and all the lower ones are
+//! validated, well before the execution of the block is finished. A [`CommitObserver`] given to
+//! the [`BlockExecutor`](crate::executor::BlockExecutor) is notified of every transaction applied
+//! as it is committed, so that a streaming indexer or a mempool manager reacts before the block is
+//! sealed.
+//!
+//! The [`ParallelLocalCallExecutor`](crate::ParallelLocalCallExecutor) sends a [`CommitEvent`] for
+//! every extrinsic committed by Block-STM to the channels returned by
+//! [`subscribe_commits`](crate::ParallelLocalCallExecutor::subscribe_commits). The extrinsics
+//! applied one after the other with the `LocalCallExecutor`, e.g. on a runtime without the batch
+//! method or after an operation not supported in parallel, are not streamed: the subscribers learn
+//! their results from the block.
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use codec::Decode;
+use crossbeam::channel::{self, Receiver, Sender};
+use sp_runtime::traits::Hash as HashT;
+use sp_runtime::ApplyExtrinsicResult;
+
+use crate::extrinsic::{Extrinsic, ExtrinsicOutput};
+use crate::scheduler::TxnIndex;
+use crate::sync_wrapper::Mutex;
+use crate::task::Transaction;
+
+/// Notified of the transactions of a block as they are committed.
+pub trait CommitObserver<T: Transaction, O>: Send + Sync {
+    /// Called once `txn`, the `txn_idx`-th transaction of the block, is committed and will be
+    /// applied with `output`. The transactions are notified in order, from the thread of the worker
+    /// committing them, which should not be held up.
+    fn on_commit(&self, txn_idx: TxnIndex, txn: &T, output: &O);
+}
+
+/// Summary of the values written by an extrinsic, its events excluded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteSetSummary {
+    /// Number of keys set.
+    pub num_writes: usize,
+    /// Number of keys removed.
+    pub num_deletions: usize,
+    /// Size of the values set, in bytes.
+    pub bytes_written: usize,
+}
+
+/// An extrinsic of a batch committed by Block-STM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitEvent<Hash> {
+    /// Index of the extrinsic among the ones executed together with Block-STM, i.e. in the batch
+    /// unless it is applied in chunks or by dispatch class.
+    pub txn_idx: TxnIndex,
+    /// Hash of the encoded extrinsic, as in the transaction pool.
+    pub extrinsic_hash: Hash,
+    /// Result of the extrinsic, as returned by `apply_extrinsic`.
+    pub result: ApplyExtrinsicResult,
+    /// Values written by the extrinsic.
+    pub writes: WriteSetSummary,
+}
+
+/// Channels the commit events are sent to, shared by the clones of the executor.
+pub struct CommitSubscribers<Hash> {
+    senders: Mutex<Vec<Sender<CommitEvent<Hash>>>>,
+}
+
+impl<Hash> Default for CommitSubscribers<Hash> {
+    fn default() -> Self {
+        Self { senders: Mutex::new(Vec::new()) }
+    }
+}
+
+impl<Hash> CommitSubscribers<Hash> {
+    /// Returns a new unbounded channel receiving the commit events from now on.
+    pub(crate) fn subscribe(&self) -> Receiver<CommitEvent<Hash>> {
+        let (sender, receiver) = channel::unbounded();
+        self.senders.lock().push(sender);
+        receiver
+    }
+
+    /// Whether any channel was subscribed, so that the commit events are worth building.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.senders.lock().is_empty()
+    }
+
+    /// Sends `event` to every channel, forgetting the ones whose receiver was dropped.
+    fn send(&self, event: CommitEvent<Hash>)
+    where
+        Hash: Clone,
+    {
+        self.senders.lock().retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+/// Sends a [`CommitEvent`] to the subscribers for every extrinsic committed, hashed with `H`.
+pub(crate) struct CommitSender<H: HashT> {
+    subscribers: Arc<CommitSubscribers<H::Output>>,
+    phantom: PhantomData<H>,
+}
+
+impl<H: HashT> CommitSender<H> {
+    pub(crate) fn new(subscribers: Arc<CommitSubscribers<H::Output>>) -> Self {
+        Self { subscribers, phantom: PhantomData }
+    }
+}
+
+impl<H: HashT> CommitObserver<Extrinsic, ExtrinsicOutput> for CommitSender<H> {
+    fn on_commit(&self, txn_idx: TxnIndex, txn: &Extrinsic, output: &ExtrinsicOutput) {
+        let Ok(result) = ApplyExtrinsicResult::decode(&mut &output.result[..]) else {
+            // The application of the batch fails.
+            return;
+        };
+        let mut writes = WriteSetSummary::default();
+        for (_, value) in &output.writes {
+            match value {
+                Some(value) => {
+                    writes.num_writes += 1;
+                    writes.bytes_written += value.len();
+                }
+                None => writes.num_deletions += 1,
+            }
+        }
+        self.subscribers.send(CommitEvent { txn_idx, extrinsic_hash: H::hash(txn.encoded()), result, writes });
+    }
+}
@@ -0,0 +1,136 @@
+//! Conflict graph of the blocks executed in parallel, for offline analysis.
+//!
+//! Every edge of the graph is a storage item that serialized two transactions of the block: the
+//! final incarnation of the higher one read the value the lower one wrote, or an incarnation of
+//! the higher one failed validation on it. With the `conflict-graph` feature, a
+//! [`BlockExecutor`](crate::executor::BlockExecutor) given a directory dumps the graph of every
+//! block it executes in parallel there, as JSON and DOT, so that runtime engineers can see which
+//! storage items serialize their chain.
+
+use std::fmt::Write;
+
+use crate::captured_reads::CapturedReads;
+use crate::scheduler::TxnIndex;
+use crate::sync_wrapper::Mutex;
+use crate::task::Transaction;
+
+/// How a storage item serialized two transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "conflict-graph", derive(serde::Serialize))]
+#[cfg_attr(feature = "conflict-graph", serde(rename_all = "snake_case"))]
+pub enum ConflictKind {
+    /// The final incarnation of the transaction read the value written by the lower one.
+    Read,
+    /// An incarnation of the transaction failed validation on the key, and was executed again.
+    Abort,
+}
+
+/// A storage item that serialized two transactions of the block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "conflict-graph", derive(serde::Serialize))]
+pub struct Conflict {
+    /// Index of the higher transaction.
+    pub txn_idx: TxnIndex,
+    /// Index of the lower transaction that wrote the key, if any. A transaction can fail
+    /// validation on a key the lower transactions do not write anymore.
+    pub maybe_dep_idx: Option<TxnIndex>,
+    /// The key, as formatted by [`Transaction::format_key`].
+    pub key: String,
+    /// How the key serialized the transactions.
+    pub kind: ConflictKind,
+}
+
+/// Conflicts between the transactions of a block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "conflict-graph", derive(serde::Serialize))]
+pub struct ConflictGraph {
+    /// Number of transactions applied.
+    pub num_txns: TxnIndex,
+    /// Conflicts of the transactions, by increasing index.
+    pub conflicts: Vec<Conflict>,
+}
+
+impl ConflictGraph {
+    /// Builds the graph of the `read_sets` of the final incarnations of the transactions applied,
+    /// in order, and of their validation failures logged in `aborts`.
+    #[cfg(feature = "conflict-graph")]
+    pub(crate) fn new<T: Transaction>(
+        read_sets: impl IntoIterator<Item = impl AsRef<CapturedReads<T>>>,
+        aborts: AbortLog<T::Key>,
+    ) -> Self {
+        let mut num_txns = 0;
+        let mut conflicts = Vec::new();
+        for (txn_idx, read_set) in read_sets.into_iter().enumerate() {
+            num_txns += 1;
+            conflicts.extend(read_set.as_ref().versioned_reads().map(|(key, dep_idx)| Conflict {
+                txn_idx: txn_idx as TxnIndex,
+                maybe_dep_idx: Some(dep_idx),
+                key: T::format_key(key),
+                kind: ConflictKind::Read,
+            }));
+        }
+        conflicts.extend(aborts.0.into_inner().into_iter().filter(|(txn_idx, ..)| *txn_idx < num_txns).map(
+            |(txn_idx, maybe_dep_idx, key)| Conflict {
+                txn_idx,
+                maybe_dep_idx,
+                key: T::format_key(&key),
+                kind: ConflictKind::Abort,
+            },
+        ));
+        conflicts.sort_by_key(|conflict| (conflict.txn_idx, conflict.maybe_dep_idx));
+        Self { num_txns, conflicts }
+    }
+
+    /// Renders the graph in the DOT language, with an edge from every transaction to the lower
+    /// ones it conflicted with, labelled with the key. The aborts are dashed.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph conflicts {\n");
+        for txn_idx in 0..self.num_txns {
+            let _ = writeln!(dot, "    {txn_idx};");
+        }
+        for conflict in &self.conflicts {
+            let Some(dep_idx) = conflict.maybe_dep_idx else {
+                continue;
+            };
+            let style = match conflict.kind {
+                ConflictKind::Read => "solid",
+                ConflictKind::Abort => "dashed",
+            };
+            let label = conflict.key.replace('\\', "\\\\").replace('"', "\\\"");
+            let _ = writeln!(dot, "    {} -> {dep_idx} [label=\"{label}\", style={style}];", conflict.txn_idx);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Renders the graph as JSON.
+    #[cfg(feature = "conflict-graph")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("The graph holds strings and integers only")
+    }
+
+    /// Writes the graph to `dir`, as `{name}.json` and `{name}.dot`.
+    #[cfg(feature = "conflict-graph")]
+    pub fn write_to(&self, dir: &std::path::Path, name: &str) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join(format!("{name}.json")), self.to_json())?;
+        std::fs::write(dir.join(format!("{name}.dot")), self.to_dot())
+    }
+}
+
+/// Validation failures of the transactions of a block, logged by the workers: the aborted
+/// transaction, the lower transaction that last wrote the key, if any, and the key.
+#[derive(Debug)]
+pub(crate) struct AbortLog<K>(Mutex<Vec<(TxnIndex, Option<TxnIndex>, K)>>);
+
+impl<K> Default for AbortLog<K> {
+    fn default() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+}
+
+impl<K> AbortLog<K> {
+    pub(crate) fn record(&self, txn_idx: TxnIndex, maybe_dep_idx: Option<TxnIndex>, key: K) {
+        self.0.lock().push((txn_idx, maybe_dep_idx, key));
+    }
+}
@@ -0,0 +1,193 @@
+//! Prediction of the conflicts between the transactions of a block, before they are executed.
+//!
+//! Block-STM discovers the conflicts between transactions by executing them, and a transaction
+//! executed before a lower one it depends on is aborted and executed again. A [`ConflictOracle`]
+//! predicts the keys accessed by the transactions instead, so that the first incarnation of a
+//! transaction waits for the lower transaction it likely depends on to be executed, and so that
+//! the transactions that provably do not depend on any lower one are not validated.
+//!
+//! A wrong partial prediction only costs parallelism, the execution remains correct either way.
+//! Exhaustive predictions must include every key the transaction accesses, and are only trusted
+//! where they are enforced: the validations are only skipped with
+//! [`SchedulerPolicy::Conservative`](crate::executor::SchedulerPolicy::Conservative), under which
+//! the block ends before the first transaction that accessed a key outside of its prediction.
+//!
+//! Besides the keys, the oracle may know of orderings between the transactions, e.g. the
+//! transactions of a sender or the tags of the transaction pool, see
+//! [`pool_tags`](crate::pool_tags).
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::pool_tags::PoolTags;
+use crate::scheduler::TxnIndex;
+use crate::task::Transaction;
+
+/// Keys a transaction is predicted to access.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySet<K> {
+    /// The transaction likely accesses these keys, and maybe others.
+    Partial(Vec<K>),
+    /// The transaction accesses no key but these.
+    Exhaustive(Vec<K>),
+}
+
+impl<K> KeySet<K> {
+    /// The predicted keys.
+    pub fn keys(&self) -> &[K] {
+        match self {
+            KeySet::Partial(keys) | KeySet::Exhaustive(keys) => keys,
+        }
+    }
+
+    /// Consumes the set, returning the predicted keys.
+    pub fn into_keys(self) -> Vec<K> {
+        match self {
+            KeySet::Partial(keys) | KeySet::Exhaustive(keys) => keys,
+        }
+    }
+
+    /// Whether the transaction accesses no key but the predicted ones.
+    pub fn is_exhaustive(&self) -> bool {
+        matches!(self, KeySet::Exhaustive(_))
+    }
+}
+
+impl<K> Default for KeySet<K> {
+    fn default() -> Self {
+        KeySet::Partial(Vec::new())
+    }
+}
+
+/// Predicts the keys accessed by the transactions of a block, e.g. from their encoding. Chains
+/// implement it for their custom pallets.
+pub trait ConflictOracle<T: Transaction>: Send + Sync {
+    /// Returns the keys `txn` is predicted to read, including the ones it writes after reading.
+    fn predict_reads(&self, txn: &T) -> KeySet<T::Key>;
+
+    /// Returns the keys `txn` is predicted to write.
+    fn predict_writes(&self, txn: &T) -> KeySet<T::Key>;
+
+    /// Returns the keys `txn` is predicted to read and to write, for oracles that predict both at
+    /// once.
+    fn predict(&self, txn: &T) -> (KeySet<T::Key>, KeySet<T::Key>) {
+        (self.predict_reads(txn), self.predict_writes(txn))
+    }
+
+    /// Returns the encoded sender of `txn`, if any. The transactions of a sender necessarily
+    /// conflict, e.g. on its nonce, so every one of them is predicted to depend on the previous
+    /// one.
+    fn sender(&self, _txn: &T) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Returns the tags `txn` provides and requires in the transaction pool, if known. Every
+    /// transaction is predicted to depend on the last lower one providing a tag it requires.
+    fn pool_tags(&self, _txn: &T) -> Option<PoolTags> {
+        None
+    }
+}
+
+/// Keys a transaction may access, when both its reads and its writes are predicted exhaustively.
+#[derive(Debug)]
+struct Declared<K> {
+    reads: HashSet<K>,
+    writes: HashSet<K>,
+}
+
+/// Conflicts predicted between the transactions of a block.
+#[derive(Debug)]
+pub(crate) struct Predictions<K> {
+    /// Highest lower transaction every transaction is predicted to depend on, if any.
+    dependencies: Vec<Option<TxnIndex>>,
+    /// Whether every transaction provably reads no key written by a lower transaction.
+    independent: Vec<bool>,
+    /// Keys every transaction may access, if predicted exhaustively.
+    declared: Vec<Option<Declared<K>>>,
+    /// Keys predicted to be accessed by any transaction.
+    keys: HashSet<K>,
+}
+
+impl<K> Default for Predictions<K> {
+    fn default() -> Self {
+        Self { dependencies: Vec::new(), independent: Vec::new(), declared: Vec::new(), keys: HashSet::new() }
+    }
+}
+
+impl<K: Eq + Hash + Clone> Predictions<K> {
+    /// Predicts the conflicts between the transactions of `block` with `oracle`.
+    pub(crate) fn new<T: Transaction<Key = K>>(oracle: &dyn ConflictOracle<T>, block: &[T]) -> Self {
+        let mut predictions = Self::default();
+        let mut last_writers = HashMap::new();
+        let mut last_txn_of_senders = HashMap::new();
+        let mut last_providers = HashMap::new();
+        // Whether the writes of all the transactions so far are exhaustive.
+        let mut exhaustive_writes = true;
+        for (txn_idx, txn) in block.iter().enumerate() {
+            let (reads, writes) = oracle.predict(txn);
+            let previous_txn_of_sender =
+                oracle.sender(txn).and_then(|sender| last_txn_of_senders.insert(sender, txn_idx as TxnIndex));
+            let tags = oracle.pool_tags(txn).unwrap_or_default();
+            let last_provider = tags.requires.iter().filter_map(|tag| last_providers.get(tag).copied()).max();
+            for tag in tags.provides {
+                last_providers.insert(tag, txn_idx as TxnIndex);
+            }
+            let dependency = reads
+                .keys()
+                .iter()
+                .chain(writes.keys())
+                .filter_map(|key| last_writers.get(key).copied())
+                .chain(previous_txn_of_sender)
+                .chain(last_provider)
+                .max();
+            predictions.dependencies.push(dependency);
+            predictions.independent.push(exhaustive_writes && reads.is_exhaustive() && dependency.is_none());
+
+            exhaustive_writes &= writes.is_exhaustive();
+            predictions.keys.extend(reads.keys().iter().chain(writes.keys()).cloned());
+            for key in writes.keys() {
+                last_writers.insert(key.clone(), txn_idx as TxnIndex);
+            }
+            predictions.declared.push((reads.is_exhaustive() && writes.is_exhaustive()).then(|| Declared {
+                reads: reads.into_keys().into_iter().collect(),
+                writes: writes.into_keys().into_iter().collect(),
+            }));
+        }
+        predictions
+    }
+
+    /// The lower transaction `txn_idx` is predicted to depend on, if any.
+    pub(crate) fn dependency(&self, txn_idx: TxnIndex) -> Option<TxnIndex> {
+        self.dependencies.get(txn_idx as usize).copied().flatten()
+    }
+
+    /// Keys predicted to be accessed by any transaction of the block.
+    pub(crate) fn keys(&self) -> &HashSet<K> {
+        &self.keys
+    }
+
+    /// Whether `txn_idx` provably reads no key written by a lower transaction, in which case its
+    /// reads are always valid. Only sound if every transaction up to `txn_idx` is checked to
+    /// [conform](Predictions::conforms) to its predictions before it is committed.
+    pub(crate) fn is_independent(&self, txn_idx: TxnIndex) -> bool {
+        self.independent.get(txn_idx as usize).copied().unwrap_or_default()
+    }
+
+    /// Whether `txn_idx` only read and wrote keys it was predicted to, if it was predicted
+    /// exhaustively. A key predicted to be written may be read as well.
+    pub(crate) fn conforms<'k>(
+        &self,
+        txn_idx: TxnIndex,
+        read_keys: impl IntoIterator<Item = &'k K>,
+        written_keys: impl IntoIterator<Item = &'k K>,
+    ) -> bool
+    where
+        K: 'k,
+    {
+        let Some(Some(declared)) = self.declared.get(txn_idx as usize) else {
+            return true;
+        };
+        read_keys.into_iter().all(|key| declared.reads.contains(key) || declared.writes.contains(key))
+            && written_keys.into_iter().all(|key| declared.writes.contains(key))
+    }
+}
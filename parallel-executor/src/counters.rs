@@ -0,0 +1,189 @@
+//! Timers of the parallel execution phases, parallelism of the blocks executed, and fallbacks to
+//! the sequential application.
+//!
+//! The metrics are global so that they can be observed from the workers without threading a
+//! metrics handle through the executor. They are exported to Prometheus once registered with
+//! [`register_metrics`], and can be read directly by benchmarks, e.g.
+//! `DEPENDENCY_WAIT_SECONDS.get_sample_sum()`.
+
+use once_cell::sync::Lazy;
+use prometheus_endpoint::{
+    exponential_buckets, register, CounterVec, Histogram, HistogramOpts, Opts, PrometheusError, Registry, U64,
+};
+
+/// Buckets from 10µs to ~40s, suitable for both single tasks and whole blocks.
+fn time_buckets() -> Vec<f64> {
+    exponential_buckets(0.000_01, 2.0, 23).expect("Buckets are valid")
+}
+
+fn time_histogram(name: &str, help: &str) -> Histogram {
+    Histogram::with_opts(HistogramOpts::new(name, help).buckets(time_buckets())).expect("Histogram options are valid")
+}
+
+/// Time spent executing a block in parallel.
+pub static PARALLEL_EXECUTION_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    time_histogram("parallel_executor_parallel_execution_seconds", "Time spent executing a block in parallel")
+});
+
+/// Time spent executing a block sequentially.
+pub static SEQUENTIAL_EXECUTION_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    time_histogram("parallel_executor_sequential_execution_seconds", "Time spent executing a block sequentially")
+});
+
+/// Time spent by a worker on an execution task.
+pub static TASK_EXECUTE_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    time_histogram("parallel_executor_task_execute_seconds", "Time spent executing a transaction incarnation")
+});
+
+/// Time spent by a worker on a validation task.
+pub static TASK_VALIDATE_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    time_histogram("parallel_executor_task_validate_seconds", "Time spent validating a transaction incarnation")
+});
+
+/// Time spent by a worker fetching its next task from the scheduler.
+pub static GET_NEXT_TASK_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    time_histogram("parallel_executor_get_next_task_seconds", "Time spent fetching the next task from the scheduler")
+});
+
+/// Time spent by an incarnation waiting on the re-execution of a lower transaction.
+pub static DEPENDENCY_WAIT_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    time_histogram("parallel_executor_dependency_wait_seconds", "Time spent waiting on a dependency to be resolved")
+});
+
+/// Time spent resolving the predicted keys in the base state before scheduling a block.
+pub static PREFETCH_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    time_histogram("parallel_executor_prefetch_seconds", "Time spent prefetching the predicted base values")
+});
+
+/// Critical path of the blocks executed in parallel, in transactions.
+pub static CRITICAL_PATH_TXNS: Lazy<Histogram> = Lazy::new(|| {
+    let opts = HistogramOpts::new(
+        "parallel_executor_critical_path_txns",
+        "Number of transactions of the critical path of a block executed in parallel",
+    )
+    .buckets(exponential_buckets(1.0, 2.0, 16).expect("Buckets are valid"));
+    Histogram::with_opts(opts).expect("Histogram options are valid")
+});
+
+/// Average number of transactions of the blocks executed in parallel that can be executed at once.
+pub static PARALLELISM_WIDTH: Lazy<Histogram> = Lazy::new(|| {
+    let opts = HistogramOpts::new(
+        "parallel_executor_parallelism_width",
+        "Number of transactions of a block executed in parallel per transaction of its critical path",
+    )
+    .buckets(exponential_buckets(1.0, 1.5, 16).expect("Buckets are valid"));
+    Histogram::with_opts(opts).expect("Histogram options are valid")
+});
+
+/// Why extrinsics meant to be applied in parallel were applied sequentially.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallbackReason {
+    /// The runtime does not declare the batch method.
+    LegacyRuntime,
+    /// An extrinsic performed an operation not supported in parallel.
+    Unsupported,
+    /// The parallel execution ended early, after an extrinsic changing the runtime code or before
+    /// one accessing keys it did not declare or computing a storage root.
+    EarlyEnd,
+    /// The deadline was reached before the parallel execution attempted every extrinsic.
+    Deadline,
+    /// The extrinsics are mandatory or operational.
+    DispatchClass,
+    /// A transaction read a module written by another, see
+    /// [`TxnLastInputOutput`](crate::txn_last_input_output::TxnLastInputOutput).
+    ModuleIntersection,
+}
+
+impl FallbackReason {
+    /// Every reason, in the order of their variants.
+    pub const ALL: [FallbackReason; 6] = [
+        FallbackReason::LegacyRuntime,
+        FallbackReason::Unsupported,
+        FallbackReason::EarlyEnd,
+        FallbackReason::Deadline,
+        FallbackReason::DispatchClass,
+        FallbackReason::ModuleIntersection,
+    ];
+
+    /// Label of the reason in the metrics.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FallbackReason::LegacyRuntime => "legacy_runtime",
+            FallbackReason::Unsupported => "unsupported",
+            FallbackReason::EarlyEnd => "early_end",
+            FallbackReason::Deadline => "deadline",
+            FallbackReason::DispatchClass => "dispatch_class",
+            FallbackReason::ModuleIntersection => "module_intersection",
+        }
+    }
+}
+
+/// Number of times extrinsics meant to be applied in parallel were applied sequentially, by
+/// [`FallbackReason`].
+pub static SEQUENTIAL_FALLBACKS: Lazy<CounterVec<U64>> = Lazy::new(|| {
+    CounterVec::new(
+        Opts::new(
+            "parallel_executor_sequential_fallbacks",
+            "Number of times extrinsics meant to be applied in parallel were applied sequentially",
+        ),
+        &["reason"],
+    )
+    .expect("Counter options are valid")
+});
+
+/// Counts a fallback to the sequential application for `reason`.
+pub fn record_fallback(reason: FallbackReason) {
+    SEQUENTIAL_FALLBACKS.with_label_values(&[reason.as_str()]).inc();
+}
+
+/// Number of fallbacks to the sequential application counted for `reason` since the start of the
+/// process.
+pub fn num_fallbacks(reason: FallbackReason) -> u64 {
+    SEQUENTIAL_FALLBACKS.with_label_values(&[reason.as_str()]).get()
+}
+
+/// Number of incarnations of audited extrinsics that called host functions whose outcome may
+/// differ from one incarnation to the next, by host function, see
+/// [`NONDETERMINISTIC_HOST_CALLS`](crate::ext::NONDETERMINISTIC_HOST_CALLS).
+pub static NONDETERMINISTIC_HOST_CALLS: Lazy<CounterVec<U64>> = Lazy::new(|| {
+    CounterVec::new(
+        Opts::new(
+            "parallel_executor_nondeterministic_host_calls",
+            "Number of incarnations of audited extrinsics that called nondeterministic host functions",
+        ),
+        &["host_call"],
+    )
+    .expect("Counter options are valid")
+});
+
+/// Counts an incarnation calling the nondeterministic `host_call`.
+pub fn record_nondeterministic_host_call(host_call: &str) {
+    NONDETERMINISTIC_HOST_CALLS.with_label_values(&[host_call]).inc();
+}
+
+/// Number of incarnations calling the nondeterministic `host_call` counted since the start of the
+/// process.
+pub fn num_nondeterministic_host_calls(host_call: &str) -> u64 {
+    NONDETERMINISTIC_HOST_CALLS.with_label_values(&[host_call]).get()
+}
+
+/// Registers the timers, the parallelism of the blocks and the fallbacks with the Prometheus
+/// `registry`.
+pub fn register_metrics(registry: &Registry) -> Result<(), PrometheusError> {
+    for histogram in [
+        &PARALLEL_EXECUTION_SECONDS,
+        &SEQUENTIAL_EXECUTION_SECONDS,
+        &TASK_EXECUTE_SECONDS,
+        &TASK_VALIDATE_SECONDS,
+        &GET_NEXT_TASK_SECONDS,
+        &DEPENDENCY_WAIT_SECONDS,
+        &PREFETCH_SECONDS,
+        &CRITICAL_PATH_TXNS,
+        &PARALLELISM_WIDTH,
+    ] {
+        register(Histogram::clone(histogram), registry)?;
+    }
+    register(SEQUENTIAL_FALLBACKS.clone(), registry)?;
+    register(NONDETERMINISTIC_HOST_CALLS.clone(), registry)?;
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Determinism audit of the parallel application of a block.
+//!
+//! A block applied in parallel must have the same outcome as when applied one extrinsic after the
+//! other, whatever the number of workers. The audit, see
+//! [`ParallelLocalCallExecutor::audit_determinism`](crate::ParallelLocalCallExecutor::audit_determinism),
+//! applies the block sequentially with the `LocalCallExecutor`, then in parallel with several
+//! numbers of workers, and diffs the results of the extrinsics, the events, the changes and the
+//! storage root of every parallel run against the sequential one. Chain teams run it locally on
+//! their blocks, e.g. with the `determinism_audit` example, before enabling the parallel executor.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Debug, Display};
+
+use sp_core::hexdisplay::HexDisplay;
+use sp_runtime::ApplyExtrinsicResult;
+use sp_state_machine::{StorageKey, StorageValue};
+
+use crate::events::EVENTS;
+
+/// Numbers of workers the block is applied with by default, besides sequentially.
+pub const AUDIT_CONCURRENCY_LEVELS: [usize; 3] = [2, 4, 8];
+
+/// Outcome of an application of the block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRun<Hash> {
+    /// Number of workers, 1 for the sequential application.
+    pub concurrency_level: usize,
+    /// Results of the extrinsics applied, in order.
+    pub results: Vec<ApplyExtrinsicResult>,
+    /// SCALE encoded events of the block, `None` if none was deposited.
+    pub events: Option<StorageValue>,
+    /// Changes of the main trie, by key. The changes of the child tries are covered by the
+    /// storage root.
+    pub changes: BTreeMap<StorageKey, Option<StorageValue>>,
+    /// Storage root of the state once the block is applied.
+    pub storage_root: Hash,
+}
+
+/// First difference of a parallel run from the sequential one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence<Hash> {
+    /// The extrinsic `txn_idx` had another result, or was only applied by one of the runs.
+    Result { txn_idx: usize, expected: Option<ApplyExtrinsicResult>, actual: Option<ApplyExtrinsicResult> },
+    /// The block deposited other events.
+    Events { expected: Option<StorageValue>, actual: Option<StorageValue> },
+    /// The key was changed to another value, or only changed by one of the runs.
+    Key { key: StorageKey, expected: Option<Option<StorageValue>>, actual: Option<Option<StorageValue>> },
+    /// The storage roots differ, e.g. because of the changes of a child trie.
+    StorageRoot { expected: Hash, actual: Hash },
+}
+
+impl<Hash: PartialEq + Clone> Divergence<Hash> {
+    /// Returns the first difference of `actual` from `expected`, if any: in the results of the
+    /// extrinsics, then in the events, then in the changed keys, in order, then in the storage
+    /// roots.
+    pub fn between(expected: &AuditRun<Hash>, actual: &AuditRun<Hash>) -> Option<Self> {
+        let num_results = expected.results.len().max(actual.results.len());
+        if let Some(txn_idx) = (0..num_results).find(|idx| expected.results.get(*idx) != actual.results.get(*idx)) {
+            return Some(Divergence::Result {
+                txn_idx,
+                expected: expected.results.get(txn_idx).cloned(),
+                actual: actual.results.get(txn_idx).cloned(),
+            });
+        }
+        if expected.events != actual.events {
+            return Some(Divergence::Events { expected: expected.events.clone(), actual: actual.events.clone() });
+        }
+        let keys: BTreeSet<_> = expected.changes.keys().chain(actual.changes.keys()).collect();
+        if let Some(key) = keys.into_iter().find(|key| expected.changes.get(*key) != actual.changes.get(*key)) {
+            return Some(Divergence::Key {
+                key: key.clone(),
+                expected: expected.changes.get(key).cloned(),
+                actual: actual.changes.get(key).cloned(),
+            });
+        }
+        (expected.storage_root != actual.storage_root).then(|| Divergence::StorageRoot {
+            expected: expected.storage_root.clone(),
+            actual: actual.storage_root.clone(),
+        })
+    }
+}
+
+impl<Hash: Debug> Display for Divergence<Hash> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Result { txn_idx, expected, actual } => {
+                write!(f, "Extrinsic {txn_idx} resulted in {actual:?} instead of {expected:?}")
+            }
+            Divergence::Events { expected, actual } => {
+                write!(f, "Events 0x{} instead of 0x{}", hex(actual.as_ref()), hex(expected.as_ref()))
+            }
+            Divergence::Key { key, expected, actual } => write!(
+                f,
+                "Key 0x{} changed to 0x{} instead of 0x{}",
+                HexDisplay::from(key),
+                hex(actual.as_ref().and_then(Option::as_ref)),
+                hex(expected.as_ref().and_then(Option::as_ref)),
+            ),
+            Divergence::StorageRoot { expected, actual } => {
+                write!(f, "Storage root {actual:?} instead of {expected:?}")
+            }
+        }
+    }
+}
+
+fn hex(maybe_value: Option<&StorageValue>) -> String {
+    maybe_value.map_or_else(|| "(none)".to_owned(), |value| HexDisplay::from(value).to_string())
+}
+
+/// Outcomes of the applications of a block, and the differences of the parallel ones from the
+/// sequential one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeterminismReport<Hash> {
+    /// Sequential run.
+    pub sequential: AuditRun<Hash>,
+    /// Parallel runs, in the order their numbers of workers were given.
+    pub parallel: Vec<AuditRun<Hash>>,
+    /// First difference of every diverging parallel run, along with its number of workers.
+    pub divergences: Vec<(usize, Divergence<Hash>)>,
+}
+
+impl<Hash: PartialEq + Clone> DeterminismReport<Hash> {
+    /// Diffs the `parallel` runs against the `sequential` one.
+    pub fn new(sequential: AuditRun<Hash>, parallel: Vec<AuditRun<Hash>>) -> Self {
+        let divergences = parallel
+            .iter()
+            .filter_map(|run| Some((run.concurrency_level, Divergence::between(&sequential, run)?)))
+            .collect();
+        Self { sequential, parallel, divergences }
+    }
+
+    /// Whether every parallel run had the outcome of the sequential one.
+    pub fn is_deterministic(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// Events of the block, among the `changes` of the main trie.
+pub(crate) fn events(changes: &BTreeMap<StorageKey, Option<StorageValue>>) -> Option<StorageValue> {
+    changes.get(EVENTS.as_slice()).cloned().flatten()
+}
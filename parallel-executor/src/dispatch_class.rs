@@ -0,0 +1,30 @@
+//! Routing of the extrinsics of a batch by dispatch class.
+//!
+//! The mandatory extrinsics, e.g. the inherents, and the operational ones come with guarantees
+//! the runtime grants them regardless of the load of the block, and are not worth executing
+//! speculatively. A [`DispatchClassifier`] tells the class of every extrinsic of a batch: the
+//! consecutive extrinsics of the same class are applied together, the normal ones in parallel
+//! with Block-STM, the others sequentially. Every run of extrinsics is applied on top of the
+//! changes committed by the runs before it, so the order of the batch is preserved.
+
+use crate::extrinsic::Extrinsic;
+
+/// Dispatch class of an extrinsic, as the `DispatchClass` of FRAME.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DispatchClass {
+    /// Regular extrinsics, applied in parallel.
+    #[default]
+    Normal,
+    /// Extrinsics of the operators of the chain, applied sequentially until the deadline.
+    Operational,
+    /// Extrinsics included whatever the weight of the block, e.g. the inherents, applied
+    /// sequentially even after the deadline.
+    Mandatory,
+}
+
+/// Tells the dispatch class of the extrinsics of a batch, e.g. by decoding their call and reading
+/// its dispatch info. Chains implement it for their runtime.
+pub trait DispatchClassifier: Send + Sync {
+    /// Returns the dispatch class of `xt`. The extrinsics that cannot be classified are normal.
+    fn dispatch_class(&self, xt: &Extrinsic) -> DispatchClass;
+}
@@ -0,0 +1,80 @@
+//! Speculative application of a batch, reporting the conflicts between its extrinsics.
+//!
+//! [`ParallelLocalCallExecutor::dry_run_batch`](crate::ParallelLocalCallExecutor::dry_run_batch)
+//! executes a batch with Block-STM on top of the state of a block without applying its changes,
+//! recording the keys every extrinsic reads. Two extrinsics conflict when the higher one read a
+//! value written by the lower one, which serializes them. DApp developers submit their usual
+//! transactions with `parallel_dryRunBatch`, see [`rpc`](crate::rpc), and reshape the ones that
+//! conflict, e.g. by sharding a counter they all increment.
+
+use std::collections::{BTreeMap, HashMap};
+
+use codec::{Decode, Encode};
+use sp_runtime::ApplyExtrinsicResult;
+use sp_state_machine::StorageKey;
+
+use crate::extrinsic::ExtrinsicOutput;
+use crate::scheduler::TxnIndex;
+
+/// Two extrinsics of a batch serialized by the keys the higher one read from the lower one.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "rpc", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "rpc", serde(rename_all = "camelCase"))]
+pub struct DryRunConflict {
+    /// Index of the higher extrinsic.
+    pub txn_idx: TxnIndex,
+    /// Index of the lower extrinsic, the last one to write the keys before the higher one.
+    pub dep_idx: TxnIndex,
+    /// Keys written by the lower extrinsic and read by the higher one, sorted.
+    pub keys: Vec<StorageKey>,
+}
+
+impl DryRunConflict {
+    /// Conflicts between the extrinsics of the `outputs`, in order, whose reads were recorded. The
+    /// conflicts are sorted by higher, then lower extrinsic.
+    pub fn between(outputs: &[ExtrinsicOutput]) -> Vec<Self> {
+        let mut last_writers: HashMap<&StorageKey, TxnIndex> = HashMap::new();
+        let mut conflicts = Vec::new();
+        for (txn_idx, output) in outputs.iter().enumerate() {
+            let txn_idx = txn_idx as TxnIndex;
+            let mut keys_by_dep: BTreeMap<TxnIndex, Vec<StorageKey>> = BTreeMap::new();
+            for key in &output.reads {
+                if let Some(dep_idx) = last_writers.get(key) {
+                    keys_by_dep.entry(*dep_idx).or_default().push(key.clone());
+                }
+            }
+            conflicts.extend(keys_by_dep.into_iter().map(|(dep_idx, mut keys)| {
+                keys.sort();
+                keys.dedup();
+                DryRunConflict { txn_idx, dep_idx, keys }
+            }));
+            for (key, _) in &output.writes {
+                last_writers.insert(key, txn_idx);
+            }
+        }
+        conflicts
+    }
+}
+
+/// Outcome of the speculative application of a batch.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "rpc", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "rpc", serde(rename_all = "camelCase"))]
+pub struct DryRunReport {
+    /// Results of the extrinsics executed, in order, as returned by `apply_extrinsic`.
+    pub results: Vec<ApplyExtrinsicResult>,
+    /// Conflicts between the extrinsics executed.
+    pub conflicts: Vec<DryRunConflict>,
+    /// Indices of the extrinsics not executed in parallel, e.g. the ones following an extrinsic
+    /// changing the runtime code.
+    pub skipped_txns: Vec<TxnIndex>,
+}
+
+impl DryRunReport {
+    /// Number of extrinsics executed that do not conflict with a lower one.
+    pub fn num_independent(&self) -> usize {
+        let mut dependents: Vec<_> = self.conflicts.iter().map(|conflict| conflict.txn_idx).collect();
+        dependents.dedup();
+        self.results.len() - dependents.len()
+    }
+}
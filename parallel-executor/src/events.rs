@@ -0,0 +1,140 @@
+//! Events and digest logs deposited by the extrinsics of a batch.
+//!
+//! Every applied extrinsic deposits events, at least `ExtrinsicSuccess` or `ExtrinsicFailed`, by
+//! appending to `System::Events` and incrementing `System::EventCount`. Some also deposit digest
+//! logs, appended to `System::Digest`. Written as such, these values would make every extrinsic
+//! depend on the previous one. The externalities of an extrinsic instead start them empty and only
+//! collect the events and logs of the extrinsic, which are reassembled in order with the ones of
+//! the block once the batch is executed.
+
+use codec::{Compact, Decode, Encode};
+use once_cell::sync::Lazy;
+use sp_core::hashing::twox_128;
+use sp_state_machine::{StorageKey, StorageValue};
+
+use crate::extrinsic::Extrinsic;
+use crate::task::WriteSet;
+
+/// Key of `System::Events`.
+pub static EVENTS: Lazy<StorageKey> = Lazy::new(|| system_storage_key(b"Events"));
+
+/// Key of `System::EventCount`.
+pub static EVENT_COUNT: Lazy<StorageKey> = Lazy::new(|| system_storage_key(b"EventCount"));
+
+/// Prefix of the keys of `System::EventTopics`, which refer to the absolute index of the events.
+pub static EVENT_TOPICS_PREFIX: Lazy<StorageKey> = Lazy::new(|| system_storage_key(b"EventTopics"));
+
+/// Key of `System::Digest`.
+pub static DIGEST: Lazy<StorageKey> = Lazy::new(|| system_storage_key(b"Digest"));
+
+/// Key of the storage value `name` of the `System` pallet.
+pub(crate) fn system_storage_key(name: &[u8]) -> StorageKey {
+    [twox_128(b"System"), twox_128(name)].concat()
+}
+
+/// Whether `key` holds values appended by the extrinsics, collected apart from their writes.
+pub fn is_collected(key: &[u8]) -> bool {
+    key == EVENTS.as_slice() || key == DIGEST.as_slice()
+}
+
+/// Events and digest logs deposited by an extrinsic.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtrinsicEvents {
+    /// SCALE encoded `Vec` of the event records, `None` if the extrinsic deposited no event.
+    pub records: Option<StorageValue>,
+    /// Number of events counted by the extrinsic.
+    pub count: u32,
+    /// SCALE encoded `Vec` of the digest items, `None` if the extrinsic deposited no log.
+    pub logs: Option<StorageValue>,
+}
+
+impl ExtrinsicEvents {
+    fn has_events(&self) -> bool {
+        self.records.is_some() || self.count != 0
+    }
+}
+
+/// Events and digest of the block, to which the ones of the extrinsics are appended in order.
+#[derive(Debug)]
+pub struct BlockEvents {
+    /// Records and count, if an extrinsic deposited events.
+    events: Option<(EncodedVec, u32)>,
+    /// Digest items, if an extrinsic deposited logs.
+    logs: Option<EncodedVec>,
+}
+
+impl BlockEvents {
+    /// Starts from the values of the block read with `read`, only for the kinds of values the
+    /// extrinsics of `batch` deposited.
+    pub fn new<'a>(
+        mut batch: impl Iterator<Item = &'a ExtrinsicEvents> + Clone,
+        read: impl Fn(&StorageKey) -> Option<StorageValue>,
+    ) -> Self {
+        let events = batch.clone().any(ExtrinsicEvents::has_events).then(|| {
+            let count = read(&EVENT_COUNT).and_then(|count| u32::decode(&mut &count[..]).ok());
+            (EncodedVec::new(read(&EVENTS).as_deref()), count.unwrap_or_default())
+        });
+        let logs = batch.any(|events| events.logs.is_some()).then(|| EncodedVec::new(read(&DIGEST).as_deref()));
+        Self { events, logs }
+    }
+
+    /// Appends the events and logs of the next extrinsic.
+    pub fn append(&mut self, extrinsic: &ExtrinsicEvents) {
+        if let Some((records, count)) = &mut self.events {
+            records.append(extrinsic.records.as_deref());
+            *count = count.saturating_add(extrinsic.count);
+        }
+        if let Some(logs) = &mut self.logs {
+            logs.append(extrinsic.logs.as_deref());
+        }
+    }
+
+    /// Returns the values of `System::Events`, `System::EventCount` and `System::Digest` to write,
+    /// if they changed.
+    pub fn into_writes(self) -> WriteSet<Extrinsic> {
+        let mut writes = Vec::new();
+        if let Some((records, count)) = self.events {
+            writes.push((EVENTS.clone(), Some(records.encode())));
+            writes.push((EVENT_COUNT.clone(), Some(count.encode())));
+        }
+        if let Some(logs) = self.logs {
+            writes.push((DIGEST.clone(), Some(logs.encode())));
+        }
+        writes
+    }
+}
+
+/// Encoded `Vec` the encoded items of other `Vec`s are appended to.
+#[derive(Debug, Default)]
+struct EncodedVec {
+    len: u32,
+    items: Vec<u8>,
+}
+
+impl EncodedVec {
+    /// As `storage_append`, an undecodable value is reset.
+    fn new(encoded: Option<&[u8]>) -> Self {
+        let mut vec = Self::default();
+        vec.append(encoded);
+        vec
+    }
+
+    fn append(&mut self, encoded: Option<&[u8]>) {
+        if let Some((len, items)) = encoded.and_then(split_vec) {
+            self.len = self.len.saturating_add(len);
+            self.items.extend_from_slice(items);
+        }
+    }
+
+    fn encode(self) -> Vec<u8> {
+        let mut encoded = Compact(self.len).encode();
+        encoded.extend(self.items);
+        encoded
+    }
+}
+
+/// Splits an encoded `Vec` into its length and its encoded items.
+fn split_vec(mut encoded: &[u8]) -> Option<(u32, &[u8])> {
+    let Compact(len) = Compact::<u32>::decode(&mut encoded).ok()?;
+    Some((len, encoded))
+}
@@ -0,0 +1,155 @@
+//! Prediction of the keys accessed by the Ethereum transactions of Frontier based chains.
+//!
+//! The Ethereum transactions are submitted to Frontier based chains as unsigned extrinsics,
+//! `pallet_ethereum::transact`, whose signer is recovered from the Ethereum signature by the
+//! runtime. The [`AccessHintProvider`](crate::access_hints::AccessHintProvider) does not see
+//! through them, though they are the workloads with the most to gain from the parallel execution:
+//! the transfers and the calls of distinct senders to distinct contracts are independent.
+//!
+//! A chain implements [`EvmBatchAdapter`] to decode its transactions, e.g. with the types of
+//! `ethereum` and its own `AddressMapping`, and the [`EvmOracle`] predicts the keys of `pallet-evm`
+//! they access: the accounts of the sender and of the recipient, the code of the recipient, and
+//! the storage slots of the access list of the transaction, if any, along with the slots the
+//! adapter derives from the call, e.g. the balances of an ERC-20 transfer. The transactions of a
+//! sender are chained by its nonce, see [`ConflictOracle::sender`].
+//!
+//! The predictions are partial: every transaction also appends to the pending transactions of
+//! `pallet-ethereum`, and a contract may access any slot.
+
+use std::sync::Arc;
+
+use codec::{Decode, Encode};
+use sp_core::crypto::AccountId32;
+use sp_core::hashing::{blake2_128, blake2_256};
+use sp_core::{H160, H256, U256};
+use sp_state_machine::StorageKey;
+
+use crate::access_hints::{blake2_128_concat, storage_key};
+use crate::conflict_oracle::{ConflictOracle, KeySet};
+use crate::extrinsic::Extrinsic;
+
+/// Name of `pallet-evm` in the runtimes of most Frontier based chains.
+pub const EVM_PALLET: &[u8] = b"EVM";
+
+/// Ethereum transaction of a `pallet_ethereum::transact` extrinsic, as far as the keys it accesses
+/// are concerned.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct EvmTransaction {
+    /// Sender of the transaction, recovered from its signature.
+    pub from: H160,
+    /// Recipient of the transaction, `None` if it creates a contract.
+    pub to: Option<H160>,
+    /// Value transferred to the recipient.
+    pub value: U256,
+    /// Input of the call, or code of the contract created.
+    pub input: Vec<u8>,
+    /// Storage slots declared by the sender, by address, as of EIP-2930.
+    pub access_list: Vec<(H160, Vec<H256>)>,
+}
+
+/// Integration point of Frontier based chains, decoding their Ethereum transactions.
+pub trait EvmBatchAdapter: Send + Sync {
+    /// Ethereum transaction of `xt` if it is a `pallet_ethereum::transact` extrinsic, `None`
+    /// otherwise.
+    fn decode_transaction(&self, xt: &Extrinsic) -> Option<EvmTransaction>;
+
+    /// Key of the account of `address` in `System::Account`, holding its balance and its nonce.
+    /// Defaults to the `HashedAddressMapping` of Frontier with `BlakeTwo256`: chains whose
+    /// accounts are Ethereum addresses, e.g. `AccountId20`, override it with
+    /// [`system_account_key`] of the address.
+    fn account_key(&self, address: &H160) -> StorageKey {
+        system_account_key(hashed_account_id(address).as_ref())
+    }
+
+    /// Storage slots `txn` is predicted to access besides the ones of its access list, by
+    /// address, e.g. decoded from the input of the calls to well-known contracts.
+    fn predict_slots(&self, _txn: &EvmTransaction) -> Vec<(H160, H256)> {
+        Vec::new()
+    }
+
+    /// Name of `pallet-evm` in the runtime.
+    fn evm_pallet(&self) -> &[u8] {
+        EVM_PALLET
+    }
+}
+
+/// Key of the account `account_id`, SCALE encoded, in `System::Account`.
+pub fn system_account_key(account_id: &[u8]) -> StorageKey {
+    storage_key(b"System", b"Account", &[&blake2_128(account_id)[..], account_id].concat())
+}
+
+/// Account of `address` with the `HashedAddressMapping` of Frontier with `BlakeTwo256`.
+pub fn hashed_account_id(address: &H160) -> AccountId32 {
+    AccountId32::new(blake2_256(&[&b"evm:"[..], address.as_bytes()].concat()))
+}
+
+/// Predicts the keys accessed by the Ethereum transactions decoded by an [`EvmBatchAdapter`], and
+/// delegates the predictions of the other extrinsics to another oracle, if any.
+pub struct EvmOracle<A> {
+    adapter: A,
+    // Predicts the keys accessed by the other extrinsics, if any.
+    maybe_oracle: Option<Arc<dyn ConflictOracle<Extrinsic>>>,
+}
+
+impl<A: EvmBatchAdapter> EvmOracle<A> {
+    /// Creates an oracle that only predicts the keys of the Ethereum transactions.
+    pub fn new(adapter: A) -> Self {
+        Self { adapter, maybe_oracle: None }
+    }
+
+    /// Predicts the keys accessed by the other extrinsics with `oracle`, e.g. an
+    /// [`AccessHintProvider`](crate::access_hints::AccessHintProvider).
+    pub fn with_oracle(mut self, oracle: impl ConflictOracle<Extrinsic> + 'static) -> Self {
+        self.maybe_oracle = Some(Arc::new(oracle));
+        self
+    }
+
+    /// Keys `txn` is predicted to read, and not write, and to write.
+    pub fn hint(&self, txn: &EvmTransaction) -> (Vec<StorageKey>, Vec<StorageKey>) {
+        let pallet = self.adapter.evm_pallet();
+        let mut reads = Vec::new();
+        // The sender pays the fees and its nonce is incremented.
+        let mut writes = vec![self.adapter.account_key(&txn.from)];
+        if let Some(to) = &txn.to {
+            reads.push(storage_key(pallet, b"AccountCodes", &blake2_128_concat(to)));
+            if !txn.value.is_zero() {
+                writes.push(self.adapter.account_key(to));
+            }
+        }
+        // The slots declared may be read or written.
+        let declared =
+            txn.access_list.iter().flat_map(|(address, slots)| slots.iter().map(move |slot| (*address, *slot)));
+        writes.extend(declared.chain(self.adapter.predict_slots(txn)).map(|(address, slot)| {
+            storage_key(pallet, b"AccountStorages", &[blake2_128_concat(&address), blake2_128_concat(&slot)].concat())
+        }));
+        (reads, writes)
+    }
+}
+
+impl<A: EvmBatchAdapter> ConflictOracle<Extrinsic> for EvmOracle<A> {
+    fn predict_reads(&self, xt: &Extrinsic) -> KeySet<StorageKey> {
+        self.predict(xt).0
+    }
+
+    fn predict_writes(&self, xt: &Extrinsic) -> KeySet<StorageKey> {
+        self.predict(xt).1
+    }
+
+    fn predict(&self, xt: &Extrinsic) -> (KeySet<StorageKey>, KeySet<StorageKey>) {
+        match self.adapter.decode_transaction(xt) {
+            Some(txn) => {
+                let (reads, writes) = self.hint(&txn);
+                (KeySet::Partial(reads), KeySet::Partial(writes))
+            }
+            None => self.maybe_oracle.as_ref().map(|oracle| oracle.predict(xt)).unwrap_or_default(),
+        }
+    }
+
+    fn sender(&self, xt: &Extrinsic) -> Option<Vec<u8>> {
+        match self.adapter.decode_transaction(xt) {
+            // The senders are identified by the key of their account, holding their nonce.
+            Some(txn) => Some(self.adapter.account_key(&txn.from)),
+            None => self.maybe_oracle.as_ref().and_then(|oracle| oracle.sender(xt)),
+        }
+    }
+}
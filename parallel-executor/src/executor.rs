@@ -0,0 +1,1067 @@
+//! Block-STM parallel block executor.
+
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use sp_weights::Weight;
+
+use crate::cancellation::CancellationToken;
+use crate::commit_events::CommitObserver;
+use crate::conflict_graph::AbortLog;
+#[cfg(feature = "conflict-graph")]
+use crate::conflict_graph::ConflictGraph;
+use crate::conflict_oracle::{ConflictOracle, Predictions};
+use crate::counters::FallbackReason;
+use crate::hot_keys::{HotKeys, DEFAULT_ABORT_THRESHOLD};
+use crate::limit_processor::{BlockLimitProcessor, ProofSizeBudget};
+use crate::parallelism::Parallelism;
+use crate::replay::{ReplayError, ScheduleEvent, ScheduleRecord, ScheduleRecorder};
+use crate::scheduler::{Scheduler, SchedulerStats, SchedulerTask, SpeculationWindow, TxnIndex, Version, Wave};
+use crate::sync_wrapper::Mutex;
+use crate::task::{
+    ExecutionCancelled, ExecutionPanic, ExecutionStatus, ExecutorTask, Transaction, TransactionOutput, WorkerId,
+};
+use crate::txn_last_input_output::TxnLastInputOutput;
+use crate::versioned_data::VersionedData;
+use crate::view::{LatestView, ReplayedWrites, StateView};
+use crate::{counters, LOG_TARGET};
+
+/// Outputs of the execution of a block.
+#[derive(Debug)]
+pub struct BlockOutput<O: TransactionOutput> {
+    /// Outputs of the transactions to apply, in order.
+    pub outputs: Vec<O>,
+    /// Final values of the keys written by the transactions to apply.
+    pub writes: HashMap<<O::Txn as Transaction>::Key, Arc<<O::Txn as Transaction>::Value>>,
+    /// Weight consumed by the transactions to apply.
+    pub consumed_weight: Weight,
+    /// Estimated size added to the storage proof by the transactions to apply, or 0 if no proof
+    /// size budget was given.
+    pub proof_size: usize,
+    /// Indices of the transactions that were not applied, because the block got full, a lower
+    /// transaction skipped the rest of the block or the deadline was reached. They can be put back
+    /// in the pool.
+    pub skipped_txns: Vec<TxnIndex>,
+    /// Why the block ended before its last transaction, if it did.
+    pub maybe_end: Option<BlockEnd>,
+    /// Dependencies realized between the transactions to apply, if executed in parallel.
+    pub maybe_parallelism: Option<Parallelism>,
+    /// How the block was executed, if executed in parallel.
+    pub maybe_stats: Option<ExecutionStats<<O::Txn as Transaction>::Key>>,
+}
+
+/// Why a block ended before its last transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockEnd {
+    /// A transaction skipped the rest of the block, e.g. as it changed the runtime code.
+    SkipRest,
+    /// A transaction is only supported sequentially, e.g. as it computed a storage root.
+    SequentialOnly,
+    /// A transaction accessed a key outside of its prediction, see
+    /// [`SchedulerPolicy::Conservative`].
+    UndeclaredAccess,
+    /// A transaction does not fit in the block weight limit or in the proof size budget.
+    BlockFull,
+    /// The deadline was reached before a transaction was attempted.
+    Deadline,
+}
+
+/// How a block was executed in parallel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionStats<K> {
+    /// Tasks handed out by the scheduler.
+    pub scheduler: SchedulerStats,
+    /// Keys whose invalidated reads aborted incarnations, along with the number of aborts they
+    /// caused, most first.
+    pub aborts_by_key: Vec<(K, u32)>,
+}
+
+/// How the transactions of a block executed in parallel are scheduled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rpc", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "rpc", serde(rename_all = "snake_case"))]
+pub enum SchedulerPolicy {
+    /// Plain Block-STM: the transactions are executed optimistically, and executed again whenever
+    /// a lower transaction invalidates their reads.
+    Optimistic,
+    /// The exhaustive predictions of the conflict oracle, e.g. the access lists declared by the
+    /// submitters of the transactions, are enforced: the block ends before the first transaction
+    /// that accessed a key outside of its prediction, so that it and the following ones can be
+    /// executed sequentially. Transactions that are not predicted exhaustively are not restricted.
+    /// Only under this policy are the transactions that provably depend on no lower one not
+    /// validated.
+    Conservative,
+    /// Optimistic until a key caused `abort_threshold` aborts, from which the transactions
+    /// accessing it are executed one after the other, see [`hot_keys`](crate::hot_keys).
+    Hybrid { abort_threshold: u32 },
+}
+
+impl Default for SchedulerPolicy {
+    fn default() -> Self {
+        SchedulerPolicy::Hybrid { abort_threshold: DEFAULT_ABORT_THRESHOLD }
+    }
+}
+
+/// Commit progress of a block executed in parallel, updated in order by the worker holding the
+/// commit lock.
+struct CommitState<'a, K, V> {
+    /// Resources consumed by the committed transactions.
+    limits: BlockLimitProcessor<'a, K>,
+    /// Final values of the keys written by the committed transactions to apply, materialized as
+    /// they are committed.
+    writes: HashMap<K, Arc<V>>,
+    /// Number of transactions to apply, and why, once a committed transaction ended the block.
+    block_end: Option<(TxnIndex, BlockEnd)>,
+    /// Number of transactions notified to the commit observer so far.
+    num_observed: TxnIndex,
+}
+
+/// Executes the transactions of a block, in parallel with Block-STM or sequentially.
+pub struct BlockExecutor<T, E, S>
+where
+    T: Transaction,
+    E: ExecutorTask<Txn = T>,
+{
+    // Number of active concurrent tasks, corresponding to the maximum number of rayon
+    // threads that may be concurrently participating in parallel execution.
+    concurrency_level: usize,
+    // Weight that the transactions of the block may consume, if limited.
+    maybe_block_weight_limit: Option<Weight>,
+    // Predicts the conflicts between the transactions of the block, if any.
+    maybe_conflict_oracle: Option<Arc<dyn ConflictOracle<T>>>,
+    // How the transactions are scheduled when executed in parallel.
+    policy: SchedulerPolicy,
+    // Bounds of the number of transactions executed ahead of the committed prefix, if limited.
+    maybe_speculation_window: Option<SpeculationWindow>,
+    // Time from which no new transaction is executed, if any.
+    maybe_deadline: Option<Instant>,
+    // Aborts the execution once cancelled, if any.
+    maybe_cancellation: Option<CancellationToken>,
+    // Whether the predicted keys are resolved in the base state before scheduling.
+    prefetch_base_values: bool,
+    // Whether the first incarnations not predicted to depend on a lower transaction read the
+    // snapshot of the committed prefix.
+    snapshot_first_incarnations: bool,
+    // Runs the workers, the global rayon pool if none.
+    maybe_thread_pool: Option<Arc<ThreadPool>>,
+    // Directory the conflict graphs of the blocks are dumped to, if any.
+    #[cfg(feature = "conflict-graph")]
+    maybe_conflict_graph_dir: Option<PathBuf>,
+    // Directory the schedules of the failed blocks are written to, if any.
+    maybe_schedule_dir: Option<PathBuf>,
+    // Notified of the transactions as they are committed, if any.
+    maybe_commit_observer: Option<Arc<dyn CommitObserver<T, E::Output>>>,
+    phantom: PhantomData<(T, E, S)>,
+}
+
+impl<T, E, S> BlockExecutor<T, E, S>
+where
+    T: Transaction,
+    E: ExecutorTask<Txn = T>,
+    S: StateView<T>,
+{
+    /// Creates an executor running at most `concurrency_level` workers in parallel. Transactions
+    /// are no longer applied once their cumulated weight would exceed `maybe_block_weight_limit`.
+    pub fn new(concurrency_level: usize, maybe_block_weight_limit: Option<Weight>) -> Self {
+        assert!(concurrency_level > 0, "Parallel execution requires at least one worker");
+        Self {
+            concurrency_level,
+            maybe_block_weight_limit,
+            maybe_conflict_oracle: None,
+            policy: SchedulerPolicy::default(),
+            maybe_speculation_window: None,
+            maybe_deadline: None,
+            maybe_cancellation: None,
+            prefetch_base_values: false,
+            snapshot_first_incarnations: false,
+            maybe_thread_pool: None,
+            #[cfg(feature = "conflict-graph")]
+            maybe_conflict_graph_dir: None,
+            maybe_schedule_dir: None,
+            maybe_commit_observer: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Predicts the conflicts between the transactions executed in parallel with `oracle`: the
+    /// first incarnation of a transaction waits for the lower transaction it is predicted to
+    /// depend on to be executed. With [`SchedulerPolicy::Conservative`], the transactions that
+    /// provably depend on no lower one are not validated either.
+    pub fn with_conflict_oracle(mut self, oracle: Arc<dyn ConflictOracle<T>>) -> Self {
+        self.maybe_conflict_oracle = Some(oracle);
+        self
+    }
+
+    /// Schedules the transactions with `policy`, [`SchedulerPolicy::Hybrid`] by default.
+    pub fn with_scheduler_policy(mut self, policy: SchedulerPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Only executes the transactions within a window of the committed prefix, tuned from the
+    /// aborts of the block within `window`, see [`scheduler`](crate::scheduler). Unlimited by
+    /// default, which suits the blocks with few conflicts.
+    pub fn with_speculation_window(mut self, window: SpeculationWindow) -> Self {
+        self.maybe_speculation_window = Some(window);
+        self
+    }
+
+    /// Stops executing new transactions once `deadline` is reached: the transactions already
+    /// attempted are executed and validated until they can be committed, and the block ends before
+    /// the first one that was not attempted.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.maybe_deadline = Some(deadline);
+        self
+    }
+
+    /// Aborts the execution of the block with [`ExecutionCancelled`] once `token` is cancelled:
+    /// the transactions being executed are finished, and the workers stop.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.maybe_cancellation = Some(token);
+        self
+    }
+
+    /// Notifies `observer` of every transaction to apply as it is committed, in order, before the
+    /// execution of the block is finished.
+    pub fn with_commit_observer(mut self, observer: Arc<dyn CommitObserver<T, E::Output>>) -> Self {
+        self.maybe_commit_observer = Some(observer);
+        self
+    }
+
+    /// Resolves the keys the conflict oracle predicts the transactions to access in the base state
+    /// in parallel, before they are scheduled, and provides them to the base view, see
+    /// [`StateView::provide_base_value`]. The first incarnations then read them without waiting on
+    /// the database.
+    pub fn with_base_value_prefetch(mut self) -> Self {
+        self.prefetch_base_values = true;
+        self
+    }
+
+    /// Executes the first incarnation of the transactions not predicted to depend on a lower one
+    /// against the snapshot of the base state and of the committed prefix: it never waits on the
+    /// transactions being executed, and is executed again, reading their latest values, once a
+    /// lower transaction not committed yet turns out to write a key it read. Lowers the overhead
+    /// of the blocks with few conflicts.
+    pub fn with_snapshot_first_incarnations(mut self) -> Self {
+        self.snapshot_first_incarnations = true;
+        self
+    }
+
+    /// Runs the workers, and prefetches the base values, on `pool` rather than on the global rayon
+    /// pool.
+    pub fn with_thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.maybe_thread_pool = Some(pool);
+        self
+    }
+
+    /// Dumps the [`ConflictGraph`] of every block executed in parallel to `dir`, as JSON and DOT,
+    /// in files named after the time the execution finished.
+    #[cfg(feature = "conflict-graph")]
+    pub fn with_conflict_graph_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.maybe_conflict_graph_dir = Some(dir.into());
+        self
+    }
+
+    /// Records the schedule of every block executed in parallel, and writes the ones of the blocks
+    /// whose execution fails to `dir`, to be replayed with [`BlockExecutor::replay`]. Every read
+    /// is recorded, which slows the execution down.
+    pub fn with_schedule_recording(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.maybe_schedule_dir = Some(dir.into());
+        self
+    }
+
+    /// Log of the validation failures of the block, if its conflict graph is dumped.
+    fn new_abort_log(&self) -> Option<AbortLog<T::Key>> {
+        #[cfg(feature = "conflict-graph")]
+        {
+            self.maybe_conflict_graph_dir.as_ref().map(|_| AbortLog::default())
+        }
+        #[cfg(not(feature = "conflict-graph"))]
+        {
+            None
+        }
+    }
+
+    /// Writes the conflict graph of the `num_applied` transactions applied to the directory of
+    /// the conflict graphs.
+    #[cfg(feature = "conflict-graph")]
+    fn dump_conflict_graph(
+        &self,
+        num_applied: TxnIndex,
+        last_input_output: &TxnLastInputOutput<T, E::Output, E::Error>,
+        abort_log: AbortLog<T::Key>,
+    ) {
+        let Some(dir) = &self.maybe_conflict_graph_dir else {
+            return;
+        };
+        let read_sets = (0..num_applied)
+            .map(|txn_idx| last_input_output.read_set(txn_idx).expect("Every applied transaction was executed"));
+        let graph = ConflictGraph::new(read_sets, abort_log);
+        let name = dump_name();
+        match graph.write_to(dir, &name) {
+            Ok(()) => tracing::debug!(target: LOG_TARGET, ?dir, name, "Dumped conflict graph"),
+            Err(err) => tracing::warn!(target: LOG_TARGET, ?dir, %err, "Failed to dump conflict graph"),
+        }
+    }
+
+    /// Writes the schedule of a block whose execution failed with `err` to the directory of the
+    /// schedules.
+    fn write_schedule(&self, record: ScheduleRecord, err: &E::Error) {
+        let Some(dir) = &self.maybe_schedule_dir else {
+            return;
+        };
+        let path = dir.join(format!("{}.schedule", dump_name()));
+        match record.write_to(&path) {
+            Ok(()) => tracing::warn!(target: LOG_TARGET, ?path, ?err, "Recorded the schedule of the failed block"),
+            Err(io_err) => {
+                tracing::warn!(target: LOG_TARGET, ?path, %io_err, "Failed to record the schedule of the failed block")
+            }
+        }
+    }
+
+    /// Replays the incarnations executed in `record`, one after the other on the calling thread:
+    /// every read of an incarnation observes what it did when recorded, i.e. the value of the base
+    /// state, or the value written by the replayed incarnation it read from. The panics of the
+    /// transactions are not caught, so that a debugger stops where they happen.
+    ///
+    /// Returns the status of every incarnation replayed, in order, or the first incarnation that
+    /// did not perform the reads it was recorded with.
+    pub fn replay(
+        &self,
+        executor_arguments: E::Argument,
+        signature_verified_block: &[T],
+        base_view: &S,
+        record: &ScheduleRecord,
+    ) -> Result<Vec<(Version, ExecutionStatus<E::Output, E::Error>)>, ReplayError> {
+        let executor = E::init(executor_arguments, 0);
+        let mut replayed_writes = ReplayedWrites::new();
+        let mut statuses = Vec::new();
+        for event in &record.events {
+            let ScheduleEvent::Execution { version, reads, .. } = event else {
+                continue;
+            };
+            let txn_idx = version.0;
+            let txn = signature_verified_block.get(txn_idx as usize).ok_or(ReplayError::UnknownTransaction(txn_idx))?;
+            let _span = tracing::debug_span!(target: LOG_TARGET, "replay", txn_idx, incarnation = version.1).entered();
+
+            let view = LatestView::new_replay(base_view, &replayed_writes, *version, reads);
+            let status = executor.execute_transaction(&view, txn, txn_idx);
+            view.finish_replay()?;
+
+            if let ExecutionStatus::Success(output) | ExecutionStatus::SkipRest(output) = &status {
+                let writes = output
+                    .get_writes()
+                    .into_iter()
+                    .map(|(key, value)| (T::format_key(&key), Arc::new(value)))
+                    .collect();
+                replayed_writes.insert(*version, writes);
+            }
+            statuses.push((*version, status));
+        }
+        Ok(statuses)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.maybe_cancellation.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+
+    fn deadline_reached(&self) -> bool {
+        self.maybe_deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    fn is_conservative(&self) -> bool {
+        self.policy == SchedulerPolicy::Conservative
+    }
+
+    fn predictions(&self, block: &[T]) -> Predictions<T::Key> {
+        match &self.maybe_conflict_oracle {
+            Some(oracle) => Predictions::new(oracle.as_ref(), block),
+            None => Predictions::default(),
+        }
+    }
+
+    /// Executes the block, in parallel if more than one worker is available. Returns the outputs
+    /// of the transactions to apply, in order, up to the first [`ExecutionStatus::SkipRest`], the
+    /// first transaction that does not fit in the block weight limit or in the proof size budget,
+    /// or the first transaction not attempted before the deadline.
+    pub fn execute_block(
+        &self,
+        executor_arguments: E::Argument,
+        signature_verified_block: &[T],
+        base_view: &S,
+        maybe_proof_size_budget: Option<ProofSizeBudget<'_, T::Key>>,
+    ) -> Result<BlockOutput<E::Output>, E::Error> {
+        if self.concurrency_level > 1 {
+            self.execute_transactions_parallel(
+                executor_arguments,
+                signature_verified_block,
+                base_view,
+                maybe_proof_size_budget,
+            )
+        } else {
+            self.execute_transactions_sequential(
+                executor_arguments,
+                signature_verified_block,
+                base_view,
+                maybe_proof_size_budget,
+            )
+        }
+    }
+
+    /// Resolves the predicted keys in the base state on the thread pool.
+    fn prefetch_base_values(&self, predictions: &Predictions<T::Key>, base_view: &S) {
+        let _timer = counters::PREFETCH_SECONDS.start_timer();
+        let prefetch = || {
+            predictions.keys().par_iter().for_each(|key| {
+                base_view.provide_base_value(key.clone(), base_view.get_state_value(key));
+            })
+        };
+        match &self.maybe_thread_pool {
+            Some(pool) => pool.install(prefetch),
+            None => prefetch(),
+        }
+        tracing::debug!(target: LOG_TARGET, num_keys = predictions.keys().len(), "Prefetched the predicted base values");
+    }
+
+    /// Runs `op` in a scope of the thread pool, in which the workers are spawned.
+    fn scope<'scope>(&self, op: impl FnOnce(&rayon::Scope<'scope>) + Send) {
+        match &self.maybe_thread_pool {
+            Some(pool) => pool.scope(op),
+            None => rayon::scope(op),
+        }
+    }
+
+    /// Executes the block with Block-STM on `concurrency_level` workers.
+    pub fn execute_transactions_parallel(
+        &self,
+        executor_initial_arguments: E::Argument,
+        signature_verified_block: &[T],
+        base_view: &S,
+        maybe_proof_size_budget: Option<ProofSizeBudget<'_, T::Key>>,
+    ) -> Result<BlockOutput<E::Output>, E::Error> {
+        let maybe_recorder = self
+            .maybe_schedule_dir
+            .as_ref()
+            .map(|_| ScheduleRecorder::new(signature_verified_block.len() as TxnIndex, self.concurrency_level));
+        let result = self.run_parallel(
+            executor_initial_arguments,
+            signature_verified_block,
+            base_view,
+            maybe_proof_size_budget,
+            maybe_recorder.as_ref(),
+        );
+        if let (Err(err), Some(recorder)) = (&result, maybe_recorder) {
+            self.write_schedule(recorder.into_record(), err);
+        }
+        result
+    }
+
+    fn run_parallel(
+        &self,
+        executor_initial_arguments: E::Argument,
+        signature_verified_block: &[T],
+        base_view: &S,
+        maybe_proof_size_budget: Option<ProofSizeBudget<'_, T::Key>>,
+        maybe_recorder: Option<&ScheduleRecorder>,
+    ) -> Result<BlockOutput<E::Output>, E::Error> {
+        let num_txns = signature_verified_block.len() as TxnIndex;
+        if num_txns == 0 {
+            return Ok(BlockOutput {
+                outputs: Vec::new(),
+                writes: HashMap::new(),
+                consumed_weight: Weight::zero(),
+                proof_size: 0,
+                skipped_txns: Vec::new(),
+                maybe_end: None,
+                maybe_parallelism: Some(Parallelism::default()),
+                maybe_stats: Some(ExecutionStats { scheduler: SchedulerStats::default(), aborts_by_key: Vec::new() }),
+            });
+        }
+
+        let _timer = counters::PARALLEL_EXECUTION_SECONDS.start_timer();
+        let predictions = self.predictions(signature_verified_block);
+        if self.prefetch_base_values {
+            self.prefetch_base_values(&predictions, base_view);
+        }
+        let versioned_data = VersionedData::new();
+        let mut scheduler = Scheduler::new(num_txns);
+        if let Some(window) = self.maybe_speculation_window {
+            scheduler = scheduler.with_speculation_window(window);
+        }
+        let last_input_output = TxnLastInputOutput::new(num_txns);
+        let hot_keys = HotKeys::new(match self.policy {
+            SchedulerPolicy::Hybrid { abort_threshold } => Some(abort_threshold),
+            SchedulerPolicy::Optimistic | SchedulerPolicy::Conservative => None,
+        });
+        let commit_state = Mutex::new(CommitState {
+            limits: BlockLimitProcessor::new(self.maybe_block_weight_limit, maybe_proof_size_budget),
+            writes: HashMap::new(),
+            block_end: None,
+            num_observed: 0,
+        });
+        // First panic caught outside of the execution of a transaction, which halts the execution.
+        let worker_panic = Mutex::new(None);
+        let maybe_abort_log = self.new_abort_log();
+
+        // The workers are spawned in the span of the block, if any, so that they are told apart
+        // from the ones of the other blocks.
+        let block_span = tracing::Span::current();
+        self.scope(|s| {
+            for worker_id in 0..self.concurrency_level {
+                let (
+                    executor_initial_arguments,
+                    predictions,
+                    last_input_output,
+                    hot_keys,
+                    maybe_abort_log,
+                    versioned_data,
+                    scheduler,
+                    commit_state,
+                    worker_panic,
+                ) = (
+                    &executor_initial_arguments,
+                    &predictions,
+                    &last_input_output,
+                    &hot_keys,
+                    maybe_abort_log.as_ref(),
+                    &versioned_data,
+                    &scheduler,
+                    &commit_state,
+                    &worker_panic,
+                );
+                let block_span = &block_span;
+                s.spawn(move |_| {
+                    let _span = tracing::debug_span!(target: LOG_TARGET, parent: block_span, "worker", worker_id).entered();
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        self.worker_loop(
+                            worker_id,
+                            *executor_initial_arguments,
+                            signature_verified_block,
+                            predictions,
+                            last_input_output,
+                            hot_keys,
+                            maybe_abort_log,
+                            maybe_recorder,
+                            versioned_data,
+                            scheduler,
+                            commit_state,
+                            base_view,
+                        )
+                    }));
+                    if let Err(payload) = result {
+                        let panic = ExecutionPanic::new(None, payload);
+                        tracing::error!(target: LOG_TARGET, worker_id, %panic, "Worker panicked, halting the execution");
+                        // Wake up the workers waiting on a dependency the panicked worker will
+                        // not resolve.
+                        scheduler.halt();
+                        worker_panic.lock().get_or_insert(panic);
+                    }
+                });
+            }
+        });
+
+        if let Some(panic) = worker_panic.into_inner() {
+            return Err(panic.into());
+        }
+        if self.is_cancelled() {
+            tracing::debug!(target: LOG_TARGET, num_txns, "Parallel execution cancelled");
+            return Err(ExecutionCancelled.into());
+        }
+
+        if last_input_output.module_read_write_intersection() {
+            tracing::debug!(target: LOG_TARGET, num_txns, "Module read-write intersection, executing the block sequentially");
+            counters::record_fallback(FallbackReason::ModuleIntersection);
+            let CommitState { limits, num_observed, .. } = commit_state.into_inner();
+            return self.run_sequential(
+                executor_initial_arguments,
+                signature_verified_block,
+                base_view,
+                limits.into_proof_size_budget(),
+                num_observed,
+            );
+        }
+
+        // Commit the transactions validated after the last commit attempt of the workers.
+        self.commit_ready_txns(
+            signature_verified_block,
+            &scheduler,
+            &predictions,
+            &last_input_output,
+            &versioned_data,
+            &mut commit_state.lock(),
+        );
+
+        tracing::debug!(target: LOG_TARGET, num_txns, stats = ?scheduler.stats(), "Parallel execution finished");
+
+        let CommitState { limits, writes, block_end, .. } = commit_state.into_inner();
+        // The execution limit is only lowered by the deadline, or by the end of the block.
+        let (num_applied, end) = block_end.unwrap_or((scheduler.execution_limit(), BlockEnd::Deadline));
+
+        let parallelism = Parallelism::from_dependencies((0..num_applied).map(|txn_idx| {
+            let read_set = last_input_output.read_set(txn_idx).expect("Every applied transaction was executed");
+            read_set.dependencies().collect::<Vec<_>>()
+        }));
+        counters::CRITICAL_PATH_TXNS.observe(parallelism.critical_path as f64);
+        counters::PARALLELISM_WIDTH.observe(parallelism.width());
+        #[cfg(feature = "conflict-graph")]
+        if let Some(abort_log) = maybe_abort_log {
+            self.dump_conflict_graph(num_applied, &last_input_output, abort_log);
+        }
+
+        let stats = ExecutionStats { scheduler: scheduler.stats(), aborts_by_key: hot_keys.into_aborts() };
+
+        let mut outputs = Vec::with_capacity(num_applied as usize);
+        for (_, status) in last_input_output.into_outputs().take_while(|(idx, _)| *idx < num_applied) {
+            match status {
+                ExecutionStatus::Success(output) | ExecutionStatus::SkipRest(output) => outputs.push(output),
+                ExecutionStatus::Abort(err) => return Err(err),
+            }
+        }
+        Ok(BlockOutput {
+            outputs,
+            writes,
+            consumed_weight: limits.consumed_weight(),
+            proof_size: limits.proof_size(),
+            skipped_txns: (num_applied..num_txns).collect(),
+            maybe_end: (num_applied < num_txns).then_some(end),
+            maybe_parallelism: Some(parallelism),
+            maybe_stats: Some(stats),
+        })
+    }
+
+    /// Executes the block sequentially on the calling thread.
+    pub fn execute_transactions_sequential(
+        &self,
+        executor_arguments: E::Argument,
+        signature_verified_block: &[T],
+        base_view: &S,
+        maybe_proof_size_budget: Option<ProofSizeBudget<'_, T::Key>>,
+    ) -> Result<BlockOutput<E::Output>, E::Error> {
+        self.run_sequential(executor_arguments, signature_verified_block, base_view, maybe_proof_size_budget, 0)
+    }
+
+    /// Executes the block sequentially, only notifying the commit observer of the transactions
+    /// from `num_observed`, the lower ones having been committed by a parallel execution.
+    fn run_sequential(
+        &self,
+        executor_arguments: E::Argument,
+        signature_verified_block: &[T],
+        base_view: &S,
+        maybe_proof_size_budget: Option<ProofSizeBudget<'_, T::Key>>,
+        num_observed: TxnIndex,
+    ) -> Result<BlockOutput<E::Output>, E::Error> {
+        let _timer = counters::SEQUENTIAL_EXECUTION_SECONDS.start_timer();
+        let num_txns = signature_verified_block.len() as TxnIndex;
+        let executor = E::init(executor_arguments, 0);
+        let mut data_map = HashMap::new();
+        let mut limits = BlockLimitProcessor::new(self.maybe_block_weight_limit, maybe_proof_size_budget);
+        let mut ret = Vec::with_capacity(signature_verified_block.len());
+        let predictions =
+            if self.is_conservative() { self.predictions(signature_verified_block) } else { Predictions::default() };
+        let mut maybe_end = None;
+
+        for (idx, txn) in signature_verified_block.iter().enumerate() {
+            if self.is_cancelled() {
+                tracing::debug!(target: LOG_TARGET, txn_idx = idx, "Sequential execution cancelled");
+                return Err(ExecutionCancelled.into());
+            }
+            if self.deadline_reached() {
+                tracing::debug!(target: LOG_TARGET, txn_idx = idx, "Deadline reached");
+                maybe_end = Some(BlockEnd::Deadline);
+                break;
+            }
+
+            let view = LatestView::new_sequential(base_view, &data_map, idx as TxnIndex);
+            let res =
+                panic::catch_unwind(AssertUnwindSafe(|| executor.execute_transaction(&view, txn, idx as TxnIndex)))
+                    .unwrap_or_else(|payload| {
+                        ExecutionStatus::Abort(ExecutionPanic::new(Some(idx as TxnIndex), payload).into())
+                    });
+            let read_keys = view.take_read_keys();
+
+            let (output, must_skip) = match res {
+                ExecutionStatus::Success(output) => (output, false),
+                ExecutionStatus::SkipRest(output) => (output, true),
+                ExecutionStatus::Abort(err) if E::ends_parallel_segment(&err) => {
+                    tracing::debug!(target: LOG_TARGET, txn_idx = idx, ?err, "Transaction only supported sequentially");
+                    maybe_end = Some(BlockEnd::SequentialOnly);
+                    break;
+                }
+                ExecutionStatus::Abort(err) => return Err(err),
+            };
+
+            let writes = output.get_writes();
+            if !predictions.conforms(idx as TxnIndex, &read_keys, writes.iter().map(|(key, _)| key)) {
+                tracing::debug!(target: LOG_TARGET, txn_idx = idx, "Transaction accessed undeclared keys");
+                maybe_end = Some(BlockEnd::UndeclaredAccess);
+                break;
+            }
+            if !limits.try_accrue(output.weight(), read_keys.iter().chain(writes.iter().map(|(key, _)| key))) {
+                tracing::debug!(target: LOG_TARGET, txn_idx = idx, "Transaction does not fit in the block");
+                maybe_end = Some(BlockEnd::BlockFull);
+                break;
+            }
+
+            for (key, value) in writes {
+                data_map.insert(key, Arc::new(value));
+            }
+            if let Some(observer) = self.maybe_commit_observer.as_ref().filter(|_| idx as TxnIndex >= num_observed) {
+                observer.on_commit(idx as TxnIndex, txn, &output);
+            }
+            ret.push(output);
+
+            if must_skip {
+                maybe_end = Some(BlockEnd::SkipRest);
+                break;
+            }
+        }
+
+        let num_applied = ret.len() as TxnIndex;
+        Ok(BlockOutput {
+            outputs: ret,
+            writes: data_map,
+            consumed_weight: limits.consumed_weight(),
+            proof_size: limits.proof_size(),
+            skipped_txns: (num_applied..num_txns).collect(),
+            maybe_end: maybe_end.filter(|_| num_applied < num_txns),
+            maybe_parallelism: None,
+            maybe_stats: None,
+        })
+    }
+
+    /// Commits the transactions that are ready, in order, and halts the execution once a
+    /// committed transaction ends the block: it does not fit in the block limits, accessed keys
+    /// outside of its prediction in conservative mode, skips the rest of the block, is only
+    /// supported sequentially or aborts the execution.
+    ///
+    /// The writes of the committed transactions to apply are materialized in the commit state,
+    /// and the values they overwrote are freed from the multi-version data: the executing
+    /// transactions are all higher, so they can no longer read them.
+    fn commit_ready_txns(
+        &self,
+        block: &[T],
+        scheduler: &Scheduler,
+        predictions: &Predictions<T::Key>,
+        last_input_output: &TxnLastInputOutput<T, E::Output, E::Error>,
+        versioned_data: &VersionedData<T::Key, T::Value>,
+        commit_state: &mut CommitState<'_, T::Key, T::Value>,
+    ) {
+        while let Some(txn_idx) = scheduler.try_commit() {
+            let read_set = last_input_output.read_set(txn_idx).expect("Read-set must be recorded after execution");
+            let modified_keys = last_input_output.modified_keys(txn_idx).unwrap_or_default();
+            let conforms = !self.is_conservative() || predictions.conforms(txn_idx, read_set.keys(), &modified_keys);
+            let mut fits =
+                |weight| conforms && commit_state.limits.try_accrue(weight, read_set.keys().chain(&modified_keys));
+            let rejected = if conforms { BlockEnd::BlockFull } else { BlockEnd::UndeclaredAccess };
+
+            let block_end = last_input_output.with_output(txn_idx, |status| match status {
+                ExecutionStatus::Success(output) => (!fits(output.weight())).then_some((txn_idx, rejected)),
+                ExecutionStatus::SkipRest(output) => {
+                    Some(if fits(output.weight()) { (txn_idx + 1, BlockEnd::SkipRest) } else { (txn_idx, rejected) })
+                }
+                ExecutionStatus::Abort(err) if E::ends_parallel_segment(err) => {
+                    Some((txn_idx, BlockEnd::SequentialOnly))
+                }
+                // The error is returned when collecting the outputs.
+                ExecutionStatus::Abort(_) => Some((txn_idx + 1, BlockEnd::SkipRest)),
+            });
+
+            if block_end.map_or(true, |(block_end, _)| txn_idx < block_end) {
+                let committed_view = versioned_data.committed_view(txn_idx + 1);
+                for key in modified_keys {
+                    let value =
+                        committed_view.get(&key).expect("Value written by a committed transaction must be readable");
+                    versioned_data.prune(&key, txn_idx);
+                    commit_state.writes.insert(key, value);
+                }
+                if let Some(observer) = &self.maybe_commit_observer {
+                    last_input_output.with_output(txn_idx, |status| match status {
+                        ExecutionStatus::Success(output) | ExecutionStatus::SkipRest(output) => {
+                            observer.on_commit(txn_idx, &block[txn_idx as usize], output)
+                        }
+                        ExecutionStatus::Abort(_) => {}
+                    });
+                    commit_state.num_observed = txn_idx + 1;
+                }
+            }
+
+            if let Some((block_end, end)) = block_end {
+                tracing::debug!(target: LOG_TARGET, txn_idx, num_applied = block_end, ?end, "Committed transaction ends the block");
+                commit_state.block_end = Some((block_end, end));
+                scheduler.skip_rest(block_end);
+                return;
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn worker_loop(
+        &self,
+        worker_id: WorkerId,
+        executor_arguments: E::Argument,
+        block: &[T],
+        predictions: &Predictions<T::Key>,
+        last_input_output: &TxnLastInputOutput<T, E::Output, E::Error>,
+        hot_keys: &HotKeys<T::Key>,
+        maybe_abort_log: Option<&AbortLog<T::Key>>,
+        maybe_recorder: Option<&ScheduleRecorder>,
+        versioned_data: &VersionedData<T::Key, T::Value>,
+        scheduler: &Scheduler,
+        commit_state: &Mutex<CommitState<'_, T::Key, T::Value>>,
+        base_view: &S,
+    ) {
+        // Make executor for each task.
+        let executor = E::init(executor_arguments, worker_id);
+
+        let mut scheduler_task = SchedulerTask::Retry;
+        loop {
+            // A single worker commits at a time, the others carry on with their tasks.
+            if let Some(mut commit_state) = commit_state.try_lock() {
+                self.commit_ready_txns(
+                    block,
+                    scheduler,
+                    predictions,
+                    last_input_output,
+                    versioned_data,
+                    &mut commit_state,
+                );
+            }
+            if self.deadline_reached() {
+                scheduler.stop_execution();
+            }
+            if self.is_cancelled() {
+                scheduler.halt();
+            }
+
+            scheduler_task = match scheduler_task {
+                SchedulerTask::ValidationTask(version_to_validate, wave) => {
+                    let _timer = counters::TASK_VALIDATE_SECONDS.start_timer();
+                    self.validate(
+                        worker_id,
+                        version_to_validate,
+                        wave,
+                        predictions,
+                        last_input_output,
+                        hot_keys,
+                        maybe_abort_log,
+                        maybe_recorder,
+                        versioned_data,
+                        scheduler,
+                        base_view,
+                    )
+                }
+                SchedulerTask::ExecutionTask(version_to_execute) => {
+                    let _timer = counters::TASK_EXECUTE_SECONDS.start_timer();
+                    self.execute(
+                        worker_id,
+                        version_to_execute,
+                        block,
+                        predictions,
+                        last_input_output,
+                        hot_keys,
+                        maybe_recorder,
+                        versioned_data,
+                        scheduler,
+                        &executor,
+                        base_view,
+                    )
+                }
+                SchedulerTask::Retry => {
+                    let _timer = counters::GET_NEXT_TASK_SECONDS.start_timer();
+                    scheduler.next_task()
+                }
+                SchedulerTask::Done => break,
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute(
+        &self,
+        worker_id: WorkerId,
+        version: Version,
+        block: &[T],
+        predictions: &Predictions<T::Key>,
+        last_input_output: &TxnLastInputOutput<T, E::Output, E::Error>,
+        hot_keys: &HotKeys<T::Key>,
+        maybe_recorder: Option<&ScheduleRecorder>,
+        versioned_data: &VersionedData<T::Key, T::Value>,
+        scheduler: &Scheduler,
+        executor: &E,
+        base_view: &S,
+    ) -> SchedulerTask {
+        let (idx_to_execute, incarnation) = version;
+        let _span =
+            tracing::debug_span!(target: LOG_TARGET, "parallel_exec", txn_idx = idx_to_execute, incarnation).entered();
+
+        let txn = &block[idx_to_execute as usize];
+        // The first incarnation waits for the transaction it is predicted to depend on, the next
+        // ones for the last lower writer of the hot keys the previous incarnation accessed.
+        let dependency = match incarnation {
+            0 => predictions.dependency(idx_to_execute),
+            _ => Self::hot_key_dependency(idx_to_execute, last_input_output, hot_keys, versioned_data),
+        };
+        let snapshot = self.snapshot_first_incarnations && incarnation == 0 && dependency.is_none();
+        let speculative_view = LatestView::new_parallel(
+            base_view,
+            versioned_data,
+            scheduler,
+            maybe_recorder,
+            idx_to_execute,
+            incarnation,
+            snapshot,
+        );
+        if let Some(dep_idx) = dependency {
+            if !speculative_view.wait_for_dependency(dep_idx) {
+                // The execution was halted, there is nothing to record.
+                return scheduler.finish_execution(idx_to_execute, incarnation, false);
+            }
+        }
+        // A panicking incarnation aborts the block, unless it is executed again after reading
+        // inconsistent values.
+        let execute_result = panic::catch_unwind(AssertUnwindSafe(|| {
+            executor.execute_transaction(&speculative_view, txn, idx_to_execute)
+        }))
+        .unwrap_or_else(|payload| ExecutionStatus::Abort(ExecutionPanic::new(Some(idx_to_execute), payload).into()));
+        // Recorded before the writes are visible, so that the incarnation precedes its readers.
+        if let Some(recorder) = maybe_recorder {
+            recorder.record_execution(worker_id, version, speculative_view.take_read_log());
+        }
+
+        let mut prev_modified_keys: HashSet<_> =
+            last_input_output.modified_keys(idx_to_execute).unwrap_or_default().into_iter().collect();
+        let mut modified_keys = Vec::new();
+        // Whether the incarnation wrote to a key that the previous incarnation did not write to.
+        let mut updates_outside = false;
+
+        let mut apply_updates = |output: &E::Output| {
+            for (key, value) in output.get_writes() {
+                if !prev_modified_keys.remove(&key) {
+                    updates_outside = true;
+                }
+                modified_keys.push(key.clone());
+                versioned_data.write(key, version, value);
+            }
+        };
+
+        let result = match execute_result {
+            ExecutionStatus::Success(output) => {
+                apply_updates(&output);
+                ExecutionStatus::Success(output)
+            }
+            ExecutionStatus::SkipRest(output) => {
+                apply_updates(&output);
+                ExecutionStatus::SkipRest(output)
+            }
+            ExecutionStatus::Abort(err) => {
+                tracing::debug!(target: LOG_TARGET, ?err, "Transaction execution aborted");
+                ExecutionStatus::Abort(err)
+            }
+        };
+
+        // Remove the entries of the previous incarnation that were not overwritten.
+        for key in prev_modified_keys {
+            versioned_data.delete(&key, idx_to_execute);
+        }
+
+        if !last_input_output.record(idx_to_execute, speculative_view.take_reads(), result, modified_keys) {
+            // Code written by a transaction was read by another one, the block is executed
+            // sequentially once the workers are done.
+            scheduler.halt();
+            return SchedulerTask::Done;
+        }
+        scheduler.finish_execution(idx_to_execute, incarnation, updates_outside)
+    }
+
+    /// Returns the highest lower transaction that wrote to a hot key accessed by the latest
+    /// incarnation of `txn_idx`, if any.
+    fn hot_key_dependency(
+        txn_idx: TxnIndex,
+        last_input_output: &TxnLastInputOutput<T, E::Output, E::Error>,
+        hot_keys: &HotKeys<T::Key>,
+        versioned_data: &VersionedData<T::Key, T::Value>,
+    ) -> Option<TxnIndex> {
+        if !hot_keys.any() {
+            return None;
+        }
+        let read_set = last_input_output.read_set(txn_idx);
+        let modified_keys = last_input_output.modified_keys(txn_idx).unwrap_or_default();
+        read_set
+            .iter()
+            .flat_map(|read_set| read_set.keys())
+            .chain(&modified_keys)
+            .filter(|key| hot_keys.is_hot(key))
+            .filter_map(|key| versioned_data.last_writer(key, txn_idx))
+            .max()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn validate(
+        &self,
+        worker_id: WorkerId,
+        version_to_validate: Version,
+        validation_wave: Wave,
+        predictions: &Predictions<T::Key>,
+        last_input_output: &TxnLastInputOutput<T, E::Output, E::Error>,
+        hot_keys: &HotKeys<T::Key>,
+        maybe_abort_log: Option<&AbortLog<T::Key>>,
+        maybe_recorder: Option<&ScheduleRecorder>,
+        versioned_data: &VersionedData<T::Key, T::Value>,
+        scheduler: &Scheduler,
+        base_view: &S,
+    ) -> SchedulerTask {
+        let (idx_to_validate, incarnation) = version_to_validate;
+        let read_set = last_input_output.read_set(idx_to_validate).expect("Prior read-set must be recorded");
+
+        // The predictions are only trusted where they are checked before commit.
+        let independent = self.is_conservative() && predictions.is_independent(idx_to_validate);
+        let invalid_read = if independent || read_set.is_validation_exempt(versioned_data) {
+            None
+        } else {
+            read_set.invalid_read(versioned_data, base_view, idx_to_validate)
+        };
+        let aborted = invalid_read.is_some() && scheduler.try_abort(idx_to_validate, incarnation);
+        if let Some(recorder) = maybe_recorder {
+            let maybe_invalid_key = invalid_read.map(T::format_key);
+            recorder.record_validation(worker_id, version_to_validate, validation_wave, maybe_invalid_key, aborted);
+        }
+
+        if let Some(key) = invalid_read.filter(|_| aborted) {
+            hot_keys.record_abort(key);
+            if let Some(abort_log) = maybe_abort_log {
+                abort_log.record(idx_to_validate, versioned_data.last_writer(key, idx_to_validate), key.clone());
+            }
+            tracing::debug!(
+                target: LOG_TARGET,
+                txn_idx = idx_to_validate,
+                incarnation,
+                wave = validation_wave,
+                "Validation failed, aborting incarnation"
+            );
+
+            // Not valid and successfully aborted, mark the latest write-set as estimates.
+            for key in last_input_output.modified_keys(idx_to_validate).unwrap_or_default() {
+                versioned_data.mark_estimate(&key, idx_to_validate);
+            }
+
+            scheduler.finish_abort(idx_to_validate, incarnation)
+        } else {
+            scheduler.finish_validation(idx_to_validate, incarnation, validation_wave);
+            SchedulerTask::Retry
+        }
+    }
+}
+
+/// Name of the files dumped for a block, after the time they are written.
+fn dump_name() -> String {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos())
+        .to_string()
+}
@@ -0,0 +1,821 @@
+//! Externalities of the runtime executing a single extrinsic of a batch in parallel.
+
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Instant;
+
+use codec::{Decode, Encode, EncodeAppend};
+use sp_core::hexdisplay::HexDisplay;
+use sp_core::offchain::{OffchainDbExt, OffchainOverlayedChange, OffchainWorkerExt, TransactionPoolExt};
+use sp_core::storage::{ChildInfo, StateVersion, TrackedStorageKey};
+use sp_core::Hasher;
+use sp_externalities::{Extension, ExtensionStore, Extensions, Externalities, MultiRemovalResults};
+use sp_state_machine::{OverlayedChanges, StateMachineStats, StorageKey, StorageValue};
+
+use crate::child_tries::{self, ChildTrieChanges, ChildTriePolicy};
+use crate::events::{self, ExtrinsicEvents, DIGEST, EVENTS, EVENT_COUNT, EVENT_TOPICS_PREFIX};
+use crate::extrinsic::Extrinsic;
+use crate::scheduler::{Incarnation, TxnIndex};
+use crate::task::WriteSet;
+use crate::view::{LatestView, ReadResult, StateView};
+use crate::{counters, LOG_TARGET};
+
+/// Target of the traces of the storage accesses, the one of the `sp_state_machine::Ext`.
+const STATE_TARGET: &str = "state";
+
+/// What becomes of the writes of an extrinsic to the offchain storage, e.g. by the offchain
+/// indexing of a pallet. They are not versioned by the block executor, and an incarnation executed
+/// speculatively may write values the extrinsic does not write once committed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OffchainPolicy {
+    /// The writes are buffered in the output of the extrinsic, along with its transactional
+    /// changes, and replayed in order once the extrinsic is committed.
+    Buffer,
+    /// The writes are discarded.
+    Discard,
+    /// The writes are not supported in parallel: the batch is applied sequentially instead.
+    #[default]
+    Error,
+}
+
+/// Operations performed through the extensions of the node whose side effects escape the
+/// externalities, e.g. a transaction submitted to the pool, so that an incarnation executed
+/// speculatively can not undo them. Only the extrinsics applied sequentially use them.
+pub const SPECULATION_UNSAFE_OPERATIONS: [&str; 3] = ["submit_transaction", "offchain_worker", "offchain_db"];
+
+/// Operation of [`SPECULATION_UNSAFE_OPERATIONS`] performed through the extension `type_id`, if
+/// any.
+fn speculation_unsafe_operation(type_id: TypeId) -> Option<&'static str> {
+    [TypeId::of::<TransactionPoolExt>(), TypeId::of::<OffchainWorkerExt>(), TypeId::of::<OffchainDbExt>()]
+        .into_iter()
+        .zip(SPECULATION_UNSAFE_OPERATIONS)
+        .find_map(|(unsafe_type_id, operation)| (unsafe_type_id == type_id).then_some(operation))
+}
+
+/// Host functions whose outcome may differ from one incarnation of an extrinsic to the next, e.g.
+/// the time and randomness of the offchain worker, or the signatures of the keystore provided by
+/// an extension unknown to the workers. Audited extrinsics calling them are applied sequentially,
+/// see [`Ext::with_host_call_audit`].
+pub const NONDETERMINISTIC_HOST_CALLS: [&str; 2] = ["offchain_worker", "unknown_extension"];
+
+/// Identifier of the next externalities created.
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+
+/// Externalities of the runtime while it applies one extrinsic of the batch.
+/// The writes of the extrinsic are buffered in an overlay of its own, which also serves the reads
+/// of keys the extrinsic already wrote. The other reads go through the [`LatestView`] of the
+/// extrinsic, so that they are captured for validation.
+///
+/// Only the top-level storage is supported, and the child tries if locked, see
+/// [`ChildTriePolicy`]. The operations that cannot be tracked by the block executor (e.g. key
+/// iteration) flag the extrinsic, which then aborts the parallel execution of the batch so that it
+/// is applied sequentially instead. A storage root computed by the extrinsic only ends the parallel
+/// execution before it, see
+/// [`SEQUENTIAL_SEGMENT_OPERATIONS`](crate::extrinsic::SEQUENTIAL_SEGMENT_OPERATIONS).
+///
+/// The extensions of the node are not available: an extrinsic using one whose side effects can not
+/// be undone, see [`SPECULATION_UNSAFE_OPERATIONS`], is applied sequentially instead.
+///
+/// The reads and writes of the extrinsic are counted by key for the benchmarking host functions,
+/// e.g. `read_write_count`, as the benchmarking state of the node counts them.
+///
+/// The storage accesses are traced as the `sp_state_machine::Ext` traces them, along with the
+/// extrinsic and its incarnation, the block being in the span of the batch.
+///
+/// The runtime cannot be interrupted, so the deadline of the extrinsic, if any, is checked
+/// whenever it accesses the state: past the deadline, the access panics so that the runtime call
+/// is unwound.
+pub struct Ext<'a, H: Hasher, S: StateView<Extrinsic>> {
+    // Identifier of the externalities in the traces, unique among the live ones.
+    id: u16,
+    overlay: OverlayedChanges<H>,
+    view: &'a LatestView<'a, Extrinsic, S>,
+    extensions: Extensions,
+    unsupported: Cell<Option<&'static str>>,
+    maybe_deadline: Option<Instant>,
+    timed_out: Cell<bool>,
+    stats: StateMachineStats,
+    // Keys read from the state before the extrinsic, if recorded.
+    maybe_reads: Option<RefCell<BTreeSet<StorageKey>>>,
+    // What becomes of the writes of the extrinsic to the offchain storage.
+    offchain_policy: OffchainPolicy,
+    // How the accesses of the extrinsic to the child tries are handled.
+    child_trie_policy: ChildTriePolicy,
+    // Host functions called by the extrinsic, if audited.
+    maybe_host_calls: Option<RefCell<BTreeSet<&'static str>>>,
+    // Reads and writes of the extrinsic by key, for the benchmarking host functions.
+    key_tracker: RefCell<KeyTracker>,
+}
+
+/// Reads from the state before the extrinsic and writes of the extrinsic by key, counted as the
+/// benchmarking state of the node counts them for the `read_write_count` host functions: the
+/// reads served by the overlay of the extrinsic and the whitelisted keys are not counted.
+#[derive(Default)]
+struct KeyTracker {
+    // Tracked keys, the whitelisted ones included.
+    keys: BTreeMap<StorageKey, TrackedStorageKey>,
+    // Keys tracked as whitelisted once the tracker is reset.
+    whitelist: Vec<TrackedStorageKey>,
+    // Keys written since the last commit of the overlay, whose next writes are merged in it.
+    uncommitted: BTreeSet<StorageKey>,
+}
+
+impl KeyTracker {
+    fn tracked(&mut self, key: &[u8]) -> &mut TrackedStorageKey {
+        self.keys.entry(key.to_vec()).or_insert_with(|| TrackedStorageKey::new(key.to_vec()))
+    }
+
+    fn add_read(&mut self, key: &[u8]) {
+        self.tracked(key).add_read();
+    }
+
+    /// Counts the first write of `key` since the last commit only, the next ones being merged in
+    /// the overlay of the extrinsic.
+    fn add_write(&mut self, key: &[u8]) {
+        if self.uncommitted.insert(key.to_vec()) {
+            self.tracked(key).add_write();
+        }
+    }
+
+    /// Counts the next writes of the keys already written again, as the benchmarking state of the
+    /// node does once the overlay is committed to it.
+    fn commit(&mut self) {
+        self.uncommitted.clear();
+    }
+
+    /// Number of keys read, of repeated reads, of keys written and of repeated writes.
+    fn read_write_count(&self) -> (u32, u32, u32, u32) {
+        let (mut reads, mut repeat_reads, mut writes, mut repeat_writes) = (0, 0, 0, 0);
+        for tracked in self.keys.values().filter(|tracked| !tracked.whitelisted) {
+            if tracked.reads > 0 {
+                reads += 1;
+                repeat_reads += tracked.reads - 1;
+            }
+            if tracked.writes > 0 {
+                writes += 1;
+                repeat_writes += tracked.writes - 1;
+            }
+        }
+        (reads, repeat_reads, writes, repeat_writes)
+    }
+
+    /// Forgets the tracked keys but the whitelisted ones.
+    fn reset(&mut self) {
+        self.keys = self
+            .whitelist
+            .iter()
+            .map(|key| {
+                let mut whitelisted = TrackedStorageKey::new(key.key.clone());
+                whitelisted.whitelist();
+                (key.key.clone(), whitelisted)
+            })
+            .collect();
+    }
+
+    /// Number of keys read and written by prefix of the keys, the prefix being their first 32
+    /// bytes, i.e. the storage item in FRAME.
+    fn read_and_written_keys(&self) -> Vec<(Vec<u8>, u32, u32, bool)> {
+        let mut by_prefix = BTreeMap::<Vec<u8>, (u32, u32)>::new();
+        for tracked in self.keys.values().filter(|tracked| !tracked.whitelisted) {
+            let prefix = &tracked.key[..tracked.key.len().min(32)];
+            let counts = by_prefix.entry(prefix.to_vec()).or_default();
+            counts.0 += tracked.reads.min(1);
+            counts.1 += tracked.writes.min(1);
+        }
+        by_prefix.into_iter().map(|(prefix, (reads, writes))| (prefix, reads, writes, false)).collect()
+    }
+}
+
+impl<'a, H: Hasher, S: StateView<Extrinsic>> Ext<'a, H, S> {
+    /// Creates the externalities of the extrinsic observing the state through `view`.
+    pub fn new(view: &'a LatestView<'a, Extrinsic, S>) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            overlay: OverlayedChanges::default(),
+            view,
+            extensions: Extensions::new(),
+            unsupported: Cell::new(None),
+            maybe_deadline: None,
+            timed_out: Cell::new(false),
+            stats: StateMachineStats::default(),
+            maybe_reads: None,
+            offchain_policy: OffchainPolicy::default(),
+            child_trie_policy: ChildTriePolicy::default(),
+            maybe_host_calls: None,
+            key_tracker: RefCell::default(),
+        }
+    }
+
+    /// Stops the execution of the extrinsic once `deadline` is reached.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.maybe_deadline = Some(deadline);
+        self
+    }
+
+    /// Records the keys the extrinsic reads from the state before it, see
+    /// [`take_reads`](Self::take_reads).
+    pub fn with_read_recording(mut self) -> Self {
+        self.maybe_reads = Some(RefCell::default());
+        self
+    }
+
+    /// Handles the writes of the extrinsic to the offchain storage according to `policy`, rather
+    /// than reporting them as unsupported.
+    pub fn with_offchain_policy(mut self, policy: OffchainPolicy) -> Self {
+        self.offchain_policy = policy;
+        self
+    }
+
+    /// Handles the accesses of the extrinsic to the child tries according to `policy`, rather
+    /// than reporting them as unsupported.
+    pub fn with_child_trie_policy(mut self, policy: ChildTriePolicy) -> Self {
+        self.child_trie_policy = policy;
+        self
+    }
+
+    /// Records the host functions called by the extrinsic, see [`host_calls`](Self::host_calls),
+    /// and applies it sequentially once it calls one of the [`NONDETERMINISTIC_HOST_CALLS`], which
+    /// are counted in the metrics.
+    pub fn with_host_call_audit(mut self) -> Self {
+        self.maybe_host_calls = Some(RefCell::default());
+        self
+    }
+
+    /// Hands the runtime the `extensions` of the worker, e.g. built by
+    /// [`WorkerExtensions`](crate::worker_extensions::WorkerExtensions), to be taken back with
+    /// [`take_extensions`](Self::take_extensions). Replaces the extensions registered so far.
+    pub fn with_extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Takes the extensions of the externalities back, e.g. for the next extrinsic of the worker.
+    pub fn take_extensions(&mut self) -> Extensions {
+        std::mem::take(&mut self.extensions)
+    }
+
+    /// Registers `extension` in the externalities of the extrinsic, e.g. the
+    /// [`SignatureCacheExt`](crate::signature_cache::SignatureCacheExt) of its batch.
+    pub fn with_extension(mut self, extension: impl Extension) -> Self {
+        self.extensions.register(extension);
+        self
+    }
+
+    /// Identifier of the externalities in the traces of the storage accesses.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Index of the extrinsic in the batch.
+    pub fn txn_idx(&self) -> TxnIndex {
+        self.view.txn_idx()
+    }
+
+    /// Incarnation of the extrinsic being executed.
+    pub fn incarnation(&self) -> Incarnation {
+        self.view.incarnation()
+    }
+
+    /// Marks the runtime as entering the execution of the extrinsic, see
+    /// [`OverlayedChanges::enter_runtime`].
+    pub(crate) fn enter_runtime(&mut self) {
+        self.overlay.enter_runtime().expect("A new extrinsic is never executed within the runtime");
+    }
+
+    /// Marks the runtime as done with the extrinsic, rolling back the storage transactions it left
+    /// open, see [`OverlayedChanges::exit_runtime`].
+    pub(crate) fn exit_runtime(&mut self) {
+        self.overlay.exit_runtime().expect("Runtime was entered before the execution of the extrinsic");
+    }
+
+    /// Returns the first operation performed by the extrinsic that is not supported in parallel,
+    /// if any.
+    pub fn unsupported(&self) -> Option<&'static str> {
+        self.unsupported.get()
+    }
+
+    /// Whether the execution of the extrinsic was stopped at its deadline.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out.get()
+    }
+
+    /// Statistics of the reads served by, and the writes buffered in, the overlay of the
+    /// extrinsic.
+    pub fn stats(&self) -> &StateMachineStats {
+        &self.stats
+    }
+
+    /// Returns the host functions called by the extrinsic so far, sorted, if audited. The calls
+    /// through an extension are reported by extension, e.g. `offchain_worker`.
+    pub fn host_calls(&self) -> Vec<&'static str> {
+        self.maybe_host_calls
+            .as_ref()
+            .map(|host_calls| host_calls.borrow().iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the keys read by the extrinsic from the state before it, sorted, if recorded. The
+    /// keys it read after writing them are not included.
+    pub fn take_reads(&mut self) -> Vec<StorageKey> {
+        self.maybe_reads.as_mut().map(|reads| reads.take().into_iter().collect()).unwrap_or_default()
+    }
+
+    /// Returns the values written by the extrinsic to the offchain storage, in order, if buffered,
+    /// see [`OffchainPolicy::Buffer`]. The writes rolled back with their storage transaction are
+    /// not included.
+    pub fn take_offchain_writes(&mut self) -> Vec<(StorageKey, Option<StorageValue>)> {
+        self.overlay
+            .offchain_drain_committed()
+            .map(|((_, key), change)| match change {
+                OffchainOverlayedChange::SetValue(value) => (key, Some(value)),
+                OffchainOverlayedChange::Remove => (key, None),
+            })
+            .collect()
+    }
+
+    /// Consumes the externalities, returning the values written by the extrinsic and the events and
+    /// logs it deposited.
+    pub fn into_changes(self) -> (WriteSet<Extrinsic>, ExtrinsicEvents) {
+        let mut writes = Vec::new();
+        let mut events = ExtrinsicEvents::default();
+        for (key, value) in self.overlay.changes() {
+            let value = value.value().cloned();
+            if *key == *EVENTS {
+                events.records = value;
+            } else if *key == *DIGEST {
+                events.logs = value;
+            } else if *key == *EVENT_COUNT {
+                events.count = value.and_then(|count| u32::decode(&mut &count[..]).ok()).unwrap_or_default();
+            } else {
+                writes.push((key.clone(), value));
+            }
+        }
+        (writes, events)
+    }
+
+    fn mark_unsupported(&self, operation: &'static str) {
+        if self.unsupported.get().is_none() {
+            tracing::debug!(
+                target: LOG_TARGET,
+                txn_idx = self.txn_idx(),
+                incarnation = self.incarnation(),
+                operation,
+                "Unsupported operation",
+            );
+            self.unsupported.set(Some(operation));
+        }
+    }
+
+    fn record_host_call(&self, host_call: &'static str) {
+        let Some(host_calls) = &self.maybe_host_calls else { return };
+        if host_calls.borrow_mut().insert(host_call) && NONDETERMINISTIC_HOST_CALLS.contains(&host_call) {
+            counters::record_nondeterministic_host_call(host_call);
+            self.mark_unsupported(host_call);
+        }
+    }
+
+    /// Unwinds the runtime call if the deadline of the extrinsic is reached.
+    fn check_deadline(&self) {
+        if self.maybe_deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            tracing::debug!(
+                target: LOG_TARGET,
+                txn_idx = self.txn_idx(),
+                incarnation = self.incarnation(),
+                "Extrinsic execution timed out",
+            );
+            self.timed_out.set(true);
+            panic!("Extrinsic {} exceeded its execution time budget", self.txn_idx());
+        }
+    }
+
+    fn read(&self, key: &[u8]) -> Option<StorageValue> {
+        self.read_with(key, |value| value.map(<[u8]>::to_vec))
+    }
+
+    /// Reads `key`, handing its value over to `f`. The value is shared with the other readers, it
+    /// is only copied if `f` needs it to be, e.g. to return it to the runtime.
+    fn read_with<R>(&self, key: &[u8], f: impl FnOnce(Option<&[u8]>) -> R) -> R {
+        self.check_deadline();
+        if events::is_collected(key) {
+            // Only the events and logs of the extrinsic are collected.
+            self.mark_unsupported("read_events");
+        }
+        if let Some(value) = self.read_own(key) {
+            return f(value);
+        }
+        if key == EVENT_COUNT.as_slice() {
+            // The events counted before the extrinsic are added back once the batch is executed.
+            return f(None);
+        }
+
+        self.record_read(key);
+        match self.view.read(&key.to_vec()) {
+            ReadResult::Value(value) => f(value.as_deref()),
+            ReadResult::Exists(_) => unreachable!("The value of the key was read"),
+            // The incarnation is discarded, whatever the runtime does with the value.
+            ReadResult::Halted => f(None),
+        }
+    }
+
+    /// Reads whether `key` exists, without capturing its value.
+    fn exists(&self, key: &[u8]) -> bool {
+        self.check_deadline();
+        if events::is_collected(key) {
+            self.mark_unsupported("read_events");
+        }
+        if let Some(value) = self.read_own(key) {
+            return value.is_some();
+        }
+        if key == EVENT_COUNT.as_slice() {
+            return false;
+        }
+
+        self.record_read(key);
+        match self.view.exists(&key.to_vec()) {
+            ReadResult::Exists(exists) => exists,
+            ReadResult::Value(_) => unreachable!("Only the existence of the key was read"),
+            ReadResult::Halted => false,
+        }
+    }
+
+    fn record_read(&self, key: &[u8]) {
+        self.key_tracker.borrow_mut().add_read(key);
+        if let Some(reads) = &self.maybe_reads {
+            reads.borrow_mut().insert(key.to_vec());
+        }
+    }
+
+    /// Returns the value written by the extrinsic, `None` if it did not write `key`.
+    fn read_own(&self, key: &[u8]) -> Option<Option<&[u8]>> {
+        let value = self.overlay.storage(key)?;
+        self.stats.tally_read_modified(value.map_or(0, |value| value.len() as u64));
+        Some(value)
+    }
+
+    fn write(&mut self, key: StorageKey, value: Option<StorageValue>) {
+        self.check_deadline();
+        self.stats.tally_write_overlay(value.as_ref().map_or(0, |value| value.len() as u64));
+        self.key_tracker.get_mut().add_write(&key);
+        self.overlay.set_storage(key, value);
+    }
+
+    /// Changes made to the child trie `child_info` so far as observed by the extrinsic, its own
+    /// included, read from the lock of the child trie.
+    fn child_changes(&self, child_info: &ChildInfo) -> ChildTrieChanges {
+        self.read_with(&child_tries::lock_key(child_info), child_tries::decode_lock)
+    }
+
+    /// Reads `key` in the child trie `child_info`, if the child tries are locked.
+    fn child_read(&self, operation: &'static str, child_info: &ChildInfo, key: &[u8]) -> Option<StorageValue> {
+        if self.child_trie_policy != ChildTriePolicy::Locked {
+            self.mark_unsupported(operation);
+            return None;
+        }
+        if let Some(value) = self.child_changes(child_info).remove(key) {
+            return value;
+        }
+        self.view.read_child_base(child_info, &key.to_vec()).unwrap_or_else(|| {
+            self.mark_unsupported(operation);
+            None
+        })
+    }
+
+    fn unsupported_removal(&self, operation: &'static str) -> MultiRemovalResults {
+        self.mark_unsupported(operation);
+        MultiRemovalResults { maybe_cursor: None, backend: 0, unique: 0, loops: 0 }
+    }
+}
+
+impl<'a, H, S> Externalities for Ext<'a, H, S>
+where
+    H: Hasher,
+    H::Out: Encode,
+    S: StateView<Extrinsic>,
+{
+    fn set_offchain_storage(&mut self, key: &[u8], value: Option<&[u8]>) {
+        self.record_host_call("set_offchain_storage");
+        match self.offchain_policy {
+            OffchainPolicy::Buffer => self.overlay.set_offchain_storage(key, value),
+            OffchainPolicy::Discard => {}
+            OffchainPolicy::Error => self.mark_unsupported("set_offchain_storage"),
+        }
+    }
+
+    fn storage(&self, key: &[u8]) -> Option<StorageValue> {
+        self.record_host_call("storage");
+        let result = self.read(key);
+        tracing::trace!(
+            target: STATE_TARGET,
+            method = "Get",
+            ext_id = %HexDisplay::from(&self.id.to_le_bytes()),
+            txn_idx = self.txn_idx(),
+            incarnation = self.incarnation(),
+            key = %HexDisplay::from(&key),
+            result = ?result.as_ref().map(HexDisplay::from),
+            result_encoded = %HexDisplay::from(&result.encode()),
+        );
+        result
+    }
+
+    fn exists_storage(&self, key: &[u8]) -> bool {
+        self.record_host_call("exists_storage");
+        let result = self.exists(key);
+        tracing::trace!(
+            target: STATE_TARGET,
+            method = "Exists",
+            ext_id = %HexDisplay::from(&self.id.to_le_bytes()),
+            txn_idx = self.txn_idx(),
+            incarnation = self.incarnation(),
+            key = %HexDisplay::from(&key),
+            %result,
+        );
+        result
+    }
+
+    fn storage_hash(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.record_host_call("storage_hash");
+        let result = self.read_with(key, |value| value.map(|value| H::hash(value).encode()));
+        tracing::trace!(
+            target: STATE_TARGET,
+            method = "Hash",
+            ext_id = %HexDisplay::from(&self.id.to_le_bytes()),
+            txn_idx = self.txn_idx(),
+            incarnation = self.incarnation(),
+            key = %HexDisplay::from(&key),
+            ?result,
+        );
+        result
+    }
+
+    fn child_storage_hash(&self, child_info: &ChildInfo, key: &[u8]) -> Option<Vec<u8>> {
+        self.record_host_call("child_storage_hash");
+        let result = self.child_read("child_storage_hash", child_info, key).map(|value| H::hash(&value).encode());
+        tracing::trace!(
+            target: STATE_TARGET,
+            method = "ChildHash",
+            ext_id = %HexDisplay::from(&self.id.to_le_bytes()),
+            txn_idx = self.txn_idx(),
+            incarnation = self.incarnation(),
+            child_info = %HexDisplay::from(&child_info.storage_key()),
+            key = %HexDisplay::from(&key),
+            ?result,
+        );
+        result
+    }
+
+    fn child_storage(&self, child_info: &ChildInfo, key: &[u8]) -> Option<StorageValue> {
+        self.record_host_call("child_storage");
+        let result = self.child_read("child_storage", child_info, key);
+        tracing::trace!(
+            target: STATE_TARGET,
+            method = "ChildGet",
+            ext_id = %HexDisplay::from(&self.id.to_le_bytes()),
+            txn_idx = self.txn_idx(),
+            incarnation = self.incarnation(),
+            child_info = %HexDisplay::from(&child_info.storage_key()),
+            key = %HexDisplay::from(&key),
+            result = ?result.as_ref().map(HexDisplay::from),
+        );
+        result
+    }
+
+    fn next_storage_key(&self, _key: &[u8]) -> Option<StorageKey> {
+        self.record_host_call("next_storage_key");
+        self.mark_unsupported("next_storage_key");
+        None
+    }
+
+    fn next_child_storage_key(&self, _child_info: &ChildInfo, _key: &[u8]) -> Option<StorageKey> {
+        self.record_host_call("next_child_storage_key");
+        self.mark_unsupported("next_child_storage_key");
+        None
+    }
+
+    fn kill_child_storage(
+        &mut self,
+        _child_info: &ChildInfo,
+        _maybe_limit: Option<u32>,
+        _maybe_cursor: Option<&[u8]>,
+    ) -> MultiRemovalResults {
+        self.record_host_call("kill_child_storage");
+        self.unsupported_removal("kill_child_storage")
+    }
+
+    fn clear_prefix(
+        &mut self,
+        _prefix: &[u8],
+        _maybe_limit: Option<u32>,
+        _maybe_cursor: Option<&[u8]>,
+    ) -> MultiRemovalResults {
+        self.record_host_call("clear_prefix");
+        self.unsupported_removal("clear_prefix")
+    }
+
+    fn clear_child_prefix(
+        &mut self,
+        _child_info: &ChildInfo,
+        _prefix: &[u8],
+        _maybe_limit: Option<u32>,
+        _maybe_cursor: Option<&[u8]>,
+    ) -> MultiRemovalResults {
+        self.record_host_call("clear_child_prefix");
+        self.unsupported_removal("clear_child_prefix")
+    }
+
+    fn place_storage(&mut self, key: StorageKey, value: Option<StorageValue>) {
+        self.record_host_call("place_storage");
+        tracing::trace!(
+            target: STATE_TARGET,
+            method = "Put",
+            ext_id = %HexDisplay::from(&self.id.to_le_bytes()),
+            txn_idx = self.txn_idx(),
+            incarnation = self.incarnation(),
+            key = %HexDisplay::from(&key),
+            value = ?value.as_ref().map(HexDisplay::from),
+            value_encoded = %HexDisplay::from(&value.encode()),
+        );
+        if events::is_collected(&key) || (key == *EVENT_COUNT && value.is_none()) {
+            self.mark_unsupported("reset_events");
+        }
+        if child_tries::is_lock_key(&key) {
+            self.mark_unsupported("place_storage");
+        }
+        self.write(key, value);
+    }
+
+    fn place_child_storage(&mut self, child_info: &ChildInfo, key: StorageKey, value: Option<StorageValue>) {
+        self.record_host_call("place_child_storage");
+        tracing::trace!(
+            target: STATE_TARGET,
+            method = "ChildPut",
+            ext_id = %HexDisplay::from(&self.id.to_le_bytes()),
+            txn_idx = self.txn_idx(),
+            incarnation = self.incarnation(),
+            child_info = %HexDisplay::from(&child_info.storage_key()),
+            key = %HexDisplay::from(&key),
+            value = ?value.as_ref().map(HexDisplay::from),
+        );
+        if self.child_trie_policy != ChildTriePolicy::Locked {
+            self.mark_unsupported("place_child_storage");
+            return;
+        }
+        // The extrinsic writes the lock of the child trie again, along with its own changes.
+        let mut changes = self.child_changes(child_info);
+        changes.insert(key, value);
+        self.write(child_tries::lock_key(child_info), Some(changes.encode()));
+    }
+
+    fn storage_root(&mut self, _state_version: StateVersion) -> Vec<u8> {
+        self.record_host_call("storage_root");
+        self.mark_unsupported("storage_root");
+        H::Out::default().encode()
+    }
+
+    fn child_storage_root(&mut self, _child_info: &ChildInfo, _state_version: StateVersion) -> Vec<u8> {
+        self.record_host_call("child_storage_root");
+        self.mark_unsupported("child_storage_root");
+        H::Out::default().encode()
+    }
+
+    fn storage_append(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.record_host_call("storage_append");
+        tracing::trace!(
+            target: STATE_TARGET,
+            method = "Append",
+            ext_id = %HexDisplay::from(&self.id.to_le_bytes()),
+            txn_idx = self.txn_idx(),
+            incarnation = self.incarnation(),
+            key = %HexDisplay::from(&key),
+            value = %HexDisplay::from(&value),
+        );
+        if key.starts_with(&EVENT_TOPICS_PREFIX) {
+            self.mark_unsupported("deposit_event_indexed");
+        }
+        let current_value = if events::is_collected(&key) {
+            self.read_own(&key).flatten().map(<[u8]>::to_vec)
+        } else {
+            self.read(&key)
+        };
+        let current_value = current_value.unwrap_or_default();
+        let value = vec![EncodeOpaqueValue(value)];
+        let appended = Vec::<EncodeOpaqueValue>::append_or_new(current_value, &value).unwrap_or_else(|_| {
+            tracing::error!(target: LOG_TARGET, "Failed to append value, resetting storage item to `[value]`.");
+            value.encode()
+        });
+        self.write(key, Some(appended));
+    }
+
+    fn storage_start_transaction(&mut self) {
+        self.record_host_call("storage_start_transaction");
+        self.overlay.start_transaction()
+    }
+
+    fn storage_rollback_transaction(&mut self) -> Result<(), ()> {
+        self.record_host_call("storage_rollback_transaction");
+        self.overlay.rollback_transaction().map_err(|_| ())
+    }
+
+    fn storage_commit_transaction(&mut self) -> Result<(), ()> {
+        self.record_host_call("storage_commit_transaction");
+        self.overlay.commit_transaction().map_err(|_| ())
+    }
+
+    fn storage_index_transaction(&mut self, _index: u32, _hash: &[u8], _size: u32) {
+        self.record_host_call("storage_index_transaction");
+        self.mark_unsupported("storage_index_transaction");
+    }
+
+    fn storage_renew_transaction_index(&mut self, _index: u32, _hash: &[u8]) {
+        self.record_host_call("storage_renew_transaction_index");
+        self.mark_unsupported("storage_renew_transaction_index");
+    }
+
+    fn wipe(&mut self) {
+        self.record_host_call("wipe");
+        // Wiping the state would make the extrinsic depend on every key written before it.
+        self.mark_unsupported("wipe");
+    }
+
+    fn commit(&mut self) {
+        self.record_host_call("commit");
+        // The changes of the extrinsic are its writes whether they are committed or not, so that
+        // committing only closes the storage transactions left open, which can no longer be rolled
+        // back, as the node does before it commits the overlay to the benchmarking state.
+        self.check_deadline();
+        for _ in 0..self.overlay.transaction_depth() {
+            self.overlay.commit_transaction().expect("Storage transactions are open");
+        }
+        self.key_tracker.get_mut().commit();
+    }
+
+    fn read_write_count(&self) -> (u32, u32, u32, u32) {
+        self.record_host_call("read_write_count");
+        self.key_tracker.borrow().read_write_count()
+    }
+
+    fn reset_read_write_count(&mut self) {
+        self.record_host_call("reset_read_write_count");
+        self.key_tracker.get_mut().reset();
+    }
+
+    fn get_whitelist(&self) -> Vec<TrackedStorageKey> {
+        self.record_host_call("get_whitelist");
+        self.key_tracker.borrow().whitelist.clone()
+    }
+
+    fn set_whitelist(&mut self, new: Vec<TrackedStorageKey>) {
+        self.record_host_call("set_whitelist");
+        self.key_tracker.get_mut().whitelist = new;
+    }
+
+    fn get_read_and_written_keys(&self) -> Vec<(Vec<u8>, u32, u32, bool)> {
+        self.record_host_call("get_read_and_written_keys");
+        self.key_tracker.borrow().read_and_written_keys()
+    }
+}
+
+impl<'a, H: Hasher, S: StateView<Extrinsic>> ExtensionStore for Ext<'a, H, S> {
+    fn extension_by_type_id(&mut self, type_id: TypeId) -> Option<&mut dyn Any> {
+        if self.extensions.get_mut(type_id).is_none() {
+            // The runtime panics without the extension: the extrinsic is applied sequentially with
+            // the extensions of the node instead.
+            match speculation_unsafe_operation(type_id) {
+                Some(operation) => {
+                    self.record_host_call(operation);
+                    self.mark_unsupported(operation);
+                }
+                None => self.record_host_call("unknown_extension"),
+            }
+        } else {
+            self.record_host_call("extension");
+        }
+        self.extensions.get_mut(type_id)
+    }
+
+    fn register_extension_with_type_id(
+        &mut self,
+        type_id: TypeId,
+        extension: Box<dyn Extension>,
+    ) -> Result<(), sp_externalities::Error> {
+        self.extensions.register_with_type_id(type_id, extension)
+    }
+
+    fn deregister_extension_by_type_id(&mut self, type_id: TypeId) -> Result<(), sp_externalities::Error> {
+        if self.extensions.deregister(type_id) {
+            Ok(())
+        } else {
+            Err(sp_externalities::Error::ExtensionIsNotRegistered(type_id))
+        }
+    }
+}
+
+/// An already encoded value, appended as is to an encoded `Vec`.
+struct EncodeOpaqueValue(Vec<u8>);
+
+impl Encode for EncodeOpaqueValue {
+    fn using_encoded<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+        f(&self.0)
+    }
+}
@@ -0,0 +1,515 @@
+//! Extrinsics of a batch, applied by the [`BlockExecutor`](crate::executor::BlockExecutor) on top
+//! of the state of the block being built.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use codec::{Compact, Decode, Encode};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use sp_core::hashing::{blake2_64, twox_128};
+use sp_core::hexdisplay::HexDisplay;
+use sp_core::storage::well_known_keys::{CODE, HEAP_PAGES};
+use sp_core::storage::ChildInfo;
+use sp_core::traits::{CallContext, CodeExecutor};
+use sp_core::Hasher;
+use sp_externalities::Extensions;
+use sp_runtime::transaction_validity::InvalidTransaction;
+use sp_runtime::ApplyExtrinsicResult;
+use sp_state_machine::{Backend, StorageKey, StorageValue};
+use sp_version::ApiId;
+use sp_weights::Weight;
+
+use crate::backend_cache::BackendCache;
+use crate::batch::LazyBatch;
+use crate::child_tries::{self, ChildTriePolicy};
+use crate::events::ExtrinsicEvents;
+use crate::ext::{Ext, OffchainPolicy};
+use crate::instance_pool::InstancePool;
+use crate::limit_processor::{block_weight, BLOCK_WEIGHT};
+use crate::read_cache::SharedReadCache;
+use crate::scheduler::TxnIndex;
+use crate::signature_cache::{CachedSignatures, SignatureCache, SignatureCacheExt};
+use crate::state_machine::{RuntimeCodeCache, StateMachine};
+use crate::task::{
+    ExecutionCancelled, ExecutionPanic, ExecutionStatus, ExecutorTask, Transaction, TransactionOutput, WorkerId,
+    WriteSet,
+};
+use crate::view::{LatestView, ReadResult, StateView};
+use crate::worker_extensions::WorkerExtensions;
+use crate::LOG_TARGET;
+
+/// Runtime method applying a single extrinsic.
+pub const APPLY_EXTRINSIC_METHOD: &str = "BlockBuilder_apply_extrinsic";
+
+/// Runtime method checking the inherents of a block against the inherent data of the node.
+pub const CHECK_INHERENTS_METHOD: &str = "BlockBuilder_check_inherents";
+
+/// Runtime method applying a batch of extrinsics, intercepted by the
+/// [`ParallelLocalCallExecutor`](crate::ParallelLocalCallExecutor) to apply them in parallel.
+pub const BATCH_APPLY_EXTRINSIC_METHOD: &str = "BlockBuilder_batch_apply_extrinsic";
+
+/// Version of the `BlockBuilder` runtime API from which the runtime declares
+/// [`BATCH_APPLY_EXTRINSIC_METHOD`].
+pub const BATCH_APPLY_EXTRINSIC_API_VERSION: u32 = 7;
+
+/// Identifier of the `BlockBuilder` runtime API in the runtime version, derived from its name as
+/// `sp_api` does.
+pub fn block_builder_api_id() -> ApiId {
+    blake2_64(b"BlockBuilder")
+}
+
+/// An extrinsic of the batch, SCALE encoded as the argument of [`APPLY_EXTRINSIC_METHOD`].
+#[derive(Debug)]
+pub struct Extrinsic {
+    encoded: Encoded,
+}
+
+/// Encoding of an extrinsic, owned or delimited in its batch on first access.
+#[derive(Debug)]
+enum Encoded {
+    Owned(Vec<u8>),
+    Lazy(Arc<LazyBatch>, TxnIndex),
+}
+
+impl Extrinsic {
+    /// Wraps an encoded extrinsic.
+    pub fn new(encoded: Vec<u8>) -> Self {
+        Self { encoded: Encoded::Owned(encoded) }
+    }
+
+    /// Returns the extrinsics of `batch`, delimited as they are accessed.
+    pub fn lazy_batch(batch: LazyBatch) -> Vec<Self> {
+        let batch = Arc::new(batch);
+        (0..batch.len()).map(|txn_idx| Self { encoded: Encoded::Lazy(batch.clone(), txn_idx) }).collect()
+    }
+
+    /// The encoded extrinsic.
+    ///
+    /// # Panics
+    ///
+    /// If the extrinsic is part of a [`LazyBatch`] and cannot be delimited, e.g. it is truncated.
+    /// The panic is caught by the block executor, which fails to apply the batch.
+    pub fn encoded(&self) -> &[u8] {
+        self.try_encoded().unwrap_or_else(|err| panic!("Extrinsic of the batch is malformed: {err}"))
+    }
+
+    /// The encoded extrinsic, or the error delimiting it in its [`LazyBatch`].
+    pub fn try_encoded(&self) -> Result<&[u8], codec::Error> {
+        match &self.encoded {
+            Encoded::Owned(encoded) => Ok(encoded),
+            Encoded::Lazy(batch, txn_idx) => batch.get(*txn_idx),
+        }
+    }
+
+    /// Splits the SCALE encoded `Vec<X>` of a batch into its encoded extrinsics, without decoding
+    /// and encoding them again.
+    pub fn split_batch<X: Decode>(encoded_batch: &[u8]) -> Result<Vec<Self>, codec::Error> {
+        let input = &mut &encoded_batch[..];
+        let Compact(len) = Compact::<u32>::decode(input)?;
+        let mut extrinsics = Vec::with_capacity((len as usize).min(input.len()));
+        for _ in 0..len {
+            let remaining = *input;
+            X::skip(input)?;
+            extrinsics.push(Self::new(remaining[..remaining.len() - input.len()].to_vec()));
+        }
+        Ok(extrinsics)
+    }
+}
+
+impl Transaction for Extrinsic {
+    type Key = StorageKey;
+    /// `None` if the key is not in the state or was removed.
+    type Value = Option<StorageValue>;
+
+    /// The runtime code and heap pages, and the storage versions of the pallets.
+    fn is_module_key(key: &StorageKey) -> bool {
+        key == CODE || key == HEAP_PAGES || (key.len() == 32 && key[16..] == *STORAGE_VERSION_SUFFIX)
+    }
+
+    fn exists(value: &Option<StorageValue>) -> bool {
+        value.is_some()
+    }
+
+    fn format_key(key: &StorageKey) -> String {
+        format!("0x{}", HexDisplay::from(key))
+    }
+}
+
+/// Suffix of the keys of the storage versions of the pallets, following the hashed pallet prefix.
+static STORAGE_VERSION_SUFFIX: Lazy<[u8; 16]> = Lazy::new(|| twox_128(b":__STORAGE_VERSION__:"));
+
+/// Output of the application of an extrinsic.
+#[derive(Debug)]
+pub struct ExtrinsicOutput {
+    /// SCALE encoded `ApplyExtrinsicResult` returned by the runtime.
+    pub result: Vec<u8>,
+    /// Values written by the extrinsic, except for its events.
+    pub writes: WriteSet<Extrinsic>,
+    /// Events deposited by the extrinsic.
+    pub events: ExtrinsicEvents,
+    /// Keys read by the extrinsic from the state before it, if recorded, see
+    /// [`ExtrinsicTaskArgs::with_read_recording`].
+    pub reads: Vec<StorageKey>,
+    /// Values written by the extrinsic to the offchain storage, in order, if buffered, see
+    /// [`ExtrinsicTaskArgs::with_offchain_policy`].
+    pub offchain_writes: Vec<(StorageKey, Option<StorageValue>)>,
+    /// Weight added by the extrinsic to `System::BlockWeight`, as accounted by the runtime from
+    /// its dispatch info.
+    pub weight: Weight,
+}
+
+impl TransactionOutput for ExtrinsicOutput {
+    type Txn = Extrinsic;
+
+    fn get_writes(&self) -> WriteSet<Extrinsic> {
+        self.writes.clone()
+    }
+
+    fn weight(&self) -> Weight {
+        self.weight
+    }
+}
+
+/// Operations only supported sequentially that end the parallel application of a batch before the
+/// extrinsic performing them, rather than aborting it: the extrinsic and the following ones are
+/// then applied sequentially, while the ones before it are kept. Storage roots are computed over
+/// the whole state, i.e. would read every key written before the extrinsic, and the
+/// [`SPECULATION_UNSAFE_OPERATIONS`](crate::ext::SPECULATION_UNSAFE_OPERATIONS) require the
+/// extensions of the node, as the extensions unknown to the workers whose calls are audited.
+pub const SEQUENTIAL_SEGMENT_OPERATIONS: [&str; 6] =
+    ["storage_root", "child_storage_root", "submit_transaction", "offchain_worker", "offchain_db", "unknown_extension"];
+
+/// Error aborting the parallel application of a batch.
+#[derive(Debug, Clone)]
+pub enum ExtrinsicError {
+    /// The extrinsic performed an operation that is not supported in parallel, the batch must be
+    /// applied sequentially, from the extrinsic on for the [`SEQUENTIAL_SEGMENT_OPERATIONS`].
+    Unsupported(&'static str),
+    /// The runtime call applying the extrinsic failed.
+    Runtime(String),
+    /// The parallel application of the batch panicked.
+    Panic(ExecutionPanic),
+    /// The application of the batch was cancelled.
+    Cancelled,
+}
+
+impl From<ExecutionPanic> for ExtrinsicError {
+    fn from(panic: ExecutionPanic) -> Self {
+        ExtrinsicError::Panic(panic)
+    }
+}
+
+impl From<ExecutionCancelled> for ExtrinsicError {
+    fn from(_: ExecutionCancelled) -> Self {
+        ExtrinsicError::Cancelled
+    }
+}
+
+/// The state the batch is applied on top of: the changes already made to the block being built,
+/// e.g. by its inherents, on top of the state of its parent.
+pub struct BackendView<'a, H: Hasher, B> {
+    changes: HashMap<StorageKey, Arc<Option<StorageValue>>>,
+    backend: &'a B,
+    // Values read from the backend by the workers, or prefetched before the extrinsics read them.
+    cache: SharedReadCache<StorageKey, Arc<Option<StorageValue>>>,
+    // Records where the values are read from, along with the storage root of the backend, if any.
+    maybe_backend_cache: Option<(&'a BackendCache<H>, H::Out)>,
+    phantom: PhantomData<H>,
+}
+
+impl<'a, H: Hasher, B: Backend<H>> BackendView<'a, H, B> {
+    /// Creates the view of `changes` on top of `backend`.
+    pub fn new(changes: HashMap<StorageKey, Option<StorageValue>>, backend: &'a B) -> Self {
+        Self {
+            changes: changes.into_iter().map(|(key, value)| (key, Arc::new(value))).collect(),
+            backend,
+            cache: SharedReadCache::new(),
+            maybe_backend_cache: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Records the reads of the backend, whose storage root is `storage_root`, in `backend_cache`,
+    /// and reads the values it pins ahead of the extrinsics.
+    pub fn with_backend_cache(mut self, backend_cache: &'a BackendCache<H>, storage_root: H::Out) -> Self
+    where
+        B: Sync,
+    {
+        self.maybe_backend_cache = Some((backend_cache, storage_root));
+        for key in backend_cache.pinned_keys() {
+            self.get_state_value(&key);
+        }
+        self
+    }
+}
+
+impl<'a, H: Hasher, B: Backend<H> + Sync> StateView<Extrinsic> for BackendView<'a, H, B> {
+    fn get_state_value(&self, key: &StorageKey) -> Arc<Option<StorageValue>> {
+        if let Some(value) = self.changes.get(key) {
+            return value.clone();
+        }
+        if child_tries::is_lock_key(key) {
+            // The locks are not part of the state, the child tries changed before the batch are
+            // locked in the changes.
+            return Arc::new(None);
+        }
+        let mut fetched = false;
+        let value = self.cache.get_or_fetch(key, || {
+            fetched = true;
+            if let Some((backend_cache, storage_root)) = &self.maybe_backend_cache {
+                backend_cache.record_backend_read(storage_root, key);
+            }
+            Arc::new(self.backend.storage(key).expect("Externalities not allowed to fail within runtime"))
+        });
+        if let (Some((backend_cache, _)), false) = (&self.maybe_backend_cache, fetched) {
+            backend_cache.record_cached_read();
+        }
+        value
+    }
+
+    fn provide_base_value(&self, key: StorageKey, value: Arc<Option<StorageValue>>) {
+        if !self.changes.contains_key(&key) {
+            self.cache.provide(key, value);
+        }
+    }
+
+    fn get_child_state_value(&self, child_info: &ChildInfo, key: &StorageKey) -> Option<Option<StorageValue>> {
+        Some(self.backend.child_storage(child_info, key).expect("Externalities not allowed to fail within runtime"))
+    }
+}
+
+/// Arguments shared by the workers applying a batch.
+pub struct ExtrinsicTaskArgs<'a, Exec, H, B> {
+    instance_pool: &'a InstancePool<Exec>,
+    code_backend: &'a B,
+    runtime_code: &'a RuntimeCodeCache<'a, H, B>,
+    context: CallContext,
+    // Execution time budget of every extrinsic, if limited.
+    maybe_timeout: Option<Duration>,
+    // Whether the keys read by every extrinsic are recorded in its output.
+    record_reads: bool,
+    // Outcomes of the signature verifications of the extrinsics, if cached.
+    maybe_signature_cache: Option<Arc<SignatureCache>>,
+    // What becomes of the writes of the extrinsics to the offchain storage.
+    offchain_policy: OffchainPolicy,
+    // How the accesses of the extrinsics to the child tries are handled.
+    child_trie_policy: ChildTriePolicy,
+    // Whether the host functions called by every extrinsic are audited.
+    audit_host_calls: bool,
+    // Extensions of the node made available to the workers, if any.
+    maybe_worker_extensions: Option<&'a WorkerExtensions>,
+}
+
+impl<'a, Exec, H, B> ExtrinsicTaskArgs<'a, Exec, H, B> {
+    /// Creates the arguments of workers applying the extrinsics with their executor of
+    /// `instance_pool`, in the runtime of `runtime_code` found in `code_backend`.
+    pub fn new(
+        instance_pool: &'a InstancePool<Exec>,
+        code_backend: &'a B,
+        runtime_code: &'a RuntimeCodeCache<'a, H, B>,
+        context: CallContext,
+    ) -> Self {
+        Self {
+            instance_pool,
+            code_backend,
+            runtime_code,
+            context,
+            maybe_timeout: None,
+            record_reads: false,
+            maybe_signature_cache: None,
+            offchain_policy: OffchainPolicy::default(),
+            child_trie_policy: ChildTriePolicy::default(),
+            audit_host_calls: false,
+            maybe_worker_extensions: None,
+        }
+    }
+
+    /// Stops the execution of an extrinsic that accesses the state after running for `timeout`.
+    /// The extrinsic is then reported as exhausting the resources of the block, without any
+    /// change, and the rest of the batch proceeds.
+    ///
+    /// Whether the deadline is reached depends on the node, so the timeout is only meant for the
+    /// blocks it authors: a block imported must apply its extrinsics without one.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.maybe_timeout = Some(timeout);
+        self
+    }
+
+    /// Records the keys read by every extrinsic from the state before it in its output, e.g. to
+    /// report its accesses.
+    pub fn with_read_recording(mut self) -> Self {
+        self.record_reads = true;
+        self
+    }
+
+    /// Verifies the signatures of every extrinsic through `cache`, so that its next incarnations
+    /// reuse the outcomes of the verifications of the previous ones.
+    pub fn with_signature_cache(mut self, cache: Arc<SignatureCache>) -> Self {
+        self.maybe_signature_cache = Some(cache);
+        self
+    }
+
+    /// Handles the writes of every extrinsic to the offchain storage according to `policy`, see
+    /// [`OffchainPolicy`].
+    pub fn with_offchain_policy(mut self, policy: OffchainPolicy) -> Self {
+        self.offchain_policy = policy;
+        self
+    }
+
+    /// Handles the accesses of every extrinsic to the child tries according to `policy`, see
+    /// [`ChildTriePolicy`].
+    pub fn with_child_trie_policy(mut self, policy: ChildTriePolicy) -> Self {
+        self.child_trie_policy = policy;
+        self
+    }
+
+    /// Audits the host functions called by every extrinsic, see [`Ext::with_host_call_audit`].
+    pub fn with_host_call_audit(mut self) -> Self {
+        self.audit_host_calls = true;
+        self
+    }
+
+    /// Hands the runtime of every worker a set of its own of the `extensions` of the node.
+    pub fn with_worker_extensions(mut self, extensions: &'a WorkerExtensions) -> Self {
+        self.maybe_worker_extensions = Some(extensions);
+        self
+    }
+}
+
+/// Applies the extrinsics of a batch on a worker thread.
+pub struct ExtrinsicTask<'a, Exec, H, B> {
+    args: &'a ExtrinsicTaskArgs<'a, Exec, H, B>,
+    exec: &'a Exec,
+    // Extensions of the worker, handed to the runtime of every extrinsic in turn. Only locked by
+    // the worker.
+    extensions: Mutex<Extensions>,
+}
+
+impl<'a, Exec, H, B> ExecutorTask for ExtrinsicTask<'a, Exec, H, B>
+where
+    Exec: CodeExecutor,
+    H: Hasher,
+    H::Out: Encode,
+    B: Backend<H> + Sync,
+{
+    type Txn = Extrinsic;
+    type Output = ExtrinsicOutput;
+    type Error = ExtrinsicError;
+    type Argument = &'a ExtrinsicTaskArgs<'a, Exec, H, B>;
+
+    fn init(args: Self::Argument, worker_id: WorkerId) -> Self {
+        let extensions = args.maybe_worker_extensions.map(|extensions| extensions.extensions(worker_id));
+        Self {
+            args,
+            exec: args.instance_pool.executor(worker_id),
+            extensions: Mutex::new(extensions.unwrap_or_default()),
+        }
+    }
+
+    fn execute_transaction<S: StateView<Extrinsic>>(
+        &self,
+        view: &LatestView<Extrinsic, S>,
+        txn: &Extrinsic,
+        txn_idx: TxnIndex,
+    ) -> ExecutionStatus<ExtrinsicOutput, ExtrinsicError> {
+        if self.args.maybe_signature_cache.as_ref().is_some_and(|cache| cache.is_rejected(txn_idx)) {
+            // As the runtime does, an extrinsic with an invalid signature is rejected before any of
+            // its changes.
+            let result: ApplyExtrinsicResult = Err(InvalidTransaction::BadProof.into());
+            let output = ExtrinsicOutput {
+                result: result.encode(),
+                writes: Vec::new(),
+                events: Default::default(),
+                reads: Vec::new(),
+                offchain_writes: Vec::new(),
+                weight: Weight::zero(),
+            };
+            return ExecutionStatus::Success(output);
+        }
+        let runtime_code = self.args.runtime_code.runtime_code();
+        let mut ext = Ext::<H, S>::new(view)
+            .with_offchain_policy(self.args.offchain_policy)
+            .with_child_trie_policy(self.args.child_trie_policy)
+            .with_extensions(std::mem::take(&mut *self.extensions.lock()));
+        if let Some(cache) = &self.args.maybe_signature_cache {
+            ext = ext.with_extension(SignatureCacheExt(CachedSignatures { cache: cache.clone(), txn_idx }));
+        }
+        if let Some(timeout) = self.args.maybe_timeout {
+            ext = ext.with_deadline(Instant::now() + timeout);
+        }
+        if self.args.record_reads {
+            ext = ext.with_read_recording();
+        }
+        if self.args.audit_host_calls {
+            ext = ext.with_host_call_audit();
+        }
+        let mut state_machine =
+            StateMachine::new(self.exec, APPLY_EXTRINSIC_METHOD, txn.encoded(), &runtime_code, self.args.context);
+        let result = state_machine.execute(&mut ext);
+        *self.extensions.lock() = ext.take_extensions();
+        // Like the `sp_state_machine::StateMachine`, report the overlay usage of every execution.
+        self.args.code_backend.register_overlay_stats(state_machine.stats());
+        if self.args.audit_host_calls {
+            tracing::debug!(
+                target: LOG_TARGET,
+                txn_idx,
+                incarnation = ext.incarnation(),
+                host_calls = ?ext.host_calls(),
+                "Host functions called",
+            );
+        }
+
+        if let Some(operation) = ext.unsupported() {
+            return ExecutionStatus::Abort(ExtrinsicError::Unsupported(operation));
+        }
+        if ext.timed_out() {
+            // As for an invalid extrinsic, none of its changes are kept.
+            let result: ApplyExtrinsicResult = Err(InvalidTransaction::ExhaustsResources.into());
+            let output = ExtrinsicOutput {
+                result: result.encode(),
+                writes: Vec::new(),
+                events: Default::default(),
+                reads: ext.take_reads(),
+                offchain_writes: Vec::new(),
+                weight: Weight::zero(),
+            };
+            return ExecutionStatus::Success(output);
+        }
+        match result {
+            Ok(result) => {
+                let reads = ext.take_reads();
+                let offchain_writes = ext.take_offchain_writes();
+                let (writes, events) = ext.into_changes();
+                let weight = consumed_weight(view, &writes);
+                // The following extrinsics must not be applied with the runtime this one replaces.
+                let changes_runtime = writes.iter().any(|(key, _)| key == CODE || key == HEAP_PAGES);
+                let output = ExtrinsicOutput { result, writes, events, reads, offchain_writes, weight };
+                if changes_runtime { ExecutionStatus::SkipRest(output) } else { ExecutionStatus::Success(output) }
+            }
+            Err(err) => ExecutionStatus::Abort(ExtrinsicError::Runtime(err.to_string())),
+        }
+    }
+
+    fn ends_parallel_segment(err: &ExtrinsicError) -> bool {
+        matches!(err, ExtrinsicError::Unsupported(operation) if SEQUENTIAL_SEGMENT_OPERATIONS.contains(operation))
+    }
+}
+
+/// Weight added to `System::BlockWeight` by the extrinsic that made `writes`, over the value it
+/// read through `view`. Zero if the extrinsic was not dispatched, e.g. as it is invalid.
+fn consumed_weight<S: StateView<Extrinsic>>(view: &LatestView<Extrinsic, S>, writes: &WriteSet<Extrinsic>) -> Weight {
+    let Some((_, Some(consumed))) = writes.iter().find(|(key, _)| *key == *BLOCK_WEIGHT) else {
+        return Weight::zero();
+    };
+    // The runtime reads the weight of the block before adding to it, the read is captured already.
+    let previous = match view.read(&BLOCK_WEIGHT) {
+        ReadResult::Value(value) => block_weight(value.as_deref()),
+        ReadResult::Exists(_) => unreachable!("The value of the key was read"),
+        // The incarnation is discarded.
+        ReadResult::Halted => Weight::zero(),
+    };
+    block_weight(Some(consumed)).saturating_sub(previous)
+}
@@ -0,0 +1,60 @@
+//! Batches of extrinsics kept on the host side, passed to the runtime API by identifier.
+//!
+//! Passing a batch as the argument of [`BATCH_APPLY_EXTRINSIC_METHOD`] encodes the whole batch in
+//! the block builder, only for the executor to split it again, see the `extrinsics_codec`
+//! benchmark. Instead, the block builder registers its batch in the [`HostBatches`] of the
+//! executor, and calls [`BATCH_APPLY_EXTRINSIC_BY_ID_METHOD`] with the [`BatchId`] it got: the
+//! executor applies the registered extrinsics by index, without any round-trip through SCALE.
+//!
+//! The runtime declares the method in its `BlockBuilder` API along with
+//! `batch_apply_extrinsic`. The batch only exists on the host side, so the method is intercepted
+//! by the [`ParallelLocalCallExecutor`](crate::ParallelLocalCallExecutor) and never executed by
+//! the runtime itself.
+//!
+//! [`BATCH_APPLY_EXTRINSIC_METHOD`]: crate::extrinsic::BATCH_APPLY_EXTRINSIC_METHOD
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::extrinsic::Extrinsic;
+use crate::sync_wrapper::Mutex;
+
+/// Runtime method applying a batch registered in the [`HostBatches`] of the executor, given the
+/// SCALE encoded [`BatchId`] of the batch as argument.
+pub const BATCH_APPLY_EXTRINSIC_BY_ID_METHOD: &str = "BlockBuilder_batch_apply_extrinsic_by_id";
+
+/// Identifier of a batch registered in the [`HostBatches`].
+pub type BatchId = u64;
+
+/// Batches registered by the block builders, until they are applied.
+#[derive(Debug, Default)]
+pub struct HostBatches {
+    next_id: AtomicU64,
+    batches: Mutex<HashMap<BatchId, Vec<Extrinsic>>>,
+}
+
+impl HostBatches {
+    /// Registers `batch`, returning the identifier to call
+    /// [`BATCH_APPLY_EXTRINSIC_BY_ID_METHOD`] with.
+    pub fn register(&self, batch: Vec<Extrinsic>) -> BatchId {
+        let batch_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.batches.lock().insert(batch_id, batch);
+        batch_id
+    }
+
+    /// Removes the batch `batch_id`, to apply it or because it is abandoned, e.g. with the
+    /// proposal of its block.
+    pub fn take(&self, batch_id: BatchId) -> Option<Vec<Extrinsic>> {
+        self.batches.lock().remove(&batch_id)
+    }
+
+    /// Number of batches registered and not applied yet.
+    pub fn len(&self) -> usize {
+        self.batches.lock().len()
+    }
+
+    /// Whether every registered batch was applied or abandoned.
+    pub fn is_empty(&self) -> bool {
+        self.batches.lock().is_empty()
+    }
+}
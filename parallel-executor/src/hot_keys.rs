@@ -0,0 +1,69 @@
+//! Detection of the hot keys of a block, written by many of its transactions.
+//!
+//! Transactions writing to the same key, e.g. the balance of a popular crowdloan fund, are
+//! executed optimistically and abort each other again and again. With the
+//! [`Hybrid`](crate::executor::SchedulerPolicy::Hybrid) policy, once the reads of a key were
+//! invalidated a given number of times, the key is hot: the next incarnations of the transactions
+//! that accessed it wait for the lower transaction that last wrote to it to be executed, so that
+//! the transactions touching the key are executed one after the other.
+
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use dashmap::{DashMap, DashSet};
+
+use crate::LOG_TARGET;
+
+/// Default number of aborts caused by a key from which it is hot.
+pub const DEFAULT_ABORT_THRESHOLD: u32 = 3;
+
+/// Keys whose reads were invalidated during the execution of a block, with the number of aborts
+/// they caused, and the hot ones.
+#[derive(Debug)]
+pub(crate) struct HotKeys<K: Hash + Eq> {
+    /// Number of aborts caused by a key from which it is hot, if hot keys are detected.
+    maybe_abort_threshold: Option<u32>,
+    aborts: DashMap<K, u32>,
+    hot: DashSet<K>,
+    /// Whether any key is hot, checked before looking up the keys of every incarnation.
+    any_hot: AtomicBool,
+}
+
+impl<K: Hash + Eq + Clone + Debug> HotKeys<K> {
+    /// Creates the hot keys of a block, detected from `maybe_abort_threshold` aborts if given.
+    pub(crate) fn new(maybe_abort_threshold: Option<u32>) -> Self {
+        Self { maybe_abort_threshold, aborts: DashMap::new(), hot: DashSet::new(), any_hot: AtomicBool::new(false) }
+    }
+
+    /// Records that an incarnation was aborted because its read of `key` was invalidated.
+    pub(crate) fn record_abort(&self, key: &K) {
+        let aborts = {
+            let mut aborts = self.aborts.entry(key.clone()).or_default();
+            *aborts += 1;
+            *aborts
+        };
+        if Some(aborts) == self.maybe_abort_threshold {
+            tracing::debug!(target: LOG_TARGET, ?key, "Hot key detected, serializing the transactions accessing it");
+            self.hot.insert(key.clone());
+            self.any_hot.store(true, Ordering::Release);
+        }
+    }
+
+    /// Whether any key is hot.
+    pub(crate) fn any(&self) -> bool {
+        self.any_hot.load(Ordering::Acquire)
+    }
+
+    /// Whether `key` is hot.
+    pub(crate) fn is_hot(&self, key: &K) -> bool {
+        self.hot.contains(key)
+    }
+
+    /// Consumes the hot keys, returning the number of aborts caused by every key, most first.
+    pub(crate) fn into_aborts(self) -> Vec<(K, u32)> {
+        let mut aborts: Vec<_> = self.aborts.into_iter().collect();
+        aborts.sort_by(|(_, a), (_, b)| b.cmp(a));
+        aborts
+    }
+}
@@ -0,0 +1,26 @@
+//! Code executors of the worker threads applying the batches of extrinsics.
+
+use crate::task::WorkerId;
+
+/// Pool of code executors, keyed by worker id.
+///
+/// The wasm executor keeps a few instances of every runtime it prepared, reused from one call to
+/// the next with their memory reset in between, and instantiates the runtime again for the calls
+/// made while they are all busy. Workers sharing an executor contend for these instances, so the
+/// pool can give every worker an executor of its own instead, whose instance the worker reuses for
+/// all the extrinsics it applies.
+pub struct InstancePool<E> {
+    executors: Vec<E>,
+}
+
+impl<E> InstancePool<E> {
+    /// Creates the executors of `num_workers` workers with `new_executor`.
+    pub fn new(num_workers: usize, new_executor: impl FnMut() -> E) -> Self {
+        Self { executors: std::iter::repeat_with(new_executor).take(num_workers.max(1)).collect() }
+    }
+
+    /// Returns the executor of the worker `worker_id`.
+    pub fn executor(&self, worker_id: WorkerId) -> &E {
+        &self.executors[worker_id % self.executors.len()]
+    }
+}
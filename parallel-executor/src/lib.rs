@@ -1,24 +1,204 @@
+pub mod access_hints;
+pub mod access_report;
+pub mod backend_cache;
+pub mod batch;
+pub mod batch_push;
+pub mod bench;
+pub mod bloom;
+pub mod cancellation;
+pub mod captured_reads;
+pub mod child_tries;
+pub mod collator;
+pub mod commit_events;
+pub mod conflict_graph;
+pub mod conflict_oracle;
+pub mod counters;
+pub mod determinism;
+pub mod dispatch_class;
+pub mod dry_run;
+pub mod events;
+pub mod evm;
+pub mod executor;
+pub mod ext;
+pub mod extrinsic;
+pub mod host_batch;
+pub mod hot_keys;
+pub mod instance_pool;
+pub mod limit_processor;
+pub mod packing;
+pub mod parallel_config;
+pub mod parallelism;
+pub mod pipeline;
+pub mod pool_tags;
+pub mod read_cache;
+pub mod read_only;
+pub mod remote_executor;
+pub mod replay;
+pub mod revalidation;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+pub mod scheduler;
+pub mod signature_cache;
+pub mod state_machine;
+pub mod storage_root;
+pub mod sync_wrapper;
+pub mod task;
+pub mod thread_pool;
+pub mod txn_last_input_output;
+pub mod versioned_data;
+pub mod view;
+pub mod worker_extensions;
+pub mod workload;
+
 use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use codec::{Decode, Encode};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use rayon::{ThreadPool, ThreadPoolBuildError};
 use sc_client_api::execution_extensions::ExecutionExtensions;
-use sc_client_api::{backend, CallExecutor};
+use sc_client_api::{backend, AuxStore, CallExecutor};
 use sc_executor::{RuntimeVersion, RuntimeVersionOf};
-use sc_service::LocalCallExecutor;
+use sc_service::{ClientConfig, LocalCallExecutor};
 use sp_api::ProofRecorder;
+use sp_blockchain::HeaderBackend;
+use sp_core::storage::StateVersion;
 use sp_core::traits::{CallContext, CodeExecutor};
+use sp_core::Hasher;
 use sp_externalities::Extensions;
-use sp_runtime::traits::{Block as BlockT, HashingFor};
-use sp_state_machine::OverlayedChanges;
+use sp_inherents::{CheckInherentsResult, InherentData};
+use sp_runtime::generic::BlockId;
+use sp_runtime::traits::{Block as BlockT, HashingFor, Header as HeaderT, NumberFor, One};
+use sp_runtime::transaction_validity::{TransactionPriority, TransactionSource, TransactionValidity};
+use sp_runtime::ApplyExtrinsicResult;
+use sp_state_machine::backend::AsTrieBackend;
+use sp_state_machine::{Backend as StateBackend, BackendTransaction, OverlayedChanges, StorageKey, StorageValue};
 use sp_trie::StorageProof;
+use sp_weights::Weight;
+
+use crate::access_report::{AccessReport, APPLY_EXTRINSIC_WITH_ACCESS_REPORT_METHOD};
+use crate::backend_cache::BackendCache;
+use crate::batch::LazyBatch;
+use crate::batch_push::BatchPusher;
+use crate::bench::{BlockBenchmark, INITIALIZE_BLOCK_METHOD};
+use crate::cancellation::CancellationToken;
+use crate::child_tries::ChildTriePolicy;
+use crate::commit_events::{CommitEvent, CommitSender, CommitSubscribers};
+use crate::conflict_oracle::ConflictOracle;
+use crate::counters::FallbackReason;
+use crate::determinism::{AuditRun, DeterminismReport};
+use crate::dispatch_class::{DispatchClass, DispatchClassifier};
+use crate::dry_run::{DryRunConflict, DryRunReport};
+use crate::events::BlockEvents;
+use crate::executor::{BlockEnd, BlockExecutor, BlockOutput, SchedulerPolicy};
+use crate::ext::OffchainPolicy;
+use crate::extrinsic::{
+    block_builder_api_id, BackendView, Extrinsic, ExtrinsicError, ExtrinsicOutput, ExtrinsicTask, ExtrinsicTaskArgs,
+    APPLY_EXTRINSIC_METHOD, BATCH_APPLY_EXTRINSIC_API_VERSION, BATCH_APPLY_EXTRINSIC_METHOD, CHECK_INHERENTS_METHOD,
+};
+use crate::host_batch::{BatchId, HostBatches, BATCH_APPLY_EXTRINSIC_BY_ID_METHOD};
+use crate::instance_pool::InstancePool;
+use crate::limit_processor::{
+    block_weight, BlockLimitProcessor, ProofRecorderEstimator, ProofSizeBudget, BLOCK_WEIGHT,
+};
+use crate::packing::BlockPacker;
+use crate::parallel_config::{
+    parallel_config_api_id, ParallelConfig, PARALLEL_CONFIG_API_VERSION, PARALLEL_CONFIG_METHOD,
+};
+use crate::parallelism::{parallelism_aux_key, Parallelism, ParallelismRecord};
+use crate::pipeline::PendingStorageChanges;
+use crate::read_only::ReadOnlyCall;
+use crate::remote_executor::{first_cross_shard_conflict, RemoteExecutor, ShardOutput, ShardRequest, ShardResponse};
+use crate::revalidation::{
+    tagged_transaction_queue_api_id, validate_transaction_call, VALIDATE_TRANSACTION_METHOD,
+    VALIDATE_TRANSACTION_MIN_API_VERSION,
+};
+use crate::scheduler::{SpeculationWindow, TxnIndex};
+use crate::signature_cache::{SignatureCache, SignatureExtractor};
+use crate::state_machine::{proving_backend, RuntimeCodeCache};
+use crate::sync_wrapper::Mutex;
+use crate::thread_pool::CoreAffinity;
+use crate::view::StateView;
+use crate::worker_extensions::WorkerExtensions;
+
+/// Log target of the parallel executor, e.g. `-l parallel_executor=debug`.
+pub(crate) const LOG_TARGET: &str = "parallel_executor";
 
 /// ParallelExecutor enables parallel execution of batched Substrate transactions.
 /// It can be used as a replacement for the substrate `LocalCallExecutor`.
 pub struct ParallelLocalCallExecutor<Block: BlockT, B, E> {
     pub executor: LocalCallExecutor<Block, B, E>,
 
+    // Backend of the `LocalCallExecutor` and code executors of the workers, used to apply the
+    // batches of extrinsics in parallel.
+    backend: Arc<B>,
+    instance_pool: Arc<InstancePool<E>>,
+
     // Number of active concurrent tasks, corresponding to the maximum number of rayon
     // threads that may be concurrently participating in parallel execution.
     concurrency_level: usize,
+    // Threads running the workers, shut down once the last clone of the executor is dropped.
+    thread_pool: Arc<ThreadPool>,
+
+    // Predicts the conflicts between the extrinsics of a batch, if any.
+    conflict_oracle: Option<Arc<dyn ConflictOracle<Extrinsic>>>,
+    // How the extrinsics of a batch are scheduled.
+    scheduler_policy: SchedulerPolicy,
+    // Bounds of the number of extrinsics executed ahead of the committed prefix, if limited.
+    maybe_speculation_window: Option<SpeculationWindow>,
+    // Whether the child tries are hashed in parallel when computing the storage root of a block.
+    parallel_storage_root: bool,
+    // Execution time budget of every extrinsic of a batch, if limited.
+    maybe_extrinsic_timeout: Option<Duration>,
+    // Aborts the application of the batches in flight once cancelled, if any.
+    maybe_cancellation: Option<CancellationToken>,
+    // Whether the keys predicted by the conflict oracle are read from the backend before the
+    // extrinsics of a batch are scheduled.
+    prefetch_base_values: bool,
+    // Whether the first execution of the extrinsics not predicted to conflict reads the snapshot
+    // of the committed prefix.
+    snapshot_first_incarnations: bool,
+    // Whether the signature verifications of every extrinsic of a batch are cached across its
+    // executions.
+    cache_signatures: bool,
+    // Extracts the signatures of the extrinsics of a batch, verified before they are scheduled,
+    // if any.
+    maybe_signature_extractor: Option<Arc<dyn SignatureExtractor>>,
+    // What becomes of the writes of the extrinsics of a batch to the offchain storage.
+    offchain_policy: OffchainPolicy,
+    // How the accesses of the extrinsics of a batch to the child tries are handled.
+    child_trie_policy: ChildTriePolicy,
+    // Whether the host functions called by the extrinsics of a batch are audited.
+    audit_host_calls: bool,
+    // Extensions of the node made available to the workers, if any.
+    maybe_worker_extensions: Option<WorkerExtensions>,
+    // Records where the base values of the batches are read from, and pins some of them, if any.
+    maybe_backend_cache: Option<Arc<BackendCache<HashingFor<Block>>>>,
+    // Batches registered by the block builders, applied by identifier.
+    host_batches: Arc<HostBatches>,
+    // Parallelism record of the batches applied on top of the last parent block, until taken.
+    block_parallelism: Arc<Mutex<Option<(Block::Hash, ParallelismRecord)>>>,
+    // Number of extrinsics of a batch executed at once, if bounded.
+    maybe_chunk_size: Option<usize>,
+    // Tells the extrinsics of a batch applied sequentially because of their dispatch class, if any.
+    maybe_dispatch_classifier: Option<Arc<dyn DispatchClassifier>>,
+    // Whether the batches are applied in parallel even if the runtime does not declare the batch
+    // method.
+    parallel_legacy_runtimes: bool,
+    // Channels the extrinsics committed by Block-STM are streamed to.
+    commit_subscribers: Arc<CommitSubscribers<Block::Hash>>,
+    // Ships shards of the batches to worker processes, if any.
+    maybe_remote_executor: Option<Arc<RemoteExecutor>>,
+    // Weight the extrinsics of the blocks built by the node may consume, if limited.
+    maybe_block_weight_limit: Option<Weight>,
+    // Size the storage proof of the blocks built by the node may reach, if limited.
+    maybe_proof_size_limit: Option<usize>,
+    // Whether the batches are part of a block built by the node, to which the authoring limits
+    // apply.
+    authoring: bool,
 }
 
 impl<Block: BlockT, B, E> Clone for ParallelLocalCallExecutor<Block, B, E>
@@ -26,10 +206,1542 @@ where
     E: Clone,
 {
     fn clone(&self) -> Self {
-        ParallelLocalCallExecutor { executor: self.executor.clone(), concurrency_level: self.concurrency_level }
+        ParallelLocalCallExecutor {
+            executor: self.executor.clone(),
+            backend: self.backend.clone(),
+            instance_pool: self.instance_pool.clone(),
+            concurrency_level: self.concurrency_level,
+            thread_pool: self.thread_pool.clone(),
+            conflict_oracle: self.conflict_oracle.clone(),
+            scheduler_policy: self.scheduler_policy,
+            maybe_speculation_window: self.maybe_speculation_window,
+            parallel_storage_root: self.parallel_storage_root,
+            maybe_extrinsic_timeout: self.maybe_extrinsic_timeout,
+            maybe_cancellation: self.maybe_cancellation.clone(),
+            prefetch_base_values: self.prefetch_base_values,
+            snapshot_first_incarnations: self.snapshot_first_incarnations,
+            cache_signatures: self.cache_signatures,
+            maybe_signature_extractor: self.maybe_signature_extractor.clone(),
+            offchain_policy: self.offchain_policy,
+            child_trie_policy: self.child_trie_policy,
+            audit_host_calls: self.audit_host_calls,
+            maybe_worker_extensions: self.maybe_worker_extensions.clone(),
+            maybe_backend_cache: self.maybe_backend_cache.clone(),
+            host_batches: self.host_batches.clone(),
+            block_parallelism: self.block_parallelism.clone(),
+            maybe_chunk_size: self.maybe_chunk_size,
+            maybe_dispatch_classifier: self.maybe_dispatch_classifier.clone(),
+            parallel_legacy_runtimes: self.parallel_legacy_runtimes,
+            commit_subscribers: self.commit_subscribers.clone(),
+            maybe_remote_executor: self.maybe_remote_executor.clone(),
+            maybe_block_weight_limit: self.maybe_block_weight_limit,
+            maybe_proof_size_limit: self.maybe_proof_size_limit,
+            authoring: self.authoring,
+        }
     }
 }
 
+impl<Block, B, E> ParallelLocalCallExecutor<Block, B, E>
+where
+    B: backend::Backend<Block>,
+    E: CodeExecutor + RuntimeVersionOf + Clone + 'static,
+    Block: BlockT,
+{
+    /// Creates an executor applying the batches of extrinsics with up to `concurrency_level`
+    /// workers, and delegating the other calls to a [`LocalCallExecutor`].
+    ///
+    /// The workers share clones of `executor`, see
+    /// [`with_worker_executors`](Self::with_worker_executors) to give each of them runtime
+    /// instances of its own.
+    pub fn new(
+        backend: Arc<B>,
+        executor: E,
+        client_config: ClientConfig<Block>,
+        execution_extensions: ExecutionExtensions<Block>,
+        concurrency_level: usize,
+    ) -> sp_blockchain::Result<Self> {
+        let local_executor =
+            LocalCallExecutor::new(backend.clone(), executor.clone(), client_config, execution_extensions)?;
+        let instance_pool = Arc::new(InstancePool::new(concurrency_level, || executor.clone()));
+        let thread_pool =
+            thread_pool::new_thread_pool(concurrency_level, CoreAffinity::Any).map_err(thread_pool_error)?;
+        Ok(Self {
+            executor: local_executor,
+            backend,
+            instance_pool,
+            concurrency_level,
+            thread_pool: Arc::new(thread_pool),
+            conflict_oracle: None,
+            scheduler_policy: SchedulerPolicy::default(),
+            maybe_speculation_window: None,
+            parallel_storage_root: false,
+            maybe_extrinsic_timeout: None,
+            maybe_cancellation: None,
+            prefetch_base_values: false,
+            snapshot_first_incarnations: false,
+            cache_signatures: false,
+            maybe_signature_extractor: None,
+            offchain_policy: OffchainPolicy::default(),
+            child_trie_policy: ChildTriePolicy::default(),
+            audit_host_calls: false,
+            maybe_worker_extensions: None,
+            maybe_backend_cache: None,
+            host_batches: Arc::default(),
+            block_parallelism: Arc::default(),
+            maybe_chunk_size: None,
+            maybe_dispatch_classifier: None,
+            parallel_legacy_runtimes: false,
+            commit_subscribers: Arc::default(),
+            maybe_remote_executor: None,
+            maybe_block_weight_limit: None,
+            maybe_proof_size_limit: None,
+            authoring: false,
+        })
+    }
+
+    /// Applies the batches with a code executor per worker created by `new_executor`, e.g. a
+    /// `WasmExecutor` caching a single runtime instance.
+    pub fn with_worker_executors(mut self, new_executor: impl FnMut() -> E) -> Self {
+        self.instance_pool = Arc::new(InstancePool::new(self.concurrency_level, new_executor));
+        self
+    }
+
+    /// Runs the workers on `pool` rather than on threads of their own, e.g. a pool spawned by the
+    /// `TaskManager` of the node with [`thread_pool::spawn_thread_pool`], or shared with another
+    /// executor. The pool should have `concurrency_level` threads at least, or the workers of a
+    /// batch take turns.
+    pub fn with_thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.thread_pool = pool;
+        self
+    }
+
+    /// Runs the workers on `concurrency_level` threads of their own pinned according to
+    /// `affinity`, e.g. to the cores of the NUMA node of the validator, replacing the pool they
+    /// ran on.
+    pub fn with_core_affinity(mut self, affinity: CoreAffinity) -> sp_blockchain::Result<Self> {
+        let thread_pool = thread_pool::new_thread_pool(self.concurrency_level, affinity).map_err(thread_pool_error)?;
+        self.thread_pool = Arc::new(thread_pool);
+        Ok(self)
+    }
+
+    /// Predicts the conflicts between the extrinsics of a batch with `oracle`, e.g. an
+    /// [`AccessHintProvider`](access_hints::AccessHintProvider), or an
+    /// [`EvmOracle`](evm::EvmOracle) on Frontier based chains, so that an extrinsic waits for the
+    /// lower one it likely depends on rather than being executed again.
+    pub fn with_conflict_oracle(mut self, oracle: impl ConflictOracle<Extrinsic> + 'static) -> Self {
+        self.conflict_oracle = Some(Arc::new(oracle));
+        self
+    }
+
+    /// Schedules the extrinsics of the batches with `policy`. With
+    /// [`SchedulerPolicy::Conservative`], the exhaustive predictions of the conflict oracle, e.g.
+    /// the access lists declared by the submitters, are enforced: the extrinsics from the first one
+    /// accessing a key it did not declare are applied sequentially.
+    pub fn with_scheduler_policy(mut self, policy: SchedulerPolicy) -> Self {
+        self.scheduler_policy = policy;
+        self
+    }
+
+    /// Only executes the extrinsics of a batch within a window of the committed prefix, tuned from
+    /// the aborts of the batch within `window`. In batches with many conflicts, the extrinsics far
+    /// ahead of the committed prefix mostly read values invalidated before they are committed.
+    pub fn with_speculation_window(mut self, window: SpeculationWindow) -> Self {
+        self.maybe_speculation_window = Some(window);
+        self
+    }
+
+    /// Stops the execution of an extrinsic of a batch running for longer than `timeout`, e.g. stuck
+    /// in a loop. The extrinsic is reported as exhausting the resources of the block, and the rest
+    /// of the batch proceeds. The deadline is checked whenever the extrinsic accesses the state, an
+    /// extrinsic only computing is not stopped.
+    ///
+    /// Only applies to the batches of a [`BatchPusher`]: whether an extrinsic reaches the deadline
+    /// depends on the node, so an imported block applies every extrinsic to completion.
+    pub fn with_extrinsic_timeout(mut self, timeout: Duration) -> Self {
+        self.maybe_extrinsic_timeout = Some(timeout);
+        self
+    }
+
+    /// Aborts the application of the batches in flight with an error once `token` is cancelled,
+    /// e.g. by the exit future of the service when the node shuts down, see
+    /// [`CancellationToken::cancel_on`]. A proposer abandoning its slot gives a clone of the
+    /// executor a [`child`](CancellationToken::child) token of its own.
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.maybe_cancellation = Some(token);
+        self
+    }
+
+    /// Reads the keys predicted by the conflict oracle from the backend in parallel before
+    /// scheduling the extrinsics of a batch, so that their first execution does not wait on the
+    /// database. Has no effect without a conflict oracle.
+    pub fn with_base_value_prefetch(mut self) -> Self {
+        self.prefetch_base_values = true;
+        self
+    }
+
+    /// Executes the extrinsics of a batch not predicted to conflict against the snapshot of the
+    /// state and of the extrinsics committed so far the first time, rather than tracking the
+    /// extrinsics being executed. An extrinsic is executed again with full tracking once it turns
+    /// out to conflict. Lowers the overhead of the batches that are mostly free of conflicts.
+    pub fn with_snapshot_first_executions(mut self) -> Self {
+        self.snapshot_first_incarnations = true;
+        self
+    }
+
+    /// Caches the outcome of the signature verifications of every extrinsic of a batch, so that
+    /// its next executions do not verify its signature again, see
+    /// [`signature_cache`](crate::signature_cache). Only effective once the host functions of
+    /// `executor` verify the signatures through the cache.
+    pub fn with_signature_cache(mut self) -> Self {
+        self.cache_signatures = true;
+        self
+    }
+
+    /// Verifies the signatures of the extrinsics of a batch extracted by `extractor` in parallel
+    /// before they are scheduled, see [`SignatureCache::verify_batch`]. The extrinsics whose
+    /// signature is invalid are rejected without being executed, and the signature verifications
+    /// are cached as with [`with_signature_cache`](Self::with_signature_cache).
+    pub fn with_signature_prepass(mut self, extractor: impl SignatureExtractor + 'static) -> Self {
+        self.cache_signatures = true;
+        self.maybe_signature_extractor = Some(Arc::new(extractor));
+        self
+    }
+
+    /// Handles the writes of the extrinsics of a batch to the offchain storage, e.g. by the
+    /// offchain indexing of a pallet, according to `policy`, see [`OffchainPolicy`]. By
+    /// default, they are not supported in parallel and the batch is applied sequentially
+    /// instead.
+    pub fn with_offchain_policy(mut self, policy: OffchainPolicy) -> Self {
+        self.offchain_policy = policy;
+        self
+    }
+
+    /// Handles the accesses of the extrinsics of a batch to the child tries, e.g. the ones of the
+    /// contracts, according to `policy`, see [`ChildTriePolicy`]. By default, they are not
+    /// supported in parallel and the batch is applied sequentially instead.
+    pub fn with_child_trie_policy(mut self, policy: ChildTriePolicy) -> Self {
+        self.child_trie_policy = policy;
+        self
+    }
+
+    /// Audits the host functions called by the extrinsics of a batch: they are traced, and the
+    /// extrinsics calling [`NONDETERMINISTIC_HOST_CALLS`](ext::NONDETERMINISTIC_HOST_CALLS) are
+    /// applied sequentially along with the rest of the batch, and counted in the metrics.
+    pub fn with_host_call_audit(mut self) -> Self {
+        self.audit_host_calls = true;
+        self
+    }
+
+    /// Hands the runtime of every worker a set of its own of the `extensions` of the node for the
+    /// duration of a batch, see [`worker_extensions`]. By default, the workers have no extension.
+    pub fn with_worker_extensions(mut self, extensions: WorkerExtensions) -> Self {
+        self.maybe_worker_extensions = Some(extensions);
+        self
+    }
+
+    /// Records in `backend_cache` whether the base values read by the batches are found in the
+    /// shared trie cache of the client database or on the disk, see [`BackendCache::stats`], and
+    /// reads the values it pins before applying every batch.
+    pub fn with_backend_cache(mut self, backend_cache: Arc<BackendCache<HashingFor<Block>>>) -> Self {
+        self.maybe_backend_cache = Some(backend_cache);
+        self
+    }
+
+    /// Applies the batches in chunks of at most `chunk_size` extrinsics, one after the other, to
+    /// bound the memory used by the block executor for the batches of tens of thousands of
+    /// extrinsics. Every chunk is executed with Block-STM on top of the changes of the previous
+    /// ones.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "Chunks must hold at least one extrinsic");
+        self.maybe_chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Applies the mandatory and operational extrinsics of the batches sequentially, as told by
+    /// `classifier`, and only the normal ones in parallel, see [`dispatch_class`].
+    pub fn with_dispatch_classifier(mut self, classifier: impl DispatchClassifier + 'static) -> Self {
+        self.maybe_dispatch_classifier = Some(Arc::new(classifier));
+        self
+    }
+
+    /// Applies the batches in parallel even on the runtimes that do not declare the batch method,
+    /// see [`supports_batch_apply`](Self::supports_batch_apply), e.g. a runtime whose extrinsics
+    /// are known to be applied in parallel correctly.
+    pub fn with_legacy_runtimes(mut self) -> Self {
+        self.parallel_legacy_runtimes = true;
+        self
+    }
+
+    /// Executes shards of the batches in the worker processes of `remote_executor`, see
+    /// [`remote_executor`]. The batches applied while recording a storage proof are executed
+    /// locally.
+    pub fn with_remote_executor(mut self, remote_executor: RemoteExecutor) -> Self {
+        self.maybe_remote_executor = Some(Arc::new(remote_executor));
+        self
+    }
+
+    /// Stops applying the batches pushed with a [`BatchPusher`] before the first extrinsic that
+    /// would take the weight of the block, as accounted by the runtime in `System::BlockWeight`,
+    /// over `limit`. The rest of the batch is reported as skipped. The extrinsics applied
+    /// sequentially, e.g. the inherents, are only limited by the runtime itself.
+    pub fn with_block_weight_limit(mut self, limit: Weight) -> Self {
+        self.maybe_block_weight_limit = Some(limit);
+        self
+    }
+
+    /// Stops applying the batches pushed with a [`BatchPusher`] while recording the storage proof
+    /// before the first extrinsic that would take the size of the proof over `limit`, e.g. the
+    /// proof of validity budget of a parachain less the room kept for its extrinsics. The size
+    /// added by every extrinsic is estimated with a [`ProofRecorderEstimator`]. The rest of the
+    /// batch is reported as skipped.
+    pub fn with_proof_size_limit(mut self, limit: usize) -> Self {
+        self.maybe_proof_size_limit = Some(limit);
+        self
+    }
+
+    /// Batches kept on the host side until they are applied, see [`host_batch`]. They are shared
+    /// with the clones of the executor.
+    pub fn host_batches(&self) -> &HostBatches {
+        &self.host_batches
+    }
+
+    /// Maximum number of workers applying a batch.
+    pub fn concurrency_level(&self) -> usize {
+        self.concurrency_level
+    }
+
+    /// How the extrinsics of a batch are scheduled.
+    pub fn scheduler_policy(&self) -> SchedulerPolicy {
+        self.scheduler_policy
+    }
+
+    /// Number of extrinsics of a batch executed at once, if bounded.
+    pub fn chunk_size(&self) -> Option<usize> {
+        self.maybe_chunk_size
+    }
+
+    /// Returns a channel receiving a [`CommitEvent`] for every extrinsic committed by Block-STM
+    /// from now on, as soon as it is committed, see [`commit_events`]. The channel is shared with
+    /// the clones of the executor, and forgotten once the receiver is dropped.
+    pub fn subscribe_commits(&self) -> crossbeam::channel::Receiver<CommitEvent<Block::Hash>> {
+        self.commit_subscribers.subscribe()
+    }
+
+    /// Hashes the child tries in parallel in [`storage_root`](Self::storage_root).
+    pub fn with_parallel_storage_root(mut self) -> Self {
+        self.parallel_storage_root = true;
+        self
+    }
+
+    /// Reorders the `extrinsics` of a block being proposed, along with their priority, so that the
+    /// extrinsics predicted to conflict by the conflict oracle are spread apart, see
+    /// [`BlockPacker`]. The extrinsics of a sender keep their order. The proposer includes them in
+    /// the block in the returned order, or in their original order if there is no conflict oracle.
+    pub fn pack_extrinsics(&self, extrinsics: Vec<(Block::Extrinsic, TransactionPriority)>) -> Vec<Block::Extrinsic> {
+        let Some(oracle) = &self.conflict_oracle else {
+            return extrinsics.into_iter().map(|(xt, _)| xt).collect();
+        };
+        let block: Vec<_> = extrinsics.iter().map(|(xt, _)| Extrinsic::new(xt.encode())).collect();
+        let priorities: Vec<_> = extrinsics.iter().map(|(_, priority)| *priority).collect();
+        let order = BlockPacker::new(oracle.as_ref(), self.concurrency_level).pack(&block, &priorities);
+
+        let mut extrinsics: Vec<_> = extrinsics.into_iter().map(|(xt, _)| Some(xt)).collect();
+        order.into_iter().filter_map(|txn_idx| extrinsics[txn_idx].take()).collect()
+    }
+
+    /// Reads the hints of the runtime at `at_hash` about the parallel application of its
+    /// extrinsics, see [`parallel_config`]. Returns `None` if the runtime does not declare the
+    /// version of the `ParallelConfig` API the node knows, e.g. before an upgrade of the runtime
+    /// adding it.
+    pub fn parallel_config(&self, at_hash: Block::Hash) -> sp_blockchain::Result<Option<ParallelConfig>> {
+        let version = CallExecutor::runtime_version(&self.executor, at_hash)?;
+        if !version.has_api_with(&parallel_config_api_id(), |api_version| api_version == PARALLEL_CONFIG_API_VERSION) {
+            tracing::debug!(target: LOG_TARGET, spec_version = version.spec_version, "Runtime without parallel config");
+            return Ok(None);
+        }
+        let result = self.executor.call(at_hash, PARALLEL_CONFIG_METHOD, &[], CallContext::Offchain)?;
+        let encoded = Vec::<u8>::decode(&mut &result[..])
+            .map_err(|err| sp_blockchain::Error::CallResultDecode(PARALLEL_CONFIG_METHOD, err))?;
+        ParallelConfig::decode_for(&version, &encoded)
+            .map_err(|err| sp_blockchain::Error::CallResultDecode(PARALLEL_CONFIG_METHOD, err))
+    }
+
+    /// Checks the inherents of an imported `block` against `inherent_data`, as the
+    /// `check_inherents` runtime API does, on top of the state of its parent.
+    ///
+    /// The runtime is called with the code executor of the first worker, whose runtime instance is
+    /// already prepared, and the runtime code resolved as for the batches, so that an import
+    /// pipeline applying the blocks in parallel does not instantiate the runtime again only to
+    /// check their inherents.
+    pub fn check_inherents_parallel(
+        &self,
+        block: &Block,
+        inherent_data: &InherentData,
+    ) -> sp_blockchain::Result<CheckInherentsResult> {
+        let parent_hash = *block.header().parent_hash();
+        let parent_number = self.backend.blockchain().expect_block_number_from_id(&BlockId::Hash(parent_hash))?;
+        let state = self.backend.state_at(parent_hash)?;
+        let trie_state = state.as_trie_backend();
+        let version = CallExecutor::runtime_version(&self.executor, parent_hash)?;
+        let runtime_code = RuntimeCodeCache::new(trie_state, version).map_err(sp_blockchain::Error::RuntimeCode)?;
+        let mut extensions = self.execution_extensions().extensions(parent_hash, parent_number);
+
+        let call_data = (block, inherent_data).encode();
+        let result = sp_state_machine::StateMachine::new(
+            trie_state,
+            &mut OverlayedChanges::default(),
+            self.instance_pool.executor(0),
+            CHECK_INHERENTS_METHOD,
+            &call_data,
+            &mut extensions,
+            &runtime_code.runtime_code(),
+            CallContext::Onchain,
+        )
+        .execute()
+        .map_err(sp_blockchain::Error::Execution)?;
+        CheckInherentsResult::decode(&mut &result[..])
+            .map_err(|err| sp_blockchain::Error::CallResultDecode(CHECK_INHERENTS_METHOD, err))
+    }
+
+    /// Computes the storage root of a finished block, whose parent is `parent_hash`, along with the
+    /// trie nodes to insert, e.g. when finalizing the block.
+    pub fn storage_root(
+        &self,
+        parent_hash: Block::Hash,
+        changes: &OverlayedChanges<HashingFor<Block>>,
+        state_version: StateVersion,
+    ) -> sp_blockchain::Result<(Block::Hash, BackendTransaction<HashingFor<Block>>)>
+    where
+        B::State: Sync,
+    {
+        let state = self.backend.state_at(parent_hash)?;
+        if self.parallel_storage_root {
+            return Ok(storage_root::full_storage_root(&state, changes, state_version));
+        }
+
+        let delta = changes.changes().map(|(key, value)| (&key[..], value.value().map(|value| &value[..])));
+        let child_deltas = changes.children().map(|(child_changes, child_info)| {
+            (child_info, child_changes.map(|(key, value)| (&key[..], value.value().map(|value| &value[..]))))
+        });
+        Ok(state.full_storage_root(delta, child_deltas, state_version))
+    }
+
+    /// Starts computing the storage changes and the storage root of a finished block, whose
+    /// parent is `parent_hash`, in the background. Meanwhile, the proposer applies the batch of
+    /// the next block at `parent_hash` on top of a clone of `changes`, and it waits for the storage
+    /// changes before importing the finished block.
+    pub fn spawn_storage_changes(
+        &self,
+        parent_hash: Block::Hash,
+        changes: OverlayedChanges<HashingFor<Block>>,
+        state_version: StateVersion,
+    ) -> sp_blockchain::Result<PendingStorageChanges<HashingFor<Block>>>
+    where
+        B::State: 'static,
+    {
+        let state = self.backend.state_at(parent_hash)?;
+        PendingStorageChanges::spawn(state, changes, state_version)
+            .map_err(|err| sp_blockchain::Error::Backend(format!("Failed to spawn the storage root thread: {err}")))
+    }
+
+    /// Applies `extrinsics` in order on top of `changes`, executing them in parallel with
+    /// Block-STM, and returns the result of every extrinsic.
+    ///
+    /// The backend reads of all the workers are recorded by `recorder`, if any, so that the
+    /// storage proof of the block covers the state accessed by the batch. The workers do not have
+    /// access to `extensions`. If an extrinsic does something that is not supported in parallel,
+    /// the whole batch is applied sequentially instead. The extrinsics following one that writes
+    /// `:code` or `:heappages` are applied sequentially as well.
+    ///
+    /// Once `maybe_deadline` is reached, no new extrinsic is applied: the ones in flight are
+    /// finished, and the results of the extrinsics applied so far are returned. The extrinsics
+    /// following them were not attempted, so the proposer can ship the block on time and put them
+    /// back in the pool.
+    #[allow(clippy::too_many_arguments)]
+    pub fn apply_extrinsics_parallel(
+        &self,
+        at_hash: Block::Hash,
+        extrinsics: &[Block::Extrinsic],
+        changes: &RefCell<OverlayedChanges<HashingFor<Block>>>,
+        recorder: &Option<ProofRecorder<Block>>,
+        call_context: CallContext,
+        extensions: &RefCell<Extensions>,
+        maybe_deadline: Option<Instant>,
+    ) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        let block: Vec<_> = extrinsics.iter().map(|xt| Extrinsic::new(xt.encode())).collect();
+        self.apply_encoded_extrinsics_parallel(
+            at_hash,
+            &block,
+            changes,
+            recorder,
+            call_context,
+            extensions,
+            maybe_deadline,
+        )
+    }
+
+    /// Applies the already encoded extrinsics of `block` as
+    /// [`apply_extrinsics_parallel`](Self::apply_extrinsics_parallel) does, e.g. the ones split
+    /// from an encoded batch with [`Extrinsic::split_batch`], so that they are neither decoded nor
+    /// encoded again.
+    ///
+    /// The runtimes older than the `batch_apply_extrinsic` method, see
+    /// [`supports_batch_apply`](Self::supports_batch_apply), are not known to be applied in
+    /// parallel correctly: their extrinsics are applied one after the other with
+    /// `apply_extrinsic` instead, so that the chain keeps producing blocks across the upgrade.
+    #[allow(clippy::too_many_arguments)]
+    pub fn apply_encoded_extrinsics_parallel(
+        &self,
+        at_hash: Block::Hash,
+        block: &[Extrinsic],
+        changes: &RefCell<OverlayedChanges<HashingFor<Block>>>,
+        recorder: &Option<ProofRecorder<Block>>,
+        call_context: CallContext,
+        extensions: &RefCell<Extensions>,
+        maybe_deadline: Option<Instant>,
+    ) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        if !self.parallel_legacy_runtimes && !self.supports_batch_apply(at_hash)? {
+            tracing::debug!(target: LOG_TARGET, num_txns = block.len(), "Runtime without batch method, applying the batch sequentially");
+            counters::record_fallback(FallbackReason::LegacyRuntime);
+            return self.apply_extrinsics_sequential(
+                at_hash,
+                block,
+                changes,
+                recorder,
+                call_context,
+                extensions,
+                maybe_deadline,
+            );
+        }
+
+        let Some(classifier) = &self.maybe_dispatch_classifier else {
+            return self.apply_batch_parallel(
+                at_hash,
+                block,
+                changes,
+                recorder,
+                call_context,
+                extensions,
+                maybe_deadline,
+            );
+        };
+
+        let classes: Vec<_> = block.iter().map(|xt| classifier.dispatch_class(xt)).collect();
+        let mut results = Vec::with_capacity(block.len());
+        let mut start = 0;
+        while start < block.len() {
+            let class = classes[start];
+            let end = classes[start..].iter().position(|other| *other != class).map_or(block.len(), |len| start + len);
+            let run = &block[start..end];
+            tracing::debug!(target: LOG_TARGET, txn_idx = start, num_txns = run.len(), ?class, "Applying extrinsics of the same class");
+            let run_results = match class {
+                DispatchClass::Normal => self.apply_batch_parallel(
+                    at_hash,
+                    run,
+                    changes,
+                    recorder,
+                    call_context,
+                    extensions,
+                    maybe_deadline,
+                )?,
+                DispatchClass::Operational => {
+                    counters::record_fallback(FallbackReason::DispatchClass);
+                    self.apply_extrinsics_sequential(
+                        at_hash,
+                        run,
+                        changes,
+                        recorder,
+                        call_context,
+                        extensions,
+                        maybe_deadline,
+                    )?
+                }
+                DispatchClass::Mandatory => {
+                    counters::record_fallback(FallbackReason::DispatchClass);
+                    self.apply_extrinsics_sequential(at_hash, run, changes, recorder, call_context, extensions, None)?
+                }
+            };
+            let applied_run = run_results.len() == run.len();
+            results.extend(run_results);
+            // The deadline was reached while applying the run.
+            if !applied_run {
+                break;
+            }
+            start = end;
+        }
+        Ok(results)
+    }
+
+    /// Applies the extrinsics of `block` with Block-STM, sharded across the worker processes if
+    /// there are any and no storage proof is recorded, locally otherwise.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_batch_parallel(
+        &self,
+        at_hash: Block::Hash,
+        block: &[Extrinsic],
+        changes: &RefCell<OverlayedChanges<HashingFor<Block>>>,
+        recorder: &Option<ProofRecorder<Block>>,
+        call_context: CallContext,
+        extensions: &RefCell<Extensions>,
+        maybe_deadline: Option<Instant>,
+    ) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        match (&self.maybe_remote_executor, recorder) {
+            (Some(remote_executor), None) => self.apply_extrinsics_sharded(
+                remote_executor,
+                at_hash,
+                block,
+                changes,
+                call_context,
+                extensions,
+                maybe_deadline,
+            ),
+            _ => {
+                self.apply_chunks_parallel(at_hash, block, changes, recorder, call_context, extensions, maybe_deadline)
+            }
+        }
+    }
+
+    /// Executes the shards of `block` in the worker processes of `remote_executor`, in rounds, and
+    /// applies the outputs of every round on top of `changes` up to its first cross-shard
+    /// conflict, see [`remote_executor`]. The rest of the batch is applied locally once the shards
+    /// are merged back.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_extrinsics_sharded(
+        &self,
+        remote_executor: &RemoteExecutor,
+        at_hash: Block::Hash,
+        block: &[Extrinsic],
+        changes: &RefCell<OverlayedChanges<HashingFor<Block>>>,
+        call_context: CallContext,
+        extensions: &RefCell<Extensions>,
+        maybe_deadline: Option<Instant>,
+    ) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        let extrinsics: Vec<Vec<u8>> = block
+            .iter()
+            .map(|xt| xt.try_encoded().map(<[u8]>::to_vec))
+            .collect::<Result<_, _>>()
+            .map_err(|err| sp_blockchain::Error::Application(Box::new(err)))?;
+        let base_changes = base_changes(&changes.borrow()).collect();
+        let mut sessions = match remote_executor.open_sessions(at_hash.encode(), base_changes) {
+            Ok(sessions) => sessions,
+            Err(err) => {
+                tracing::warn!(target: LOG_TARGET, %err, "Failed to open the shard sessions, applying the batch locally");
+                return self.apply_chunks_parallel(
+                    at_hash,
+                    block,
+                    changes,
+                    &None,
+                    call_context,
+                    extensions,
+                    maybe_deadline,
+                );
+            }
+        };
+
+        let state = self.backend.state_at(at_hash)?;
+        let read = |key: &StorageKey| {
+            let maybe_value = changes.borrow_mut().storage(key).map(|value| value.map(<[u8]>::to_vec));
+            maybe_value.unwrap_or_else(|| state.storage(key).expect("Externalities not allowed to fail within runtime"))
+        };
+        let mut results = Vec::with_capacity(block.len());
+        let mut num_rounds = 0;
+        let mut block_full = false;
+        while results.len() < block.len() {
+            let started = Instant::now();
+            let txn_offset = results.len();
+            let shards = remote_executor.partition(&block[txn_offset..], self.conflict_oracle.as_deref());
+            let outputs = match sessions.execute(&extrinsics[txn_offset..], &shards) {
+                Ok(outputs) => outputs,
+                Err(err) => {
+                    tracing::warn!(target: LOG_TARGET, %err, txn_idx = txn_offset, "Sharded execution failed, applying the rest of the batch locally");
+                    break;
+                }
+            };
+            num_rounds += 1;
+
+            let num_left = block.len() - txn_offset;
+            let mut num_kept = first_cross_shard_conflict(num_left, &shards, &outputs);
+            let mut kept: Vec<Option<ShardOutput>> = vec![None; num_kept];
+            let mut critical_path = 0;
+            for (txn_indices, shard_outputs) in shards.iter().zip(outputs) {
+                let mut num_kept_in_shard = 0;
+                for (txn_idx, output) in txn_indices.iter().zip(shard_outputs) {
+                    if let Some(slot) = kept.get_mut(*txn_idx as usize) {
+                        *slot = Some(output);
+                        num_kept_in_shard += 1;
+                    }
+                }
+                critical_path = critical_path.max(num_kept_in_shard);
+            }
+            let mut outputs: Vec<ExtrinsicOutput> =
+                kept.into_iter().map(|output| output.expect("Every kept extrinsic was executed").into()).collect();
+            if let Some(weight_limit) = self.remaining_block_weight(&read) {
+                let mut limits = BlockLimitProcessor::<StorageKey>::new(Some(weight_limit), None);
+                let num_fitting = outputs.iter().take_while(|output| limits.try_accrue(output.weight, [])).count();
+                if num_fitting < num_kept {
+                    // The rest of the batch does not fit in the block either.
+                    block_full = true;
+                    num_kept = num_fitting;
+                    outputs.truncate(num_fitting);
+                    critical_path = critical_path.min(num_kept as u32);
+                }
+            }
+            let mut writes = HashMap::new();
+            for output in &outputs {
+                for (key, value) in &output.writes {
+                    writes.insert(key.clone(), Arc::new(value.clone()));
+                }
+            }
+            // Forwarded to the workers, on which the rest of the batch waits.
+            let committed: Vec<_> = writes.iter().map(|(key, value)| (key.clone(), (**value).clone())).collect();
+
+            if num_kept > 0 {
+                let block_events = BlockEvents::new(outputs.iter().map(|output| &output.events), &read);
+                results.extend(commit_outputs(changes, outputs, writes, block_events)?);
+                let parallelism = Parallelism { num_txns: num_kept as u32, critical_path };
+                self.record_parallelism(
+                    at_hash,
+                    ParallelismRecord::new(parallelism, remote_executor.num_shards(), 0, started.elapsed()),
+                );
+            }
+
+            if block_full {
+                tracing::debug!(target: LOG_TARGET, txn_idx = results.len(), "Block full, skipping the rest of the batch");
+                return Ok(results);
+            }
+            if results.len() == block.len() {
+                break;
+            }
+            if !remote_executor.keeps_sharding(num_rounds, num_left, num_kept) {
+                tracing::debug!(target: LOG_TARGET, txn_idx = results.len(), num_rounds, "Too many cross-shard conflicts, merging the shards back");
+                break;
+            }
+            tracing::debug!(target: LOG_TARGET, txn_idx = results.len(), num_rounds, "Cross-shard conflict, forwarding the committed writes");
+            if let Err(err) = sessions.remote_commit(committed) {
+                tracing::warn!(target: LOG_TARGET, %err, "Failed to forward the committed writes, applying the rest of the batch locally");
+                break;
+            }
+        }
+        drop(sessions);
+
+        if results.len() < block.len() {
+            let txn_offset = results.len();
+            results.extend(self.apply_chunks_parallel(
+                at_hash,
+                &block[txn_offset..],
+                changes,
+                &None,
+                call_context,
+                extensions,
+                maybe_deadline,
+            )?);
+        }
+        Ok(results)
+    }
+
+    /// Executes the extrinsics of a shard received from a coordinator, see [`remote_executor`], on
+    /// top of the state of the block and of the changes of the request, without applying them.
+    pub fn execute_shard(&self, request: ShardRequest) -> ShardResponse {
+        let execute = || -> sp_blockchain::Result<Vec<ShardOutput>> {
+            let at_hash = Block::Hash::decode(&mut &request.at_hash[..])
+                .map_err(|err| sp_blockchain::Error::Application(Box::new(err)))?;
+            let mut overlay = OverlayedChanges::default();
+            for (key, value) in request.base_changes {
+                overlay.set_storage(key, value);
+            }
+            let changes = RefCell::new(overlay);
+            let block: Vec<_> = request.extrinsics.into_iter().map(Extrinsic::new).collect();
+            // The shard is not part of a block built by this node.
+            let executor = self.detached();
+            let (block_output, _) = executor
+                .execute_chunk(at_hash, &block, &changes, &None, CallContext::Onchain, None, true)?
+                .map_err(execution_error)?;
+            Ok(block_output.outputs.into_iter().map(ShardOutput::from).collect())
+        };
+        match execute() {
+            Ok(outputs) => ShardResponse::Executed(outputs),
+            Err(err) => ShardResponse::Failed(err.to_string()),
+        }
+    }
+
+    /// Applies the extrinsics of `block` with Block-STM, in chunks if their size is bounded.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_chunks_parallel(
+        &self,
+        at_hash: Block::Hash,
+        block: &[Extrinsic],
+        changes: &RefCell<OverlayedChanges<HashingFor<Block>>>,
+        recorder: &Option<ProofRecorder<Block>>,
+        call_context: CallContext,
+        extensions: &RefCell<Extensions>,
+        maybe_deadline: Option<Instant>,
+    ) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        let Some(chunk_size) = self.maybe_chunk_size.filter(|chunk_size| block.len() > *chunk_size) else {
+            return self.apply_chunk_parallel(
+                at_hash,
+                block,
+                changes,
+                recorder,
+                call_context,
+                extensions,
+                maybe_deadline,
+            );
+        };
+
+        let mut results = Vec::with_capacity(block.len());
+        for chunk in block.chunks(chunk_size) {
+            tracing::debug!(target: LOG_TARGET, txn_idx = results.len(), num_txns = chunk.len(), "Applying chunk of the batch");
+            let chunk_results =
+                self.apply_chunk_parallel(at_hash, chunk, changes, recorder, call_context, extensions, maybe_deadline)?;
+            let applied_chunk = chunk_results.len() == chunk.len();
+            results.extend(chunk_results);
+            // The deadline was reached while applying the chunk.
+            if !applied_chunk {
+                break;
+            }
+        }
+        Ok(results)
+    }
+
+    /// Whether the runtime at `at_hash` declares [`BATCH_APPLY_EXTRINSIC_METHOD`] in its
+    /// `BlockBuilder` API, i.e. whether its batches are applied in parallel.
+    pub fn supports_batch_apply(&self, at_hash: Block::Hash) -> sp_blockchain::Result<bool> {
+        let version = CallExecutor::runtime_version(&self.executor, at_hash)?;
+        Ok(version
+            .has_api_with(&block_builder_api_id(), |api_version| api_version >= BATCH_APPLY_EXTRINSIC_API_VERSION))
+    }
+
+    /// Starts building a block at `at_hash` on top of `changes` from batches of extrinsics pushed
+    /// one after the other as they stream out of the transaction pool, see [`batch_push`]. Every
+    /// batch is applied in parallel on top of the changes of the batches pushed before it.
+    /// The inherents of the block are applied beforehand with
+    /// [`BatchPusher::apply_inherents`], and its epilogue, if any, afterwards with
+    /// [`BatchPusher::apply_epilogue`].
+    pub fn batch_pusher<'a>(
+        &'a self,
+        at_hash: Block::Hash,
+        changes: &'a RefCell<OverlayedChanges<HashingFor<Block>>>,
+        recorder: &'a Option<ProofRecorder<Block>>,
+        call_context: CallContext,
+        extensions: &'a RefCell<Extensions>,
+    ) -> BatchPusher<'a, Block, B, E> {
+        BatchPusher::new(self.authoring(), at_hash, changes, recorder, call_context, extensions)
+    }
+
+    /// Applies the extrinsics of `block`, a chunk of the batch, with Block-STM on top of `changes`,
+    /// which already hold the changes of the previous chunks.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_chunk_parallel(
+        &self,
+        at_hash: Block::Hash,
+        block: &[Extrinsic],
+        changes: &RefCell<OverlayedChanges<HashingFor<Block>>>,
+        recorder: &Option<ProofRecorder<Block>>,
+        call_context: CallContext,
+        extensions: &RefCell<Extensions>,
+        maybe_deadline: Option<Instant>,
+    ) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        let started = Instant::now();
+        let (block_output, block_events) = match self.execute_chunk(
+            at_hash,
+            block,
+            changes,
+            recorder,
+            call_context,
+            maybe_deadline,
+            false,
+        )? {
+            Ok(result) => result,
+            Err(ExtrinsicError::Unsupported(operation)) => {
+                tracing::debug!(target: LOG_TARGET, operation, "Batch not supported in parallel, applying it sequentially");
+                counters::record_fallback(FallbackReason::Unsupported);
+                return self.apply_extrinsics_sequential(
+                    at_hash,
+                    block,
+                    changes,
+                    recorder,
+                    call_context,
+                    extensions,
+                    maybe_deadline,
+                );
+            }
+            Err(err) => return Err(execution_error(err)),
+        };
+        let BlockOutput { outputs, writes, skipped_txns, maybe_end, maybe_parallelism, maybe_stats, .. } = block_output;
+        let mut results = commit_outputs(changes, outputs, writes, block_events)?;
+        if let Some(parallelism) = maybe_parallelism {
+            let num_aborts = maybe_stats.map_or(0, |stats| stats.scheduler.re_executions);
+            self.record_parallelism(
+                at_hash,
+                ParallelismRecord::new(parallelism, self.concurrency_level, num_aborts, started.elapsed()),
+            );
+        }
+
+        // The parallel execution stops after an extrinsic changing the runtime code, the
+        // following ones were executed speculatively with the previous code. It also stops before
+        // an extrinsic accessing keys it did not declare in conservative mode, or computing a
+        // storage root, and once the deadline is reached.
+        if let Some(&txn_idx) = skipped_txns.first() {
+            if maybe_end == Some(BlockEnd::BlockFull) {
+                // The rest of the batch does not fit in the block either.
+                tracing::debug!(target: LOG_TARGET, txn_idx, "Block full, skipping the rest of the batch");
+                return Ok(results);
+            }
+            tracing::debug!(target: LOG_TARGET, txn_idx, ?maybe_end, "Parallel execution ended early, applying the rest of the batch sequentially");
+            counters::record_fallback(match maybe_end {
+                Some(BlockEnd::Deadline) => FallbackReason::Deadline,
+                _ => FallbackReason::EarlyEnd,
+            });
+            results.extend(self.apply_extrinsics_sequential(
+                at_hash,
+                &block[txn_idx as usize..],
+                changes,
+                recorder,
+                call_context,
+                extensions,
+                maybe_deadline,
+            )?);
+        }
+
+        Ok(results)
+    }
+
+    /// Applies the extrinsic `xt` on top of `changes` as a batch of its own, and reports the keys
+    /// it read and wrote along with its result, see [`access_report`]. The extrinsic must only
+    /// perform operations supported in parallel.
+    pub fn apply_extrinsic_with_access_report(
+        &self,
+        at_hash: Block::Hash,
+        xt: &Extrinsic,
+        changes: &RefCell<OverlayedChanges<HashingFor<Block>>>,
+        recorder: &Option<ProofRecorder<Block>>,
+        call_context: CallContext,
+    ) -> sp_blockchain::Result<AccessReport> {
+        let block = std::slice::from_ref(xt);
+        let (block_output, block_events) = self
+            .execute_chunk(at_hash, block, changes, recorder, call_context, None, true)?
+            .map_err(execution_error)?;
+        let BlockOutput { mut outputs, writes, .. } = block_output;
+        let Some(output) = outputs.first_mut() else {
+            // The cancellations are reported as errors, so the parallel execution ended before the
+            // extrinsic, e.g. as it changed the runtime code, computed a storage root or used the
+            // offchain extensions. Its accesses are only known when applied in parallel.
+            return Err(sequential_only_error());
+        };
+        let reads = std::mem::take(&mut output.reads);
+        let mut written: Vec<_> = output.writes.iter().map(|(key, _)| key.clone()).collect();
+        written.sort();
+        let result = commit_outputs(changes, outputs, writes, block_events)?.remove(0);
+        Ok(AccessReport { result, reads, writes: written })
+    }
+
+    /// Executes `extrinsics` speculatively with Block-STM on top of the state at `at_hash`, without
+    /// applying their changes, and reports their results along with the conflicts between them,
+    /// see [`dry_run`]. The extrinsics must only perform operations supported in parallel.
+    pub fn dry_run_batch(&self, at_hash: Block::Hash, extrinsics: &[Extrinsic]) -> sp_blockchain::Result<DryRunReport> {
+        let changes = RefCell::new(OverlayedChanges::default());
+        // The batch is not part of the block being built.
+        let executor = self.detached();
+        let (block_output, _) = executor
+            .execute_chunk(at_hash, extrinsics, &changes, &None, CallContext::Offchain, None, true)?
+            .map_err(execution_error)?;
+        let BlockOutput { outputs, skipped_txns, .. } = block_output;
+        let results =
+            outputs.iter().map(|output| decode_apply_result(&output.result)).collect::<sp_blockchain::Result<_>>()?;
+        Ok(DryRunReport { results, conflicts: DryRunConflict::between(&outputs), skipped_txns })
+    }
+
+    /// Executes the read-only runtime API `calls` at once on top of the state at `at_hash`, see
+    /// [`read_only`], and returns the SCALE encoded result of every call, in order. Their writes
+    /// are discarded.
+    pub fn call_read_only(
+        &self,
+        at_hash: Block::Hash,
+        calls: &[ReadOnlyCall],
+    ) -> sp_blockchain::Result<Vec<sp_blockchain::Result<Vec<u8>>>> {
+        let state = self.backend.state_at(at_hash)?;
+        let trie_state = state.as_trie_backend();
+        let version = CallExecutor::runtime_version(&self.executor, at_hash)?;
+        let runtime_code = RuntimeCodeCache::new(trie_state, version).map_err(sp_blockchain::Error::RuntimeCode)?;
+        let base_view = self.base_view(HashMap::new(), trie_state, *trie_state.root());
+
+        let _span = tracing::debug_span!(target: LOG_TARGET, "read_only", ?at_hash, num_calls = calls.len()).entered();
+        Ok(self.thread_pool.install(|| {
+            calls
+                .par_iter()
+                .enumerate()
+                .map(|(call_idx, call)| {
+                    let exec = self.instance_pool.executor(rayon::current_thread_index().unwrap_or(0));
+                    let result = read_only::execute_read_only::<HashingFor<Block>, _, _>(
+                        exec,
+                        &runtime_code.runtime_code(),
+                        call,
+                        call_idx as TxnIndex,
+                        &base_view,
+                    );
+                    match result {
+                        Err(ExtrinsicError::Unsupported(operation)) => {
+                            tracing::debug!(target: LOG_TARGET, method = %call.method, operation, "Call not supported by the workers, executing it with the local executor");
+                            counters::record_fallback(FallbackReason::Unsupported);
+                            self.executor.call(at_hash, &call.method, &call.call_data, CallContext::Offchain)
+                        }
+                        result => result.map_err(execution_error),
+                    }
+                })
+                .collect()
+        }))
+    }
+
+    /// Validates the transactions `extrinsics` of the pool, submitted from `source`, at once on
+    /// top of the state at `at_hash`, see [`revalidation`], and returns their validity, in order.
+    pub fn validate_transactions(
+        &self,
+        at_hash: Block::Hash,
+        source: TransactionSource,
+        extrinsics: &[Block::Extrinsic],
+    ) -> sp_blockchain::Result<Vec<sp_blockchain::Result<TransactionValidity>>> {
+        let version = CallExecutor::runtime_version(&self.executor, at_hash)?;
+        let api_version = version
+            .api_version(&tagged_transaction_queue_api_id())
+            .filter(|api_version| *api_version >= VALIDATE_TRANSACTION_MIN_API_VERSION)
+            .ok_or_else(|| {
+                sp_blockchain::Error::Application(
+                    format!("Runtime without {VALIDATE_TRANSACTION_METHOD} taking the transaction source").into(),
+                )
+            })?;
+        let calls: Vec<_> =
+            extrinsics.iter().map(|xt| validate_transaction_call(api_version, source, xt, at_hash)).collect();
+        Ok(self
+            .call_read_only(at_hash, &calls)?
+            .into_iter()
+            .map(|result| {
+                TransactionValidity::decode(&mut &result?[..])
+                    .map_err(|err| sp_blockchain::Error::CallResultDecode(VALIDATE_TRANSACTION_METHOD, err))
+            })
+            .collect())
+    }
+
+    /// Executes the extrinsics of `block` with Block-STM on top of `changes`, without applying
+    /// their changes yet. The keys read by every extrinsic are recorded in its output if
+    /// `record_reads` is set.
+    #[allow(clippy::too_many_arguments)]
+    fn execute_chunk(
+        &self,
+        at_hash: Block::Hash,
+        block: &[Extrinsic],
+        changes: &RefCell<OverlayedChanges<HashingFor<Block>>>,
+        recorder: &Option<ProofRecorder<Block>>,
+        call_context: CallContext,
+        maybe_deadline: Option<Instant>,
+        record_reads: bool,
+    ) -> sp_blockchain::Result<Result<(BlockOutput<ExtrinsicOutput>, BlockEvents), ExtrinsicError>> {
+        let state = self.backend.state_at(at_hash)?;
+        let trie_state = state.as_trie_backend();
+
+        let block_changes = base_changes(&changes.borrow()).collect();
+        // As in the `LocalCallExecutor`, the runtime code is not recorded in the proof. It is
+        // resolved once for all the workers.
+        let version = CallExecutor::runtime_version(&self.executor, at_hash)?;
+        let runtime_code = RuntimeCodeCache::new(trie_state, version).map_err(sp_blockchain::Error::RuntimeCode)?;
+        let mut args = ExtrinsicTaskArgs::new(&self.instance_pool, trie_state, &runtime_code, call_context)
+            .with_offchain_policy(self.offchain_policy)
+            .with_child_trie_policy(self.child_trie_policy);
+        // The timeout is nondeterministic, it is ignored outside of block authoring.
+        if let Some(timeout) = self.maybe_extrinsic_timeout.filter(|_| self.authoring) {
+            args = args.with_timeout(timeout);
+        }
+        if record_reads {
+            args = args.with_read_recording();
+        }
+        if self.audit_host_calls {
+            args = args.with_host_call_audit();
+        }
+        if let Some(extensions) = &self.maybe_worker_extensions {
+            args = args.with_worker_extensions(extensions);
+        }
+        if self.cache_signatures {
+            let cache = Arc::new(SignatureCache::new());
+            if let Some(extractor) = &self.maybe_signature_extractor {
+                let num_rejected = self.thread_pool.install(|| cache.verify_batch(block, extractor.as_ref()));
+                tracing::debug!(target: LOG_TARGET, num_rejected, "Verified the signatures of the batch");
+            }
+            args = args.with_signature_cache(cache);
+        }
+        tracing::debug!(
+            target: LOG_TARGET,
+            num_txns = block.len(),
+            spec_version = runtime_code.version().spec_version,
+            "Applying batch in parallel",
+        );
+
+        // The workers enter the span of the batch, which tells their traces apart from the ones of
+        // the other batches.
+        let _span = tracing::debug_span!(target: LOG_TARGET, "batch", ?at_hash, num_txns = block.len()).entered();
+        let storage_root = *trie_state.root();
+        Ok(match recorder {
+            Some(recorder) => {
+                let backend = proving_backend(trie_state, recorder);
+                let base_view = self.base_view(block_changes, &backend, storage_root);
+                let mut estimator = ProofRecorderEstimator::new(trie_state);
+                let maybe_proof_size_budget = self
+                    .remaining_proof_size(recorder)
+                    .map(|limit| ProofSizeBudget { estimator: &mut estimator, limit });
+                self.execute_batch(&args, block, &base_view, maybe_deadline, maybe_proof_size_budget)
+            }
+            None => {
+                let base_view = self.base_view(block_changes, trie_state, storage_root);
+                self.execute_batch(&args, block, &base_view, maybe_deadline, None)
+            }
+        })
+    }
+
+    /// Applies the extrinsics of `block` one after the other on top of `changes` with the
+    /// [`LocalCallExecutor`], until `maybe_deadline` is reached.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_extrinsics_sequential(
+        &self,
+        at_hash: Block::Hash,
+        block: &[Extrinsic],
+        changes: &RefCell<OverlayedChanges<HashingFor<Block>>>,
+        recorder: &Option<ProofRecorder<Block>>,
+        call_context: CallContext,
+        extensions: &RefCell<Extensions>,
+        maybe_deadline: Option<Instant>,
+    ) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+        let started = Instant::now();
+        let results = block
+            .iter()
+            .take_while(|_| maybe_deadline.map_or(true, |deadline| Instant::now() < deadline))
+            .map(|xt| {
+                if self.maybe_cancellation.as_ref().is_some_and(CancellationToken::is_cancelled) {
+                    return Err(cancelled_error());
+                }
+                let encoded = xt.try_encoded().map_err(|err| sp_blockchain::Error::Application(Box::new(err)))?;
+                let result = self.executor.contextual_call(
+                    at_hash,
+                    APPLY_EXTRINSIC_METHOD,
+                    encoded,
+                    changes,
+                    recorder,
+                    call_context,
+                    extensions,
+                )?;
+                decode_apply_result(&result)
+            })
+            .collect::<sp_blockchain::Result<Vec<_>>>()?;
+        let parallelism = Parallelism::sequential(results.len() as u32);
+        self.record_parallelism(at_hash, ParallelismRecord::new(parallelism, 1, 0, started.elapsed()));
+        Ok(results)
+    }
+
+    /// Adds the record of a batch applied on top of `at_hash` to the one of its block, and forgets
+    /// the one of the blocks built on top of another parent.
+    fn record_parallelism(&self, at_hash: Block::Hash, record: ParallelismRecord) {
+        let mut block_parallelism = self.block_parallelism.lock();
+        *block_parallelism = Some(match block_parallelism.take() {
+            Some((parent_hash, previous)) if parent_hash == at_hash => (at_hash, previous.chain(record)),
+            _ => (at_hash, record),
+        });
+    }
+
+    /// Takes the parallelism record of the batches applied on top of `parent_hash` since the last
+    /// call, i.e. of the block being built or imported on top of it, if it is the last parent a
+    /// batch was applied on top of.
+    pub fn take_parallelism(&self, parent_hash: Block::Hash) -> Option<ParallelismRecord> {
+        let mut block_parallelism = self.block_parallelism.lock();
+        match *block_parallelism {
+            Some((hash, _)) if hash == parent_hash => block_parallelism.take().map(|(_, parallelism)| parallelism),
+            _ => None,
+        }
+    }
+
+    /// Stores the parallelism `record` of the block of hash `block_hash` in the auxiliary storage
+    /// of the backend, e.g. once the block taken from [`Self::take_parallelism`] is imported.
+    pub fn store_parallelism(&self, block_hash: Block::Hash, record: ParallelismRecord) -> sp_blockchain::Result<()> {
+        let key = parallelism_aux_key(block_hash.as_ref());
+        self.backend.insert_aux(&[(&key[..], &record.encode()[..])], &[])
+    }
+
+    /// Reads the parallelism record of the block of hash `block_hash` from the auxiliary storage
+    /// of the backend, if it was stored.
+    pub fn parallelism(&self, block_hash: Block::Hash) -> sp_blockchain::Result<Option<ParallelismRecord>> {
+        let Some(encoded) = self.backend.get_aux(&parallelism_aux_key(block_hash.as_ref()))? else {
+            return Ok(None);
+        };
+        ParallelismRecord::decode_aux(&encoded)
+            .map(Some)
+            .map_err(|err| sp_blockchain::Error::Backend(format!("Invalid parallelism of block {block_hash:?}: {err}")))
+    }
+
+    /// Reads the parallelism records of the canonical blocks of `numbers` from the auxiliary
+    /// storage of the backend, in order, skipping the blocks without one, e.g. to chart them.
+    pub fn parallelism_history(
+        &self,
+        numbers: RangeInclusive<NumberFor<Block>>,
+    ) -> sp_blockchain::Result<Vec<(NumberFor<Block>, Block::Hash, ParallelismRecord)>> {
+        let mut history = Vec::new();
+        let mut number = *numbers.start();
+        while number <= *numbers.end() {
+            let Some(block_hash) = self.backend.blockchain().hash(number)? else {
+                break;
+            };
+            if let Some(record) = self.parallelism(block_hash)? {
+                history.push((number, block_hash, record));
+            }
+            number += One::one();
+        }
+        Ok(history)
+    }
+
+    /// Audits the determinism of the parallel application of `extrinsics` on top of the state at
+    /// `at_hash`, see [`determinism`]: the block is applied sequentially with the
+    /// [`LocalCallExecutor`], then in parallel with each of `concurrency_levels` workers, e.g.
+    /// [`AUDIT_CONCURRENCY_LEVELS`](determinism::AUDIT_CONCURRENCY_LEVELS), and every parallel run
+    /// is diffed against the sequential one.
+    ///
+    /// The parallel runs are applied with the settings of the executor, on threads of their own.
+    /// The parallelism of the block being built at `at_hash`, if any, is left untouched.
+    pub fn audit_determinism(
+        &self,
+        at_hash: Block::Hash,
+        extrinsics: &[Block::Extrinsic],
+        concurrency_levels: &[usize],
+        state_version: StateVersion,
+    ) -> sp_blockchain::Result<DeterminismReport<Block::Hash>>
+    where
+        B::State: Sync,
+    {
+        let block: Vec<_> = extrinsics.iter().map(|xt| Extrinsic::new(xt.encode())).collect();
+        let sequential = self.audit_run(at_hash, &block, 1, state_version)?;
+        let parallel = concurrency_levels
+            .iter()
+            .map(|concurrency_level| self.audit_run(at_hash, &block, *concurrency_level, state_version))
+            .collect::<sp_blockchain::Result<Vec<_>>>()?;
+        let report = DeterminismReport::new(sequential, parallel);
+        for (concurrency_level, divergence) in &report.divergences {
+            tracing::warn!(target: LOG_TARGET, concurrency_level, %divergence, "Parallel application diverged");
+        }
+        Ok(report)
+    }
+
+    /// Applies `block` on top of the state at `at_hash` with `concurrency_level` workers, or
+    /// sequentially with the [`LocalCallExecutor`] if 1, and returns its outcome.
+    fn audit_run(
+        &self,
+        at_hash: Block::Hash,
+        block: &[Extrinsic],
+        concurrency_level: usize,
+        state_version: StateVersion,
+    ) -> sp_blockchain::Result<AuditRun<Block::Hash>>
+    where
+        B::State: Sync,
+    {
+        let mut executor = self.detached();
+        executor.concurrency_level = concurrency_level;
+
+        let changes = RefCell::new(OverlayedChanges::default());
+        let extensions = RefCell::new(Extensions::default());
+        let results = if concurrency_level > 1 {
+            let thread_pool =
+                thread_pool::new_thread_pool(concurrency_level, CoreAffinity::Any).map_err(thread_pool_error)?;
+            executor.thread_pool = Arc::new(thread_pool);
+            executor.apply_encoded_extrinsics_parallel(
+                at_hash,
+                block,
+                &changes,
+                &None,
+                CallContext::Offchain,
+                &extensions,
+                None,
+            )?
+        } else {
+            executor.apply_extrinsics_sequential(
+                at_hash,
+                block,
+                &changes,
+                &None,
+                CallContext::Offchain,
+                &extensions,
+                None,
+            )?
+        };
+
+        let changes = changes.into_inner();
+        let (storage_root, _) = executor.storage_root(at_hash, &changes, state_version)?;
+        let changes: BTreeMap<_, _> =
+            changes.changes().map(|(key, value)| (key.clone(), value.value().cloned())).collect();
+        Ok(AuditRun { concurrency_level, results, events: determinism::events(&changes), changes, storage_root })
+    }
+
+    /// Re-executes the block of header `header` and body `extrinsics` on top of the state of its
+    /// parent, sequentially then in parallel, see [`bench`], and reports the time spent by both.
+    pub fn benchmark_block(
+        &self,
+        header: &Block::Header,
+        extrinsics: &[Block::Extrinsic],
+    ) -> sp_blockchain::Result<BlockBenchmark> {
+        let executor = self.detached();
+        let parent_hash = *header.parent_hash();
+        let block: Vec<_> = extrinsics.iter().map(|xt| Extrinsic::new(xt.encode())).collect();
+        let extensions = RefCell::new(Extensions::default());
+        let apply_sequential = |block: &[Extrinsic], changes: &RefCell<OverlayedChanges<HashingFor<Block>>>| {
+            executor.apply_extrinsics_sequential(
+                parent_hash,
+                block,
+                changes,
+                &None,
+                CallContext::Offchain,
+                &extensions,
+                None,
+            )
+        };
+
+        let changes = executor.initialized_block(header, &extensions)?;
+        let started = Instant::now();
+        apply_sequential(&block, &changes)?;
+        let sequential = started.elapsed();
+
+        let changes = executor.initialized_block(header, &extensions)?;
+        let started = Instant::now();
+        let maybe_stats = match executor.execute_chunk(
+            parent_hash,
+            &block,
+            &changes,
+            &None,
+            CallContext::Offchain,
+            None,
+            false,
+        )? {
+            Ok((block_output, block_events)) => {
+                let BlockOutput { outputs, writes, skipped_txns, maybe_stats, .. } = block_output;
+                commit_outputs(&changes, outputs, writes, block_events)?;
+                if let Some(&txn_idx) = skipped_txns.first() {
+                    apply_sequential(&block[txn_idx as usize..], &changes)?;
+                }
+                maybe_stats
+            }
+            Err(ExtrinsicError::Unsupported(operation)) => {
+                tracing::debug!(target: LOG_TARGET, operation, "Block not supported in parallel, applying it sequentially");
+                apply_sequential(&block, &changes)?;
+                None
+            }
+            Err(err) => return Err(execution_error(err)),
+        };
+        let parallel = started.elapsed();
+
+        Ok(BlockBenchmark { num_extrinsics: block.len(), sequential, parallel, maybe_stats })
+    }
+
+    /// Returns the changes of the block of header `header` once initialized on top of its parent.
+    fn initialized_block(
+        &self,
+        header: &Block::Header,
+        extensions: &RefCell<Extensions>,
+    ) -> sp_blockchain::Result<RefCell<OverlayedChanges<HashingFor<Block>>>> {
+        let changes = RefCell::new(OverlayedChanges::default());
+        self.executor.contextual_call(
+            *header.parent_hash(),
+            INITIALIZE_BLOCK_METHOD,
+            &header.encode(),
+            &changes,
+            &None,
+            CallContext::Offchain,
+            extensions,
+        )?;
+        Ok(changes)
+    }
+
+    /// Clone of the executor whose batches are not part of the block being built, e.g. to apply
+    /// blocks for an audit or a benchmark.
+    fn detached(&self) -> Self {
+        let mut executor = self.clone();
+        executor.block_parallelism = Arc::default();
+        executor.commit_subscribers = Arc::default();
+        executor.authoring = false;
+        executor
+    }
+
+    /// Clone of the executor whose batches are part of a block built by the node, to which the
+    /// authoring limits apply, e.g. [`with_block_weight_limit`](Self::with_block_weight_limit).
+    fn authoring(&self) -> Self {
+        let mut executor = self.clone();
+        executor.authoring = true;
+        executor
+    }
+
+    /// Weight the extrinsics applied on top of the weight of the block, read with `read`, may
+    /// consume, if limited.
+    fn remaining_block_weight(&self, read: impl FnOnce(&StorageKey) -> Option<StorageValue>) -> Option<Weight> {
+        let limit = self.maybe_block_weight_limit.filter(|_| self.authoring)?;
+        Some(limit.saturating_sub(block_weight(read(&BLOCK_WEIGHT).as_deref())))
+    }
+
+    /// Size the extrinsics applied on top of the storage proof recorded by `recorder` may add to
+    /// it, if limited.
+    fn remaining_proof_size(&self, recorder: &ProofRecorder<Block>) -> Option<usize> {
+        let limit = self.maybe_proof_size_limit.filter(|_| self.authoring)?;
+        Some(limit.saturating_sub(recorder.estimate_encoded_size()))
+    }
+
+    /// Creates the view of `changes` on top of `backend`, whose storage root is `storage_root`,
+    /// recording its reads in the backend cache, if any.
+    fn base_view<'a, S>(
+        &'a self,
+        changes: HashMap<StorageKey, Option<StorageValue>>,
+        backend: &'a S,
+        storage_root: Block::Hash,
+    ) -> BackendView<'a, HashingFor<Block>, S>
+    where
+        S: StateBackend<HashingFor<Block>> + Sync,
+    {
+        let base_view = BackendView::new(changes, backend);
+        match &self.maybe_backend_cache {
+            Some(backend_cache) => base_view.with_backend_cache(backend_cache, storage_root),
+            None => base_view,
+        }
+    }
+
+    /// Executes the batch within `maybe_proof_size_budget`, returning its output along with the
+    /// events and digest of the block the ones of the batch are appended to.
+    fn execute_batch<R, S>(
+        &self,
+        args: &ExtrinsicTaskArgs<'_, E, HashingFor<Block>, R>,
+        block: &[Extrinsic],
+        base_view: &BackendView<'_, HashingFor<Block>, S>,
+        maybe_deadline: Option<Instant>,
+        maybe_proof_size_budget: Option<ProofSizeBudget<'_, StorageKey>>,
+    ) -> Result<(BlockOutput<ExtrinsicOutput>, BlockEvents), ExtrinsicError>
+    where
+        R: StateBackend<HashingFor<Block>> + Sync,
+        S: StateBackend<HashingFor<Block>> + Sync,
+    {
+        // Read through the base view, as the extrinsics do.
+        let maybe_weight_limit = self.remaining_block_weight(|key| (*base_view.get_state_value(key)).clone());
+        let mut executor = BlockExecutor::<_, ExtrinsicTask<'_, E, HashingFor<Block>, R>, _>::new(
+            self.concurrency_level,
+            maybe_weight_limit,
+        )
+        .with_scheduler_policy(self.scheduler_policy)
+        .with_thread_pool(self.thread_pool.clone());
+        if let Some(oracle) = &self.conflict_oracle {
+            executor = executor.with_conflict_oracle(oracle.clone());
+        }
+        if let Some(window) = self.maybe_speculation_window {
+            executor = executor.with_speculation_window(window);
+        }
+        if let Some(deadline) = maybe_deadline {
+            executor = executor.with_deadline(deadline);
+        }
+        if let Some(token) = &self.maybe_cancellation {
+            executor = executor.with_cancellation(token.clone());
+        }
+        if self.prefetch_base_values {
+            executor = executor.with_base_value_prefetch();
+        }
+        if self.snapshot_first_incarnations {
+            executor = executor.with_snapshot_first_incarnations();
+        }
+        if !self.commit_subscribers.is_empty() {
+            executor = executor.with_commit_observer(Arc::new(CommitSender::<HashingFor<Block>>::new(
+                self.commit_subscribers.clone(),
+            )));
+        }
+        let block_output = executor.execute_block(args, block, base_view, maybe_proof_size_budget)?;
+
+        // Read through the base view, so that the events of the block are in the storage proof.
+        let block_events = BlockEvents::new(block_output.outputs.iter().map(|output| &output.events), |key| {
+            (*base_view.get_state_value(key)).clone()
+        });
+        Ok((block_output, block_events))
+    }
+}
+
+fn thread_pool_error(err: ThreadPoolBuildError) -> sp_blockchain::Error {
+    sp_blockchain::Error::Backend(format!("Failed to spawn the worker threads: {err}"))
+}
+
+/// Reads the number of extrinsics of the batch, which are delimited as the workers apply them.
+fn decode_batch<Block: BlockT>(call_data: &[u8]) -> sp_blockchain::Result<Vec<Extrinsic>> {
+    let batch = LazyBatch::new::<Block::Extrinsic>(call_data.to_vec())
+        .map_err(|err| sp_blockchain::Error::Application(Box::new(err)))?;
+    Ok(Extrinsic::lazy_batch(batch))
+}
+
+/// Changes of the top trie in `changes`, along with the locks of the child tries changed, on top
+/// of which the extrinsics of a batch are applied.
+fn base_changes<H: Hasher>(
+    changes: &OverlayedChanges<H>,
+) -> impl Iterator<Item = (StorageKey, Option<StorageValue>)> + '_ {
+    let top_changes = changes.changes().map(|(key, value)| (key.clone(), value.value().cloned()));
+    top_changes.chain(child_tries::base_locks(changes))
+}
+
+/// Applies the `outputs` of the extrinsics of a batch, whose final values are `writes`, to
+/// `changes`, along with the events of the block, and returns the results of the extrinsics.
+fn commit_outputs<H: Hasher>(
+    changes: &RefCell<OverlayedChanges<H>>,
+    outputs: Vec<ExtrinsicOutput>,
+    writes: HashMap<StorageKey, Arc<Option<StorageValue>>>,
+    mut block_events: BlockEvents,
+) -> sp_blockchain::Result<Vec<ApplyExtrinsicResult>> {
+    let mut changes = changes.borrow_mut();
+    let results = outputs
+        .into_iter()
+        .map(|output| {
+            block_events.append(&output.events);
+            // The offchain writes are not versioned, they are replayed in the order of the
+            // extrinsics.
+            for (key, value) in &output.offchain_writes {
+                changes.set_offchain_storage(key, value.as_deref());
+            }
+            decode_apply_result(&output.result)
+        })
+        .collect::<sp_blockchain::Result<Vec<_>>>()?;
+
+    for (key, value) in writes {
+        // The lock of a child trie holds the last changes made to it, see `child_tries`.
+        if let Some((child_info, child_changes)) = child_tries::child_changes(&key, value.as_deref()) {
+            for (child_key, child_value) in child_changes {
+                changes.set_child_storage(&child_info, child_key, child_value);
+            }
+            continue;
+        }
+        changes.set_storage(key, Arc::try_unwrap(value).unwrap_or_else(|value| (*value).clone()));
+    }
+
+    for (key, value) in block_events.into_writes() {
+        changes.set_storage(key, value);
+    }
+    Ok(results)
+}
+
+fn execution_error(err: ExtrinsicError) -> sp_blockchain::Error {
+    match err {
+        ExtrinsicError::Unsupported(operation) => {
+            sp_blockchain::Error::Application(format!("Operation not supported in parallel: {operation}").into())
+        }
+        ExtrinsicError::Runtime(err) => sp_blockchain::Error::Execution(Box::new(err)),
+        ExtrinsicError::Cancelled => cancelled_error(),
+        ExtrinsicError::Panic(panic) => {
+            tracing::error!(target: LOG_TARGET, %panic, "Parallel application of the batch panicked");
+            sp_blockchain::Error::Execution(Box::new(panic.to_string()))
+        }
+    }
+}
+
+fn cancelled_error() -> sp_blockchain::Error {
+    sp_blockchain::Error::Execution(Box::new("Application of the batch cancelled".to_owned()))
+}
+
+fn sequential_only_error() -> sp_blockchain::Error {
+    sp_blockchain::Error::Application("Extrinsic not supported in parallel".into())
+}
+
+fn decode_apply_result(result: &[u8]) -> sp_blockchain::Result<ApplyExtrinsicResult> {
+    ApplyExtrinsicResult::decode(&mut &result[..])
+        .map_err(|err| sp_blockchain::Error::CallResultDecode(APPLY_EXTRINSIC_METHOD, err))
+}
+
 impl<B, E, Block> CallExecutor<Block> for ParallelLocalCallExecutor<Block, B, E>
 where
     B: backend::Backend<Block>,
@@ -41,7 +1753,7 @@ where
     type Backend = B;
 
     fn execution_extensions(&self) -> &ExecutionExtensions<Block> {
-        &self.executor.execution_extensions()
+        self.executor.execution_extensions()
     }
 
     fn call(
@@ -64,6 +1776,30 @@ where
         call_context: CallContext,
         extensions: &RefCell<Extensions>,
     ) -> Result<Vec<u8>, sp_blockchain::Error> {
+        if method == BATCH_APPLY_EXTRINSIC_METHOD {
+            let block = decode_batch::<Block>(call_data)?;
+            return self
+                .apply_encoded_extrinsics_parallel(at_hash, &block, changes, recorder, call_context, extensions, None)
+                .map(|results| results.encode());
+        }
+        if method == BATCH_APPLY_EXTRINSIC_BY_ID_METHOD {
+            let batch_id =
+                BatchId::decode(&mut &call_data[..]).map_err(|err| sp_blockchain::Error::Application(Box::new(err)))?;
+            let block = self.host_batches.take(batch_id).ok_or_else(|| {
+                sp_blockchain::Error::Application(format!("Batch {batch_id} is not registered").into())
+            })?;
+            return self
+                .apply_encoded_extrinsics_parallel(at_hash, &block, changes, recorder, call_context, extensions, None)
+                .map(|results| results.encode());
+        }
+
+        if method == APPLY_EXTRINSIC_WITH_ACCESS_REPORT_METHOD {
+            let xt = Extrinsic::new(call_data.to_vec());
+            return self
+                .apply_extrinsic_with_access_report(at_hash, &xt, changes, recorder, call_context)
+                .map(|report| report.encode());
+        }
+
         self.executor.contextual_call(at_hash, method, call_data, changes, recorder, call_context, extensions)
     }
 
@@ -77,6 +1813,25 @@ where
         method: &str,
         call_data: &[u8],
     ) -> sp_blockchain::Result<(Vec<u8>, StorageProof)> {
+        if method == BATCH_APPLY_EXTRINSIC_METHOD {
+            let block = decode_batch::<Block>(call_data)?;
+            let at_number = self.backend.blockchain().expect_block_number_from_id(&BlockId::Hash(at_hash))?;
+            let extensions = RefCell::new(self.execution_extensions().extensions(at_hash, at_number));
+
+            // The batch is applied on top of the state at `at_hash`, as a single runtime call would be.
+            let recorder = ProofRecorder::<Block>::default();
+            let results = self.apply_encoded_extrinsics_parallel(
+                at_hash,
+                &block,
+                &RefCell::default(),
+                &Some(recorder.clone()),
+                CallContext::Offchain,
+                &extensions,
+                None,
+            )?;
+            return Ok((results.encode(), recorder.drain_storage_proof()));
+        }
+
         self.executor.prove_execution(at_hash, method, call_data)
     }
 }
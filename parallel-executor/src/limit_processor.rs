@@ -0,0 +1,148 @@
+//! Accounting of the resources consumed by the transactions of a block against the block limits.
+
+use codec::Decode;
+use once_cell::sync::Lazy;
+use sp_core::Hasher;
+use sp_state_machine::{Backend, StorageKey, TrieBackend, TrieBackendStorage};
+use sp_trie::recorder::Recorder;
+use sp_weights::Weight;
+
+use crate::events::system_storage_key;
+use crate::state_machine::proving_backend;
+use crate::LOG_TARGET;
+
+/// Key of `System::BlockWeight`, the weight consumed by the block so far by dispatch class.
+pub(crate) static BLOCK_WEIGHT: Lazy<StorageKey> = Lazy::new(|| system_storage_key(b"BlockWeight"));
+
+/// Weight consumed by the block for all the dispatch classes, as accounted by the runtime in the
+/// `encoded` value of `System::BlockWeight`. Zero if the runtime did not account any weight yet.
+pub(crate) fn block_weight(encoded: Option<&[u8]>) -> Weight {
+    let Some(mut encoded) = encoded else {
+        return Weight::zero();
+    };
+    match <(Weight, Weight, Weight)>::decode(&mut encoded) {
+        Ok((normal, operational, mandatory)) => normal.saturating_add(operational).saturating_add(mandatory),
+        Err(err) => {
+            tracing::debug!(target: LOG_TARGET, ?err, "Invalid block weight");
+            Weight::zero()
+        }
+    }
+}
+
+/// Estimates how much the storage proof of the block grows as transactions are applied, for
+/// parachains whose blocks are bounded by the size of their proof of validity (PoV) rather than
+/// by their weight only.
+pub trait ProofSizeEstimator<K>: Send {
+    /// Records the trie nodes proving the access to `key` in the base state, and returns the
+    /// encoded size of the nodes that were not part of the proof yet.
+    fn record_access(&mut self, key: &K) -> usize;
+}
+
+/// Estimates the size added to the storage proof by recording the trie nodes proving the keys
+/// accessed in the state of the block, in a recorder of its own. The nodes already in the proof of
+/// the block, e.g. recorded by the previous batches, are counted again: the estimate is an upper
+/// bound.
+pub struct ProofRecorderEstimator<H: Hasher, B> {
+    // State of the block, whose nodes read are recorded in `recorder`.
+    backend: B,
+    recorder: Recorder<H>,
+}
+
+impl<'a, S, H, C> ProofRecorderEstimator<H, TrieBackend<&'a S, H, &'a C>>
+where
+    S: TrieBackendStorage<H>,
+    H: Hasher,
+{
+    /// Creates the estimator of the proof of the accesses to `state`.
+    pub fn new(state: &'a TrieBackend<S, H, C>) -> Self {
+        let recorder = Recorder::default();
+        Self { backend: proving_backend(state, &recorder), recorder }
+    }
+}
+
+impl<H: Hasher, B: Backend<H> + Send> ProofSizeEstimator<StorageKey> for ProofRecorderEstimator<H, B> {
+    fn record_access(&mut self, key: &StorageKey) -> usize {
+        let recorded = self.recorder.estimate_encoded_size();
+        // Reading the key records the nodes proving its value, or its absence.
+        if let Err(err) = self.backend.storage(key) {
+            tracing::debug!(target: LOG_TARGET, ?err, "Failed to record the proof of a key");
+        }
+        self.recorder.estimate_encoded_size().saturating_sub(recorded)
+    }
+}
+
+/// Size that the transactions of a block may add to the storage proof.
+pub struct ProofSizeBudget<'a, K> {
+    /// Estimator of the size added by every applied transaction.
+    pub estimator: &'a mut dyn ProofSizeEstimator<K>,
+    /// Maximum size, in bytes, that the transactions may add to the proof.
+    pub limit: usize,
+}
+
+/// Accounts the resources consumed by the transactions applied to the block, in order, and tells
+/// when the next transaction no longer fits in the block.
+pub(crate) struct BlockLimitProcessor<'a, K> {
+    maybe_weight_limit: Option<Weight>,
+    maybe_proof_size_budget: Option<ProofSizeBudget<'a, K>>,
+    consumed_weight: Weight,
+    proof_size: usize,
+}
+
+impl<'a, K> BlockLimitProcessor<'a, K> {
+    pub(crate) fn new(
+        maybe_weight_limit: Option<Weight>,
+        maybe_proof_size_budget: Option<ProofSizeBudget<'a, K>>,
+    ) -> Self {
+        Self { maybe_weight_limit, maybe_proof_size_budget, consumed_weight: Weight::zero(), proof_size: 0 }
+    }
+
+    /// Accounts the next transaction of the block, which consumed `weight` and accessed `keys`.
+    ///
+    /// Returns `false` if the transaction does not fit in the block, in which case the consumed
+    /// resources are left untouched and the block must end before the transaction.
+    pub(crate) fn try_accrue<'k>(&mut self, weight: Weight, keys: impl IntoIterator<Item = &'k K>) -> bool
+    where
+        K: 'k,
+    {
+        let consumed_weight = match self.maybe_weight_limit {
+            Some(limit) => match self.consumed_weight.try_add(&weight, &limit) {
+                Some(consumed_weight) => consumed_weight,
+                None => return false,
+            },
+            None => self.consumed_weight.saturating_add(weight),
+        };
+
+        let proof_size = match &mut self.maybe_proof_size_budget {
+            Some(budget) => {
+                let proof_size = keys
+                    .into_iter()
+                    .fold(self.proof_size, |size, key| size.saturating_add(budget.estimator.record_access(key)));
+                if proof_size > budget.limit {
+                    return false;
+                }
+                proof_size
+            }
+            None => self.proof_size,
+        };
+
+        self.consumed_weight = consumed_weight;
+        self.proof_size = proof_size;
+        true
+    }
+
+    /// Weight consumed by the accounted transactions.
+    pub(crate) fn consumed_weight(&self) -> Weight {
+        self.consumed_weight
+    }
+
+    /// Estimated size added to the storage proof by the accounted transactions, or 0 if the proof
+    /// size is not tracked.
+    pub(crate) fn proof_size(&self) -> usize {
+        self.proof_size
+    }
+
+    /// Gives the proof size budget back, e.g. to execute the block again from scratch.
+    pub(crate) fn into_proof_size_budget(self) -> Option<ProofSizeBudget<'a, K>> {
+        self.maybe_proof_size_budget
+    }
+}
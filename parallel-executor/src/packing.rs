@@ -0,0 +1,144 @@
+//! Packing of the transactions of a block, before it is built.
+//!
+//! Block-STM executes the transactions of a block optimistically, and two conflicting transactions
+//! executed at the same time likely abort the higher one. The [`BlockPacker`] reorders the
+//! transactions with the keys predicted by a [`ConflictOracle`], so that conflicting transactions
+//! are spread apart and executed in different waves. The transactions of a sender keep their
+//! relative order, e.g. their nonce order.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::hash::Hash;
+
+use crate::conflict_oracle::ConflictOracle;
+use crate::task::Transaction;
+
+/// Reorders the transactions of a block to maximize parallelism.
+pub struct BlockPacker<'a, T: Transaction> {
+    oracle: &'a dyn ConflictOracle<T>,
+    window: usize,
+}
+
+impl<'a, T: Transaction> BlockPacker<'a, T> {
+    /// Creates a packer predicting the conflicts with `oracle`, which keeps apart conflicting
+    /// transactions up to `window` positions, e.g. the number of workers executing the block.
+    pub fn new(oracle: &'a dyn ConflictOracle<T>, window: usize) -> Self {
+        Self { oracle, window: window.max(1) }
+    }
+
+    /// Returns the indices of the transactions of `block` in the order they should be included in
+    /// the block, given their `priorities`.
+    ///
+    /// The next transaction is the one with the highest priority among the first transaction of
+    /// every sender that does not conflict with the `window` previous ones. It is only picked
+    /// among the `window` candidates with the highest priority, so that a transaction is not
+    /// postponed indefinitely, and if all of them conflict, the one with the highest priority is.
+    pub fn pack(&self, block: &[T], priorities: &[u64]) -> Vec<usize> {
+        let predictions: Vec<_> = block
+            .iter()
+            .map(|txn| {
+                let (reads, writes) = self.oracle.predict(txn);
+                (reads.into_keys(), writes.into_keys())
+            })
+            .collect();
+
+        // The transactions of every sender in their original order, transactions without sender
+        // being queued apart.
+        let mut queues: Vec<VecDeque<usize>> = Vec::new();
+        let mut queue_of_senders = HashMap::new();
+        for (txn_idx, txn) in block.iter().enumerate() {
+            match self.oracle.sender(txn) {
+                Some(sender) => {
+                    let queue_idx = *queue_of_senders.entry(sender).or_insert_with(|| {
+                        queues.push(VecDeque::new());
+                        queues.len() - 1
+                    });
+                    queues[queue_idx].push_back(txn_idx);
+                }
+                None => queues.push(VecDeque::from([txn_idx])),
+            }
+        }
+
+        let candidate = |txn_idx: usize, queue_idx: usize| {
+            (Reverse(priorities.get(txn_idx).copied().unwrap_or_default()), txn_idx, queue_idx)
+        };
+        let mut candidates: BTreeSet<_> = queues
+            .iter()
+            .enumerate()
+            .filter_map(|(queue_idx, queue)| queue.front().map(|&txn_idx| candidate(txn_idx, queue_idx)))
+            .collect();
+
+        let mut order = Vec::with_capacity(block.len());
+        let mut window = Window::default();
+        while let Some(&first) = candidates.first() {
+            let next = candidates
+                .iter()
+                .take(self.window)
+                .find(|(_, txn_idx, _)| !window.conflicts(&predictions[*txn_idx]))
+                .copied()
+                .unwrap_or(first);
+            candidates.remove(&next);
+
+            let (_, txn_idx, queue_idx) = next;
+            order.push(txn_idx);
+            window.push(txn_idx, &predictions[txn_idx]);
+            if window.placed.len() > self.window {
+                if let Some(oldest) = window.placed.front().copied() {
+                    window.pop(&predictions[oldest]);
+                }
+            }
+
+            queues[queue_idx].pop_front();
+            if let Some(&txn_idx) = queues[queue_idx].front() {
+                candidates.insert(candidate(txn_idx, queue_idx));
+            }
+        }
+        order
+    }
+}
+
+/// Keys predicted to be accessed by the last placed transactions.
+struct Window<'p, K> {
+    placed: VecDeque<usize>,
+    reads: HashMap<&'p K, usize>,
+    writes: HashMap<&'p K, usize>,
+}
+
+impl<K> Default for Window<'_, K> {
+    fn default() -> Self {
+        Self { placed: VecDeque::new(), reads: HashMap::new(), writes: HashMap::new() }
+    }
+}
+
+impl<'p, K: Eq + Hash> Window<'p, K> {
+    /// Whether a transaction predicted to access `(reads, writes)` conflicts with the window.
+    fn conflicts(&self, (reads, writes): &(Vec<K>, Vec<K>)) -> bool {
+        reads.iter().chain(writes).any(|key| self.writes.contains_key(key))
+            || writes.iter().any(|key| self.reads.contains_key(key))
+    }
+
+    fn push(&mut self, txn_idx: usize, (reads, writes): &'p (Vec<K>, Vec<K>)) {
+        self.placed.push_back(txn_idx);
+        for key in reads {
+            *self.reads.entry(key).or_default() += 1;
+        }
+        for key in writes {
+            *self.writes.entry(key).or_default() += 1;
+        }
+    }
+
+    /// Removes the oldest transaction, predicted to access `(reads, writes)`.
+    fn pop(&mut self, (reads, writes): &'p (Vec<K>, Vec<K>)) {
+        self.placed.pop_front();
+        for (counts, keys) in [(&mut self.reads, reads), (&mut self.writes, writes)] {
+            for key in keys {
+                if let Some(count) = counts.get_mut(key) {
+                    *count -= 1;
+                    if *count == 0 {
+                        counts.remove(key);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Hints of the runtime about the parallel application of its extrinsics.
+//!
+//! The runtime knows its pallets better than the node: it declares them through a `ParallelConfig`
+//! runtime API, next to its `BlockBuilder` API, whose `parallel_config` method returns the SCALE
+//! encoded [`ParallelConfig`]. The node reads the config at every block it builds on, so that the
+//! hints follow the upgrades of the runtime. The version of the API in the runtime version tells
+//! the encoding of the config: the node ignores the configs of the versions it does not know.
+//!
+//! The runtime declares the API with `sp_api::decl_runtime_apis!`:
+//!
+//! ```ignore
+//! pub trait ParallelConfig {
+//!     fn parallel_config() -> Vec<u8>;
+//! }
+//! ```
+
+use codec::{Decode, Encode};
+use sp_core::hashing::blake2_64;
+use sp_state_machine::StorageKey;
+use sp_version::{ApiId, RuntimeVersion};
+
+use crate::access_hints::AccessHint;
+
+/// Runtime method returning the SCALE encoded [`ParallelConfig`] of the runtime.
+pub const PARALLEL_CONFIG_METHOD: &str = "ParallelConfig_parallel_config";
+
+/// Version of the `ParallelConfig` runtime API whose config the node decodes.
+pub const PARALLEL_CONFIG_API_VERSION: u32 = 1;
+
+/// Identifier of the `ParallelConfig` runtime API in the runtime version, derived from its name as
+/// `sp_api` does.
+pub fn parallel_config_api_id() -> ApiId {
+    blake2_64(b"ParallelConfig")
+}
+
+/// Hints of the runtime about the parallel application of its extrinsics.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct ParallelConfig {
+    /// Keys whose writes commute, e.g. the total issuance or a counter of events, so that the
+    /// extrinsics only incrementing them do not conflict.
+    pub commutative_keys: Vec<StorageKey>,
+    /// Indices of the pallets whose calls are not supported in parallel, e.g. because they access
+    /// the state in ways the workers do not track. Their extrinsics are applied sequentially.
+    pub unsafe_pallets: Vec<u8>,
+    /// Keys accessed by the calls, by pallet and call indices, whatever their arguments.
+    pub call_hints: Vec<((u8, u8), AccessHint<StorageKey>)>,
+}
+
+impl ParallelConfig {
+    /// Decodes the config returned by the runtime of `version`, if the runtime declares a version
+    /// of the API the node knows.
+    pub fn decode_for(version: &RuntimeVersion, encoded: &[u8]) -> Result<Option<Self>, codec::Error> {
+        match version.api_version(&parallel_config_api_id()) {
+            Some(PARALLEL_CONFIG_API_VERSION) => Ok(Some(Self::decode(&mut &encoded[..])?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether the calls of the pallet `pallet_index` must be applied sequentially.
+    pub fn is_unsafe_pallet(&self, pallet_index: u8) -> bool {
+        self.unsafe_pallets.contains(&pallet_index)
+    }
+}
@@ -0,0 +1,124 @@
+//! Degree of parallelism of the blocks executed with Block-STM.
+//!
+//! Once a block is committed, the final incarnation of every transaction read some of its values
+//! from lower transactions of the block. These reads form the dependency graph the execution
+//! actually realized: its longest chain, the critical path, bounds the speedup that any number of
+//! workers can achieve on the block, whatever the scheduling.
+//!
+//! Along with how its batches were applied, the parallelism of a block is stored as a compact
+//! [`ParallelismRecord`] in the auxiliary storage of the backend, so that operators chart the
+//! parallel performance of the chain over its history without external tooling.
+
+use std::time::Duration;
+
+use codec::{Decode, DecodeAll, Encode};
+
+use crate::scheduler::TxnIndex;
+
+/// Prefix of the keys of the auxiliary storage holding the parallelism records of the blocks,
+/// followed by their hash.
+pub const PARALLELISM_AUX_PREFIX: &[u8] = b"parallel_executor:parallelism:";
+
+/// Key of the auxiliary storage holding the parallelism record of the block of hash `block_hash`.
+pub fn parallelism_aux_key(block_hash: &[u8]) -> Vec<u8> {
+    [PARALLELISM_AUX_PREFIX, block_hash].concat()
+}
+
+/// Number of transactions of a block, and length of the longest chain of transactions reading a
+/// value written by the previous one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+pub struct Parallelism {
+    /// Number of transactions applied.
+    pub num_txns: u32,
+    /// Number of transactions of the critical path of the dependency graph.
+    pub critical_path: u32,
+}
+
+impl Parallelism {
+    /// Computes the parallelism of the transactions whose dependencies, i.e. the lower
+    /// transactions they read a value written by, are given in order.
+    pub fn from_dependencies<D: IntoIterator<Item = TxnIndex>>(dependencies: impl IntoIterator<Item = D>) -> Self {
+        // Number of transactions of the longest chain ending with every transaction.
+        let mut depths: Vec<u32> = Vec::new();
+        for txn_dependencies in dependencies {
+            let depth = txn_dependencies.into_iter().map(|dep_idx| depths[dep_idx as usize]).max().unwrap_or(0) + 1;
+            depths.push(depth);
+        }
+        Self { num_txns: depths.len() as u32, critical_path: depths.into_iter().max().unwrap_or(0) }
+    }
+
+    /// Parallelism of a sequence of transactions that depend on the previous one.
+    pub fn sequential(num_txns: u32) -> Self {
+        Self { num_txns, critical_path: num_txns }
+    }
+
+    /// Average number of transactions that can be executed at once, i.e. the speedup ceiling of
+    /// the block. 0 if the block is empty.
+    pub fn width(&self) -> f64 {
+        if self.critical_path == 0 {
+            return 0.0;
+        }
+        self.num_txns as f64 / self.critical_path as f64
+    }
+
+    /// Parallelism of the transactions of `self` followed by the ones of `next`, e.g. of two
+    /// batches of a block applied one after the other.
+    pub fn chain(self, next: Parallelism) -> Self {
+        Self { num_txns: self.num_txns + next.num_txns, critical_path: self.critical_path + next.critical_path }
+    }
+}
+
+/// Parallelism of a block, and how its batches were applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+pub struct ParallelismRecord {
+    /// Number of transactions applied, and critical path of the block.
+    pub parallelism: Parallelism,
+    /// Highest number of workers a batch of the block was applied with, 1 if all of them were
+    /// applied sequentially.
+    #[codec(compact)]
+    pub concurrency_level: u32,
+    /// Number of incarnations aborted and executed again.
+    #[codec(compact)]
+    pub num_aborts: u32,
+    /// Time spent applying the batches of the block, in microseconds.
+    #[codec(compact)]
+    pub wall_time_micros: u64,
+}
+
+impl ParallelismRecord {
+    /// Record of a batch of the given `parallelism`, applied with `concurrency_level` workers in
+    /// `wall_time`.
+    pub fn new(parallelism: Parallelism, concurrency_level: usize, num_aborts: u32, wall_time: Duration) -> Self {
+        Self {
+            parallelism,
+            concurrency_level: concurrency_level as u32,
+            num_aborts,
+            wall_time_micros: wall_time.as_micros() as u64,
+        }
+    }
+
+    /// Decodes a record read from the auxiliary storage, or the bare [`Parallelism`] stored
+    /// before the records, whose other fields are then zero.
+    pub fn decode_aux(encoded: &[u8]) -> Result<Self, codec::Error> {
+        Self::decode_all(&mut &encoded[..]).or_else(|err| {
+            let parallelism = Parallelism::decode_all(&mut &encoded[..]).map_err(|_| err)?;
+            Ok(Self { parallelism, ..Default::default() })
+        })
+    }
+
+    /// Speedup of the block over its sequential application the workers can achieve: the width
+    /// of its parallelism, bounded by their number.
+    pub fn estimated_speedup(&self) -> f64 {
+        self.parallelism.width().min(self.concurrency_level as f64)
+    }
+
+    /// Record of the batches of `self` followed by the ones of `next`.
+    pub fn chain(self, next: ParallelismRecord) -> Self {
+        Self {
+            parallelism: self.parallelism.chain(next.parallelism),
+            concurrency_level: self.concurrency_level.max(next.concurrency_level),
+            num_aborts: self.num_aborts + next.num_aborts,
+            wall_time_micros: self.wall_time_micros + next.wall_time_micros,
+        }
+    }
+}
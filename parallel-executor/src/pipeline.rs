@@ -0,0 +1,53 @@
+//! Computation of the storage changes of a finished block in the background.
+//!
+//! Hashing the trie nodes modified by a block to compute its storage root is sequential, and
+//! leaves the other cores idle. The proposer hands the overlay of a finished block over to a
+//! [`PendingStorageChanges`], and applies the batch of the next block in parallel meanwhile.
+
+use std::sync::mpsc;
+use std::thread;
+
+use codec::Encode;
+use sp_core::storage::StateVersion;
+use sp_core::Hasher;
+use sp_state_machine::{Backend as StateBackend, OverlayedChanges, StorageChanges};
+
+use crate::LOG_TARGET;
+
+/// Storage changes of a finished block, along with its storage root, being computed on a thread
+/// of their own.
+pub struct PendingStorageChanges<H: Hasher> {
+    receiver: mpsc::Receiver<Result<StorageChanges<H>, sp_state_machine::DefaultError>>,
+}
+
+impl<H> PendingStorageChanges<H>
+where
+    H: Hasher,
+    H::Out: Ord + Encode + 'static,
+{
+    /// Starts computing the storage changes of `changes` applied on top of `state`, the state of
+    /// the parent of the block.
+    pub fn spawn<S>(state: S, mut changes: OverlayedChanges<H>, state_version: StateVersion) -> std::io::Result<Self>
+    where
+        S: StateBackend<H> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        thread::Builder::new().name("storage-root".into()).spawn(move || {
+            let result = changes.drain_storage_changes(&state, state_version);
+            if let Ok(storage_changes) = &result {
+                tracing::debug!(target: LOG_TARGET, root = ?storage_changes.transaction_storage_root, "Storage root computed");
+            }
+            // The handle may have been dropped, in which case the changes are not needed anymore.
+            let _ = sender.send(result);
+        })?;
+        Ok(Self { receiver })
+    }
+
+    /// Blocks until the storage changes are computed, and returns them.
+    pub fn wait(self) -> sp_blockchain::Result<StorageChanges<H>> {
+        self.receiver
+            .recv()
+            .map_err(|_| sp_blockchain::Error::Backend("Storage root computation panicked".into()))?
+            .map_err(sp_blockchain::Error::StorageChanges)
+    }
+}
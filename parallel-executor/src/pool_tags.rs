@@ -0,0 +1,97 @@
+//! Dependencies between the extrinsics of a batch known from the tags of the transaction pool.
+//!
+//! Every transaction of the pool provides tags, e.g. its sender and nonce, and requires the tags
+//! provided by the transactions that must precede it, e.g. its sender and the previous nonce, or
+//! the phase of an election. The pool only includes a transaction in a block after the ones
+//! providing the tags it requires, whose writes it most likely reads. The [`PoolTagOracle`]
+//! reports the tags of the extrinsics registered by the proposer, see
+//! [`ConflictOracle::pool_tags`], so that these hard orderings are explicit dependencies of the
+//! scheduler rather than rediscovered through aborts.
+
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use sp_core::hashing::blake2_256;
+use sp_runtime::transaction_validity::{TransactionTag, ValidTransaction};
+use sp_state_machine::StorageKey;
+
+use crate::conflict_oracle::{ConflictOracle, KeySet};
+use crate::extrinsic::Extrinsic;
+
+/// Tags of a transaction in the pool.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolTags {
+    /// Tags the transaction provides to the ones that require them.
+    pub provides: Vec<TransactionTag>,
+    /// Tags the transaction requires from the ones preceding it.
+    pub requires: Vec<TransactionTag>,
+}
+
+impl From<&ValidTransaction> for PoolTags {
+    fn from(valid: &ValidTransaction) -> Self {
+        Self { provides: valid.provides.clone(), requires: valid.requires.clone() }
+    }
+}
+
+/// Reports the tags of the extrinsics registered by the proposer as it includes them in the block,
+/// and delegates the predictions of their keys to another oracle, if any.
+///
+/// The clones of the oracle share the registered tags: the proposer keeps one to register the
+/// tags, and gives another to the executor.
+#[derive(Clone, Default)]
+pub struct PoolTagOracle {
+    // Tags of the registered extrinsics, by hash of their encoding.
+    tags: Arc<DashMap<[u8; 32], PoolTags>>,
+    // Predicts the keys accessed by the extrinsics, if any.
+    maybe_oracle: Option<Arc<dyn ConflictOracle<Extrinsic>>>,
+}
+
+impl PoolTagOracle {
+    /// Creates an oracle that only reports the tags of the extrinsics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Predicts the keys accessed by the extrinsics, and their sender, with `oracle`.
+    pub fn with_oracle(mut self, oracle: impl ConflictOracle<Extrinsic> + 'static) -> Self {
+        self.maybe_oracle = Some(Arc::new(oracle));
+        self
+    }
+
+    /// Registers the `tags` of the SCALE encoded extrinsic `xt`.
+    pub fn register(&self, xt: &[u8], tags: PoolTags) {
+        self.tags.insert(blake2_256(xt), tags);
+    }
+
+    /// Forgets the tags of the SCALE encoded extrinsic `xt`, e.g. once its block is built.
+    pub fn forget(&self, xt: &[u8]) {
+        self.tags.remove(&blake2_256(xt));
+    }
+
+    /// Forgets the tags of every extrinsic.
+    pub fn clear(&self) {
+        self.tags.clear();
+    }
+}
+
+impl ConflictOracle<Extrinsic> for PoolTagOracle {
+    fn predict_reads(&self, txn: &Extrinsic) -> KeySet<StorageKey> {
+        self.maybe_oracle.as_ref().map(|oracle| oracle.predict_reads(txn)).unwrap_or_default()
+    }
+
+    fn predict_writes(&self, txn: &Extrinsic) -> KeySet<StorageKey> {
+        self.maybe_oracle.as_ref().map(|oracle| oracle.predict_writes(txn)).unwrap_or_default()
+    }
+
+    fn predict(&self, txn: &Extrinsic) -> (KeySet<StorageKey>, KeySet<StorageKey>) {
+        self.maybe_oracle.as_ref().map(|oracle| oracle.predict(txn)).unwrap_or_default()
+    }
+
+    fn sender(&self, txn: &Extrinsic) -> Option<Vec<u8>> {
+        self.maybe_oracle.as_ref().and_then(|oracle| oracle.sender(txn))
+    }
+
+    fn pool_tags(&self, txn: &Extrinsic) -> Option<PoolTags> {
+        self.tags.get(&blake2_256(txn.encoded())).map(|tags| tags.clone())
+    }
+}
@@ -0,0 +1,58 @@
+//! Cache of the base values read by the workers executing a block.
+//!
+//! The workers missing the multi-version data on a key read its value from the base state, i.e.
+//! from the trie backend. Several transactions often read the same keys at once, e.g. the account
+//! of a popular recipient: the first worker fetches the value while the others wait for it, so that
+//! every key is fetched from the database at most once per block. The absent keys are cached as
+//! well.
+
+use std::hash::Hash;
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use once_cell::sync::OnceCell;
+
+/// Read-through cache of the values of a base state, shared by the workers.
+#[derive(Debug)]
+pub struct SharedReadCache<K: Hash + Eq, V> {
+    values: DashMap<K, Arc<OnceCell<V>>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SharedReadCache<K, V> {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self { values: DashMap::new() }
+    }
+
+    /// Returns the value of `key`, fetched with `fetch` if it is not cached yet. A concurrent
+    /// read of the key waits for the value being fetched rather than fetching it again.
+    pub fn get_or_fetch(&self, key: &K, fetch: impl FnOnce() -> V) -> V {
+        // The shard of the key is not kept locked while the value is fetched.
+        let cell = match self.values.get(key) {
+            Some(cell) => cell.clone(),
+            None => self.values.entry(key.clone()).or_default().clone(),
+        };
+        cell.get_or_init(fetch).clone()
+    }
+
+    /// Caches the `value` of `key` fetched ahead of its reads, unless it is cached already.
+    pub fn provide(&self, key: K, value: V) {
+        let _ = self.values.entry(key).or_default().set(value);
+    }
+
+    /// Number of keys cached.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether no key is cached.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Default for SharedReadCache<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,67 @@
+//! Read-only runtime API calls served concurrently.
+//!
+//! The runtime API calls made by the RPC handlers, e.g. `state_call` or
+//! `TransactionPaymentApi::query_info`, only read the state of a block: whatever they write is
+//! discarded once they return. Calls at the same block share no writes by construction, so
+//! [`ParallelLocalCallExecutor::call_read_only`](crate::ParallelLocalCallExecutor::call_read_only)
+//! executes them at once on the workers, each with the runtime instance of its worker and on top
+//! of the same view of the state, whose reads are cached for all of them.
+//!
+//! A call is executed in isolation: it observes the state of the block and its own writes only.
+//! A call performing an operation not supported by the externalities of the workers, e.g. key
+//! iteration, is executed again with the `LocalCallExecutor`.
+
+use std::collections::HashMap;
+
+use codec::Encode;
+use sp_core::traits::{CallContext, CodeExecutor, RuntimeCode};
+use sp_core::Hasher;
+
+use crate::ext::Ext;
+use crate::extrinsic::{Extrinsic, ExtrinsicError};
+use crate::scheduler::TxnIndex;
+use crate::state_machine::StateMachine;
+use crate::view::{LatestView, StateView};
+
+/// A read-only runtime API call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadOnlyCall {
+    /// Runtime method, e.g. `TransactionPaymentApi_query_info`.
+    pub method: String,
+    /// SCALE encoded arguments of the method.
+    pub call_data: Vec<u8>,
+}
+
+impl ReadOnlyCall {
+    /// Call of `method` with the SCALE encoded `call_data`.
+    pub fn new(method: impl Into<String>, call_data: Vec<u8>) -> Self {
+        Self { method: method.into(), call_data }
+    }
+}
+
+/// Executes `call`, the `call_idx`-th of the calls served at once, with `exec` in the runtime of
+/// `runtime_code` on top of `base_view`. Returns the SCALE encoded result of the call.
+pub(crate) fn execute_read_only<H, S, Exec>(
+    exec: &Exec,
+    runtime_code: &RuntimeCode<'_>,
+    call: &ReadOnlyCall,
+    call_idx: TxnIndex,
+    base_view: &S,
+) -> Result<Vec<u8>, ExtrinsicError>
+where
+    H: Hasher,
+    H::Out: Encode,
+    S: StateView<Extrinsic>,
+    Exec: CodeExecutor,
+{
+    // No value is written before the call.
+    let no_writes = HashMap::new();
+    let view = LatestView::new_sequential(base_view, &no_writes, call_idx);
+    let mut ext = Ext::<H, S>::new(&view);
+    let result =
+        StateMachine::new(exec, &call.method, &call.call_data, runtime_code, CallContext::Offchain).execute(&mut ext);
+    if let Some(operation) = ext.unsupported() {
+        return Err(ExtrinsicError::Unsupported(operation));
+    }
+    result.map_err(|err| ExtrinsicError::Runtime(err.to_string()))
+}
@@ -0,0 +1,437 @@
+//! Execution of the batches sharded across several processes, e.g. on other machines.
+//!
+//! A single machine bounds the parallelism of a batch by its number of cores. For appchain
+//! sequencers producing large blocks, the [`RemoteExecutor`] partitions a batch into shards by
+//! sender, or by the partition id of the extrinsics, and ships every shard to a worker process
+//! over TCP. Every worker runs a node of the chain, serving the sessions of the coordinator with
+//! [`serve`]: it executes its shard with Block-STM on top of the state of the same block and of
+//! the changes of the block being built, recording the keys every extrinsic reads, and returns the
+//! outputs of the extrinsics.
+//!
+//! The coordinator merges the outputs in the order of the batch. Within a round, every key is
+//! owned by the shard of the last extrinsic that wrote it, and an extrinsic is only kept if every
+//! key it read is owned by its own shard, or by none: the keys written by the other shards were not
+//! visible to it. The round is applied up to the first extrinsic reading a key owned by another
+//! shard, or not executed by its shard. The coordinator then forwards the writes it committed to
+//! every worker with a [`ShardMessage::RemoteCommit`], on which the rest of the batch waits, and
+//! executes the rest in another round of the same session.
+//!
+//! Every round resolves at least one cross-shard dependency, but costs a round trip to the
+//! workers. Once a round keeps too few of the extrinsics left, see
+//! [`RemoteExecutor::with_min_progress`], or after too many rounds, the shards are merged back
+//! and the rest of the batch is applied locally.
+//!
+//! The messages are SCALE encoded, and prefixed with their length as a little-endian `u32`. A
+//! worker failing to execute its shard, e.g. because an extrinsic performs an operation not
+//! supported in parallel, or not answering in time, ends the session, and the rest of the batch
+//! is applied locally.
+//!
+//! The protocol is neither authenticated nor encrypted: the coordinator and the workers must run
+//! on a trusted network. A worker only serves the sessions of the coordinators it is told about,
+//! by address, see [`serve`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use codec::{Decode, Encode};
+use sp_core::hashing::twox_64;
+use sp_state_machine::{StorageKey, StorageValue};
+use sp_weights::Weight;
+
+use crate::conflict_oracle::ConflictOracle;
+use crate::events::ExtrinsicEvents;
+use crate::extrinsic::{Extrinsic, ExtrinsicOutput};
+use crate::scheduler::TxnIndex;
+use crate::LOG_TARGET;
+
+/// Version of the protocol, sent when opening a session.
+pub const PROTOCOL_VERSION: u8 = 4;
+
+/// Size of the largest message read, in bytes.
+pub const MAX_MESSAGE_LEN: u32 = 256 * 1024 * 1024;
+
+/// Time a worker may take to answer a request, by default.
+pub const DEFAULT_SHARD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time a worker waits for the next message of a session before closing it.
+pub const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of rounds of a batch executed by the workers, by default.
+pub const DEFAULT_MAX_ROUNDS: usize = 4;
+
+/// Share of the extrinsics left a round must keep for the next one to be executed by the workers,
+/// by default.
+pub const DEFAULT_MIN_PROGRESS: f64 = 0.5;
+
+/// Returns the partition id of an extrinsic, if it has one. The extrinsics of the same partition
+/// are executed by the same shard.
+pub type Partitioner = fn(&Extrinsic) -> Option<Vec<u8>>;
+
+/// Message of the coordinator to a worker, within a session.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum ShardMessage {
+    /// Opens a session on top of the state of a block and of the changes of the block being built.
+    /// First message of every session, preceded by the [`PROTOCOL_VERSION`].
+    Open {
+        /// SCALE encoded hash of the block the batch is applied on top of.
+        at_hash: Vec<u8>,
+        /// Changes of the block being built, on top of the state of the block.
+        base_changes: Vec<(StorageKey, Option<StorageValue>)>,
+    },
+    /// Executes the SCALE encoded extrinsics of the shard, in the order of the batch, on top of
+    /// the state of the session, without applying them. Answered with a [`ShardResponse`].
+    Execute(Vec<Vec<u8>>),
+    /// Writes of the extrinsics committed by the coordinator, of every shard, applied to the state
+    /// of the session before the extrinsics executed next.
+    RemoteCommit(Vec<(StorageKey, Option<StorageValue>)>),
+}
+
+/// A shard of a batch, executed by a worker on top of the state of its session.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ShardRequest {
+    /// SCALE encoded hash of the block the batch is applied on top of.
+    pub at_hash: Vec<u8>,
+    /// Changes of the block being built, on top of the state of the block, including the writes
+    /// committed in the previous rounds.
+    pub base_changes: Vec<(StorageKey, Option<StorageValue>)>,
+    /// SCALE encoded extrinsics of the shard, in the order of the batch.
+    pub extrinsics: Vec<Vec<u8>>,
+}
+
+/// Output of an extrinsic executed by a worker.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ShardOutput {
+    /// SCALE encoded `ApplyExtrinsicResult` returned by the runtime.
+    pub result: Vec<u8>,
+    /// Values written by the extrinsic, except for its events.
+    pub writes: Vec<(StorageKey, Option<StorageValue>)>,
+    /// SCALE encoded `Vec` of the event records deposited, if any.
+    pub event_records: Option<StorageValue>,
+    /// Number of events counted by the extrinsic.
+    pub event_count: u32,
+    /// SCALE encoded `Vec` of the digest items deposited, if any.
+    pub logs: Option<StorageValue>,
+    /// Keys read by the extrinsic from the state before it.
+    pub reads: Vec<StorageKey>,
+    /// Values written by the extrinsic to the offchain storage, in order, if buffered.
+    pub offchain_writes: Vec<(StorageKey, Option<StorageValue>)>,
+    /// Weight added by the extrinsic to `System::BlockWeight`.
+    pub weight: Weight,
+}
+
+impl From<ExtrinsicOutput> for ShardOutput {
+    fn from(output: ExtrinsicOutput) -> Self {
+        Self {
+            result: output.result,
+            writes: output.writes,
+            event_records: output.events.records,
+            event_count: output.events.count,
+            logs: output.events.logs,
+            reads: output.reads,
+            offchain_writes: output.offchain_writes,
+            weight: output.weight,
+        }
+    }
+}
+
+impl From<ShardOutput> for ExtrinsicOutput {
+    fn from(output: ShardOutput) -> Self {
+        Self {
+            result: output.result,
+            writes: output.writes,
+            events: ExtrinsicEvents { records: output.event_records, count: output.event_count, logs: output.logs },
+            reads: output.reads,
+            offchain_writes: output.offchain_writes,
+            weight: output.weight,
+        }
+    }
+}
+
+/// Answer of a worker to a [`ShardMessage::Execute`].
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum ShardResponse {
+    /// Outputs of the extrinsics of the shard executed, in order. The execution ends early, e.g.
+    /// after an extrinsic changing the runtime code.
+    Executed(Vec<ShardOutput>),
+    /// Why the shard could not be executed.
+    Failed(String),
+}
+
+/// Ships the shards of the batches to the worker processes.
+#[derive(Clone)]
+pub struct RemoteExecutor {
+    // Addresses of the workers, one shard each.
+    workers: Vec<SocketAddr>,
+    // Time a worker may take to answer a request.
+    timeout: Duration,
+    // Partition id of the extrinsics, the sender predicted by the conflict oracle if none.
+    maybe_partitioner: Option<Partitioner>,
+    // Number of rounds of a batch executed by the workers.
+    max_rounds: usize,
+    // Share of the extrinsics left a round must keep for the next one to be executed remotely.
+    min_progress: f64,
+}
+
+impl RemoteExecutor {
+    /// Creates an executor shipping a shard of every batch to each of `workers`.
+    pub fn new(workers: Vec<SocketAddr>) -> Self {
+        Self {
+            workers,
+            timeout: DEFAULT_SHARD_TIMEOUT,
+            maybe_partitioner: None,
+            max_rounds: DEFAULT_MAX_ROUNDS,
+            min_progress: DEFAULT_MIN_PROGRESS,
+        }
+    }
+
+    /// Gives up on the workers that did not answer within `timeout`, [`DEFAULT_SHARD_TIMEOUT`] by
+    /// default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Executes the extrinsics of the same partition, as told by `partitioner`, in the same shard.
+    pub fn with_partitioner(mut self, partitioner: Partitioner) -> Self {
+        self.maybe_partitioner = Some(partitioner);
+        self
+    }
+
+    /// Executes at most `max_rounds` rounds of a batch in the workers, [`DEFAULT_MAX_ROUNDS`] by
+    /// default, before applying the rest locally.
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Applies the rest of a batch locally once a round keeps less than `min_progress` of the
+    /// extrinsics left, [`DEFAULT_MIN_PROGRESS`] by default: the cross-shard dependencies are too
+    /// many for the round trips to the workers to pay off.
+    pub fn with_min_progress(mut self, min_progress: f64) -> Self {
+        self.min_progress = min_progress;
+        self
+    }
+
+    /// Whether the rest of a batch is executed by the workers after `num_rounds` rounds, the last
+    /// of which kept `num_kept` of the `num_left` extrinsics left, rather than applied locally.
+    pub fn keeps_sharding(&self, num_rounds: usize, num_left: usize, num_kept: usize) -> bool {
+        num_kept > 0 && num_rounds < self.max_rounds && num_kept as f64 >= self.min_progress * num_left as f64
+    }
+
+    /// Number of shards of every batch.
+    pub fn num_shards(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Splits `block` into a shard per worker, returning the indices of the extrinsics of every
+    /// shard, in order. The extrinsics are partitioned by partition id, or by sender as predicted
+    /// by `maybe_oracle`, and the ones without any are spread over the shards.
+    pub fn partition(
+        &self,
+        block: &[Extrinsic],
+        maybe_oracle: Option<&dyn ConflictOracle<Extrinsic>>,
+    ) -> Vec<Vec<TxnIndex>> {
+        let num_shards = self.num_shards().max(1);
+        let mut shards = vec![Vec::new(); num_shards];
+        for (txn_idx, xt) in block.iter().enumerate() {
+            let partition_id = match self.maybe_partitioner {
+                Some(partitioner) => partitioner(xt),
+                None => maybe_oracle.and_then(|oracle| oracle.sender(xt)),
+            };
+            let shard = match partition_id {
+                Some(partition_id) => u64::from_le_bytes(twox_64(&partition_id)) as usize % num_shards,
+                None => txn_idx % num_shards,
+            };
+            shards[shard].push(txn_idx as TxnIndex);
+        }
+        shards
+    }
+
+    /// Opens a session with every worker on top of the state of the block `at_hash`, SCALE
+    /// encoded, and of the `base_changes` of the block being built.
+    pub fn open_sessions(
+        &self,
+        at_hash: Vec<u8>,
+        base_changes: Vec<(StorageKey, Option<StorageValue>)>,
+    ) -> io::Result<ShardSessions> {
+        let open = (PROTOCOL_VERSION, ShardMessage::Open { at_hash, base_changes });
+        let streams = self
+            .workers
+            .iter()
+            .map(|worker| {
+                let mut stream = TcpStream::connect_timeout(worker, self.timeout)?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                write_message(&mut stream, &open)?;
+                Ok((*worker, stream))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(ShardSessions { streams })
+    }
+}
+
+/// Sessions of the coordinator with the workers, one per shard, for the application of a batch.
+/// Dropping them closes the sessions.
+pub struct ShardSessions {
+    // Address of every worker and the connection of its session.
+    streams: Vec<(SocketAddr, TcpStream)>,
+}
+
+impl ShardSessions {
+    /// Sends the `shards` of a round to the workers, every shard made of the `extrinsics` whose
+    /// indices it holds, and returns their outputs, by shard.
+    pub fn execute(
+        &mut self,
+        extrinsics: &[Vec<u8>],
+        shards: &[Vec<TxnIndex>],
+    ) -> Result<Vec<Vec<ShardOutput>>, String> {
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .streams
+                .iter_mut()
+                .zip(shards)
+                .map(|((worker, stream), shard)| {
+                    let message = ShardMessage::Execute(
+                        shard.iter().map(|txn_idx| extrinsics[*txn_idx as usize].clone()).collect(),
+                    );
+                    scope.spawn(move || {
+                        if shard.is_empty() {
+                            return Ok(Vec::new());
+                        }
+                        let response = write_message(stream, &message).and_then(|_| read_message(stream));
+                        match response {
+                            Ok(ShardResponse::Executed(outputs)) => Ok(outputs),
+                            Ok(ShardResponse::Failed(err)) => Err(format!("Worker {worker} failed: {err}")),
+                            Err(err) => Err(format!("Worker {worker} unreachable: {err}")),
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|_| Err("Shard thread panicked".to_owned())))
+                .collect()
+        })
+    }
+
+    /// Forwards the `writes` committed by the coordinator to every worker, before the next round.
+    pub fn remote_commit(&mut self, writes: Vec<(StorageKey, Option<StorageValue>)>) -> io::Result<()> {
+        let message = ShardMessage::RemoteCommit(writes);
+        for (_, stream) in &mut self.streams {
+            write_message(stream, &message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serves the sessions opened on `listener` by the `coordinators` with `execute`, one session after
+/// the other, until accepting a connection fails. The connections of any other peer are closed
+/// right away. Every [`ShardMessage::Execute`] of a session is executed as a [`ShardRequest`] on
+/// top of the changes the session was opened with and of the writes committed since.
+pub fn serve(
+    listener: TcpListener,
+    coordinators: &[IpAddr],
+    mut execute: impl FnMut(ShardRequest) -> ShardResponse,
+) -> io::Result<()> {
+    loop {
+        let (mut stream, peer) = listener.accept()?;
+        if !coordinators.contains(&peer.ip()) {
+            tracing::debug!(target: LOG_TARGET, %peer, "Shard session refused, not a coordinator");
+            continue;
+        }
+        if let Err(err) = serve_session(&mut stream, &mut execute) {
+            tracing::debug!(target: LOG_TARGET, %peer, %err, "Shard session closed");
+        }
+    }
+}
+
+fn serve_session(stream: &mut TcpStream, execute: &mut impl FnMut(ShardRequest) -> ShardResponse) -> io::Result<()> {
+    stream.set_read_timeout(Some(SESSION_IDLE_TIMEOUT))?;
+    let (at_hash, mut base_changes) = match read_message::<(u8, ShardMessage)>(stream)? {
+        (PROTOCOL_VERSION, ShardMessage::Open { at_hash, base_changes }) => {
+            (at_hash, base_changes.into_iter().collect::<BTreeMap<_, _>>())
+        }
+        (PROTOCOL_VERSION, _) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Session not opened")),
+        (version, _) => {
+            let response = ShardResponse::Failed(format!("Unsupported protocol version {version}"));
+            return write_message(stream, &response);
+        }
+    };
+    loop {
+        match read_message::<ShardMessage>(stream) {
+            Ok(ShardMessage::Execute(extrinsics)) => {
+                tracing::debug!(target: LOG_TARGET, num_txns = extrinsics.len(), "Executing shard");
+                let request = ShardRequest {
+                    at_hash: at_hash.clone(),
+                    base_changes: base_changes.iter().map(|(key, value)| (key.clone(), value.clone())).collect(),
+                    extrinsics,
+                };
+                write_message(stream, &execute(request))?;
+            }
+            Ok(ShardMessage::RemoteCommit(writes)) => base_changes.extend(writes),
+            Ok(ShardMessage::Open { .. }) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Session already opened"));
+            }
+            // The coordinator closed the session.
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Index of the first extrinsic of a round of `num_txns` whose output cannot be kept, given the
+/// `outputs` of the `shards` of the round: it was not executed, or it read a key owned by another
+/// shard, i.e. last written by a lower extrinsic of another shard. `num_txns` if every output is
+/// kept.
+pub fn first_cross_shard_conflict(num_txns: usize, shards: &[Vec<TxnIndex>], outputs: &[Vec<ShardOutput>]) -> usize {
+    let mut by_txn: Vec<Option<(usize, &ShardOutput)>> = vec![None; num_txns];
+    for (shard, (txn_indices, shard_outputs)) in shards.iter().zip(outputs).enumerate() {
+        for (txn_idx, output) in txn_indices.iter().zip(shard_outputs) {
+            by_txn[*txn_idx as usize] = Some((shard, output));
+        }
+    }
+
+    // Owner of every key so far, the shard of the last extrinsic that wrote it.
+    let mut last_writers: HashMap<&StorageKey, usize> = HashMap::new();
+    for (txn_idx, maybe_output) in by_txn.into_iter().enumerate() {
+        let Some((shard, output)) = maybe_output else {
+            return txn_idx;
+        };
+        if output.reads.iter().any(|key| last_writers.get(key).is_some_and(|writer| *writer != shard)) {
+            return txn_idx;
+        }
+        for (key, _) in &output.writes {
+            last_writers.insert(key, shard);
+        }
+    }
+    num_txns
+}
+
+fn write_message(stream: &mut TcpStream, message: &impl Encode) -> io::Result<()> {
+    let encoded = message.encode();
+    let len = u32::try_from(encoded.len())
+        .ok()
+        .filter(|len| *len <= MAX_MESSAGE_LEN)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Message too large"))?;
+    stream.write_all(&len.to_le_bytes())?;
+    stream.write_all(&encoded)?;
+    stream.flush()
+}
+
+fn read_message<M: Decode>(stream: &mut TcpStream) -> io::Result<M> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Message too large"));
+    }
+    // The body is read as it arrives rather than allocated upfront: the length is sent by the peer.
+    let mut encoded = Vec::new();
+    stream.take(len.into()).read_to_end(&mut encoded)?;
+    if encoded.len() < len as usize {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Message truncated"));
+    }
+    M::decode(&mut &encoded[..]).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
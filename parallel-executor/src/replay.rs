@@ -0,0 +1,164 @@
+//! Record and replay of the schedules of the blocks executed in parallel, to reproduce the bugs
+//! that only show up under some interleavings of the workers.
+//!
+//! A [`BlockExecutor`](crate::executor::BlockExecutor) given a directory records the schedule of
+//! every block it executes in parallel: the incarnations executed by every worker along with the
+//! results of their reads, the dependencies they waited on, and the outcome of the validations,
+//! in the order they happened. The schedules of the blocks whose execution fails are written to
+//! the directory.
+//!
+//! [`BlockExecutor::replay`](crate::executor::BlockExecutor::replay) then executes the recorded
+//! incarnations again, one after the other on the calling thread: every read observes the value
+//! it did when recorded, so that each incarnation can be stepped through in a debugger.
+
+use std::fmt::{self, Display};
+use std::io;
+use std::path::Path;
+
+use codec::{Decode, Encode};
+
+use crate::scheduler::{Incarnation, TxnIndex, Version, Wave};
+use crate::sync_wrapper::Mutex;
+use crate::task::WorkerId;
+
+/// What a read observed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub enum ReadOrigin {
+    /// The value written by the given version of a lower transaction.
+    Versioned(TxnIndex, Incarnation),
+    /// The value of the base state.
+    Storage,
+    /// Only whether the key exists.
+    Exists(bool),
+    /// The execution of the block was halted while the read waited on a dependency.
+    Halted,
+}
+
+/// A read performed by an incarnation, except the reads of the values it wrote itself.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct RecordedRead {
+    /// The key, as formatted by [`Transaction::format_key`](crate::task::Transaction::format_key).
+    pub key: String,
+    /// What the read observed.
+    pub origin: ReadOrigin,
+}
+
+/// An event of the schedule of a block.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum ScheduleEvent {
+    /// A worker executed an incarnation, which performed the given reads, in order. Recorded
+    /// before its writes are visible to the other transactions.
+    Execution { worker_id: u32, version: Version, reads: Vec<RecordedRead> },
+    /// An incarnation of `txn_idx` waited for the lower transaction `dep_idx` to be executed
+    /// again, until it was, or the execution was halted.
+    Dependency { txn_idx: TxnIndex, dep_idx: TxnIndex, halted: bool },
+    /// A worker validated an incarnation in the given wave. `maybe_invalid_key` is the key the
+    /// incarnation read and that was since overwritten, if any, and `aborted` whether the
+    /// incarnation was aborted because of it.
+    Validation { worker_id: u32, version: Version, wave: Wave, maybe_invalid_key: Option<String>, aborted: bool },
+}
+
+/// Schedule of a block executed in parallel.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct ScheduleRecord {
+    /// Number of transactions of the block.
+    pub num_txns: TxnIndex,
+    /// Number of workers that executed the block.
+    pub concurrency_level: u32,
+    /// Events of the schedule, in the order they happened.
+    pub events: Vec<ScheduleEvent>,
+}
+
+impl ScheduleRecord {
+    /// Writes the SCALE encoded record to `path`.
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.encode())
+    }
+
+    /// Reads a record written by [`ScheduleRecord::write_to`].
+    pub fn read_from(path: &Path) -> io::Result<Self> {
+        let encoded = std::fs::read(path)?;
+        Self::decode(&mut &encoded[..]).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+}
+
+/// Records the events of the schedule of a block, from all the workers.
+#[derive(Debug)]
+pub(crate) struct ScheduleRecorder {
+    num_txns: TxnIndex,
+    concurrency_level: u32,
+    events: Mutex<Vec<ScheduleEvent>>,
+}
+
+impl ScheduleRecorder {
+    pub(crate) fn new(num_txns: TxnIndex, concurrency_level: usize) -> Self {
+        Self { num_txns, concurrency_level: concurrency_level as u32, events: Mutex::new(Vec::new()) }
+    }
+
+    pub(crate) fn record_execution(&self, worker_id: WorkerId, version: Version, reads: Vec<RecordedRead>) {
+        self.record(ScheduleEvent::Execution { worker_id: worker_id as u32, version, reads });
+    }
+
+    pub(crate) fn record_dependency(&self, txn_idx: TxnIndex, dep_idx: TxnIndex, halted: bool) {
+        self.record(ScheduleEvent::Dependency { txn_idx, dep_idx, halted });
+    }
+
+    pub(crate) fn record_validation(
+        &self,
+        worker_id: WorkerId,
+        version: Version,
+        wave: Wave,
+        maybe_invalid_key: Option<String>,
+        aborted: bool,
+    ) {
+        self.record(ScheduleEvent::Validation {
+            worker_id: worker_id as u32,
+            version,
+            wave,
+            maybe_invalid_key,
+            aborted,
+        });
+    }
+
+    fn record(&self, event: ScheduleEvent) {
+        self.events.lock().push(event);
+    }
+
+    pub(crate) fn into_record(self) -> ScheduleRecord {
+        ScheduleRecord {
+            num_txns: self.num_txns,
+            concurrency_level: self.concurrency_level,
+            events: self.events.into_inner(),
+        }
+    }
+}
+
+/// Reason why a schedule could not be replayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The record holds an incarnation of a transaction the block does not have.
+    UnknownTransaction(TxnIndex),
+    /// The incarnation did not perform the reads it was recorded with, e.g. because the block or
+    /// the transactions are not deterministic. `key` is the first key read differently.
+    Diverged { version: Version, key: String },
+    /// The incarnation read a value recorded as written by a version that did not write it when
+    /// replayed.
+    MissingWrite { version: Version, writer: Version, key: String },
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::UnknownTransaction(txn_idx) => write!(f, "Transaction {txn_idx} is not part of the block"),
+            ReplayError::Diverged { version, key } => {
+                write!(f, "Incarnation {version:?} diverged from its record when reading {key}")
+            }
+            ReplayError::MissingWrite { version, writer, key } => {
+                write!(f, "Incarnation {version:?} read {key} as written by {writer:?}, which did not write it")
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Revalidation of the transactions of the pool in parallel.
+//!
+//! After every imported block, the maintenance task of the transaction pool revalidates the
+//! pending transactions against the new best block, one `validate_transaction` call after the
+//! other, and the pool stalls until they are all revalidated. Validating a transaction only reads
+//! the state, so that the validations are read-only calls, see [`read_only`](crate::read_only):
+//! [`ParallelLocalCallExecutor::validate_transactions`](crate::ParallelLocalCallExecutor::validate_transactions)
+//! executes them at once on the workers. The maintenance task calls it from a blocking task, with
+//! the transactions to revalidate in batches.
+
+use codec::Encode;
+use sp_core::hashing::blake2_64;
+use sp_runtime::transaction_validity::TransactionSource;
+use sp_version::ApiId;
+
+use crate::read_only::ReadOnlyCall;
+
+/// Runtime method validating a transaction of the pool.
+pub const VALIDATE_TRANSACTION_METHOD: &str = "TaggedTransactionQueue_validate_transaction";
+
+/// Lowest version of the `TaggedTransactionQueue` runtime API taking the source of the transaction
+/// as argument, along with the transaction.
+pub const VALIDATE_TRANSACTION_MIN_API_VERSION: u32 = 2;
+
+/// Identifier of the `TaggedTransactionQueue` runtime API in the runtime version, derived from its
+/// name as `sp_api` does.
+pub fn tagged_transaction_queue_api_id() -> ApiId {
+    blake2_64(b"TaggedTransactionQueue")
+}
+
+/// Call of [`VALIDATE_TRANSACTION_METHOD`] validating `xt`, submitted from `source`, on top of the
+/// block `block_hash`, whose runtime declares the `api_version` of the `TaggedTransactionQueue`,
+/// at least [`VALIDATE_TRANSACTION_MIN_API_VERSION`].
+pub fn validate_transaction_call<Xt: Encode, Hash: Encode>(
+    api_version: u32,
+    source: TransactionSource,
+    xt: &Xt,
+    block_hash: Hash,
+) -> ReadOnlyCall {
+    let call_data = match api_version {
+        // The block hash was added in the third version.
+        ..=2 => (source, xt).encode(),
+        _ => (source, xt, block_hash).encode(),
+    };
+    ReadOnlyCall::new(VALIDATE_TRANSACTION_METHOD, call_data)
+}
@@ -0,0 +1,242 @@
+//! RPC methods reporting how the blocks are applied in parallel, for the dashboards and the
+//! debugging of the node operators, and how the batches of the dApp developers would be.
+//!
+//! `parallel_executionStats` returns the [`ParallelismRecord`]s of the last canonical blocks, the
+//! settings of the executor, and the number of fallbacks to the sequential application since the
+//! node started, see [`counters::SEQUENTIAL_FALLBACKS`]:
+//!
+//! ```text
+//! curl -H 'Content-Type: application/json' \
+//!     -d '{"id": 1, "jsonrpc": "2.0", "method": "parallel_executionStats", "params": [16]}' \
+//!     http://localhost:9944
+//! ```
+//!
+//! The records are the ones stored by the node with
+//! [`store_parallelism`](crate::ParallelLocalCallExecutor::store_parallelism): the blocks imported
+//! without one are left out.
+//!
+//! `parallel_dryRunBatch` executes the extrinsics given, SCALE encoded, on top of the state of a
+//! block, the best one by default, and returns their results along with the conflicts between
+//! them, see [`dry_run`](crate::dry_run). As `system_dryRun`, it executes arbitrary extrinsics and
+//! should only be exposed on the unsafe RPC interface of the node.
+//!
+//! `parallel_callBatch` executes read-only runtime API calls at once on top of the state of a
+//! block, as many `state_call`s, see [`read_only`](crate::read_only).
+//!
+//! The module is merged into the RPC extensions of the node with
+//! `ParallelExecution::new(client, executor).into_rpc()`.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use jsonrpsee::core::RpcResult;
+use jsonrpsee::proc_macros::rpc;
+use jsonrpsee::types::error::{ErrorObjectOwned, INTERNAL_ERROR_CODE, INVALID_PARAMS_CODE};
+use sc_client_api::backend;
+use sc_executor::RuntimeVersionOf;
+use serde::{Deserialize, Serialize};
+use sp_blockchain::HeaderBackend;
+use sp_core::traits::CodeExecutor;
+use sp_core::Bytes;
+use sp_runtime::traits::{Block as BlockT, NumberFor, Saturating};
+
+use crate::counters::{self, FallbackReason};
+use crate::dry_run::DryRunReport;
+use crate::executor::SchedulerPolicy;
+use crate::extrinsic::Extrinsic;
+use crate::parallelism::ParallelismRecord;
+use crate::read_only::ReadOnlyCall;
+use crate::ParallelLocalCallExecutor;
+
+/// Number of blocks reported when not given.
+pub const DEFAULT_NUM_BLOCKS: u32 = 16;
+
+/// Highest number of blocks reported at once, so that a call does not read the whole chain.
+pub const MAX_NUM_BLOCKS: u32 = 1024;
+
+/// Highest number of extrinsics of a batch dry run at once.
+pub const MAX_DRY_RUN_EXTRINSICS: usize = 4096;
+
+/// Highest number of runtime API calls executed at once.
+pub const MAX_BATCH_CALLS: usize = 1024;
+
+/// Settings of the executor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutorSettings {
+    /// Maximum number of workers applying a batch.
+    pub concurrency_level: u32,
+    /// How the extrinsics of a batch are scheduled.
+    pub scheduler_policy: SchedulerPolicy,
+    /// Number of extrinsics of a batch executed at once, if bounded.
+    pub chunk_size: Option<u32>,
+}
+
+/// How a canonical block was applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockStats<Hash, Number> {
+    /// Number of the block.
+    pub number: Number,
+    /// Hash of the block.
+    pub hash: Hash,
+    /// Number of extrinsics applied.
+    pub num_txns: u32,
+    /// Number of extrinsics of the critical path of the block.
+    pub critical_path: u32,
+    /// Highest number of workers a batch of the block was applied with.
+    pub concurrency_level: u32,
+    /// Number of incarnations aborted and executed again.
+    pub num_aborts: u32,
+    /// Time spent applying the batches of the block, in microseconds.
+    pub wall_time_micros: u64,
+    /// Speedup of the block over its sequential application the workers can achieve.
+    pub estimated_speedup: f64,
+}
+
+impl<Hash, Number> BlockStats<Hash, Number> {
+    fn new(number: Number, hash: Hash, record: ParallelismRecord) -> Self {
+        Self {
+            number,
+            hash,
+            num_txns: record.parallelism.num_txns,
+            critical_path: record.parallelism.critical_path,
+            concurrency_level: record.concurrency_level,
+            num_aborts: record.num_aborts,
+            wall_time_micros: record.wall_time_micros,
+            estimated_speedup: record.estimated_speedup(),
+        }
+    }
+}
+
+/// Statistics returned by `parallel_executionStats`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionStats<Hash, Number> {
+    /// Settings of the executor.
+    pub settings: ExecutorSettings,
+    /// Number of fallbacks to the sequential application since the node started, by reason.
+    pub fallbacks: BTreeMap<String, u64>,
+    /// Last canonical blocks with a parallelism record, in order.
+    pub blocks: Vec<BlockStats<Hash, Number>>,
+}
+
+/// Result of a runtime API call of `parallel_callBatch`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CallResult {
+    /// SCALE encoded result of the call.
+    Ok(Bytes),
+    /// Why the call failed.
+    Err(String),
+}
+
+/// Statistics of the parallel execution.
+#[rpc(server)]
+pub trait ParallelExecutionApi<Hash, Number> {
+    /// Returns the statistics of the last `num_blocks` canonical blocks, [`DEFAULT_NUM_BLOCKS`] if
+    /// not given and [`MAX_NUM_BLOCKS`] at most, along with the settings and the fallbacks.
+    #[method(name = "parallel_executionStats")]
+    fn execution_stats(&self, num_blocks: Option<u32>) -> RpcResult<ExecutionStats<Hash, Number>>;
+
+    /// Executes the SCALE encoded `extrinsics` speculatively on top of the state of the block
+    /// `at`, the best one if not given, and returns their results along with the conflicts between
+    /// them. Their changes are discarded.
+    #[method(name = "parallel_dryRunBatch", blocking)]
+    fn dry_run_batch(&self, extrinsics: Vec<Bytes>, at: Option<Hash>) -> RpcResult<DryRunReport>;
+
+    /// Executes the read-only runtime API `calls`, given as method and SCALE encoded arguments as
+    /// for `state_call`, at once on top of the state of the block `at`, the best one if not given.
+    /// Returns the result of every call, in order.
+    #[method(name = "parallel_callBatch", blocking)]
+    fn call_batch(&self, calls: Vec<(String, Bytes)>, at: Option<Hash>) -> RpcResult<Vec<CallResult>>;
+}
+
+/// Serves [`ParallelExecutionApiServer`] from the client of the node and its executor.
+pub struct ParallelExecution<C, Block: BlockT, B, E> {
+    // Tells the best block.
+    client: Arc<C>,
+    // Executor applying the blocks, reading their parallelism records.
+    executor: ParallelLocalCallExecutor<Block, B, E>,
+}
+
+impl<C, Block: BlockT, B, E> ParallelExecution<C, Block, B, E> {
+    /// Serves the statistics of `executor`, reading the best block from `client`.
+    pub fn new(client: Arc<C>, executor: ParallelLocalCallExecutor<Block, B, E>) -> Self {
+        Self { client, executor }
+    }
+}
+
+impl<C, Block, B, E> ParallelExecutionApiServer<Block::Hash, NumberFor<Block>> for ParallelExecution<C, Block, B, E>
+where
+    C: HeaderBackend<Block> + Send + Sync + 'static,
+    B: backend::Backend<Block> + Send + Sync + 'static,
+    E: CodeExecutor + RuntimeVersionOf + Clone + 'static,
+    Block: BlockT,
+{
+    fn execution_stats(&self, num_blocks: Option<u32>) -> RpcResult<ExecutionStats<Block::Hash, NumberFor<Block>>> {
+        let num_blocks = num_blocks.unwrap_or(DEFAULT_NUM_BLOCKS).min(MAX_NUM_BLOCKS);
+        let blocks = match num_blocks.checked_sub(1) {
+            Some(num_parents) => {
+                let best_number = self.client.info().best_number;
+                let first_number = best_number.saturating_sub(num_parents.into());
+                self.executor
+                    .parallelism_history(first_number..=best_number)
+                    .map_err(internal_error)?
+                    .into_iter()
+                    .map(|(number, hash, record)| BlockStats::new(number, hash, record))
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+
+        let settings = ExecutorSettings {
+            concurrency_level: self.executor.concurrency_level() as u32,
+            scheduler_policy: self.executor.scheduler_policy(),
+            chunk_size: self.executor.chunk_size().map(|chunk_size| chunk_size as u32),
+        };
+        let fallbacks = FallbackReason::ALL
+            .into_iter()
+            .map(|reason| (reason.as_str().to_owned(), counters::num_fallbacks(reason)))
+            .collect();
+        Ok(ExecutionStats { settings, fallbacks, blocks })
+    }
+
+    fn dry_run_batch(&self, extrinsics: Vec<Bytes>, at: Option<Block::Hash>) -> RpcResult<DryRunReport> {
+        if extrinsics.len() > MAX_DRY_RUN_EXTRINSICS {
+            return Err(ErrorObjectOwned::owned(
+                INVALID_PARAMS_CODE,
+                format!("At most {MAX_DRY_RUN_EXTRINSICS} extrinsics are dry run at once"),
+                None::<()>,
+            ));
+        }
+        let at_hash = at.unwrap_or_else(|| self.client.info().best_hash);
+        let extrinsics: Vec<_> = extrinsics.into_iter().map(|xt| Extrinsic::new(xt.0)).collect();
+        self.executor.dry_run_batch(at_hash, &extrinsics).map_err(internal_error)
+    }
+
+    fn call_batch(&self, calls: Vec<(String, Bytes)>, at: Option<Block::Hash>) -> RpcResult<Vec<CallResult>> {
+        if calls.len() > MAX_BATCH_CALLS {
+            return Err(ErrorObjectOwned::owned(
+                INVALID_PARAMS_CODE,
+                format!("At most {MAX_BATCH_CALLS} calls are executed at once"),
+                None::<()>,
+            ));
+        }
+        let at_hash = at.unwrap_or_else(|| self.client.info().best_hash);
+        let calls: Vec<_> =
+            calls.into_iter().map(|(method, call_data)| ReadOnlyCall::new(method, call_data.0)).collect();
+        let results = self.executor.call_read_only(at_hash, &calls).map_err(internal_error)?;
+        Ok(results
+            .into_iter()
+            .map(|result| match result {
+                Ok(result) => CallResult::Ok(result.into()),
+                Err(err) => CallResult::Err(err.to_string()),
+            })
+            .collect())
+    }
+}
+
+fn internal_error(err: sp_blockchain::Error) -> ErrorObjectOwned {
+    ErrorObjectOwned::owned(INTERNAL_ERROR_CODE, err.to_string(), None::<()>)
+}
@@ -0,0 +1,773 @@
+//! Block-STM collaborative scheduler.
+//!
+//! The scheduler hands out execution and validation tasks to the worker threads. Transactions
+//! are executed optimistically in index order, and every executed incarnation must be validated
+//! before the block can be considered done. Validation is organised in waves: whenever a
+//! transaction is aborted (or re-executes and writes to a new location), the validation index is
+//! lowered and a new wave starts, so that every higher transaction is validated again against the
+//! updated state.
+//!
+//! Transactions are committed in order, once their latest incarnation was validated in a wave
+//! that guarantees it can no longer be aborted.
+//!
+//! The transactions far ahead of the committed prefix are the most likely to read values that
+//! are invalidated before they are committed. With a [`SpeculationWindow`], the scheduler only
+//! executes the transactions within a window of the committed prefix, which is halved on every
+//! abort and grows by one on every commit of a first incarnation.
+
+use std::cmp::{max, min};
+
+use crossbeam::utils::CachePadded;
+
+use crate::sync_wrapper::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use crate::sync_wrapper::{Mutex, WaitCell};
+use crate::LOG_TARGET;
+
+/// Index of a transaction in the batch.
+pub type TxnIndex = u32;
+
+/// Number of times a transaction has been (re-)executed, starting from 0.
+pub type Incarnation = u32;
+
+/// A transaction version is identified by its index and the incarnation that produced it.
+pub type Version = (TxnIndex, Incarnation);
+
+/// Validation wave. Incremented every time the validation index is decreased.
+pub type Wave = u32;
+
+/// Status of a dependency that a suspended transaction is waiting on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyStatus {
+    /// The blocking transaction has finished executing, the waiter may proceed.
+    Resolved,
+    /// The blocking transaction has not finished executing yet.
+    Unresolved,
+    /// The whole block execution was halted, the waiter must stop executing.
+    ExecutionHalted,
+}
+
+/// Result of registering a read dependency with [`Scheduler::wait_for_dependency`].
+#[derive(Debug)]
+pub enum DependencyResult {
+    /// The dependency was registered, the caller must wait for it to be resolved with
+    /// [`Scheduler::wait_for_resolution`].
+    Dependency,
+    /// The blocking transaction has already been executed, the caller can read again.
+    Resolved,
+    /// The block execution was halted, the caller must stop executing.
+    ExecutionHalted,
+}
+
+/// A task returned by the scheduler to a worker thread.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SchedulerTask {
+    /// Execute the given version of a transaction.
+    ExecutionTask(Version),
+    /// Validate the read-set of the given version, as part of the given wave.
+    ValidationTask(Version, Wave),
+    /// No task is currently available, but the block is not done yet.
+    Retry,
+    /// All transactions are executed and validated (or the execution was halted).
+    Done,
+}
+
+/// Execution status of a single transaction.
+///
+/// ```text
+/// ReadyToExecute(i) ---> Executing(i) ---> Executed(i) ---> Aborting(i) ---> ReadyToExecute(i + 1)
+///                            |   ^             |
+///                            v   |             v
+///                         Suspended(i)     Committed(i)
+/// ```
+#[derive(Debug)]
+enum TransactionStatus {
+    /// The incarnation is ready to be picked up by a worker. Set if the incarnation was previously
+    /// suspended on a dependency, in which case its worker waits to be resumed.
+    ReadyToExecute(Incarnation, bool),
+    /// The incarnation is being executed by a worker.
+    Executing(Incarnation),
+    /// The incarnation is waiting for a lower transaction to finish executing.
+    Suspended(Incarnation),
+    /// The incarnation finished executing, and can be validated.
+    Executed(Incarnation),
+    /// The incarnation is final, all the lower transactions are committed.
+    Committed(Incarnation),
+    /// The incarnation failed validation and is being aborted.
+    Aborting(Incarnation),
+    /// The block execution was halted, the transaction will not be executed again.
+    ExecutionHalted,
+}
+
+/// Validation bookkeeping of a single transaction, in terms of waves.
+#[derive(Debug, Default)]
+struct ValidationStatus {
+    /// The highest wave triggered by this transaction, i.e. the minimal wave that all the
+    /// higher transactions must be validated in after this transaction was aborted or wrote to a
+    /// new location.
+    max_triggered_wave: Wave,
+    /// The wave in which the latest incarnation of this transaction was scheduled for validation.
+    required_wave: Wave,
+    /// The highest wave in which the latest incarnation of this transaction was successfully
+    /// validated, if any.
+    maybe_max_validated_wave: Option<Wave>,
+}
+
+/// Bounds of the number of transactions executed ahead of the committed prefix, see
+/// [`Scheduler::with_speculation_window`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeculationWindow {
+    /// Size of the window once it shrank the most, at least 1.
+    pub min: TxnIndex,
+    /// Size of the window at the start of the block, and once it grew the most.
+    pub max: TxnIndex,
+}
+
+/// Snapshot of the scheduler counters for the block, see [`Scheduler::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulerStats {
+    /// Number of incarnations handed out for execution, including re-executions.
+    pub executions: u32,
+    /// Number of incarnations after the first one handed out for execution.
+    pub re_executions: u32,
+    /// Number of validation tasks handed out.
+    pub validations: u32,
+    /// Number of validations that failed and aborted the validated incarnation.
+    pub validation_failures: u32,
+    /// Number of times an execution was suspended waiting on a lower transaction.
+    pub dependency_waits: u32,
+}
+
+/// Counters backing [`SchedulerStats`], updated concurrently by the workers.
+#[derive(Debug, Default)]
+struct SchedulerCounters {
+    executions: AtomicU32,
+    re_executions: AtomicU32,
+    validations: AtomicU32,
+    validation_failures: AtomicU32,
+    dependency_waits: AtomicU32,
+}
+
+impl SchedulerCounters {
+    fn snapshot(&self) -> SchedulerStats {
+        SchedulerStats {
+            executions: self.executions.load(Ordering::Relaxed),
+            re_executions: self.re_executions.load(Ordering::Relaxed),
+            validations: self.validations.load(Ordering::Relaxed),
+            validation_failures: self.validation_failures.load(Ordering::Relaxed),
+            dependency_waits: self.dependency_waits.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Block-STM scheduler shared by all the worker threads executing a block.
+pub struct Scheduler {
+    /// Number of transactions in the block.
+    num_txns: TxnIndex,
+
+    /// For each transaction, the indices of the transactions that are suspended waiting for it
+    /// to finish executing.
+    txn_dependency: Vec<CachePadded<Mutex<Vec<TxnIndex>>>>,
+
+    /// For each transaction, the status of the dependency its suspended incarnation waits on.
+    dependency_status: Vec<CachePadded<WaitCell>>,
+
+    /// Execution and validation status of each transaction. When both are needed, the validation
+    /// status lock is always acquired first.
+    txn_status: Vec<CachePadded<(Mutex<TransactionStatus>, Mutex<ValidationStatus>)>>,
+
+    /// Next transaction index to commit, along with the highest wave triggered by the committed
+    /// transactions.
+    commit_state: CachePadded<Mutex<(TxnIndex, Wave)>>,
+
+    /// Next transaction index to be considered for execution.
+    execution_idx: AtomicU32,
+
+    /// Number of transactions to execute, lowered when the execution is stopped or the rest of the
+    /// block skipped.
+    execution_limit: AtomicU32,
+
+    /// Number of transactions committed so far, mirrored from the commit state.
+    num_committed: AtomicU32,
+
+    /// Number of transactions executed ahead of the committed prefix, tuned within the bounds of
+    /// the speculation window, if any.
+    window: AtomicU32,
+
+    /// Bounds of the speculation window, if the execution ahead of the committed prefix is limited.
+    maybe_window_bounds: Option<SpeculationWindow>,
+
+    /// Index following the highest transaction handed out for execution so far.
+    num_attempted: AtomicU32,
+
+    /// Next transaction index to be considered for validation, packed with the current wave in
+    /// the high 32 bits.
+    validation_idx: AtomicU64,
+
+    /// Incremented every time the execution or validation index is decreased. Used to detect
+    /// concurrent decreases when checking for completion.
+    decrease_cnt: AtomicU32,
+
+    /// Number of tasks currently being processed by the workers.
+    num_active_tasks: AtomicU32,
+
+    /// Set once all transactions are executed and validated, or the execution is halted.
+    done_marker: AtomicBool,
+
+    /// Set once the execution is halted.
+    has_halted: AtomicBool,
+
+    /// Execution and validation counters of the block.
+    counters: SchedulerCounters,
+}
+
+impl Scheduler {
+    /// Creates a scheduler for a block of `num_txns` transactions.
+    pub fn new(num_txns: TxnIndex) -> Self {
+        Self {
+            num_txns,
+            txn_dependency: (0..num_txns).map(|_| CachePadded::new(Mutex::new(Vec::new()))).collect(),
+            dependency_status: (0..num_txns)
+                .map(|_| CachePadded::new(WaitCell::new(DependencyStatus::Resolved as u8)))
+                .collect(),
+            txn_status: (0..num_txns)
+                .map(|_| {
+                    CachePadded::new((
+                        Mutex::new(TransactionStatus::ReadyToExecute(0, false)),
+                        Mutex::new(ValidationStatus::default()),
+                    ))
+                })
+                .collect(),
+            commit_state: CachePadded::new(Mutex::new((0, 0))),
+            execution_idx: AtomicU32::new(0),
+            execution_limit: AtomicU32::new(num_txns),
+            num_committed: AtomicU32::new(0),
+            window: AtomicU32::new(num_txns),
+            maybe_window_bounds: None,
+            num_attempted: AtomicU32::new(0),
+            validation_idx: AtomicU64::new(0),
+            decrease_cnt: AtomicU32::new(0),
+            num_active_tasks: AtomicU32::new(0),
+            done_marker: AtomicBool::new(false),
+            has_halted: AtomicBool::new(false),
+            counters: SchedulerCounters::default(),
+        }
+    }
+
+    /// Only executes the transactions within a window of the committed prefix, starting at
+    /// `window.max` transactions and tuned from the aborts of the block within `window`.
+    pub fn with_speculation_window(mut self, window: SpeculationWindow) -> Self {
+        let window = SpeculationWindow { min: window.min.max(1), max: window.max.max(window.min.max(1)) };
+        self.window = AtomicU32::new(window.max);
+        self.maybe_window_bounds = Some(window);
+        self
+    }
+
+    /// Number of transactions currently executed ahead of the committed prefix.
+    pub fn speculation_window(&self) -> TxnIndex {
+        self.window.load(Ordering::Relaxed)
+    }
+
+    /// Number of transactions committed so far.
+    pub fn num_committed(&self) -> TxnIndex {
+        self.num_committed.load(Ordering::SeqCst)
+    }
+
+    /// Number of transactions in the block.
+    pub fn num_txns(&self) -> TxnIndex {
+        self.num_txns
+    }
+
+    /// Returns a snapshot of the execution and validation counters of the block.
+    ///
+    /// The snapshot is only exact once the block is done, as the workers keep updating the
+    /// counters while it is being executed.
+    pub fn stats(&self) -> SchedulerStats {
+        self.counters.snapshot()
+    }
+
+    /// Returns the next task for the calling worker.
+    pub fn next_task(&self) -> SchedulerTask {
+        loop {
+            if self.done() {
+                return SchedulerTask::Done;
+            }
+
+            let (idx_to_validate, wave) = Self::unpack_validation_idx(self.validation_idx.load(Ordering::SeqCst));
+            let idx_to_execute = self.execution_idx.load(Ordering::SeqCst);
+
+            if min(idx_to_execute, idx_to_validate) >= self.execution_limit() {
+                return if self.check_done() { SchedulerTask::Done } else { SchedulerTask::Retry };
+            }
+
+            if idx_to_validate < idx_to_execute {
+                if let Some((version_to_validate, wave)) = self.try_validate_next_version(idx_to_validate, wave) {
+                    return SchedulerTask::ValidationTask(version_to_validate, wave);
+                }
+            } else if !self.within_window(idx_to_execute) {
+                // Wait for the committed prefix to catch up, committing is up to the workers.
+                return SchedulerTask::Retry;
+            } else if let Some(version_to_execute) = self.try_execute_next_version() {
+                return SchedulerTask::ExecutionTask(version_to_execute);
+            }
+        }
+    }
+
+    /// Registers that `txn_idx` read a value written by the (currently aborted) `dep_txn_idx`.
+    ///
+    /// On [`DependencyResult::Dependency`] the transaction is suspended, and the caller must
+    /// wait with [`Scheduler::wait_for_resolution`]. The dependency is resolved once `dep_txn_idx`
+    /// finishes executing, at which point some worker picks the suspended incarnation up again
+    /// and wakes the caller.
+    pub fn wait_for_dependency(&self, txn_idx: TxnIndex, dep_txn_idx: TxnIndex) -> DependencyResult {
+        // Holding the dependency lock guarantees that `finish_execution` of `dep_txn_idx` either
+        // happened before (and we observe the executed status), or will see the dependency below.
+        let mut stored_deps = self.txn_dependency[dep_txn_idx as usize].lock();
+
+        if self.is_executed(dep_txn_idx).is_some() {
+            // The dependency got resolved in the meantime. We must not add a (stale) dependency,
+            // as nothing would resolve it anymore.
+            return DependencyResult::Resolved;
+        }
+
+        // Reset before suspending, as the dependency can be resolved as soon as the transaction
+        // is suspended.
+        self.dependency_status[txn_idx as usize].store(DependencyStatus::Unresolved as u8);
+        if !self.suspend(txn_idx) {
+            return DependencyResult::ExecutionHalted;
+        }
+
+        stored_deps.push(txn_idx);
+        self.counters.dependency_waits.fetch_add(1, Ordering::Relaxed);
+
+        DependencyResult::Dependency
+    }
+
+    /// Blocks the worker executing `txn_idx`, suspended on a dependency, until the dependency is
+    /// resolved or the execution halted.
+    pub fn wait_for_resolution(&self, txn_idx: TxnIndex) -> DependencyStatus {
+        let status = self.dependency_status[txn_idx as usize].wait_while(DependencyStatus::Unresolved as u8);
+        match status {
+            status if status == DependencyStatus::Resolved as u8 => DependencyStatus::Resolved,
+            status if status == DependencyStatus::ExecutionHalted as u8 => DependencyStatus::ExecutionHalted,
+            status => unreachable!("Unexpected dependency status {status} of transaction {txn_idx}"),
+        }
+    }
+
+    /// Marks the incarnation as executed, resumes the transactions waiting on it, and returns
+    /// the validation task for it if the validation index already passed `txn_idx`.
+    ///
+    /// `revalidate_suffix` must be set when the incarnation wrote to a location that the previous
+    /// incarnation did not write to, so that all the higher transactions are validated again.
+    pub fn finish_execution(
+        &self,
+        txn_idx: TxnIndex,
+        incarnation: Incarnation,
+        revalidate_suffix: bool,
+    ) -> SchedulerTask {
+        // Hold the validation lock for the whole function, so that no validation of this
+        // transaction can be recorded before its validation status is updated.
+        let mut validation_status = self.txn_status[txn_idx as usize].1.lock();
+        if !self.set_executed_status(txn_idx, incarnation) {
+            // The execution was halted concurrently.
+            drop(validation_status);
+            return self.finish_task();
+        }
+        validation_status.maybe_max_validated_wave = None;
+
+        let txn_deps = std::mem::take(&mut *self.txn_dependency[txn_idx as usize].lock());
+        let min_dep = txn_deps
+            .into_iter()
+            .map(|dep| {
+                self.resume(dep);
+                dep
+            })
+            .min();
+        if let Some(execution_target_idx) = min_dep {
+            // Make sure the resumed transactions are picked up again by a worker.
+            self.decrease_execution_idx(execution_target_idx);
+        }
+
+        let (cur_val_idx, mut cur_wave) = Self::unpack_validation_idx(self.validation_idx.load(Ordering::SeqCst));
+
+        // If the validation index is not past `txn_idx`, the transaction will be validated as
+        // part of the current wave and there is nothing more to do.
+        if cur_val_idx > txn_idx {
+            if revalidate_suffix {
+                // The transaction itself is validated by the returned task.
+                if let Some(wave) = self.decrease_validation_idx(txn_idx + 1) {
+                    cur_wave = wave;
+                    validation_status.max_triggered_wave = max(validation_status.max_triggered_wave, wave);
+                }
+            }
+            validation_status.required_wave = cur_wave;
+            self.counters.validations.fetch_add(1, Ordering::Relaxed);
+            return SchedulerTask::ValidationTask((txn_idx, incarnation), cur_wave);
+        }
+
+        drop(validation_status);
+        self.finish_task()
+    }
+
+    /// Commits the next transaction in order if its latest incarnation is executed and was
+    /// validated after every abort of the committed transactions, returning its index.
+    ///
+    /// A committed transaction can no longer be aborted, so its output is final.
+    pub fn try_commit(&self) -> Option<TxnIndex> {
+        let mut commit_state = self.commit_state.lock();
+        let (commit_idx, commit_wave) = &mut *commit_state;
+        if *commit_idx >= self.execution_limit() {
+            return None;
+        }
+
+        let txn_idx = *commit_idx;
+        let validation_status = self.txn_status[txn_idx as usize].1.lock();
+        let mut status = self.txn_status[txn_idx as usize].0.lock();
+        let TransactionStatus::Executed(incarnation) = *status else {
+            return None;
+        };
+
+        // The wave is only updated with the triggered waves, as they affect all the higher
+        // transactions, while the required wave only concerns this transaction.
+        *commit_wave = max(*commit_wave, validation_status.max_triggered_wave);
+        let required_wave = max(*commit_wave, validation_status.required_wave);
+        if !validation_status.maybe_max_validated_wave.is_some_and(|wave| wave >= required_wave) {
+            return None;
+        }
+
+        *status = TransactionStatus::Committed(incarnation);
+        *commit_idx += 1;
+        self.num_committed.store(*commit_idx, Ordering::SeqCst);
+        if incarnation == 0 {
+            self.tune_window(|window, bounds| min(window + 1, bounds.max));
+        }
+        Some(txn_idx)
+    }
+
+    /// Tries to abort the given version after a failed validation. Returns `true` if the caller
+    /// won the race and must proceed with [`Scheduler::finish_abort`].
+    pub fn try_abort(&self, txn_idx: TxnIndex, incarnation: Incarnation) -> bool {
+        let mut status = self.txn_status[txn_idx as usize].0.lock();
+        if matches!(*status, TransactionStatus::Executed(i) if i == incarnation) {
+            *status = TransactionStatus::Aborting(incarnation);
+            self.counters.validation_failures.fetch_add(1, Ordering::Relaxed);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Records a successful (or superseded) validation of the given version in `wave`.
+    pub fn finish_validation(&self, txn_idx: TxnIndex, incarnation: Incarnation, wave: Wave) {
+        {
+            let mut validation_status = self.txn_status[txn_idx as usize].1.lock();
+            // Only record the wave if the validated incarnation is still the latest one.
+            if self.is_executed(txn_idx) == Some(incarnation) {
+                validation_status.maybe_max_validated_wave =
+                    Some(validation_status.maybe_max_validated_wave.map_or(wave, |prev| max(prev, wave)));
+            }
+        }
+        self.finish_task();
+    }
+
+    /// Finishes the abort of the given version: makes the next incarnation ready, schedules all
+    /// the higher transactions for validation in a new wave, and returns the execution task of
+    /// the next incarnation if the caller should execute it right away.
+    pub fn finish_abort(&self, txn_idx: TxnIndex, incarnation: Incarnation) -> SchedulerTask {
+        {
+            // Hold the validation lock while decreasing the validation index, so that the
+            // triggered wave is recorded before any higher transaction can observe the new wave.
+            let mut validation_status = self.txn_status[txn_idx as usize].1.lock();
+            if !self.set_aborted_status(txn_idx, incarnation) {
+                drop(validation_status);
+                return self.finish_task();
+            }
+
+            self.tune_window(|window, bounds| max(window / 2, bounds.min));
+
+            // Schedule the higher transactions for validation, skipping `txn_idx` itself as it
+            // has to be executed again first.
+            if let Some(new_wave) = self.decrease_validation_idx(txn_idx + 1) {
+                validation_status.max_triggered_wave = max(validation_status.max_triggered_wave, new_wave);
+            }
+        }
+
+        if self.execution_idx.load(Ordering::SeqCst) > txn_idx {
+            // The execution index already passed `txn_idx`, so it is up to us to re-execute it.
+            if let Some(incarnation) = self.try_incarnate(txn_idx) {
+                return SchedulerTask::ExecutionTask((txn_idx, incarnation));
+            }
+        }
+
+        self.finish_task()
+    }
+
+    /// Stops handing out the transactions that were never executed, e.g. once the deadline of the
+    /// block is reached. The transactions already attempted are still executed again and validated
+    /// as needed, until they can all be committed.
+    pub fn stop_execution(&self) {
+        let num_attempted = self.num_attempted.load(Ordering::SeqCst);
+        if self.execution_limit.fetch_min(num_attempted, Ordering::SeqCst) > num_attempted {
+            tracing::debug!(target: LOG_TARGET, num_attempted, "Stopping the execution of new transactions");
+        }
+    }
+
+    /// Number of transactions to execute, all of them unless the execution was stopped or the rest
+    /// of the block skipped.
+    pub fn execution_limit(&self) -> TxnIndex {
+        self.execution_limit.load(Ordering::SeqCst)
+    }
+
+    /// Halts the execution of the block, waking up every suspended worker. Returns `true` if the
+    /// calling thread is the one that halted the execution.
+    pub fn halt(&self) -> bool {
+        if self.has_halted.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+
+        tracing::debug!(target: LOG_TARGET, "Halting parallel execution");
+
+        self.done_marker.store(true, Ordering::SeqCst);
+        for txn_idx in 0..self.num_txns {
+            self.halt_transaction_execution(txn_idx);
+        }
+        true
+    }
+
+    /// Ends the block before `block_end`, once a committed transaction skips the rest of the block
+    /// or the block is full: the transactions from `block_end` on are skipped and never committed,
+    /// and the workers suspended on them are woken up. Unlike [`Scheduler::halt`], the committed
+    /// transactions keep their status. Returns `true` if the calling thread ended the block.
+    pub fn skip_rest(&self, block_end: TxnIndex) -> bool {
+        if self.has_halted.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+
+        tracing::debug!(target: LOG_TARGET, block_end, "Skipping the rest of the block");
+
+        self.execution_limit.fetch_min(block_end, Ordering::SeqCst);
+        self.done_marker.store(true, Ordering::SeqCst);
+        for txn_idx in block_end..self.num_txns {
+            self.halt_transaction_execution(txn_idx);
+        }
+        true
+    }
+
+    /// Whether the execution was halted.
+    pub fn has_halted(&self) -> bool {
+        self.has_halted.load(Ordering::SeqCst)
+    }
+
+    /// Whether all transactions are executed and validated, or the execution was halted.
+    pub fn done(&self) -> bool {
+        self.done_marker.load(Ordering::Acquire)
+    }
+
+    fn try_validate_next_version(&self, idx_to_validate: TxnIndex, wave: Wave) -> Option<(Version, Wave)> {
+        self.num_active_tasks.fetch_add(1, Ordering::SeqCst);
+
+        // Compare-and-swap rather than fetch-and-increment, so that a concurrent decrease of the
+        // validation index (which starts a new wave) is never overwritten.
+        if self
+            .validation_idx
+            .compare_exchange(
+                Self::pack_validation_idx(idx_to_validate, wave),
+                Self::pack_validation_idx(idx_to_validate + 1, wave),
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+        {
+            // Only validate the transaction if its latest incarnation was executed, otherwise it
+            // will be validated when its execution finishes.
+            if let Some(incarnation) = self.is_executed(idx_to_validate) {
+                self.counters.validations.fetch_add(1, Ordering::Relaxed);
+                return Some(((idx_to_validate, incarnation), wave));
+            }
+        }
+
+        self.num_active_tasks.fetch_sub(1, Ordering::SeqCst);
+        None
+    }
+
+    /// Whether `txn_idx` is within the speculation window of the committed prefix.
+    fn within_window(&self, txn_idx: TxnIndex) -> bool {
+        self.maybe_window_bounds.is_none()
+            || txn_idx < self.num_committed.load(Ordering::SeqCst).saturating_add(self.window.load(Ordering::SeqCst))
+    }
+
+    /// Updates the speculation window with `tune`, given its bounds, if it is limited.
+    fn tune_window(&self, tune: impl Fn(TxnIndex, SpeculationWindow) -> TxnIndex) {
+        if let Some(bounds) = self.maybe_window_bounds {
+            let _ = self.window.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |window| Some(tune(window, bounds)));
+        }
+    }
+
+    fn try_execute_next_version(&self) -> Option<Version> {
+        self.num_active_tasks.fetch_add(1, Ordering::SeqCst);
+
+        let idx_to_execute = self.execution_idx.fetch_add(1, Ordering::SeqCst);
+        if idx_to_execute < self.execution_limit() {
+            if let Some(incarnation) = self.try_incarnate(idx_to_execute) {
+                return Some((idx_to_execute, incarnation));
+            }
+        }
+
+        self.num_active_tasks.fetch_sub(1, Ordering::SeqCst);
+        None
+    }
+
+    /// Tries to move the transaction from ready to executing. If the ready incarnation belongs to
+    /// a suspended worker, that worker is woken up to continue the execution and `None` is
+    /// returned, as there is nothing left for the caller to execute.
+    fn try_incarnate(&self, txn_idx: TxnIndex) -> Option<Incarnation> {
+        let mut status = self.txn_status[txn_idx as usize].0.lock();
+        let TransactionStatus::ReadyToExecute(incarnation, resumed) = *status else {
+            return None;
+        };
+
+        *status = TransactionStatus::Executing(incarnation);
+        drop(status);
+
+        if resumed {
+            self.resolve_dependency(txn_idx, DependencyStatus::Resolved);
+            return None;
+        }
+
+        self.counters.executions.fetch_add(1, Ordering::Relaxed);
+        if incarnation > 0 {
+            self.counters.re_executions.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.num_attempted.fetch_max(txn_idx + 1, Ordering::SeqCst);
+        }
+        Some(incarnation)
+    }
+
+    /// Returns the incarnation of the transaction if its latest incarnation is executed (or
+    /// committed).
+    fn is_executed(&self, txn_idx: TxnIndex) -> Option<Incarnation> {
+        match &*self.txn_status[txn_idx as usize].0.lock() {
+            TransactionStatus::Executed(incarnation) | TransactionStatus::Committed(incarnation) => Some(*incarnation),
+            _ => None,
+        }
+    }
+
+    /// Suspends the executing transaction on a dependency. Returns `false` if the execution was
+    /// halted in the meantime.
+    fn suspend(&self, txn_idx: TxnIndex) -> bool {
+        let mut status = self.txn_status[txn_idx as usize].0.lock();
+        match &*status {
+            TransactionStatus::Executing(incarnation) => {
+                *status = TransactionStatus::Suspended(*incarnation);
+                true
+            }
+            TransactionStatus::ExecutionHalted => false,
+            status => unreachable!("Suspending transaction {txn_idx} in unexpected status {status:?}"),
+        }
+    }
+
+    /// Moves a suspended transaction back to ready, noting that its worker waits so that it is
+    /// woken up when the incarnation is picked up again.
+    fn resume(&self, txn_idx: TxnIndex) {
+        let mut status = self.txn_status[txn_idx as usize].0.lock();
+        match &*status {
+            TransactionStatus::Suspended(incarnation) => {
+                *status = TransactionStatus::ReadyToExecute(*incarnation, true);
+            }
+            TransactionStatus::ExecutionHalted => {}
+            status => unreachable!("Resuming transaction {txn_idx} in unexpected status {status:?}"),
+        }
+    }
+
+    fn set_executed_status(&self, txn_idx: TxnIndex, incarnation: Incarnation) -> bool {
+        let mut status = self.txn_status[txn_idx as usize].0.lock();
+        match &*status {
+            TransactionStatus::Executing(i) if *i == incarnation => {
+                *status = TransactionStatus::Executed(incarnation);
+                true
+            }
+            TransactionStatus::ExecutionHalted => false,
+            status => unreachable!("Finishing execution of {txn_idx} in unexpected status {status:?}"),
+        }
+    }
+
+    fn set_aborted_status(&self, txn_idx: TxnIndex, incarnation: Incarnation) -> bool {
+        let mut status = self.txn_status[txn_idx as usize].0.lock();
+        match &*status {
+            TransactionStatus::Aborting(i) if *i == incarnation => {
+                *status = TransactionStatus::ReadyToExecute(incarnation + 1, false);
+                true
+            }
+            TransactionStatus::ExecutionHalted => false,
+            status => unreachable!("Finishing abort of {txn_idx} in unexpected status {status:?}"),
+        }
+    }
+
+    fn halt_transaction_execution(&self, txn_idx: TxnIndex) {
+        let mut status = self.txn_status[txn_idx as usize].0.lock();
+        match std::mem::replace(&mut *status, TransactionStatus::ExecutionHalted) {
+            TransactionStatus::Suspended(_) | TransactionStatus::ReadyToExecute(_, true) => {
+                self.resolve_dependency(txn_idx, DependencyStatus::ExecutionHalted);
+            }
+            _ => {}
+        }
+    }
+
+    /// Wakes up the worker of `txn_idx` suspended on a dependency.
+    fn resolve_dependency(&self, txn_idx: TxnIndex, dependency_status: DependencyStatus) {
+        self.dependency_status[txn_idx as usize].set(dependency_status as u8);
+    }
+
+    fn decrease_execution_idx(&self, target_idx: TxnIndex) {
+        self.execution_idx.fetch_min(target_idx, Ordering::SeqCst);
+        self.decrease_cnt.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Lowers the validation index to `target_idx` and starts a new wave. Returns the new wave, or
+    /// `None` if the validation index was already at or below `target_idx`.
+    fn decrease_validation_idx(&self, target_idx: TxnIndex) -> Option<Wave> {
+        let prev_val_idx = self
+            .validation_idx
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |val_idx| {
+                let (txn_idx, wave) = Self::unpack_validation_idx(val_idx);
+                (txn_idx > target_idx).then(|| Self::pack_validation_idx(target_idx, wave + 1))
+            })
+            .ok()?;
+
+        self.decrease_cnt.fetch_add(1, Ordering::SeqCst);
+        let (_, wave) = Self::unpack_validation_idx(prev_val_idx);
+        Some(wave + 1)
+    }
+
+    /// Releases a task slot, returning [`SchedulerTask::Retry`] for convenience.
+    fn finish_task(&self) -> SchedulerTask {
+        self.num_active_tasks.fetch_sub(1, Ordering::SeqCst);
+        SchedulerTask::Retry
+    }
+
+    /// Checks whether all the transactions are executed and validated, with no task in flight.
+    fn check_done(&self) -> bool {
+        let observed_cnt = self.decrease_cnt.load(Ordering::SeqCst);
+
+        let (val_idx, _) = Self::unpack_validation_idx(self.validation_idx.load(Ordering::SeqCst));
+        let exec_idx = self.execution_idx.load(Ordering::SeqCst);
+        let num_tasks = self.num_active_tasks.load(Ordering::SeqCst);
+        if min(exec_idx, val_idx) < self.execution_limit() || num_tasks > 0 {
+            return false;
+        }
+
+        // Nothing was rescheduled while we were observing the indices and the active tasks.
+        if observed_cnt == self.decrease_cnt.load(Ordering::SeqCst) {
+            self.done_marker.store(true, Ordering::Release);
+            return true;
+        }
+        false
+    }
+
+    fn pack_validation_idx(txn_idx: TxnIndex, wave: Wave) -> u64 {
+        (txn_idx as u64) | ((wave as u64) << 32)
+    }
+
+    fn unpack_validation_idx(validation_idx: u64) -> (TxnIndex, Wave) {
+        ((validation_idx & (u32::MAX as u64)) as TxnIndex, (validation_idx >> 32) as Wave)
+    }
+}
@@ -0,0 +1,194 @@
+//! Signature verifications of the extrinsics cached across their incarnations.
+//!
+//! Verifying the signature of an extrinsic is the most expensive of the checks of
+//! `apply_extrinsic`, and an extrinsic executed again after reading a stale value verifies the
+//! same signature of the same payload again. Unlike the era or the nonce, checked against the
+//! state, the outcome of a verification only depends on the signature, the message and the public
+//! key, so that it can be reused by the next incarnations of the extrinsic.
+//!
+//! The runtime verifies the signatures with the `crypto` host functions. Once the batches are
+//! applied with
+//! [`ParallelLocalCallExecutor::with_signature_cache`](crate::ParallelLocalCallExecutor::with_signature_cache),
+//! the externalities of every extrinsic hold the [`SignatureCache`] of its batch: the node
+//! overrides the host functions verifying signatures, e.g. with
+//! `sc_executor::ExtendedHostFunctions`, so that they go through [`verify_cached`]:
+//!
+//! ```ignore
+//! fn sr25519_verify(&mut self, sig: &sr25519::Signature, msg: &[u8], pub_key: &sr25519::Public) -> bool {
+//!     verify_cached(*self, SignatureScheme::Sr25519, sig.as_ref(), msg, pub_key.as_ref(), || {
+//!         sr25519::Pair::verify(sig, msg, pub_key)
+//!     })
+//! }
+//! ```
+//!
+//! The verifications can also run before the extrinsics of a batch are scheduled, in parallel on
+//! the workers, see [`SignatureCache::verify_batch`]: the extrinsics whose signature is invalid
+//! are rejected without being executed, and the runtime of the others finds the outcome of their
+//! verification in the cache.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use codec::Encode;
+use dashmap::{DashMap, DashSet};
+use rayon::prelude::*;
+use sp_core::hashing::blake2_256;
+use sp_core::{ecdsa, ed25519, sr25519, Pair};
+use sp_externalities::{Externalities, ExternalitiesExt};
+
+use crate::extrinsic::Extrinsic;
+use crate::scheduler::TxnIndex;
+
+/// Signature scheme of a verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode)]
+pub enum SignatureScheme {
+    /// `ed25519` signatures.
+    Ed25519,
+    /// `sr25519` signatures.
+    Sr25519,
+    /// `ecdsa` signatures over `secp256k1`.
+    Ecdsa,
+}
+
+impl SignatureScheme {
+    /// Whether `signature` is a valid signature of `message` by `public` in the scheme, as
+    /// verified by the `crypto` host functions.
+    pub fn verify(&self, signature: &[u8], message: &[u8], public: &[u8]) -> bool {
+        match self {
+            Self::Ed25519 => match (ed25519::Signature::try_from(signature), ed25519::Public::try_from(public)) {
+                (Ok(signature), Ok(public)) => ed25519::Pair::verify(&signature, message, &public),
+                _ => false,
+            },
+            Self::Sr25519 => match (sr25519::Signature::try_from(signature), sr25519::Public::try_from(public)) {
+                (Ok(signature), Ok(public)) => sr25519::Pair::verify(&signature, message, &public),
+                _ => false,
+            },
+            Self::Ecdsa => match (ecdsa::Signature::try_from(signature), ecdsa::Public::try_from(public)) {
+                (Ok(signature), Ok(public)) => ecdsa::Pair::verify(&signature, message, &public),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Signature of an extrinsic, along with what it signs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedPayload {
+    /// Signature scheme of the signer.
+    pub scheme: SignatureScheme,
+    /// Signature of the extrinsic.
+    pub signature: Vec<u8>,
+    /// Payload signed by the signer, as given to the host function by the runtime, i.e. hashed if
+    /// the runtime hashes it.
+    pub message: Vec<u8>,
+    /// Public key of the signer.
+    pub public: Vec<u8>,
+}
+
+/// Extracts the signatures of the extrinsics, which requires their types in the runtime.
+pub trait SignatureExtractor: Send + Sync {
+    /// Signature of `xt` and what it signs, if signed. `None` if the payload or the signer can not
+    /// be resolved without the state, e.g. the signer is looked up in an index: the runtime then
+    /// verifies the signature.
+    fn signed_payload(&self, xt: &Extrinsic) -> Option<SignedPayload>;
+}
+
+/// Outcomes of the signature verifications of the extrinsics of a batch, by extrinsic.
+#[derive(Debug, Default)]
+pub struct SignatureCache {
+    // Outcome of every verification, by extrinsic and hash of its inputs.
+    verified: DashMap<(TxnIndex, [u8; 32]), bool>,
+    // Number of verifications served from the cache.
+    num_hits: AtomicU64,
+    // Extrinsics whose signature was found invalid before they were scheduled.
+    rejected: DashSet<TxnIndex>,
+}
+
+impl SignatureCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Verifies the `signature` of `message` by `public` for the extrinsic `txn_idx` with `verify`,
+    /// unless a previous incarnation of the extrinsic verified it already.
+    pub fn verify(
+        &self,
+        txn_idx: TxnIndex,
+        scheme: SignatureScheme,
+        signature: &[u8],
+        message: &[u8],
+        public: &[u8],
+        verify: impl FnOnce() -> bool,
+    ) -> bool {
+        let inputs = blake2_256(&(scheme, signature, message, public).encode());
+        if let Some(valid) = self.verified.get(&(txn_idx, inputs)) {
+            self.num_hits.fetch_add(1, Ordering::Relaxed);
+            return *valid;
+        }
+        let valid = verify();
+        self.verified.insert((txn_idx, inputs), valid);
+        valid
+    }
+
+    /// Verifies the signatures of the extrinsics of `block`, in parallel on the current rayon pool,
+    /// before they are scheduled. Returns the number of extrinsics whose signature is invalid,
+    /// which are [`rejected`](Self::is_rejected).
+    pub fn verify_batch(&self, block: &[Extrinsic], extractor: &dyn SignatureExtractor) -> usize {
+        block
+            .par_iter()
+            .enumerate()
+            .filter_map(|(txn_idx, xt)| Some((txn_idx as TxnIndex, extractor.signed_payload(xt)?)))
+            .filter(|(txn_idx, payload)| {
+                let SignedPayload { scheme, signature, message, public } = payload;
+                let valid = self.verify(*txn_idx, *scheme, signature, message, public, || {
+                    scheme.verify(signature, message, public)
+                });
+                if !valid {
+                    self.rejected.insert(*txn_idx);
+                }
+                !valid
+            })
+            .count()
+    }
+
+    /// Whether the signature of the extrinsic `txn_idx` was found invalid before it was scheduled,
+    /// see [`verify_batch`](Self::verify_batch).
+    pub fn is_rejected(&self, txn_idx: TxnIndex) -> bool {
+        self.rejected.contains(&txn_idx)
+    }
+
+    /// Number of verifications served from the cache so far.
+    pub fn num_hits(&self) -> u64 {
+        self.num_hits.load(Ordering::Relaxed)
+    }
+}
+
+/// The [`SignatureCache`] of a batch, for the extrinsic being executed.
+pub struct CachedSignatures {
+    /// Cache of the batch.
+    pub cache: Arc<SignatureCache>,
+    /// Index of the extrinsic in the batch.
+    pub txn_idx: TxnIndex,
+}
+
+sp_externalities::decl_extension! {
+    /// Extension of the externalities of an extrinsic holding the [`SignatureCache`] of its batch.
+    pub struct SignatureCacheExt(CachedSignatures);
+}
+
+/// Verifies a `signature` with `verify`, through the [`SignatureCache`] of the batch if `ext` are
+/// the externalities of one of its extrinsics. Meant for the host functions verifying signatures.
+pub fn verify_cached(
+    ext: &mut dyn Externalities,
+    scheme: SignatureScheme,
+    signature: &[u8],
+    message: &[u8],
+    public: &[u8],
+    verify: impl FnOnce() -> bool,
+) -> bool {
+    match ext.extension::<SignatureCacheExt>() {
+        Some(cached) => cached.cache.verify(cached.txn_idx, scheme, signature, message, public, verify),
+        None => verify(),
+    }
+}
@@ -0,0 +1,146 @@
+//! Runtime call applying a single extrinsic of a batch.
+
+use codec::Encode;
+use sp_core::hexdisplay::HexDisplay;
+use sp_core::traits::{CallContext, CodeExecutor, RuntimeCode};
+use sp_core::Hasher;
+use sp_state_machine::backend::BackendRuntimeCode;
+use sp_state_machine::{Backend, StateMachineStats, TrieBackend, TrieBackendBuilder, TrieBackendStorage};
+use sp_trie::recorder::Recorder;
+use sp_trie::StorageProof;
+use sp_version::RuntimeVersion;
+
+use crate::ext::Ext;
+use crate::extrinsic::Extrinsic;
+use crate::view::StateView;
+use crate::LOG_TARGET;
+
+/// Executes a runtime call on top of the externalities of a single extrinsic, as the
+/// `sp_state_machine::StateMachine` does on top of the overlay of the whole block.
+pub struct StateMachine<'a, Exec> {
+    exec: &'a Exec,
+    method: &'a str,
+    call_data: &'a [u8],
+    runtime_code: &'a RuntimeCode<'a>,
+    context: CallContext,
+    stats: StateMachineStats,
+}
+
+impl<'a, Exec: CodeExecutor> StateMachine<'a, Exec> {
+    /// Creates the state machine calling `method` with `call_data` in the given runtime.
+    pub fn new(
+        exec: &'a Exec,
+        method: &'a str,
+        call_data: &'a [u8],
+        runtime_code: &'a RuntimeCode<'a>,
+        context: CallContext,
+    ) -> Self {
+        Self { exec, method, call_data, runtime_code, context, stats: StateMachineStats::default() }
+    }
+
+    /// Executes the call, buffering its writes in `ext`. Returns the SCALE encoded result of the
+    /// call.
+    ///
+    /// The runtime is instantiated from `runtime_code`, with the heap pages it specifies, and is
+    /// never the native one. The overlay statistics of the call are added to
+    /// [`stats`](Self::stats).
+    pub fn execute<H, S>(&mut self, ext: &mut Ext<'_, H, S>) -> Result<Vec<u8>, Box<dyn sp_state_machine::Error>>
+    where
+        H: Hasher,
+        H::Out: Encode,
+        S: StateView<Extrinsic>,
+    {
+        ext.enter_runtime();
+
+        tracing::trace!(
+            target: LOG_TARGET,
+            txn_idx = ext.txn_idx(),
+            method = %self.method,
+            heap_pages = ?self.runtime_code.heap_pages,
+            input = ?HexDisplay::from(&self.call_data),
+            "Call",
+        );
+
+        let result = self.exec.call(ext, self.runtime_code, self.method, self.call_data, false, self.context).0;
+
+        ext.exit_runtime();
+        self.stats.add(ext.stats());
+
+        tracing::trace!(target: LOG_TARGET, txn_idx = ext.txn_idx(), ?result, "Return");
+
+        result.map_err(|e| Box::new(e) as Box<_>)
+    }
+
+    /// Executes the call as [`execute`](Self::execute), on externalities reading the state through
+    /// a [`proving_backend`] recording with `recorder`. Returns the result of the call along
+    /// with the proof of all the reads recorded so far, by this call and by the other workers
+    /// sharing the recorder.
+    pub fn prove_execute<H, S>(
+        &mut self,
+        ext: &mut Ext<'_, H, S>,
+        recorder: &Recorder<H>,
+    ) -> Result<(Vec<u8>, StorageProof), Box<dyn sp_state_machine::Error>>
+    where
+        H: Hasher,
+        H::Out: Encode,
+        S: StateView<Extrinsic>,
+    {
+        let result = self.execute(ext)?;
+        Ok((result, recorder.to_storage_proof()))
+    }
+
+    /// Statistics of the overlay accesses of the calls executed so far.
+    pub fn stats(&self) -> &StateMachineStats {
+        &self.stats
+    }
+}
+
+/// Wraps `backend` in a proxy recording the trie nodes read through it with `recorder`.
+///
+/// The recorder is shared by the proxies of all the workers applying a batch, so that the proof of
+/// the batch covers the reads of every extrinsic while recording each node only once.
+pub fn proving_backend<'a, S, H, C>(
+    backend: &'a TrieBackend<S, H, C>,
+    recorder: &Recorder<H>,
+) -> TrieBackend<&'a S, H, &'a C>
+where
+    S: TrieBackendStorage<H>,
+    H: Hasher,
+{
+    TrieBackendBuilder::wrap(backend).with_recorder(recorder.clone()).build()
+}
+
+/// Runtime code of a block, resolved once from the state of its parent and shared read-only by the
+/// workers applying its batches.
+pub struct RuntimeCodeCache<'a, H, B> {
+    fetcher: BackendRuntimeCode<'a, B, H>,
+    hash: Vec<u8>,
+    heap_pages: Option<u64>,
+    version: RuntimeVersion,
+}
+
+impl<'a, H, B> RuntimeCodeCache<'a, H, B>
+where
+    H: Hasher,
+    H::Out: Encode,
+    B: Backend<H>,
+{
+    /// Resolves the hash and heap pages of the runtime code found in `backend`, of the given
+    /// `version`.
+    pub fn new(backend: &'a B, version: RuntimeVersion) -> Result<Self, &'static str> {
+        let fetcher = BackendRuntimeCode::new(backend);
+        let RuntimeCode { hash, heap_pages, .. } = fetcher.runtime_code()?;
+        Ok(Self { fetcher, hash, heap_pages, version })
+    }
+
+    /// Returns the runtime code. The code itself is only read from the backend if the executor
+    /// has not cached the runtime yet.
+    pub fn runtime_code(&self) -> RuntimeCode<'_> {
+        RuntimeCode { code_fetcher: &self.fetcher, hash: self.hash.clone(), heap_pages: self.heap_pages }
+    }
+
+    /// Version of the runtime.
+    pub fn version(&self) -> &RuntimeVersion {
+        &self.version
+    }
+}
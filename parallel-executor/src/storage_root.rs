@@ -0,0 +1,60 @@
+//! Storage root of a block, with its child tries hashed in parallel.
+//!
+//! Once the overlay of a block is materialized, computing its storage root hashes the modified
+//! trie nodes one after the other. The child tries, e.g. the ones of the contracts, are
+//! independent of each other: their roots are computed in parallel, before the main trie is
+//! hashed with the updated child roots.
+
+use codec::Encode;
+use rayon::prelude::*;
+use sp_core::storage::{ChildInfo, StateVersion};
+use sp_core::Hasher;
+use sp_state_machine::{Backend, BackendTransaction, OverlayedChanges};
+
+/// Changes of a trie, as expected by the [`Backend`] to compute its root.
+type Delta<'a> = Vec<(&'a [u8], Option<&'a [u8]>)>;
+
+/// Computes the storage root of `changes` applied on top of `backend`, as
+/// [`Backend::full_storage_root`] does, along with the trie nodes to insert.
+pub fn full_storage_root<H, B>(
+    backend: &B,
+    changes: &OverlayedChanges<H>,
+    state_version: StateVersion,
+) -> (H::Out, BackendTransaction<H>)
+where
+    H: Hasher,
+    H::Out: Ord + Encode,
+    B: Backend<H> + Sync,
+{
+    let child_deltas: Vec<(&ChildInfo, Delta<'_>)> = changes
+        .children()
+        .map(|(child_changes, child_info)| {
+            let delta = child_changes.map(|(key, value)| (&key[..], value.value().map(|value| &value[..])));
+            (child_info, delta.collect())
+        })
+        .collect();
+
+    let child_roots: Vec<_> = child_deltas
+        .into_par_iter()
+        .map(|(child_info, delta)| {
+            let (child_root, empty, transaction) =
+                backend.child_storage_root(child_info, delta.into_iter(), state_version);
+            let prefixed_storage_key = child_info.prefixed_storage_key().into_inner();
+            (prefixed_storage_key, (!empty).then(|| child_root.encode()), transaction)
+        })
+        .collect();
+
+    let mut transaction = BackendTransaction::default();
+    let mut child_root_entries = Vec::with_capacity(child_roots.len());
+    for (prefixed_storage_key, child_root, child_transaction) in child_roots {
+        transaction.consolidate(child_transaction);
+        child_root_entries.push((prefixed_storage_key, child_root));
+    }
+
+    let delta = changes.changes().map(|(key, value)| (&key[..], value.value().map(|value| &value[..]))).chain(
+        child_root_entries.iter().map(|(key, child_root)| (&key[..], child_root.as_ref().map(|root| &root[..]))),
+    );
+    let (root, main_transaction) = backend.storage_root(delta, state_version);
+    transaction.consolidate(main_transaction);
+    (root, transaction)
+}
@@ -0,0 +1,276 @@
+//! Synchronization primitives shared by the parallel execution components.
+//!
+//! The locks are the ones of `parking_lot`, which are smaller and faster to acquire when
+//! uncontended than the ones of `std`. When built with `--cfg loom`, the primitives are the ones
+//! of `loom` instead, so that the concurrency tests can explore every interleaving of the
+//! scheduler.
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic;
+#[cfg(loom)]
+use std::sync::{PoisonError, TryLockError};
+
+#[cfg(not(loom))]
+use crossbeam::utils::Backoff;
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic;
+#[cfg(loom)]
+use loom::sync::{Condvar as RawCondvar, Mutex as RawMutex, RwLock as RawRwLock};
+#[cfg(loom)]
+pub use loom::sync::{MutexGuard, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(loom))]
+use parking_lot::{Condvar as RawCondvar, Mutex as RawMutex, RwLock as RawRwLock};
+#[cfg(not(loom))]
+pub use parking_lot::{MutexGuard, RwLockReadGuard, RwLockWriteGuard};
+
+/// A thin wrapper around `parking_lot::Mutex`.
+///
+/// The lock is not poisoned when a worker panics while holding it. That worker halts the
+/// execution of the block, and the other workers acquire the lock again to wind down instead of
+/// panicking in turn.
+#[derive(Debug, Default)]
+pub struct Mutex<T>(RawMutex<T>);
+
+impl<T> Mutex<T> {
+    /// Creates a new unlocked mutex.
+    pub fn new(t: T) -> Self {
+        Self(RawMutex::new(t))
+    }
+
+    /// Acquires the lock, blocking the current thread until it is able to do so.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        #[cfg(not(loom))]
+        {
+            self.0.lock()
+        }
+        #[cfg(loom)]
+        {
+            self.0.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    /// Attempts to acquire the lock without blocking, returning `None` if it is held elsewhere.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        #[cfg(not(loom))]
+        {
+            self.0.try_lock()
+        }
+        #[cfg(loom)]
+        {
+            match self.0.try_lock() {
+                Ok(guard) => Some(guard),
+                Err(TryLockError::WouldBlock) => None,
+                Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            }
+        }
+    }
+
+    /// Consumes the mutex, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        #[cfg(not(loom))]
+        {
+            self.0.into_inner()
+        }
+        #[cfg(loom)]
+        {
+            self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+}
+
+/// A thin wrapper around `parking_lot::RwLock`, for the state read by every worker and seldom
+/// written. As the [`Mutex`], it is not poisoned when a worker panics while holding it.
+#[derive(Debug, Default)]
+pub struct RwLock<T>(RawRwLock<T>);
+
+impl<T> RwLock<T> {
+    /// Creates a new unlocked lock.
+    pub fn new(t: T) -> Self {
+        Self(RawRwLock::new(t))
+    }
+
+    /// Acquires the lock with shared read access, blocking the current thread while it is held
+    /// for writing.
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        #[cfg(not(loom))]
+        {
+            self.0.read()
+        }
+        #[cfg(loom)]
+        {
+            self.0.read().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    /// Acquires the lock with exclusive write access, blocking the current thread while it is held
+    /// elsewhere.
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        #[cfg(not(loom))]
+        {
+            self.0.write()
+        }
+        #[cfg(loom)]
+        {
+            self.0.write().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    /// Consumes the lock, returning the underlying data.
+    pub fn into_inner(self) -> T {
+        #[cfg(not(loom))]
+        {
+            self.0.into_inner()
+        }
+        #[cfg(loom)]
+        {
+            self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+}
+
+/// A thin wrapper around `parking_lot::Condvar`, waited on with the guards of a [`Mutex`].
+#[derive(Debug)]
+pub struct Condvar(RawCondvar);
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Condvar {
+    /// Creates a new condition variable.
+    pub fn new() -> Self {
+        Self(RawCondvar::new())
+    }
+
+    /// Releases the lock of `guard` and blocks the current thread until it is notified, then
+    /// acquires the lock again. The thread may be woken up spuriously, so the caller checks its
+    /// condition again in a loop.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        #[cfg(not(loom))]
+        {
+            let mut guard = guard;
+            self.0.wait(&mut guard);
+            guard
+        }
+        #[cfg(loom)]
+        {
+            self.0.wait(guard).unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    /// Wakes up one of the threads blocked on the condition variable, if any.
+    pub fn notify_one(&self) {
+        self.0.notify_one();
+    }
+
+    /// Wakes up all the threads blocked on the condition variable.
+    pub fn notify_all(&self) {
+        self.0.notify_all();
+    }
+}
+
+/// A status byte that workers wait on until another worker changes it.
+///
+/// Most waits are short, so the waiter spins with a growing backoff first, then parks its thread
+/// until [`WaitCell::set`] wakes it up. Unlike a condition variable, the cell needs no lock, and
+/// can be reused from one wait to the next without allocating.
+#[derive(Debug)]
+pub struct WaitCell {
+    #[cfg(not(loom))]
+    state: atomic::AtomicU8,
+    #[cfg(loom)]
+    state: RawMutex<u8>,
+    #[cfg(loom)]
+    condvar: RawCondvar,
+}
+
+impl WaitCell {
+    /// Creates a cell holding `state`.
+    pub fn new(state: u8) -> Self {
+        #[cfg(not(loom))]
+        {
+            Self { state: atomic::AtomicU8::new(state) }
+        }
+        #[cfg(loom)]
+        {
+            Self { state: RawMutex::new(state), condvar: RawCondvar::new() }
+        }
+    }
+
+    /// Stores `state` without waking up the waiters, e.g. before the caller starts waiting.
+    pub fn store(&self, state: u8) {
+        #[cfg(not(loom))]
+        {
+            self.state.store(state, atomic::Ordering::SeqCst);
+        }
+        #[cfg(loom)]
+        {
+            *self.state.lock().unwrap_or_else(PoisonError::into_inner) = state;
+        }
+    }
+
+    /// Stores `state` and wakes up all the threads waiting on the cell.
+    pub fn set(&self, state: u8) {
+        #[cfg(not(loom))]
+        {
+            self.state.store(state, atomic::Ordering::SeqCst);
+            // SAFETY: the key is the address of the cell, which is only parked on by `wait_while`
+            // with the default tokens.
+            unsafe {
+                parking_lot_core::unpark_all(self.key(), parking_lot_core::DEFAULT_UNPARK_TOKEN);
+            }
+        }
+        #[cfg(loom)]
+        {
+            *self.state.lock().unwrap_or_else(PoisonError::into_inner) = state;
+            self.condvar.notify_all();
+        }
+    }
+
+    /// Blocks the current thread as long as the cell holds `state`, and returns the new state.
+    pub fn wait_while(&self, state: u8) -> u8 {
+        #[cfg(not(loom))]
+        {
+            let backoff = Backoff::new();
+            loop {
+                let current = self.state.load(atomic::Ordering::SeqCst);
+                if current != state {
+                    return current;
+                }
+                if !backoff.is_completed() {
+                    backoff.snooze();
+                    continue;
+                }
+                // The state is checked again under the lock of the parking queue, which `set`
+                // takes to wake up the waiters, so that no wake-up is lost in between.
+                // SAFETY: the key is the address of the cell, and the callbacks do not panic nor
+                // call back into `parking_lot_core`.
+                unsafe {
+                    parking_lot_core::park(
+                        self.key(),
+                        || self.state.load(atomic::Ordering::SeqCst) == state,
+                        || {},
+                        |_, _| {},
+                        parking_lot_core::DEFAULT_PARK_TOKEN,
+                        None,
+                    );
+                }
+            }
+        }
+        #[cfg(loom)]
+        {
+            let mut current = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            while *current == state {
+                current = self.condvar.wait(current).unwrap_or_else(PoisonError::into_inner);
+            }
+            *current
+        }
+    }
+
+    #[cfg(not(loom))]
+    fn key(&self) -> usize {
+        &self.state as *const _ as usize
+    }
+}
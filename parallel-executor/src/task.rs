@@ -0,0 +1,139 @@
+//! Abstractions over the transactions executed by the
+//! [`BlockExecutor`](crate::executor::BlockExecutor).
+
+use std::any::Any;
+use std::fmt::{self, Debug, Display};
+use std::hash::Hash;
+
+use sp_weights::Weight;
+
+use crate::scheduler::TxnIndex;
+use crate::view::{LatestView, StateView};
+
+/// A transaction that can be executed by the block executor.
+pub trait Transaction: Sync + Send + 'static {
+    /// Key of the state accessed by the transaction.
+    type Key: Eq + Hash + Clone + Debug + Send + Sync + 'static;
+    /// Value of the state accessed by the transaction. Values are compared to tell whether an
+    /// incarnation changed what the previous one wrote.
+    type Value: PartialEq + Debug + Send + Sync + 'static;
+
+    /// Whether `key` holds code, e.g. the code of the runtime, which is not versioned by the
+    /// executor. The block is executed sequentially if a transaction reads code written by
+    /// another one.
+    fn is_module_key(_key: &Self::Key) -> bool {
+        false
+    }
+
+    /// Whether `value` denotes a key present in the state, as observed by the reads of its
+    /// existence only.
+    fn exists(_value: &Self::Value) -> bool {
+        true
+    }
+
+    /// Formats `key` for humans, e.g. in the
+    /// [`ConflictGraph`](crate::conflict_graph::ConflictGraph) of a block.
+    fn format_key(key: &Self::Key) -> String {
+        format!("{key:?}")
+    }
+}
+
+/// Identifier of a worker thread of the block executor, from `0` to its concurrency level
+/// (excluded).
+pub type WorkerId = usize;
+
+/// Values written by a transaction, by key.
+pub type WriteSet<T> = Vec<(<T as Transaction>::Key, <T as Transaction>::Value)>;
+
+/// Result of the execution of a single transaction.
+#[derive(Debug)]
+pub enum ExecutionStatus<O, E> {
+    /// The transaction was executed, its output must be applied.
+    Success(O),
+    /// The execution failed with an error that invalidates the whole block.
+    Abort(E),
+    /// The transaction was executed and its output must be applied, but none of the following
+    /// transactions must be (e.g. the block is sealed by an inherent).
+    SkipRest(O),
+}
+
+/// Panic caught while executing a block, which aborts its execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionPanic {
+    /// Transaction whose execution panicked, `None` if the panic happened in the executor itself,
+    /// e.g. while validating or committing transactions.
+    pub maybe_txn_idx: Option<TxnIndex>,
+    /// Message of the panic.
+    pub message: String,
+}
+
+impl ExecutionPanic {
+    /// Wraps the `payload` of a panic caught while executing `maybe_txn_idx`.
+    pub fn new(maybe_txn_idx: Option<TxnIndex>, payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().map_or("Box<dyn Any>", |message| message).to_owned(),
+        };
+        Self { maybe_txn_idx, message }
+    }
+}
+
+impl Display for ExecutionPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.maybe_txn_idx {
+            Some(txn_idx) => write!(f, "Execution of transaction {txn_idx} panicked: {}", self.message),
+            None => write!(f, "Block executor panicked: {}", self.message),
+        }
+    }
+}
+
+/// The execution of a block was cancelled, see
+/// [`BlockExecutor::with_cancellation`](crate::executor::BlockExecutor::with_cancellation).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionCancelled;
+
+/// Executes the transactions of a block. An instance is created for every worker thread.
+pub trait ExecutorTask: Sync {
+    /// Type of the transactions executed by the task.
+    type Txn: Transaction;
+
+    /// Output of the execution of a transaction.
+    type Output: TransactionOutput<Txn = Self::Txn> + 'static;
+
+    /// Error aborting the execution of the block, including the panics caught while executing it
+    /// and its cancellation.
+    type Error: Debug + Clone + Send + Sync + From<ExecutionPanic> + From<ExecutionCancelled> + 'static;
+
+    /// Arguments shared by all the worker threads to create their task.
+    type Argument: Sync + Copy;
+
+    /// Creates the task of the worker thread `worker_id`.
+    fn init(args: Self::Argument, worker_id: WorkerId) -> Self;
+
+    /// Executes a single transaction, reading the state through `view`.
+    fn execute_transaction<S: StateView<Self::Txn>>(
+        &self,
+        view: &LatestView<Self::Txn, S>,
+        txn: &Self::Txn,
+        txn_idx: TxnIndex,
+    ) -> ExecutionStatus<Self::Output, Self::Error>;
+
+    /// Whether `err`, returned by a transaction, only requires it to be executed sequentially
+    /// rather than failing the whole block: the block ends before the transaction, which is
+    /// skipped along with the following ones.
+    fn ends_parallel_segment(_err: &Self::Error) -> bool {
+        false
+    }
+}
+
+/// Output of the execution of a transaction.
+pub trait TransactionOutput: Send + Sync + Debug {
+    /// Type of the transaction producing the output.
+    type Txn: Transaction;
+
+    /// Returns the values written by the transaction.
+    fn get_writes(&self) -> WriteSet<Self::Txn>;
+
+    /// Returns the weight consumed by the transaction, accounted against the block weight limit.
+    fn weight(&self) -> Weight;
+}
@@ -0,0 +1,74 @@
+//! Thread pools running the workers of the batches applied in parallel.
+//!
+//! The executor owns its pool rather than sharing the global rayon one, so that its workers are
+//! told apart from the other rayon users of the node when profiling, and are shut down once the
+//! last clone of the executor is dropped.
+//!
+//! On machines with several NUMA nodes, the workers can be pinned to the cores of a single node:
+//! the memory is allocated on the node of the thread that first touches it, so the multi-version
+//! data of the batches, written by the workers, then stays local to all of them.
+
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use sp_core::traits::SpawnNamed;
+
+use crate::LOG_TARGET;
+
+/// Name of the worker threads, followed by their index in the pool.
+pub const WORKER_THREAD_NAME: &str = "block-stm";
+
+/// Task group of the worker threads spawned by a [`SpawnNamed`].
+pub const WORKER_TASK_GROUP: &str = "parallel-executor";
+
+/// Cores the worker threads run on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CoreAffinity {
+    /// The worker threads run on any core, as scheduled by the operating system.
+    #[default]
+    Any,
+    /// The `i`-th worker thread is pinned to the `i`-th core of the list, modulo its length, e.g.
+    /// the cores of a single NUMA node. An empty list pins no thread.
+    Cores(Vec<usize>),
+}
+
+/// Creates a pool of `num_threads` worker threads, named after [`WORKER_THREAD_NAME`] and pinned
+/// according to `affinity`.
+pub fn new_thread_pool(num_threads: usize, affinity: CoreAffinity) -> Result<ThreadPool, ThreadPoolBuildError> {
+    builder(num_threads, affinity).build()
+}
+
+/// Creates a pool of `num_threads` worker threads spawned as blocking tasks of `spawner`, e.g.
+/// the handle of the `TaskManager` of the node, so that they are accounted for along with its
+/// other tasks, and pinned according to `affinity`.
+pub fn spawn_thread_pool(
+    num_threads: usize,
+    affinity: CoreAffinity,
+    spawner: impl SpawnNamed + 'static,
+) -> Result<ThreadPool, ThreadPoolBuildError> {
+    builder(num_threads, affinity)
+        .spawn_handler(move |thread| {
+            spawner.spawn_blocking(WORKER_THREAD_NAME, Some(WORKER_TASK_GROUP), Box::pin(async move { thread.run() }));
+            Ok(())
+        })
+        .build()
+}
+
+fn builder(num_threads: usize, affinity: CoreAffinity) -> ThreadPoolBuilder {
+    let builder =
+        ThreadPoolBuilder::new().num_threads(num_threads).thread_name(|idx| format!("{WORKER_THREAD_NAME}-{idx}"));
+    match affinity {
+        CoreAffinity::Cores(cores) if !cores.is_empty() => {
+            builder.start_handler(move |idx| pin_current_thread(cores[idx % cores.len()]))
+        }
+        CoreAffinity::Any | CoreAffinity::Cores(_) => builder,
+    }
+}
+
+/// Pins the current thread to `core`. The thread keeps running on any core if the operating
+/// system does not support pinning, or `core` does not exist.
+fn pin_current_thread(core: usize) {
+    if core_affinity::set_for_current(core_affinity::CoreId { id: core }) {
+        tracing::debug!(target: LOG_TARGET, core, "Pinned worker thread");
+    } else {
+        tracing::warn!(target: LOG_TARGET, core, "Failed to pin worker thread");
+    }
+}
@@ -0,0 +1,117 @@
+//! Inputs and outputs of the latest incarnation of every transaction of the block.
+
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use arc_swap::ArcSwapOption;
+use crossbeam::utils::CachePadded;
+use dashmap::DashSet;
+
+use crate::captured_reads::CapturedReads;
+use crate::scheduler::TxnIndex;
+use crate::task::{ExecutionStatus, Transaction, TransactionOutput};
+
+/// Output of the latest incarnation of a transaction, along with the keys it wrote to.
+struct TxnOutput<T: Transaction, O, E> {
+    status: ExecutionStatus<O, E>,
+    modified_keys: Vec<T::Key>,
+}
+
+/// Stores the read-set and the output of the latest incarnation of every transaction. Read-sets
+/// are used to validate the incarnations, and write-sets to update the multi-version data when a
+/// transaction is re-executed or aborted.
+pub struct TxnLastInputOutput<T: Transaction, O: TransactionOutput<Txn = T>, E> {
+    inputs: Vec<CachePadded<ArcSwapOption<CapturedReads<T>>>>,
+    outputs: Vec<CachePadded<ArcSwapOption<TxnOutput<T, O, E>>>>,
+    /// Module keys, see [`Transaction::is_module_key`], read by any incarnation.
+    module_reads: DashSet<T::Key>,
+    /// Module keys written by any incarnation.
+    module_writes: DashSet<T::Key>,
+    /// Whether a module key was both read and written, in which case the block must be executed
+    /// sequentially.
+    module_read_write_intersection: AtomicBool,
+}
+
+impl<T: Transaction, O: TransactionOutput<Txn = T>, E: Send + Sync> TxnLastInputOutput<T, O, E> {
+    /// Creates the storage for a block of `num_txns` transactions.
+    pub fn new(num_txns: TxnIndex) -> Self {
+        Self {
+            inputs: (0..num_txns).map(|_| CachePadded::new(ArcSwapOption::empty())).collect(),
+            outputs: (0..num_txns).map(|_| CachePadded::new(ArcSwapOption::empty())).collect(),
+            module_reads: DashSet::new(),
+            module_writes: DashSet::new(),
+            module_read_write_intersection: AtomicBool::new(false),
+        }
+    }
+
+    /// Records the read-set and the output of the latest incarnation of `txn_idx`.
+    ///
+    /// Returns `false` if a module key read by an incarnation was written by another one so far,
+    /// in which case the execution must be halted and the block executed sequentially.
+    pub fn record(
+        &self,
+        txn_idx: TxnIndex,
+        input: CapturedReads<T>,
+        output: ExecutionStatus<O, E>,
+        modified_keys: Vec<T::Key>,
+    ) -> bool {
+        let read_modules: Vec<_> = input.keys().filter(|key| T::is_module_key(key)).collect();
+        let written_modules: Vec<_> = modified_keys.iter().filter(|key| T::is_module_key(key)).collect();
+
+        // The keys are published before checking the keys of the other incarnations (flags
+        // principle): of two incarnations recorded concurrently, at least one sees the keys of
+        // the other.
+        if !self.module_read_write_intersection.load(Ordering::Acquire)
+            && (append_and_check(&read_modules, &self.module_reads, &self.module_writes)
+                || append_and_check(&written_modules, &self.module_writes, &self.module_reads))
+        {
+            self.module_read_write_intersection.store(true, Ordering::Release);
+        }
+
+        self.inputs[txn_idx as usize].store(Some(Arc::new(input)));
+        self.outputs[txn_idx as usize].store(Some(Arc::new(TxnOutput { status: output, modified_keys })));
+        !self.module_read_write_intersection.load(Ordering::Acquire)
+    }
+
+    /// Whether a module key was both read and written by the recorded incarnations.
+    pub fn module_read_write_intersection(&self) -> bool {
+        self.module_read_write_intersection.load(Ordering::Acquire)
+    }
+
+    /// Returns the read-set of the latest incarnation of `txn_idx`.
+    pub fn read_set(&self, txn_idx: TxnIndex) -> Option<Arc<CapturedReads<T>>> {
+        self.inputs[txn_idx as usize].load_full()
+    }
+
+    /// Returns the keys written by the latest incarnation of `txn_idx`.
+    pub fn modified_keys(&self, txn_idx: TxnIndex) -> Option<Vec<T::Key>> {
+        self.outputs[txn_idx as usize].load().as_ref().map(|output| output.modified_keys.clone())
+    }
+
+    /// Calls `f` with the output of the latest incarnation of `txn_idx`.
+    pub fn with_output<R>(&self, txn_idx: TxnIndex, f: impl FnOnce(&ExecutionStatus<O, E>) -> R) -> R {
+        let output = self.outputs[txn_idx as usize].load();
+        f(&output.as_ref().expect("Output must be recorded after execution").status)
+    }
+
+    /// Consumes the storage once the block is executed, yielding in order the index and the
+    /// output of the latest incarnation of every executed transaction. The outputs of the
+    /// committed transactions, which hold their result, writes and weight, come first.
+    pub fn into_outputs(self) -> impl Iterator<Item = (TxnIndex, ExecutionStatus<O, E>)> {
+        self.outputs.into_iter().enumerate().filter_map(|(txn_idx, output)| {
+            let output = CachePadded::into_inner(output).into_inner()?;
+            let output =
+                Arc::try_unwrap(output).unwrap_or_else(|_| panic!("Output must be uniquely owned after execution"));
+            Some((txn_idx as TxnIndex, output.status))
+        })
+    }
+}
+
+/// Adds `keys` to `set_to_append`, then returns whether any of them is in `set_to_check`.
+fn append_and_check<K: Eq + Hash + Clone>(keys: &[&K], set_to_append: &DashSet<K>, set_to_check: &DashSet<K>) -> bool {
+    for key in keys {
+        set_to_append.insert((*key).clone());
+    }
+    keys.iter().any(|key| set_to_check.contains(*key))
+}
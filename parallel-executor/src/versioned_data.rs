@@ -0,0 +1,227 @@
+//! Multi-version data structure holding the values written by the transactions of a block.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crossbeam::utils::CachePadded;
+use dashmap::DashMap;
+
+use crate::scheduler::{Incarnation, TxnIndex, Version};
+use crate::sync_wrapper::Mutex;
+
+/// Marks whether the value of an entry can be read, or was written by an aborted incarnation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flag {
+    Done,
+    Estimate,
+}
+
+/// A value written by some incarnation of a transaction.
+#[derive(Debug)]
+struct Entry<V> {
+    flag: Flag,
+    incarnation: Incarnation,
+    /// First of the consecutive incarnations that wrote the same value, read as the version of
+    /// the entry, so that the readers of the value stay valid when it is written again.
+    value_incarnation: Incarnation,
+    value: Arc<V>,
+}
+
+/// Reason why no value could be read from the [`VersionedData`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MVDataError {
+    /// No lower transaction wrote to the key, the value must be read from the base state.
+    NotFound,
+    /// The closest lower write is an estimate of the given aborted transaction, the reader
+    /// depends on it.
+    Dependency(TxnIndex),
+}
+
+/// Multi-version map from keys to the values written by each transaction of the block.
+#[derive(Debug)]
+pub struct VersionedData<K: Hash + Eq, V> {
+    values: DashMap<K, BTreeMap<TxnIndex, CachePadded<Entry<V>>>>,
+    /// Keys of the entries written, marked as estimates or deleted so far, in order. A key is
+    /// logged once its entry is updated, so that a validation observes the updates logged before
+    /// it started.
+    write_log: Mutex<Vec<K>>,
+    /// Highest transaction that wrote to each key so far. Kept when the entry is deleted or
+    /// pruned, a key missing from it was never written by the block.
+    max_writers: DashMap<K, TxnIndex>,
+}
+
+impl<K: Hash + Eq + Clone, V> VersionedData<K, V> {
+    /// Creates an empty multi-version map.
+    pub fn new() -> Self {
+        Self { values: DashMap::new(), write_log: Mutex::new(Vec::new()), max_writers: DashMap::new() }
+    }
+
+    /// Number of updates logged so far.
+    pub fn num_updates(&self) -> usize {
+        self.write_log.lock().len()
+    }
+
+    /// Returns the keys updated since the first `num_updates` updates.
+    pub fn keys_updated_since(&self, num_updates: usize) -> Vec<K> {
+        self.write_log.lock()[num_updates..].to_vec()
+    }
+
+    /// Returns the value written by the highest transaction lower than `txn_idx`.
+    pub fn fetch_data(&self, key: &K, txn_idx: TxnIndex) -> Result<(Version, Arc<V>), MVDataError> {
+        let Some(versioned_values) = self.values.get(key) else {
+            return Err(MVDataError::NotFound);
+        };
+
+        match versioned_values.range(0..txn_idx).next_back() {
+            Some((idx, entry)) if entry.flag == Flag::Estimate => Err(MVDataError::Dependency(*idx)),
+            Some((idx, entry)) => Ok(((*idx, entry.value_incarnation), entry.value.clone())),
+            None => Err(MVDataError::NotFound),
+        }
+    }
+
+    /// Returns the value written by the highest transaction lower than `num_committed`, the number
+    /// of transactions committed so far, ignoring the values of the transactions not committed yet.
+    ///
+    /// The values overwritten by the transactions committed since `num_committed` was observed may
+    /// be pruned already, in which case the key is not found: the read is invalidated like any
+    /// other read of a stale value.
+    pub fn fetch_committed(&self, key: &K, num_committed: TxnIndex) -> Result<(Version, Arc<V>), MVDataError> {
+        let Some(versioned_values) = self.values.get(key) else {
+            return Err(MVDataError::NotFound);
+        };
+
+        match versioned_values.range(0..num_committed).next_back() {
+            Some((idx, entry)) => Ok(((*idx, entry.value_incarnation), entry.value.clone())),
+            None => Err(MVDataError::NotFound),
+        }
+    }
+
+    /// Returns a read-only view of the values written by the transactions lower than `up_to_txn`,
+    /// which must all be committed.
+    ///
+    /// The values overwritten by a committed transaction are pruned, so `up_to_txn` must not be
+    /// lower than the number of transactions committed so far.
+    pub fn committed_view(&self, up_to_txn: TxnIndex) -> CommittedView<'_, K, V> {
+        CommittedView { values: &self.values, up_to_txn }
+    }
+
+    /// Returns the highest transaction lower than `txn_idx` that wrote to `key`, whether its value
+    /// is an estimate or not.
+    pub fn last_writer(&self, key: &K, txn_idx: TxnIndex) -> Option<TxnIndex> {
+        self.values.get(key)?.range(0..txn_idx).next_back().map(|(idx, _)| *idx)
+    }
+
+    /// Returns the highest transaction that wrote to `key` so far, if any.
+    pub fn max_writer(&self, key: &K) -> Option<TxnIndex> {
+        self.max_writers.get(key).map(|max_writer| *max_writer)
+    }
+
+    /// Records the value written to `key` by the given version.
+    ///
+    /// An incarnation writing the same value as the previous incarnation of the transaction keeps
+    /// the version of the value, and the update is not logged: only the readers of the keys whose
+    /// value changed are invalidated, rather than the readers of every key the transaction writes.
+    pub fn write(&self, key: K, version: Version, value: V)
+    where
+        V: PartialEq,
+    {
+        let (txn_idx, incarnation) = version;
+        {
+            let mut max_writer = self.max_writers.entry(key.clone()).or_insert(txn_idx);
+            *max_writer = (*max_writer).max(txn_idx);
+        }
+        let changed = {
+            let mut versioned_values = self.values.entry(key.clone()).or_default();
+            let (value_incarnation, value, changed) = match versioned_values.get(&txn_idx) {
+                Some(prev_entry) if *prev_entry.value == value => {
+                    (prev_entry.value_incarnation, prev_entry.value.clone(), false)
+                }
+                _ => (incarnation, Arc::new(value), true),
+            };
+            let prev_entry = versioned_values
+                .insert(txn_idx, CachePadded::new(Entry { flag: Flag::Done, incarnation, value_incarnation, value }));
+
+            // A transaction only overwrites the entries of its previous incarnations.
+            assert!(prev_entry.map_or(true, |entry| entry.incarnation < incarnation));
+            changed
+        };
+        // Readers that observed the entry as an estimate were invalidated when it was marked, and
+        // observe the same version again.
+        if changed {
+            self.write_log.lock().push(key);
+        }
+    }
+
+    /// Marks the value written to `key` by `txn_idx` as an estimate, after its incarnation was
+    /// aborted. Readers of the estimate wait for the transaction to be executed again.
+    pub fn mark_estimate(&self, key: &K, txn_idx: TxnIndex) {
+        {
+            let mut versioned_values = self.values.get_mut(key).expect("Path must exist");
+            versioned_values.get_mut(&txn_idx).expect("Entry by the txn must exist to mark estimate").flag =
+                Flag::Estimate;
+        }
+        self.write_log.lock().push(key.clone());
+    }
+
+    /// Frees the values written to `key` by the transactions lower than `txn_idx`, once `txn_idx`
+    /// is committed. The higher transactions read the same values, so the update is not logged.
+    pub fn prune(&self, key: &K, txn_idx: TxnIndex) {
+        if let Some(mut versioned_values) = self.values.get_mut(key) {
+            *versioned_values = versioned_values.split_off(&txn_idx);
+        }
+    }
+
+    /// Removes the value written to `key` by `txn_idx`, when its latest incarnation no longer
+    /// writes to it.
+    pub fn delete(&self, key: &K, txn_idx: TxnIndex) {
+        {
+            let mut versioned_values = self.values.get_mut(key).expect("Path must exist");
+            assert!(versioned_values.remove(&txn_idx).is_some(), "Entry by the txn must exist to be deleted");
+        }
+        self.write_log.lock().push(key.clone());
+    }
+}
+
+/// Consistent snapshot of the state written by a committed prefix of the transactions of the block,
+/// see [`VersionedData::committed_view`].
+pub struct CommittedView<'a, K: Hash + Eq, V> {
+    values: &'a DashMap<K, BTreeMap<TxnIndex, CachePadded<Entry<V>>>>,
+    up_to_txn: TxnIndex,
+}
+
+impl<'a, K: Hash + Eq + Clone, V> CommittedView<'a, K, V> {
+    /// Index of the first transaction that is not part of the committed prefix.
+    pub fn up_to_txn(&self) -> TxnIndex {
+        self.up_to_txn
+    }
+
+    /// Returns the last value written to `key` by the committed prefix, `None` if no transaction
+    /// of the prefix wrote to it.
+    pub fn get(&self, key: &K) -> Option<Arc<V>> {
+        self.last_committed(&*self.values.get(key)?)
+    }
+
+    /// Returns the last values written by the committed prefix, by key.
+    pub fn to_map(&self) -> HashMap<K, Arc<V>> {
+        self.values
+            .iter()
+            .filter_map(|versioned_values| {
+                let value = self.last_committed(versioned_values.value())?;
+                Some((versioned_values.key().clone(), value))
+            })
+            .collect()
+    }
+
+    fn last_committed(&self, versioned_values: &BTreeMap<TxnIndex, CachePadded<Entry<V>>>) -> Option<Arc<V>> {
+        let (_, entry) = versioned_values.range(0..self.up_to_txn).next_back()?;
+        assert_eq!(entry.flag, Flag::Done, "Values written by committed transactions are never estimates");
+        Some(entry.value.clone())
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Default for VersionedData<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,441 @@
+//! Views of the state observed by a transaction during its execution.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use sp_core::storage::ChildInfo;
+
+use crate::captured_reads::{CapturedReads, DataRead, ReadKind};
+use crate::replay::{ReadOrigin, RecordedRead, ReplayError, ScheduleRecorder};
+use crate::scheduler::{DependencyResult, DependencyStatus, Incarnation, Scheduler, TxnIndex, Version};
+use crate::task::Transaction;
+use crate::versioned_data::{MVDataError, VersionedData};
+use crate::{counters, LOG_TARGET};
+
+/// The state the block is executed on top of.
+pub trait StateView<T: Transaction>: Sync {
+    /// Returns the value of `key` in the base state, shared with the other readers of the key.
+    fn get_state_value(&self, key: &T::Key) -> Arc<T::Value>;
+
+    /// Provides the value of `key` in the base state, resolved before the transactions reading it
+    /// are executed, see
+    /// [`BlockExecutor::with_base_value_prefetch`](crate::executor::BlockExecutor::with_base_value_prefetch).
+    /// Views that do not cache the values ignore it.
+    fn provide_base_value(&self, _key: T::Key, _value: Arc<T::Value>) {}
+
+    /// Returns the value of `key` in the child trie `child_info` of the base state, `None` if the
+    /// view has no child tries. The changes made to the child tries before the batch are not
+    /// included, see [`child_tries`](crate::child_tries).
+    fn get_child_state_value(&self, _child_info: &ChildInfo, _key: &T::Key) -> Option<T::Value> {
+        None
+    }
+}
+
+/// Result of a read through a [`LatestView`].
+#[derive(Debug)]
+pub enum ReadResult<V> {
+    /// The value observed by the transaction.
+    Value(Arc<V>),
+    /// Whether the key exists, as observed by the transaction.
+    Exists(bool),
+    /// The block execution was halted while the transaction was waiting on a dependency. The
+    /// incarnation will be discarded, so the executor task should return as soon as possible.
+    Halted,
+}
+
+impl<V> ReadResult<V> {
+    fn from_data_read<T: Transaction<Value = V>>(data_read: &DataRead<V>, kind: ReadKind) -> Self {
+        match (kind, data_read.value()) {
+            (ReadKind::Value, Some(value)) => ReadResult::Value(value.clone()),
+            (ReadKind::Value, None) => unreachable!("Value read from an existence read"),
+            (ReadKind::Exists, _) => ReadResult::Exists(CapturedReads::<T>::exists(data_read)),
+        }
+    }
+}
+
+impl ReadOrigin {
+    fn from_data_read<T: Transaction>(data_read: &DataRead<T::Value>, kind: ReadKind) -> Self {
+        match (kind, data_read) {
+            (ReadKind::Value, DataRead::Versioned((txn_idx, incarnation), _)) => {
+                ReadOrigin::Versioned(*txn_idx, *incarnation)
+            }
+            (ReadKind::Value, _) => ReadOrigin::Storage,
+            (ReadKind::Exists, _) => ReadOrigin::Exists(CapturedReads::<T>::exists(data_read)),
+        }
+    }
+}
+
+/// State observed by an incarnation executed in parallel: its own writes, on top of the values
+/// written by the lower transactions, on top of the base state. All the reads of values not
+/// written by the incarnation itself are captured for validation.
+///
+/// An incarnation reading a snapshot only observes the values written by the committed
+/// transactions, which are final: it never waits on a lower transaction, and a value written by a
+/// lower transaction not committed yet invalidates the read, so that the transaction is executed
+/// again with the latest values.
+pub(crate) struct ParallelState<'a, T: Transaction> {
+    versioned_map: &'a VersionedData<T::Key, T::Value>,
+    scheduler: &'a Scheduler,
+    /// Whether the incarnation reads the snapshot of the committed prefix.
+    snapshot: bool,
+    captured_reads: RefCell<CapturedReads<T>>,
+    /// Values written by the incarnation so far, only visible to itself until it is executed.
+    own_writes: RefCell<HashMap<T::Key, Arc<T::Value>>>,
+    /// Records the schedule of the block, if any.
+    maybe_recorder: Option<&'a ScheduleRecorder>,
+    /// Reads performed by the incarnation, in order, if the schedule is recorded.
+    read_log: RefCell<Vec<RecordedRead>>,
+}
+
+impl<'a, T: Transaction> ParallelState<'a, T> {
+    /// Reads `key` as the incarnation last wrote it, or else as its first read did, if any. A read
+    /// of the value after a read of the existence only is served from the [`VersionedData`], and
+    /// captured in its stead.
+    fn read_data<S: StateView<T>>(
+        &self,
+        key: &T::Key,
+        kind: ReadKind,
+        txn_idx: TxnIndex,
+        base_view: &S,
+    ) -> ReadResult<T::Value> {
+        if let Some(value) = self.own_writes.borrow().get(key) {
+            return match kind {
+                ReadKind::Value => ReadResult::Value(value.clone()),
+                ReadKind::Exists => ReadResult::Exists(T::exists(value)),
+            };
+        }
+        if let Some(data_read) = self.captured_reads.borrow().get(key) {
+            if kind == ReadKind::Exists || data_read.kind() == ReadKind::Value {
+                self.log_read(key, || ReadOrigin::from_data_read::<T>(data_read, kind));
+                return ReadResult::from_data_read::<T>(data_read, kind);
+            }
+        }
+
+        loop {
+            let fetched = if self.snapshot {
+                self.versioned_map.fetch_committed(key, self.scheduler.num_committed().min(txn_idx))
+            } else {
+                self.versioned_map.fetch_data(key, txn_idx)
+            };
+            let data_read = match (fetched, kind) {
+                (Ok((version, value)), ReadKind::Value) => DataRead::Versioned(version, value),
+                (Ok((_, value)), ReadKind::Exists) => DataRead::Exists(T::exists(&value)),
+                (Err(MVDataError::NotFound), ReadKind::Value) => DataRead::Storage(base_view.get_state_value(key)),
+                (Err(MVDataError::NotFound), ReadKind::Exists) => {
+                    DataRead::Exists(T::exists(&base_view.get_state_value(key)))
+                }
+                (Err(MVDataError::Dependency(dep_idx)), _) => {
+                    if !self.wait_for_dependency(txn_idx, dep_idx) {
+                        self.log_read(key, || ReadOrigin::Halted);
+                        return ReadResult::Halted;
+                    }
+                    continue;
+                }
+            };
+
+            let result = ReadResult::from_data_read::<T>(&data_read, kind);
+            self.log_read(key, || ReadOrigin::from_data_read::<T>(&data_read, kind));
+            self.captured_reads.borrow_mut().capture_read(key.clone(), data_read);
+            return result;
+        }
+    }
+
+    /// Blocks until `dep_idx` is executed again. Returns `false` if the block execution was halted
+    /// in the meantime.
+    fn wait_for_dependency(&self, txn_idx: TxnIndex, dep_idx: TxnIndex) -> bool {
+        match self.scheduler.wait_for_dependency(txn_idx, dep_idx) {
+            DependencyResult::Dependency => {
+                tracing::debug!(target: LOG_TARGET, txn_idx, dep_idx, "Waiting on dependency");
+                let _timer = counters::DEPENDENCY_WAIT_SECONDS.start_timer();
+
+                let halted = self.scheduler.wait_for_resolution(txn_idx) == DependencyStatus::ExecutionHalted;
+                if let Some(recorder) = self.maybe_recorder {
+                    recorder.record_dependency(txn_idx, dep_idx, halted);
+                }
+                if halted {
+                    tracing::debug!(target: LOG_TARGET, txn_idx, dep_idx, "Execution halted while waiting on dependency");
+                    return false;
+                }
+                true
+            }
+            DependencyResult::Resolved => true,
+            DependencyResult::ExecutionHalted => false,
+        }
+    }
+
+    /// Logs a read of `key` observing `origin`, if the schedule is recorded.
+    fn log_read(&self, key: &T::Key, origin: impl FnOnce() -> ReadOrigin) {
+        if self.maybe_recorder.is_some() {
+            self.read_log.borrow_mut().push(RecordedRead { key: T::format_key(key), origin: origin() });
+        }
+    }
+}
+
+/// State observed by a transaction executed sequentially: its own writes, on top of the values
+/// written by the previous transactions, on top of the base state. The read keys are recorded to
+/// account the proof size of the transaction.
+pub(crate) struct SequentialState<'a, T: Transaction> {
+    unsync_map: &'a HashMap<T::Key, Arc<T::Value>>,
+    read_keys: RefCell<HashSet<T::Key>>,
+    own_writes: RefCell<HashMap<T::Key, Arc<T::Value>>>,
+}
+
+impl<'a, T: Transaction> SequentialState<'a, T> {
+    fn read_value<S: StateView<T>>(&self, key: &T::Key, base_view: &S) -> Arc<T::Value> {
+        if let Some(value) = self.own_writes.borrow().get(key) {
+            return value.clone();
+        }
+        self.read_keys.borrow_mut().insert(key.clone());
+        match self.unsync_map.get(key) {
+            Some(value) => value.clone(),
+            None => base_view.get_state_value(key),
+        }
+    }
+}
+
+/// Values written by the incarnations replayed so far, by version and formatted key.
+pub(crate) type ReplayedWrites<V> = HashMap<Version, HashMap<String, Arc<V>>>;
+
+/// State observed by an incarnation replayed from a recorded schedule: its own writes, on top of
+/// the values its reads observed when recorded.
+pub(crate) struct ReplayState<'a, T: Transaction> {
+    version: Version,
+    replayed_writes: &'a ReplayedWrites<T::Value>,
+    /// Reads of the incarnation when recorded, in order.
+    reads: &'a [RecordedRead],
+    next_read: Cell<usize>,
+    /// First divergence of the incarnation from its record, after which every read is halted.
+    maybe_error: RefCell<Option<ReplayError>>,
+    own_writes: RefCell<HashMap<T::Key, Arc<T::Value>>>,
+}
+
+impl<'a, T: Transaction> ReplayState<'a, T> {
+    fn read_data<S: StateView<T>>(&self, key: &T::Key, kind: ReadKind, base_view: &S) -> ReadResult<T::Value> {
+        if let Some(value) = self.own_writes.borrow().get(key) {
+            return match kind {
+                ReadKind::Value => ReadResult::Value(value.clone()),
+                ReadKind::Exists => ReadResult::Exists(T::exists(value)),
+            };
+        }
+        if self.maybe_error.borrow().is_some() {
+            return ReadResult::Halted;
+        }
+
+        let formatted_key = T::format_key(key);
+        let read_idx = self.next_read.replace(self.next_read.get() + 1);
+        let result = match self.reads.get(read_idx) {
+            Some(read) if read.key == formatted_key => {
+                self.replay_read(key, formatted_key, kind, read.origin, base_view)
+            }
+            _ => Err(ReplayError::Diverged { version: self.version, key: formatted_key }),
+        };
+        result.unwrap_or_else(|err| {
+            *self.maybe_error.borrow_mut() = Some(err);
+            ReadResult::Halted
+        })
+    }
+
+    fn replay_read<S: StateView<T>>(
+        &self,
+        key: &T::Key,
+        formatted_key: String,
+        kind: ReadKind,
+        origin: ReadOrigin,
+        base_view: &S,
+    ) -> Result<ReadResult<T::Value>, ReplayError> {
+        match (kind, origin) {
+            (_, ReadOrigin::Halted) => Ok(ReadResult::Halted),
+            (ReadKind::Exists, ReadOrigin::Exists(exists)) => Ok(ReadResult::Exists(exists)),
+            (ReadKind::Value, ReadOrigin::Storage) => Ok(ReadResult::Value(base_view.get_state_value(key))),
+            (ReadKind::Value, ReadOrigin::Versioned(txn_idx, incarnation)) => {
+                let writer = (txn_idx, incarnation);
+                match self.replayed_writes.get(&writer).and_then(|writes| writes.get(&formatted_key)) {
+                    Some(value) => Ok(ReadResult::Value(value.clone())),
+                    None => Err(ReplayError::MissingWrite { version: self.version, writer, key: formatted_key }),
+                }
+            }
+            _ => Err(ReplayError::Diverged { version: self.version, key: formatted_key }),
+        }
+    }
+}
+
+enum ViewState<'a, T: Transaction> {
+    Sync(ParallelState<'a, T>),
+    Unsync(SequentialState<'a, T>),
+    Replay(ReplayState<'a, T>),
+}
+
+/// The latest state observed by a transaction, whether it is executed in parallel or
+/// sequentially.
+pub struct LatestView<'a, T: Transaction, S: StateView<T>> {
+    base_view: &'a S,
+    latest_view: ViewState<'a, T>,
+    txn_idx: TxnIndex,
+    incarnation: Incarnation,
+}
+
+impl<'a, T: Transaction, S: StateView<T>> LatestView<'a, T, S> {
+    pub(crate) fn new_parallel(
+        base_view: &'a S,
+        versioned_map: &'a VersionedData<T::Key, T::Value>,
+        scheduler: &'a Scheduler,
+        maybe_recorder: Option<&'a ScheduleRecorder>,
+        txn_idx: TxnIndex,
+        incarnation: Incarnation,
+        snapshot: bool,
+    ) -> Self {
+        Self {
+            base_view,
+            latest_view: ViewState::Sync(ParallelState {
+                versioned_map,
+                scheduler,
+                snapshot,
+                captured_reads: RefCell::new(CapturedReads::default()),
+                own_writes: RefCell::default(),
+                maybe_recorder,
+                read_log: RefCell::default(),
+            }),
+            txn_idx,
+            incarnation,
+        }
+    }
+
+    pub(crate) fn new_sequential(
+        base_view: &'a S,
+        unsync_map: &'a HashMap<T::Key, Arc<T::Value>>,
+        txn_idx: TxnIndex,
+    ) -> Self {
+        Self {
+            base_view,
+            latest_view: ViewState::Unsync(SequentialState {
+                unsync_map,
+                read_keys: RefCell::default(),
+                own_writes: RefCell::default(),
+            }),
+            txn_idx,
+            // A transaction is executed once sequentially.
+            incarnation: 0,
+        }
+    }
+
+    pub(crate) fn new_replay(
+        base_view: &'a S,
+        replayed_writes: &'a ReplayedWrites<T::Value>,
+        version: Version,
+        reads: &'a [RecordedRead],
+    ) -> Self {
+        Self {
+            base_view,
+            latest_view: ViewState::Replay(ReplayState {
+                version,
+                replayed_writes,
+                reads,
+                next_read: Cell::new(0),
+                maybe_error: RefCell::default(),
+                own_writes: RefCell::default(),
+            }),
+            txn_idx: version.0,
+            incarnation: version.1,
+        }
+    }
+
+    /// Index of the transaction observing the state.
+    pub fn txn_idx(&self) -> TxnIndex {
+        self.txn_idx
+    }
+
+    /// Incarnation of the transaction observing the state, `0` if it is executed sequentially.
+    pub fn incarnation(&self) -> Incarnation {
+        self.incarnation
+    }
+
+    /// Reads the value of `key` in the child trie `child_info` of the base state, see
+    /// [`StateView::get_child_state_value`]. The read is not captured.
+    pub fn read_child_base(&self, child_info: &ChildInfo, key: &T::Key) -> Option<T::Value> {
+        self.base_view.get_child_state_value(child_info, key)
+    }
+
+    /// Reads the value of `key` as observed by the transaction, i.e. the value it wrote last, if
+    /// any.
+    pub fn read(&self, key: &T::Key) -> ReadResult<T::Value> {
+        match &self.latest_view {
+            ViewState::Sync(state) => state.read_data(key, ReadKind::Value, self.txn_idx, self.base_view),
+            ViewState::Unsync(state) => ReadResult::Value(state.read_value(key, self.base_view)),
+            ViewState::Replay(state) => state.read_data(key, ReadKind::Value, self.base_view),
+        }
+    }
+
+    /// Reads whether `key` exists as observed by the transaction. Only its existence is captured,
+    /// so the read stays valid when lower transactions overwrite the value of an existing key.
+    pub fn exists(&self, key: &T::Key) -> ReadResult<T::Value> {
+        match &self.latest_view {
+            ViewState::Sync(state) => state.read_data(key, ReadKind::Exists, self.txn_idx, self.base_view),
+            ViewState::Unsync(state) => ReadResult::Exists(T::exists(&state.read_value(key, self.base_view))),
+            ViewState::Replay(state) => state.read_data(key, ReadKind::Exists, self.base_view),
+        }
+    }
+
+    /// Records the value written to `key` by the transaction, observed by its next reads of the
+    /// key. The write is not visible to the other transactions: it must be part of the output of
+    /// the transaction to be applied.
+    pub fn write(&self, key: T::Key, value: T::Value) {
+        let own_writes = match &self.latest_view {
+            ViewState::Sync(state) => &state.own_writes,
+            ViewState::Unsync(state) => &state.own_writes,
+            ViewState::Replay(state) => &state.own_writes,
+        };
+        own_writes.borrow_mut().insert(key, Arc::new(value));
+    }
+
+    /// Blocks until the lower transaction `dep_idx` is executed, when executed in parallel.
+    /// Returns `false` if the block execution was halted in the meantime.
+    pub(crate) fn wait_for_dependency(&self, dep_idx: TxnIndex) -> bool {
+        match &self.latest_view {
+            ViewState::Sync(state) => state.wait_for_dependency(self.txn_idx, dep_idx),
+            ViewState::Unsync(_) | ViewState::Replay(_) => true,
+        }
+    }
+
+    /// Takes the reads performed so far during a parallel execution, if the schedule is recorded.
+    pub(crate) fn take_read_log(&self) -> Vec<RecordedRead> {
+        match &self.latest_view {
+            ViewState::Sync(state) => state.read_log.take(),
+            ViewState::Unsync(_) | ViewState::Replay(_) => unreachable!("Reads are only logged by the parallel view"),
+        }
+    }
+
+    /// Consumes the view, returning the reads captured during a parallel execution.
+    pub(crate) fn take_reads(self) -> CapturedReads<T> {
+        match self.latest_view {
+            ViewState::Sync(state) => state.captured_reads.into_inner(),
+            ViewState::Unsync(_) | ViewState::Replay(_) => unreachable!("Reads are only captured by the parallel view"),
+        }
+    }
+
+    /// Consumes the view, returning the keys read during a sequential execution.
+    pub(crate) fn take_read_keys(self) -> HashSet<T::Key> {
+        match self.latest_view {
+            ViewState::Sync(_) | ViewState::Replay(_) => {
+                unreachable!("Read keys are only recorded by the sequential view")
+            }
+            ViewState::Unsync(state) => state.read_keys.into_inner(),
+        }
+    }
+
+    /// Consumes the view of a replayed incarnation, returning whether it performed the reads it
+    /// was recorded with.
+    pub(crate) fn finish_replay(self) -> Result<(), ReplayError> {
+        let ViewState::Replay(state) = self.latest_view else {
+            unreachable!("Only replayed incarnations follow a record");
+        };
+        if let Some(err) = state.maybe_error.into_inner() {
+            return Err(err);
+        }
+        match state.reads.get(state.next_read.get()) {
+            // The incarnation stopped before performing all the recorded reads.
+            Some(read) => Err(ReplayError::Diverged { version: state.version, key: read.key.clone() }),
+            None => Ok(()),
+        }
+    }
+}
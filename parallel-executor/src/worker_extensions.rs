@@ -0,0 +1,92 @@
+//! Extensions of the node made available to the workers applying a batch.
+//!
+//! The `LocalCallExecutor` hands the runtime the extensions of the node, e.g. the keystore, in a
+//! `RefCell<Extensions>` owned by the calling thread, which the workers can not share. Every
+//! worker rather gets a set of its own for the duration of the batch, built by
+//! [`WorkerExtensions`] from extensions registered along with their [`Shareability`]: the
+//! thread-safe ones are shared, and the thread-bound ones are built for every worker, or stubbed
+//! when they are unsafe under speculation, e.g. the transaction pool.
+
+use std::any::TypeId;
+use std::sync::Arc;
+
+use sp_externalities::{Extension, Extensions};
+
+use crate::task::WorkerId;
+
+/// How an extension of the node is made available to the workers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shareability {
+    /// The extension is thread-safe: the workers share it, e.g. the keystore behind an `Arc`.
+    Shared,
+    /// The extension is thread-bound: every worker has an instance of its own.
+    PerWorker,
+    /// The extension is thread-bound and its side effects can not be undone: every worker has a
+    /// stub of its own, e.g. dropping the transactions submitted to the pool.
+    Stubbed,
+}
+
+/// Builds the extension registered under its type for a worker.
+type ExtensionBuilder = dyn Fn(WorkerId) -> Box<dyn Extension> + Send + Sync;
+
+/// Extensions of the node registered for the workers, along with their [`Shareability`].
+#[derive(Clone, Default)]
+pub struct WorkerExtensions {
+    // Builds every extension of a worker, by type of the extension.
+    builders: Vec<(TypeId, Shareability, Arc<ExtensionBuilder>)>,
+}
+
+impl WorkerExtensions {
+    /// Creates an empty set of extensions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shares the thread-safe `extension` with every worker, see [`Shareability::Shared`].
+    pub fn with_shared<E: Extension + Clone + Sync>(self, extension: E) -> Self {
+        self.with_builder(Shareability::Shared, move |_| extension.clone())
+    }
+
+    /// Builds the thread-bound extension of every worker with `build`, see
+    /// [`Shareability::PerWorker`].
+    pub fn with_per_worker<E: Extension>(self, build: impl Fn(WorkerId) -> E + Send + Sync + 'static) -> Self {
+        self.with_builder(Shareability::PerWorker, build)
+    }
+
+    /// Gives every worker the stub built by `stub` in place of a thread-bound extension unsafe
+    /// under speculation, see [`Shareability::Stubbed`].
+    pub fn with_stub<E: Extension>(self, stub: impl Fn() -> E + Send + Sync + 'static) -> Self {
+        self.with_builder(Shareability::Stubbed, move |_| stub())
+    }
+
+    fn with_builder<E: Extension>(
+        mut self,
+        shareability: Shareability,
+        build: impl Fn(WorkerId) -> E + Send + Sync + 'static,
+    ) -> Self {
+        let type_id = TypeId::of::<E>();
+        self.builders.retain(|(registered, _, _)| *registered != type_id);
+        self.builders.push((
+            type_id,
+            shareability,
+            Arc::new(move |worker_id| -> Box<dyn Extension> { Box::new(build(worker_id)) }),
+        ));
+        self
+    }
+
+    /// Shareability of the extension of type `type_id`, `None` if it is not registered.
+    pub fn shareability(&self, type_id: TypeId) -> Option<Shareability> {
+        self.builders.iter().find(|(registered, _, _)| *registered == type_id).map(|(_, shareability, _)| *shareability)
+    }
+
+    /// Builds the extensions of the worker `worker_id`.
+    pub fn extensions(&self, worker_id: WorkerId) -> Extensions {
+        let mut extensions = Extensions::new();
+        for (type_id, _, build) in &self.builders {
+            extensions
+                .register_with_type_id(*type_id, build(worker_id))
+                .expect("Every type of extension is registered once");
+        }
+        extensions
+    }
+}
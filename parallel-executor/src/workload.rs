@@ -0,0 +1,236 @@
+//! Synthetic workloads with a controlled contention, shared by the benches, the fuzz targets and
+//! the integration tests so that their results are comparable from one experiment to the next.
+//!
+//! A [`WorkloadConfig`] generates a batch of [`SyntheticTransaction`]s, each reading and writing a
+//! given number of keys. Every key accessed is one of a few hot keys shared by the whole batch
+//! with the conflict probability of the config, or a key of its own otherwise. The transactions
+//! are wrapped in [`Extrinsic`]s, SCALE encoded, and executed by the [`SyntheticTask`] with the
+//! [`BlockExecutor`](crate::executor::BlockExecutor) without any runtime.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use codec::{Decode, Encode};
+use sp_state_machine::{StorageKey, StorageValue};
+use sp_weights::Weight;
+
+use crate::extrinsic::{Extrinsic, ExtrinsicError};
+use crate::scheduler::TxnIndex;
+use crate::task::{ExecutionStatus, ExecutorTask, TransactionOutput, WorkerId, WriteSet};
+use crate::view::{LatestView, ReadResult, StateView};
+
+/// Prefix of the keys shared by the whole batch.
+const HOT_KEY_PREFIX: &[u8] = b":synthetic:hot:";
+
+/// Prefix of the keys accessed by a single transaction.
+const COLD_KEY_PREFIX: &[u8] = b":synthetic:cold:";
+
+/// Shape of a synthetic batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorkloadConfig {
+    /// Number of transactions of the batch.
+    pub num_txns: usize,
+    /// Number of keys read by every transaction.
+    pub num_reads: usize,
+    /// Number of keys written by every transaction, after its reads.
+    pub num_writes: usize,
+    /// Size of the values written, in bytes.
+    pub value_size: usize,
+    /// Probability, from 0 to 1, that a key accessed is one of the hot keys.
+    pub conflict_probability: f64,
+    /// Number of hot keys. The fewer, the more the accesses to them conflict.
+    pub num_hot_keys: u32,
+    /// Seed of the generator, the same config always generating the same batch.
+    pub seed: u64,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        Self {
+            num_txns: 1_000,
+            num_reads: 4,
+            num_writes: 2,
+            value_size: 32,
+            conflict_probability: 0.0,
+            num_hot_keys: 16,
+            seed: 0x5eed,
+        }
+    }
+}
+
+impl WorkloadConfig {
+    /// Generates the transactions of the batch.
+    pub fn generate(&self) -> Vec<SyntheticTransaction> {
+        assert!((0.0..=1.0).contains(&self.conflict_probability), "The conflict probability is between 0 and 1");
+        assert!(self.num_hot_keys > 0 || self.conflict_probability == 0.0, "Conflicts require a hot key");
+
+        let mut rng = Rng::new(self.seed);
+        (0..self.num_txns as u32)
+            .map(|txn_idx| {
+                let mut key = |access_idx: u32| {
+                    if rng.next_f64() < self.conflict_probability {
+                        hot_key(rng.below(self.num_hot_keys as u64) as u32)
+                    } else {
+                        cold_key(txn_idx, access_idx)
+                    }
+                };
+                let reads = (0..self.num_reads as u32).map(&mut key).collect();
+                let write_keys: Vec<_> =
+                    (self.num_reads as u32..(self.num_reads + self.num_writes) as u32).map(key).collect();
+                let writes = write_keys.into_iter().map(|key| (key, rng.bytes(self.value_size))).collect();
+                SyntheticTransaction { reads, writes }
+            })
+            .collect()
+    }
+
+    /// Generates the transactions of the batch, wrapped in extrinsics.
+    pub fn extrinsics(&self) -> Vec<Extrinsic> {
+        self.generate().iter().map(SyntheticTransaction::to_extrinsic).collect()
+    }
+}
+
+/// The `idx`-th key shared by the whole batch.
+pub fn hot_key(idx: u32) -> StorageKey {
+    [HOT_KEY_PREFIX, &idx.to_be_bytes()].concat()
+}
+
+/// The `access_idx`-th key accessed by the transaction `txn_idx` only.
+fn cold_key(txn_idx: u32, access_idx: u32) -> StorageKey {
+    [COLD_KEY_PREFIX, &txn_idx.to_be_bytes(), &access_idx.to_be_bytes()].concat()
+}
+
+/// Transaction reading some keys, then writing others.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct SyntheticTransaction {
+    /// Keys read, in order.
+    pub reads: Vec<StorageKey>,
+    /// Keys written, in order, along with the values written. The first byte of every value is
+    /// offset by the first bytes of the values read, so that the writes depend on the reads.
+    pub writes: Vec<(StorageKey, StorageValue)>,
+}
+
+impl SyntheticTransaction {
+    /// Wraps the SCALE encoded transaction in an extrinsic.
+    pub fn to_extrinsic(&self) -> Extrinsic {
+        Extrinsic::new(self.encode())
+    }
+
+    /// Performs the accesses of the transaction, reading the keys with `read` and writing them
+    /// with `write`. Returns `None` if a read did not observe any value, e.g. because it halted.
+    pub fn apply(
+        &self,
+        mut read: impl FnMut(&StorageKey) -> Option<Option<StorageValue>>,
+        mut write: impl FnMut(StorageKey, Option<StorageValue>),
+    ) -> Option<SyntheticOutput> {
+        let reads = self.reads.iter().map(&mut read).collect::<Option<Vec<_>>>()?;
+        let offset =
+            reads.iter().flatten().filter_map(|value| value.first()).fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+        let mut writes = BTreeMap::new();
+        for (key, value) in &self.writes {
+            let mut value = value.clone();
+            if let Some(first) = value.first_mut() {
+                *first = first.wrapping_add(offset);
+            }
+            write(key.clone(), Some(value.clone()));
+            writes.insert(key.clone(), Some(value));
+        }
+        Some(SyntheticOutput { reads, writes: writes.into_iter().collect() })
+    }
+}
+
+/// Values read by a synthetic transaction, in order, and the final values of the keys it wrote.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyntheticOutput {
+    /// Values read, `None` for the keys not in the state.
+    pub reads: Vec<Option<StorageValue>>,
+    /// Values written, by key.
+    pub writes: WriteSet<Extrinsic>,
+}
+
+impl TransactionOutput for SyntheticOutput {
+    type Txn = Extrinsic;
+
+    fn get_writes(&self) -> WriteSet<Extrinsic> {
+        self.writes.clone()
+    }
+
+    fn weight(&self) -> Weight {
+        Weight::zero()
+    }
+}
+
+/// Executes the [`SyntheticTransaction`]s encoded in the extrinsics of the batch.
+pub struct SyntheticTask;
+
+impl ExecutorTask for SyntheticTask {
+    type Txn = Extrinsic;
+    type Output = SyntheticOutput;
+    type Error = ExtrinsicError;
+    type Argument = ();
+
+    fn init(_args: (), _worker_id: WorkerId) -> Self {
+        SyntheticTask
+    }
+
+    fn execute_transaction<S: StateView<Extrinsic>>(
+        &self,
+        view: &LatestView<Extrinsic, S>,
+        txn: &Extrinsic,
+        _txn_idx: TxnIndex,
+    ) -> ExecutionStatus<SyntheticOutput, ExtrinsicError> {
+        let synthetic_txn = match SyntheticTransaction::decode(&mut txn.encoded()) {
+            Ok(synthetic_txn) => synthetic_txn,
+            Err(err) => return ExecutionStatus::Abort(ExtrinsicError::Runtime(format!("Invalid transaction: {err}"))),
+        };
+        let read = |key: &StorageKey| match view.read(key) {
+            ReadResult::Value(value) => Some((*value).clone()),
+            ReadResult::Exists(_) => unreachable!("The values of the keys are read"),
+            ReadResult::Halted => None,
+        };
+        match synthetic_txn.apply(read, |key, value| view.write(key, value)) {
+            Some(output) => ExecutionStatus::Success(output),
+            // The incarnation is discarded.
+            None => ExecutionStatus::Success(SyntheticOutput::default()),
+        }
+    }
+}
+
+/// Base state of the synthetic batches, in which no key exists.
+pub struct EmptyState;
+
+impl StateView<Extrinsic> for EmptyState {
+    fn get_state_value(&self, _key: &StorageKey) -> Arc<Option<StorageValue>> {
+        Arc::new(None)
+    }
+}
+
+/// Xorshift generator, so that the batches are random but the same for a given seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift is stuck at 0.
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
@@ -0,0 +1,111 @@
+//! Sequential prologue and epilogue of a block around its batches of extrinsics.
+
+mod common;
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+use common::transfer;
+use sp_blockchain::HeaderBackend;
+use sp_core::traits::CallContext;
+use sp_keyring::AccountKeyring;
+use sp_runtime::transaction_validity::InvalidTransaction;
+use sp_state_machine::OverlayedChanges;
+use sp_weights::Weight;
+use substrate_test_runtime_client::runtime::ExtrinsicBuilder;
+
+#[test]
+fn batches_are_applied_between_the_prologue_and_the_epilogue() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let parallel_executor = common::parallel_executor(backend, 4);
+
+    let changes = RefCell::new(OverlayedChanges::default());
+    let extensions = RefCell::default();
+    let mut pusher = parallel_executor.batch_pusher(genesis_hash, &changes, &None, CallContext::Onchain, &extensions);
+
+    // The batch reads the writes of the prologue, and the epilogue the ones of the batch.
+    let prologue = pusher.apply_inherents(&[transfer(AccountKeyring::Alice, AccountKeyring::Bob, 69, 0)]).unwrap();
+    let batch = pusher
+        .batch_push(&[
+            transfer(AccountKeyring::Alice, AccountKeyring::Charlie, 1, 1),
+            transfer(AccountKeyring::Bob, AccountKeyring::Dave, 42, 0),
+        ])
+        .unwrap();
+    let epilogue = pusher.apply_epilogue(&[transfer(AccountKeyring::Alice, AccountKeyring::Eve, 1, 2)]).unwrap();
+    assert!(prologue.iter().chain(&batch).chain(&epilogue).all(|result| result.is_ok()));
+    assert_eq!(pusher.num_applied(), 4);
+    assert_eq!(pusher.outcome().included.len(), 2);
+
+    let late = [transfer(AccountKeyring::Ferdie, AccountKeyring::Alice, 3, 0)];
+    assert!(pusher.batch_push(&late).is_err());
+    assert!(pusher.apply_inherents(&late).is_err());
+    assert!(pusher.apply_epilogue(&late).is_err());
+    assert_eq!(pusher.num_applied(), 4);
+}
+
+#[test]
+fn batch_stops_at_the_block_weight_limit() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let extensions = RefCell::default();
+    let batch = [
+        transfer(AccountKeyring::Alice, AccountKeyring::Bob, 1, 0),
+        transfer(AccountKeyring::Charlie, AccountKeyring::Dave, 1, 0),
+        transfer(AccountKeyring::Eve, AccountKeyring::Ferdie, 1, 0),
+        transfer(AccountKeyring::Dave, AccountKeyring::Alice, 1, 0),
+    ];
+
+    // Weight of a transfer, as accounted by the runtime.
+    let changes = RefCell::new(OverlayedChanges::default());
+    let parallel_executor = common::parallel_executor(backend.clone(), 1);
+    let mut pusher = parallel_executor.batch_pusher(genesis_hash, &changes, &None, CallContext::Onchain, &extensions);
+    pusher.batch_push(&batch[..1]).unwrap();
+    let weight = pusher.estimated_block_weight();
+    assert!(weight.any_gt(Weight::zero()));
+
+    // Two transfers and a half fit in the block.
+    let limit = Weight::from_parts(weight.ref_time() * 5 / 2, weight.proof_size() * 5 / 2);
+    for concurrency_level in [1, 4] {
+        let parallel_executor =
+            common::parallel_executor(backend.clone(), concurrency_level).with_block_weight_limit(limit);
+        let changes = RefCell::new(OverlayedChanges::default());
+        let mut pusher =
+            parallel_executor.batch_pusher(genesis_hash, &changes, &None, CallContext::Onchain, &extensions);
+        let results = pusher.batch_push(&batch).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(pusher.estimated_block_weight(), weight.saturating_mul(2));
+        assert_eq!(pusher.outcome().included.len(), 2);
+        assert_eq!(pusher.outcome().skipped.len(), 2);
+        assert!(pusher.is_finished());
+    }
+}
+
+#[test]
+fn runaway_extrinsic_is_stopped() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let extensions = RefCell::default();
+    // Reads the state for hours, the deadline is checked at every read.
+    let runaway = ExtrinsicBuilder::new_read(u32::MAX).build();
+    let batch = [
+        transfer(AccountKeyring::Bob, AccountKeyring::Charlie, 1, 0),
+        runaway,
+        transfer(AccountKeyring::Dave, AccountKeyring::Eve, 1, 0),
+    ];
+
+    for concurrency_level in [1, 4] {
+        let parallel_executor = common::parallel_executor(backend.clone(), concurrency_level)
+            .with_extrinsic_timeout(Duration::from_millis(100));
+        let changes = RefCell::new(OverlayedChanges::default());
+        let mut pusher =
+            parallel_executor.batch_pusher(genesis_hash, &changes, &None, CallContext::Onchain, &extensions);
+        let results = pusher.batch_push(&batch).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok() && results[2].is_ok());
+        assert_eq!(results[1], Err(InvalidTransaction::ExhaustsResources.into()));
+        assert_eq!(pusher.outcome().included.len(), 2);
+        assert_eq!(pusher.outcome().skipped.len(), 1);
+    }
+}
@@ -0,0 +1,96 @@
+//! Validation of the existence reads captured by an incarnation, of their upgrade to reads of the
+//! value, and of the reads of values written again by the next incarnation of their writer.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parallel_executor::captured_reads::{CapturedReads, DataRead};
+use parallel_executor::extrinsic::Extrinsic;
+use parallel_executor::versioned_data::VersionedData;
+use parallel_executor::view::StateView;
+
+struct BaseState(HashMap<Vec<u8>, Vec<u8>>);
+
+impl StateView<Extrinsic> for BaseState {
+    fn get_state_value(&self, key: &Vec<u8>) -> Arc<Option<Vec<u8>>> {
+        Arc::new(self.0.get(key).cloned())
+    }
+}
+
+fn key() -> Vec<u8> {
+    b"key".to_vec()
+}
+
+#[test]
+fn existence_read_survives_overwrites_of_an_existing_key() {
+    let base_state = BaseState(HashMap::from([(key(), b"base".to_vec())]));
+    let data_map = VersionedData::new();
+    let mut reads = CapturedReads::<Extrinsic>::default();
+    reads.capture_read(key(), DataRead::Exists(true));
+
+    data_map.write(key(), (0, 0), Some(b"first".to_vec()));
+    assert!(reads.validate_data_reads(&data_map, &base_state, 2));
+    data_map.write(key(), (1, 0), None);
+    assert!(!reads.validate_data_reads(&data_map, &base_state, 2));
+}
+
+#[test]
+fn existence_read_is_validated_against_the_base_state() {
+    let data_map = VersionedData::new();
+    let mut reads = CapturedReads::<Extrinsic>::default();
+    reads.capture_read(key(), DataRead::Exists(false));
+    assert!(reads.validate_data_reads(&data_map, &BaseState(HashMap::new()), 1));
+
+    let mut reads = CapturedReads::<Extrinsic>::default();
+    reads.capture_read(key(), DataRead::Exists(false));
+    assert!(!reads.validate_data_reads(&data_map, &BaseState(HashMap::from([(key(), Vec::new())])), 1));
+}
+
+#[test]
+fn upgrade_to_versioned_read_validates_the_version() {
+    let base_state = BaseState(HashMap::new());
+    let data_map = VersionedData::new();
+    let mut reads = CapturedReads::<Extrinsic>::default();
+    data_map.write(key(), (0, 0), Some(b"first".to_vec()));
+    reads.capture_read(key(), DataRead::Exists(true));
+    reads.capture_read(key(), DataRead::Versioned((0, 0), Arc::new(Some(b"first".to_vec()))));
+
+    assert!(reads.validate_data_reads(&data_map, &base_state, 2));
+    // The key still exists, but the value read changed.
+    data_map.write(key(), (0, 1), Some(b"second".to_vec()));
+    assert!(!reads.validate_data_reads(&data_map, &base_state, 2));
+}
+
+#[test]
+fn upgrade_disagreeing_with_the_existence_read_is_never_valid() {
+    let base_state = BaseState(HashMap::new());
+    let data_map = VersionedData::new();
+    let mut reads = CapturedReads::<Extrinsic>::default();
+    reads.capture_read(key(), DataRead::Exists(false));
+    data_map.write(key(), (0, 0), Some(b"first".to_vec()));
+    reads.capture_read(key(), DataRead::Versioned((0, 0), Arc::new(Some(b"first".to_vec()))));
+
+    assert_eq!(reads.invalid_read(&data_map, &base_state, 1), Some(&key()));
+}
+
+#[test]
+fn rewrite_of_the_same_value_keeps_the_readers_valid() {
+    let base_state = BaseState(HashMap::new());
+    let data_map = VersionedData::new();
+    data_map.write(key(), (0, 0), Some(b"first".to_vec()));
+    let mut reads = CapturedReads::<Extrinsic>::default();
+    let (version, value) = data_map.fetch_data(&key(), 1).unwrap();
+    reads.capture_read(key(), DataRead::Versioned(version, value));
+    let num_updates = data_map.num_updates();
+
+    // The writer is aborted, and its next incarnation writes the same value.
+    data_map.mark_estimate(&key(), 0);
+    assert!(!reads.validate_data_reads(&data_map, &base_state, 1));
+    data_map.write(key(), (0, 1), Some(b"first".to_vec()));
+    assert!(reads.validate_data_reads(&data_map, &base_state, 1));
+    assert_eq!(data_map.num_updates(), num_updates + 1);
+
+    // The next one changes it.
+    data_map.write(key(), (0, 2), Some(b"second".to_vec()));
+    assert!(!reads.validate_data_reads(&data_map, &base_state, 1));
+}
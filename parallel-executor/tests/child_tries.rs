@@ -0,0 +1,47 @@
+//! Locks of the child tries, versioning the changes made to every child trie as a whole.
+
+use codec::Encode;
+use parallel_executor::child_tries::{self, ChildTrieChanges};
+use sp_core::storage::ChildInfo;
+use sp_runtime::traits::BlakeTwo256;
+use sp_state_machine::OverlayedChanges;
+
+#[test]
+fn locks_are_told_apart_from_the_keys_of_the_runtime() {
+    let child_info = ChildInfo::new_default(b"contract");
+    let lock_key = child_tries::lock_key(&child_info);
+
+    assert!(child_tries::is_lock_key(&lock_key));
+    assert_eq!(child_tries::locked_child_info(&lock_key), Some(child_info.clone()));
+    assert!(!child_tries::is_lock_key(&child_info.prefixed_storage_key().into_inner()));
+    assert_eq!(child_tries::locked_child_info(b"key"), None);
+    assert_ne!(child_tries::lock_key(&ChildInfo::new_default(b"other")), lock_key);
+}
+
+#[test]
+fn child_changes_before_the_batch_are_locked() {
+    let first = ChildInfo::new_default(b"first");
+    let second = ChildInfo::new_default(b"second");
+    let mut changes = OverlayedChanges::<BlakeTwo256>::default();
+    changes.set_storage(b"top".to_vec(), Some(b"value".to_vec()));
+    changes.set_child_storage(&first, b"key".to_vec(), Some(b"value".to_vec()));
+    changes.set_child_storage(&second, b"removed".to_vec(), None);
+
+    let mut locks = child_tries::base_locks(&changes);
+    locks.sort();
+    let expected_first = ChildTrieChanges::from([(b"key".to_vec(), Some(b"value".to_vec()))]);
+    let expected_second = ChildTrieChanges::from([(b"removed".to_vec(), None)]);
+    assert_eq!(
+        locks,
+        vec![
+            (child_tries::lock_key(&first), Some(expected_first.encode())),
+            (child_tries::lock_key(&second), Some(expected_second.encode())),
+        ]
+    );
+
+    let (child_info, child_changes) = child_tries::child_changes(&locks[0].0, locks[0].1.as_deref()).unwrap();
+    assert_eq!(child_info, first);
+    assert_eq!(child_changes, expected_first);
+    assert!(child_tries::child_changes(b"top", Some(b"value")).is_none());
+    assert!(child_tries::decode_lock(None).is_empty());
+}
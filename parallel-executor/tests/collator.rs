@@ -0,0 +1,121 @@
+//! Blocks of a parachain built in batches within the budget of their proof of validity.
+
+mod common;
+
+use std::cell::RefCell;
+
+use codec::Encode;
+use common::transfer;
+use parallel_executor::collator::{compact_proof, PARACHAIN_SYSTEM_PALLET};
+use parallel_executor::extrinsic::Extrinsic as EncodedExtrinsic;
+use parallel_executor::limit_processor::{ProofRecorderEstimator, ProofSizeEstimator};
+use sc_client_api::Backend as _;
+use sp_api::ProofRecorder;
+use sp_blockchain::HeaderBackend;
+use sp_core::traits::CallContext;
+use sp_keyring::AccountKeyring;
+use sp_runtime::traits::{BlakeTwo256, Header as _};
+use sp_state_machine::backend::AsTrieBackend;
+use sp_state_machine::OverlayedChanges;
+use substrate_test_runtime_client::runtime::{Block, Extrinsic};
+
+fn transfers() -> Vec<Extrinsic> {
+    vec![
+        transfer(AccountKeyring::Alice, AccountKeyring::Bob, 69, 0),
+        transfer(AccountKeyring::Bob, AccountKeyring::Charlie, 42, 0),
+        transfer(AccountKeyring::Charlie, AccountKeyring::Dave, 7, 0),
+        transfer(AccountKeyring::Ferdie, AccountKeyring::Alice, 3, 0),
+    ]
+}
+
+#[test]
+fn batches_stop_at_the_size_limit_and_the_proof_is_compacted() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let state_root = *client.header(genesis_hash).unwrap().unwrap().state_root();
+    let parallel_executor = common::parallel_executor(backend, 4);
+
+    let extrinsics = transfers();
+    // Room for the first two transfers only, the proof aside.
+    let size_limit = 1 + extrinsics[..2].iter().map(|xt| xt.encoded_size()).sum::<usize>();
+
+    let changes = RefCell::new(OverlayedChanges::default());
+    let recorder = Some(ProofRecorder::<Block>::default());
+    let extensions = RefCell::default();
+    let mut pusher = parallel_executor
+        .batch_pusher(genesis_hash, &changes, &recorder, CallContext::Onchain, &extensions)
+        .with_size_limit(size_limit);
+    let results = pusher.batch_push(&extrinsics).unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result.is_ok()));
+    assert_eq!(pusher.outcome().skipped.len(), 2);
+    assert!(pusher.is_finished());
+
+    // The next batches are not applied.
+    assert!(pusher.batch_push(&transfers()[2..]).unwrap().is_empty());
+    assert_eq!(pusher.outcome().skipped.len(), 4);
+
+    let recorder = recorder.unwrap();
+    let proof = compact_proof(&recorder, state_root).unwrap();
+    let (storage_proof, root) = proof.to_storage_proof::<BlakeTwo256>(Some(&state_root)).unwrap();
+    assert_eq!(root, state_root);
+    assert_eq!(storage_proof, recorder.to_storage_proof());
+}
+
+#[test]
+fn batches_stop_at_the_proof_size_limit() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let extrinsics = transfers();
+
+    // Size added to the proof by every transfer, the nodes proving the keys it accesses that the
+    // previous transfers did not.
+    let state = backend.state_at(genesis_hash).unwrap();
+    let mut estimator = ProofRecorderEstimator::new(state.as_trie_backend());
+    let parallel_executor = common::parallel_executor(backend.clone(), 1);
+    let changes = RefCell::new(OverlayedChanges::default());
+    let proof_sizes: Vec<usize> = extrinsics
+        .iter()
+        .map(|xt| {
+            let xt = EncodedExtrinsic::new(xt.encode());
+            let report = parallel_executor
+                .apply_extrinsic_with_access_report(genesis_hash, &xt, &changes, &None, CallContext::Onchain)
+                .unwrap();
+            report.reads.iter().chain(&report.writes).map(|key| estimator.record_access(key)).sum()
+        })
+        .collect();
+    assert!(proof_sizes[2] > 0);
+
+    for concurrency_level in [1, 4] {
+        let recorder = Some(ProofRecorder::<Block>::default());
+        // Room for the proof of the first two transfers, but not of the third one.
+        let limit = recorder.as_ref().unwrap().estimate_encoded_size() + proof_sizes[..3].iter().sum::<usize>() - 1;
+        let parallel_executor =
+            common::parallel_executor(backend.clone(), concurrency_level).with_proof_size_limit(limit);
+        let changes = RefCell::new(OverlayedChanges::default());
+        let extensions = RefCell::default();
+        let mut pusher =
+            parallel_executor.batch_pusher(genesis_hash, &changes, &recorder, CallContext::Onchain, &extensions);
+        let results = pusher.batch_push(&extrinsics).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(pusher.outcome().skipped.len(), 2);
+        assert!(pusher.is_finished());
+    }
+}
+
+#[test]
+fn batches_wait_for_the_validation_data() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let parallel_executor = common::parallel_executor(backend, 4);
+
+    let changes = RefCell::new(OverlayedChanges::default());
+    let extensions = RefCell::default();
+    let mut pusher = parallel_executor
+        .batch_pusher(genesis_hash, &changes, &None, CallContext::Onchain, &extensions)
+        .with_validation_data(PARACHAIN_SYSTEM_PALLET);
+    // The test runtime has no validation data inherent.
+    assert!(pusher.batch_push(&transfers()).is_err());
+    assert_eq!(pusher.num_applied(), 0);
+}
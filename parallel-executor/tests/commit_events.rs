@@ -0,0 +1,60 @@
+//! Transactions notified to the commit observer of the block executor as they are committed.
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{MockIncarnation, MockOutput, MockState, MockTask, MockTransaction};
+use parallel_executor::commit_events::CommitObserver;
+use parallel_executor::executor::BlockExecutor;
+use parallel_executor::scheduler::TxnIndex;
+
+/// Collects the transactions committed, along with their output.
+#[derive(Default)]
+struct Collector(Mutex<Vec<(TxnIndex, MockOutput)>>);
+
+impl CommitObserver<MockTransaction, MockOutput> for Collector {
+    fn on_commit(&self, txn_idx: TxnIndex, _txn: &MockTransaction, output: &MockOutput) {
+        self.0.lock().unwrap().push((txn_idx, output.clone()));
+    }
+}
+
+/// Every transaction adds its index to the same key, and half of them write a key of their own.
+fn conflicting_block(len: usize) -> Vec<MockTransaction> {
+    (0..len)
+        .map(|txn_idx| {
+            let writes = if txn_idx % 2 == 0 { vec![(100 + txn_idx as u32, 1)] } else { vec![] };
+            MockTransaction::from_behavior(MockIncarnation::new(vec![], writes, vec![(0, txn_idx as u64)]))
+        })
+        .collect()
+}
+
+#[test]
+fn committed_transactions_are_observed_in_order_with_their_final_output() {
+    for concurrency_level in [1, 4] {
+        let block = conflicting_block(32);
+        let collector = Arc::new(Collector::default());
+        let executor = BlockExecutor::<MockTransaction, MockTask, MockState>::new(concurrency_level, None)
+            .with_commit_observer(collector.clone());
+        let block_output = executor.execute_block((), &block, &MockState, None).unwrap();
+
+        let observed = std::mem::take(&mut *collector.0.lock().unwrap());
+        let expected: Vec<_> =
+            block_output.outputs.into_iter().enumerate().map(|(idx, output)| (idx as TxnIndex, output)).collect();
+        assert_eq!(observed, expected, "Concurrency level {concurrency_level}");
+    }
+}
+
+#[test]
+fn transactions_left_out_of_the_block_are_not_observed() {
+    let mut block = conflicting_block(16);
+    block.insert(8, MockTransaction::SkipRest);
+    let collector = Arc::new(Collector::default());
+    let executor =
+        BlockExecutor::<MockTransaction, MockTask, MockState>::new(4, None).with_commit_observer(collector.clone());
+    let block_output = executor.execute_block((), &block, &MockState, None).unwrap();
+
+    let observed: Vec<_> = collector.0.lock().unwrap().iter().map(|(txn_idx, _)| *txn_idx).collect();
+    assert_eq!(observed, (0..9).collect::<Vec<_>>());
+    assert_eq!(block_output.outputs.len(), 9);
+}
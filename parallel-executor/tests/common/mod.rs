@@ -0,0 +1,292 @@
+//! Mock transactions with declarative behaviors, and the baseline sequential execution they are
+//! checked against, to test the block executor without a runtime. Along with the client of the
+//! test runtime and its transfers, to test the `ParallelLocalCallExecutor`.
+
+// Every test only uses some of the helpers.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use parallel_executor::executor::BlockOutput;
+use parallel_executor::scheduler::TxnIndex;
+use parallel_executor::task::{
+    ExecutionCancelled, ExecutionPanic, ExecutionStatus, ExecutorTask, Transaction, TransactionOutput, WorkerId,
+    WriteSet,
+};
+use parallel_executor::view::{LatestView, ReadResult, StateView};
+use parallel_executor::ParallelLocalCallExecutor;
+use sc_client_api::execution_extensions::ExecutionExtensions;
+use sc_service::ClientConfig;
+use sp_keyring::AccountKeyring;
+use sp_weights::Weight;
+use substrate_test_runtime_client::runtime::{Block, Extrinsic, Transfer};
+use substrate_test_runtime_client::{
+    Backend, Client, DefaultTestClientBuilderExt, TestClientBuilder, TestClientBuilderExt, WasmExecutor,
+};
+
+pub type Key = u32;
+pub type Value = u64;
+
+/// Accesses of an incarnation of a [`MockTransaction`].
+#[derive(Debug, Clone, Default)]
+pub struct MockIncarnation {
+    /// Keys read, whose values are reported in the output.
+    pub reads: Vec<Key>,
+    /// Values written.
+    pub writes: Vec<(Key, Value)>,
+    /// Amounts added to the values of keys, read and written after the plain writes.
+    pub deltas: Vec<(Key, Value)>,
+}
+
+impl MockIncarnation {
+    pub fn new(reads: Vec<Key>, writes: Vec<(Key, Value)>, deltas: Vec<(Key, Value)>) -> Self {
+        Self { reads, writes, deltas }
+    }
+}
+
+/// Transaction whose behavior is declared up front.
+#[derive(Debug, Clone)]
+pub enum MockTransaction {
+    /// Performs the accesses of its incarnation, the `i`-th incarnation behaving as the `i`-th
+    /// behavior modulo their number.
+    Write { incarnation_counter: Arc<AtomicUsize>, incarnation_behaviors: Vec<MockIncarnation> },
+    /// Succeeds without any access, and skips the rest of the block.
+    SkipRest,
+    /// Fails, aborting the execution of the block.
+    Abort,
+    /// Fails, ending the block before it as it is only supported sequentially.
+    SequentialOnly,
+}
+
+impl MockTransaction {
+    /// Transaction behaving the same at every incarnation.
+    pub fn from_behavior(behavior: MockIncarnation) -> Self {
+        Self::from_behaviors(vec![behavior])
+    }
+
+    /// Transaction behaving as the given behaviors, in turn, from one incarnation to the next.
+    pub fn from_behaviors(incarnation_behaviors: Vec<MockIncarnation>) -> Self {
+        assert!(!incarnation_behaviors.is_empty(), "A transaction behaves one way at least");
+        Self::Write { incarnation_counter: Arc::default(), incarnation_behaviors }
+    }
+
+    /// Behavior of the last incarnation executed, if any.
+    fn last_behavior(&self) -> Option<&MockIncarnation> {
+        match self {
+            Self::Write { incarnation_counter, incarnation_behaviors } => {
+                let executed = incarnation_counter.load(Ordering::SeqCst);
+                let incarnation = executed.checked_sub(1)?;
+                Some(&incarnation_behaviors[incarnation % incarnation_behaviors.len()])
+            }
+            Self::SkipRest | Self::Abort | Self::SequentialOnly => None,
+        }
+    }
+}
+
+impl Transaction for MockTransaction {
+    type Key = Key;
+    type Value = Value;
+}
+
+/// Values read by a mock transaction, in order, and the final values of the keys it wrote.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MockOutput {
+    pub reads: Vec<Value>,
+    pub writes: WriteSet<MockTransaction>,
+}
+
+impl TransactionOutput for MockOutput {
+    type Txn = MockTransaction;
+
+    fn get_writes(&self) -> WriteSet<MockTransaction> {
+        self.writes.clone()
+    }
+
+    fn weight(&self) -> Weight {
+        Weight::zero()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockError {
+    /// The transaction declared to abort the block.
+    Aborted,
+    /// The transaction declared to be only supported sequentially.
+    SequentialOnly,
+    Panic(ExecutionPanic),
+    Cancelled,
+}
+
+impl From<ExecutionPanic> for MockError {
+    fn from(panic: ExecutionPanic) -> Self {
+        MockError::Panic(panic)
+    }
+}
+
+impl From<ExecutionCancelled> for MockError {
+    fn from(_: ExecutionCancelled) -> Self {
+        MockError::Cancelled
+    }
+}
+
+/// Performs the accesses of `behavior`, reading the keys with `read` and writing them with
+/// `write`. Returns `None` if a read halted the incarnation.
+fn perform(
+    behavior: &MockIncarnation,
+    mut read: impl FnMut(Key) -> Option<Value>,
+    mut write: impl FnMut(Key, Value),
+) -> Option<MockOutput> {
+    let reads = behavior.reads.iter().map(|key| read(*key)).collect::<Option<Vec<_>>>()?;
+    let mut writes = HashMap::new();
+    for (key, value) in &behavior.writes {
+        write(*key, *value);
+        writes.insert(*key, *value);
+    }
+    for (key, delta) in &behavior.deltas {
+        let value = read(*key)? + delta;
+        write(*key, value);
+        writes.insert(*key, value);
+    }
+    let mut writes: Vec<_> = writes.into_iter().collect();
+    writes.sort();
+    Some(MockOutput { reads, writes })
+}
+
+pub struct MockTask;
+
+impl ExecutorTask for MockTask {
+    type Txn = MockTransaction;
+    type Output = MockOutput;
+    type Error = MockError;
+    type Argument = ();
+
+    fn init(_args: (), _worker_id: WorkerId) -> Self {
+        MockTask
+    }
+
+    fn execute_transaction<S: StateView<MockTransaction>>(
+        &self,
+        view: &LatestView<MockTransaction, S>,
+        txn: &MockTransaction,
+        _txn_idx: TxnIndex,
+    ) -> ExecutionStatus<MockOutput, MockError> {
+        let (incarnation_counter, incarnation_behaviors) = match txn {
+            MockTransaction::Write { incarnation_counter, incarnation_behaviors } => {
+                (incarnation_counter, incarnation_behaviors)
+            }
+            MockTransaction::SkipRest => return ExecutionStatus::SkipRest(MockOutput::default()),
+            MockTransaction::Abort => return ExecutionStatus::Abort(MockError::Aborted),
+            MockTransaction::SequentialOnly => return ExecutionStatus::Abort(MockError::SequentialOnly),
+        };
+        let incarnation = incarnation_counter.fetch_add(1, Ordering::SeqCst);
+        let behavior = &incarnation_behaviors[incarnation % incarnation_behaviors.len()];
+        let read = |key| match view.read(&key) {
+            ReadResult::Value(value) => Some(*value),
+            ReadResult::Exists(_) => unreachable!("The values of the keys are read"),
+            ReadResult::Halted => None,
+        };
+        match perform(behavior, read, |key, value| view.write(key, value)) {
+            Some(output) => ExecutionStatus::Success(output),
+            // The incarnation is discarded.
+            None => ExecutionStatus::Success(MockOutput::default()),
+        }
+    }
+
+    fn ends_parallel_segment(err: &MockError) -> bool {
+        *err == MockError::SequentialOnly
+    }
+}
+
+/// Base state in which every key holds its own index.
+pub struct MockState;
+
+impl StateView<MockTransaction> for MockState {
+    fn get_state_value(&self, key: &Key) -> Arc<Value> {
+        Arc::new(*key as Value)
+    }
+}
+
+/// Outputs of a block of mock transactions executed one after the other.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BaselineOutput {
+    Success { outputs: Vec<MockOutput>, writes: HashMap<Key, Value> },
+    Aborted,
+}
+
+impl BaselineOutput {
+    /// Executes `block` sequentially on top of [`MockState`], every transaction behaving as its
+    /// last incarnation executed by the block executor.
+    pub fn generate(block: &[MockTransaction]) -> Self {
+        // Read and written by every access, so that a transaction reads its own writes.
+        let state = RefCell::new(HashMap::new());
+        let mut outputs = Vec::new();
+        for txn in block {
+            match txn {
+                MockTransaction::Write { .. } => {
+                    let behavior = txn.last_behavior().expect("Every transaction applied is executed");
+                    let read = |key| Some(state.borrow().get(&key).copied().unwrap_or(key as Value));
+                    let output = perform(behavior, read, |key, value| {
+                        state.borrow_mut().insert(key, value);
+                    })
+                    .expect("Sequential reads never halt");
+                    outputs.push(output);
+                }
+                MockTransaction::SkipRest => {
+                    outputs.push(MockOutput::default());
+                    break;
+                }
+                MockTransaction::Abort => return Self::Aborted,
+                MockTransaction::SequentialOnly => break,
+            }
+        }
+        Self::Success { outputs, writes: state.into_inner() }
+    }
+
+    /// Asserts that `result`, the output of the block executor, matches the baseline.
+    pub fn assert_output(&self, result: &Result<BlockOutput<MockOutput>, MockError>) {
+        match (self, result) {
+            (Self::Success { outputs, writes }, Ok(block_output)) => {
+                assert_eq!(&block_output.outputs, outputs);
+                let block_writes: HashMap<_, _> =
+                    block_output.writes.iter().map(|(key, value)| (*key, **value)).collect();
+                assert_eq!(&block_writes, writes);
+            }
+            (Self::Aborted, Err(MockError::Aborted)) => {}
+            (baseline, result) => panic!("Baseline {baseline:?} does not match {result:?}"),
+        }
+    }
+}
+
+/// Client of the test runtime at genesis, along with its backend.
+pub fn test_client() -> (Client<Backend>, Arc<Backend>) {
+    let builder = TestClientBuilder::new();
+    let backend = builder.backend();
+    (builder.build(), backend)
+}
+
+/// Executor applying the extrinsics of the test runtime on top of `backend` with
+/// `concurrency_level` workers.
+pub fn parallel_executor(
+    backend: Arc<Backend>,
+    concurrency_level: usize,
+) -> ParallelLocalCallExecutor<Block, Backend, WasmExecutor> {
+    let executor = substrate_test_runtime_client::new_native_or_wasm_executor();
+    ParallelLocalCallExecutor::new(
+        backend,
+        executor.clone(),
+        ClientConfig::default(),
+        ExecutionExtensions::new(None, Arc::new(executor)),
+        concurrency_level,
+    )
+    .unwrap()
+    // The test runtime does not declare the batch method.
+    .with_legacy_runtimes()
+}
+
+/// Transfer of `amount` from `from` to `to`, the `nonce`-th extrinsic of `from`.
+pub fn transfer(from: AccountKeyring, to: AccountKeyring, amount: u64, nonce: u64) -> Extrinsic {
+    Transfer { from: from.into(), to: to.into(), amount, nonce }.into_unchecked_extrinsic()
+}
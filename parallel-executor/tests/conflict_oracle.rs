@@ -0,0 +1,55 @@
+//! Exhaustive predictions of a conflict oracle, only trusted where the scheduler policy enforces
+//! them.
+
+mod common;
+
+use std::sync::Arc;
+
+use common::{BaselineOutput, Key, MockIncarnation, MockState, MockTask, MockTransaction};
+use parallel_executor::conflict_oracle::{ConflictOracle, KeySet};
+use parallel_executor::executor::{BlockExecutor, SchedulerPolicy};
+use parallel_executor::hot_keys::DEFAULT_ABORT_THRESHOLD;
+
+/// Declares every transaction to read key 0 and to write nothing, exhaustively.
+struct ReadOnlyOracle;
+
+impl ConflictOracle<MockTransaction> for ReadOnlyOracle {
+    fn predict_reads(&self, _txn: &MockTransaction) -> KeySet<Key> {
+        KeySet::Exhaustive(vec![0])
+    }
+
+    fn predict_writes(&self, _txn: &MockTransaction) -> KeySet<Key> {
+        KeySet::Exhaustive(Vec::new())
+    }
+}
+
+/// Every transaction adds to key 0, which the lower transactions wrongly declare not to write, so
+/// that every one of them is predicted to depend on no lower one.
+fn wrongly_declared_deltas() -> Vec<MockTransaction> {
+    (0..32).map(|_| MockTransaction::from_behavior(MockIncarnation::new(vec![], vec![], vec![(0, 1)]))).collect()
+}
+
+fn executor(policy: SchedulerPolicy) -> BlockExecutor<MockTransaction, MockTask, MockState> {
+    BlockExecutor::new(4, None).with_conflict_oracle(Arc::new(ReadOnlyOracle)).with_scheduler_policy(policy)
+}
+
+#[test]
+fn wrong_exhaustive_predictions_are_validated_when_not_enforced() {
+    let executor = executor(SchedulerPolicy::Hybrid { abort_threshold: DEFAULT_ABORT_THRESHOLD });
+    // The higher transactions read the key before the lower ones write it in some of the runs.
+    for _ in 0..16 {
+        let block = wrongly_declared_deltas();
+        let result = executor.execute_block((), &block, &MockState, None);
+        BaselineOutput::generate(&block).assert_output(&result);
+    }
+}
+
+#[test]
+fn wrong_exhaustive_predictions_end_the_block_when_enforced() {
+    let executor = executor(SchedulerPolicy::Conservative);
+    let block_output = executor.execute_block((), &wrongly_declared_deltas(), &MockState, None).unwrap();
+
+    // The first transaction writes a key it did not declare.
+    assert!(block_output.outputs.is_empty());
+    assert!(block_output.writes.is_empty());
+}
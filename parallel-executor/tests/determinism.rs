@@ -0,0 +1,58 @@
+//! Determinism audit of the parallel application of a block by the `ParallelLocalCallExecutor`.
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::transfer;
+use parallel_executor::determinism::{AuditRun, Divergence, AUDIT_CONCURRENCY_LEVELS};
+use sp_blockchain::HeaderBackend;
+use sp_core::storage::StateVersion;
+use sp_keyring::AccountKeyring;
+
+fn run(concurrency_level: usize, changes: &[(&[u8], &[u8])]) -> AuditRun<u64> {
+    AuditRun {
+        concurrency_level,
+        results: vec![Ok(Ok(())); 2],
+        events: None,
+        changes: changes.iter().map(|(key, value)| (key.to_vec(), Some(value.to_vec()))).collect::<BTreeMap<_, _>>(),
+        storage_root: 0,
+    }
+}
+
+#[test]
+fn transfers_are_applied_deterministically() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let parallel_executor = common::parallel_executor(backend, 4);
+
+    let extrinsics = vec![
+        transfer(AccountKeyring::Alice, AccountKeyring::Bob, 69, 0),
+        transfer(AccountKeyring::Bob, AccountKeyring::Charlie, 42, 0),
+        transfer(AccountKeyring::Alice, AccountKeyring::Eve, 1, 1),
+        transfer(AccountKeyring::Ferdie, AccountKeyring::Alice, 3, 0),
+    ];
+    let report = parallel_executor
+        .audit_determinism(genesis_hash, &extrinsics, &AUDIT_CONCURRENCY_LEVELS, StateVersion::V1)
+        .unwrap();
+
+    assert!(report.is_deterministic(), "{:?}", report.divergences);
+    assert_eq!(report.sequential.results.len(), extrinsics.len());
+    assert_eq!(report.parallel.len(), AUDIT_CONCURRENCY_LEVELS.len());
+}
+
+#[test]
+fn first_divergent_key_is_reported() {
+    let sequential = run(1, &[(b"a", b"1"), (b"b", b"2"), (b"c", b"3")]);
+    let parallel = run(4, &[(b"a", b"1"), (b"b", b"5"), (b"c", b"6")]);
+
+    assert_eq!(Divergence::between(&sequential, &sequential), None);
+    assert_eq!(
+        Divergence::between(&sequential, &parallel),
+        Some(Divergence::Key {
+            key: b"b".to_vec(),
+            expected: Some(Some(b"2".to_vec())),
+            actual: Some(Some(b"5".to_vec()))
+        })
+    );
+}
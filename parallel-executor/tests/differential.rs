@@ -0,0 +1,138 @@
+//! Differential test of the parallel application of batches by the `ParallelLocalCallExecutor`:
+//! blocks of random transfers and data inclusions must result in the same changes, storage root
+//! and results as when their extrinsics are applied one after the other with `apply_extrinsic`,
+//! as the block builder does, whatever the number of workers.
+
+mod common;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use codec::{Decode, Encode};
+use common::transfer;
+use parallel_executor::extrinsic::APPLY_EXTRINSIC_METHOD;
+use sc_client_api::CallExecutor;
+use sp_blockchain::HeaderBackend;
+use sp_core::storage::StateVersion;
+use sp_core::traits::CallContext;
+use sp_keyring::AccountKeyring;
+use sp_runtime::traits::HashingFor;
+use sp_runtime::ApplyExtrinsicResult;
+use sp_state_machine::{OverlayedChanges, StorageKey, StorageValue};
+use substrate_test_runtime_client::runtime::{Block, Extrinsic, ExtrinsicBuilder};
+
+const ACCOUNTS: [AccountKeyring; 6] = [
+    AccountKeyring::Alice,
+    AccountKeyring::Bob,
+    AccountKeyring::Charlie,
+    AccountKeyring::Dave,
+    AccountKeyring::Eve,
+    AccountKeyring::Ferdie,
+];
+
+/// Xorshift generator, so that the blocks are random but the same from one run to the next.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+/// Random block of transfers between the test accounts, some of them with a stale or future
+/// nonce, interleaved with data inclusions.
+fn random_block(seed: u64, len: usize) -> Vec<Extrinsic> {
+    let mut rng = Rng(seed);
+    let mut nonces = [0; ACCOUNTS.len()];
+    (0..len)
+        .map(|_| {
+            if rng.below(4) == 0 {
+                let data = rng.next().to_le_bytes().to_vec();
+                return ExtrinsicBuilder::new_include_data(data).build();
+            }
+            let from = rng.below(ACCOUNTS.len() as u64) as usize;
+            let to = rng.below(ACCOUNTS.len() as u64) as usize;
+            let nonce = match rng.below(8) {
+                0 => nonces[from] + 1,
+                _ => {
+                    nonces[from] += 1;
+                    nonces[from] - 1
+                }
+            };
+            transfer(ACCOUNTS[from], ACCOUNTS[to], rng.below(100), nonce)
+        })
+        .collect()
+}
+
+type Changes = BTreeMap<StorageKey, Option<StorageValue>>;
+
+fn into_changes(changes: &RefCell<OverlayedChanges<HashingFor<Block>>>) -> Changes {
+    changes.borrow().changes().map(|(key, value)| (key.clone(), value.value().cloned())).collect()
+}
+
+#[test]
+fn parallel_blocks_match_sequential_blocks() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let parallel_executor = |concurrency_level| common::parallel_executor(backend.clone(), concurrency_level);
+    let sequential_executor = parallel_executor(1);
+
+    for seed in 1..=8 {
+        let block = random_block(seed, 40);
+
+        let sequential_changes = RefCell::new(OverlayedChanges::default());
+        let sequential_results: Vec<ApplyExtrinsicResult> = block
+            .iter()
+            .map(|xt| {
+                let result = sequential_executor
+                    .executor
+                    .contextual_call(
+                        genesis_hash,
+                        APPLY_EXTRINSIC_METHOD,
+                        &xt.encode(),
+                        &sequential_changes,
+                        &None,
+                        CallContext::Onchain,
+                        &RefCell::default(),
+                    )
+                    .unwrap();
+                Decode::decode(&mut &result[..]).unwrap()
+            })
+            .collect();
+        let (sequential_root, _) =
+            sequential_executor.storage_root(genesis_hash, &sequential_changes.borrow(), StateVersion::V1).unwrap();
+
+        for concurrency_level in 1..=16 {
+            let parallel_executor = parallel_executor(concurrency_level);
+            let changes = RefCell::new(OverlayedChanges::default());
+            let results = parallel_executor
+                .apply_extrinsics_parallel(
+                    genesis_hash,
+                    &block,
+                    &changes,
+                    &None,
+                    CallContext::Onchain,
+                    &RefCell::default(),
+                    None,
+                )
+                .unwrap();
+            let (root, _) = parallel_executor.storage_root(genesis_hash, &changes.borrow(), StateVersion::V1).unwrap();
+
+            assert_eq!(results, sequential_results, "seed {seed}, {concurrency_level} workers");
+            // The events are compared along with the rest of the changes.
+            assert_eq!(
+                into_changes(&changes),
+                into_changes(&sequential_changes),
+                "seed {seed}, {concurrency_level} workers"
+            );
+            assert_eq!(root, sequential_root, "seed {seed}, {concurrency_level} workers");
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Property-based differential test of the block executor: random blocks of transactions
+//! accessing a few shared keys must observe and write the same values whether they are executed
+//! in parallel with Block-STM or one after the other. On divergence, proptest shrinks the block to
+//! a minimal conflicting one.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parallel_executor::executor::BlockExecutor;
+use parallel_executor::scheduler::TxnIndex;
+use parallel_executor::task::{
+    ExecutionCancelled, ExecutionPanic, ExecutionStatus, ExecutorTask, Transaction, TransactionOutput, WorkerId,
+    WriteSet,
+};
+use parallel_executor::view::{LatestView, ReadResult, StateView};
+use proptest::prelude::*;
+use sp_weights::Weight;
+
+type Key = u32;
+type Value = Vec<u64>;
+
+/// Operation of a transaction on the keys of the block.
+#[derive(Debug, Clone)]
+enum Op {
+    /// Replaces the value of the key by the sum of its items plus the amount, as a transfer would.
+    Add(Key, u64),
+    /// Removes every item of the value of the key.
+    Clear(Key),
+    /// Appends an item to the value of the key.
+    Append(Key, u64),
+    /// Copies the value of the first key to the second one.
+    Copy(Key, Key),
+}
+
+#[derive(Debug, Clone)]
+struct Ops(Vec<Op>);
+
+impl Transaction for Ops {
+    type Key = Key;
+    type Value = Value;
+}
+
+/// Values the transaction observed, in order, along with the final values of the keys it wrote.
+#[derive(Debug)]
+struct OpsOutput {
+    observed: Vec<Value>,
+    writes: WriteSet<Ops>,
+}
+
+impl TransactionOutput for OpsOutput {
+    type Txn = Ops;
+
+    fn get_writes(&self) -> WriteSet<Ops> {
+        self.writes.clone()
+    }
+
+    fn weight(&self) -> Weight {
+        Weight::zero()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum OpsError {
+    Panic(ExecutionPanic),
+    Cancelled,
+}
+
+impl From<ExecutionPanic> for OpsError {
+    fn from(panic: ExecutionPanic) -> Self {
+        OpsError::Panic(panic)
+    }
+}
+
+impl From<ExecutionCancelled> for OpsError {
+    fn from(_: ExecutionCancelled) -> Self {
+        OpsError::Cancelled
+    }
+}
+
+/// Applies the operations of a transaction, reading the keys with `read` and writing them with
+/// `write`. Returns `None` if a read halted the incarnation.
+fn apply(
+    ops: &Ops,
+    mut read: impl FnMut(Key) -> Option<Value>,
+    mut write: impl FnMut(Key, Value),
+) -> Option<OpsOutput> {
+    let mut observed = Vec::new();
+    let mut writes = HashMap::new();
+    for op in &ops.0 {
+        let (key, value) = match op {
+            Op::Add(key, amount) => {
+                let value = read(*key)?;
+                observed.push(value.clone());
+                (*key, vec![value.iter().sum::<u64>() + amount])
+            }
+            Op::Clear(key) => (*key, Vec::new()),
+            Op::Append(key, item) => {
+                let mut value = read(*key)?;
+                observed.push(value.clone());
+                value.push(*item);
+                (*key, value)
+            }
+            Op::Copy(from, to) => {
+                let value = read(*from)?;
+                observed.push(value.clone());
+                (*to, value)
+            }
+        };
+        write(key, value.clone());
+        writes.insert(key, value);
+    }
+    let mut writes: Vec<_> = writes.into_iter().collect();
+    writes.sort();
+    Some(OpsOutput { observed, writes })
+}
+
+struct OpsTask;
+
+impl ExecutorTask for OpsTask {
+    type Txn = Ops;
+    type Output = OpsOutput;
+    type Error = OpsError;
+    type Argument = ();
+
+    fn init(_args: (), _worker_id: WorkerId) -> Self {
+        OpsTask
+    }
+
+    fn execute_transaction<S: StateView<Ops>>(
+        &self,
+        view: &LatestView<Ops, S>,
+        txn: &Ops,
+        _txn_idx: TxnIndex,
+    ) -> ExecutionStatus<OpsOutput, OpsError> {
+        let read = |key| match view.read(&key) {
+            ReadResult::Value(value) => Some((*value).clone()),
+            ReadResult::Exists(_) => unreachable!("The values of the keys are read"),
+            ReadResult::Halted => None,
+        };
+        match apply(txn, read, |key, value| view.write(key, value)) {
+            Some(output) => ExecutionStatus::Success(output),
+            // The incarnation is discarded.
+            None => ExecutionStatus::Success(OpsOutput { observed: Vec::new(), writes: Vec::new() }),
+        }
+    }
+}
+
+/// Base state in which every key holds its own index.
+struct BaseState;
+
+impl StateView<Ops> for BaseState {
+    fn get_state_value(&self, key: &Key) -> Arc<Value> {
+        Arc::new(vec![*key as u64])
+    }
+}
+
+/// Executes `block` one transaction after the other, returning the values observed by every
+/// transaction and the final values of the keys written.
+fn execute_sequentially(block: &[Ops]) -> (Vec<Vec<Value>>, HashMap<Key, Value>) {
+    // Read and written by every operation, so that a transaction reads its own writes.
+    let state = RefCell::new(HashMap::new());
+    let observed = block
+        .iter()
+        .map(|txn| {
+            let read = |key| Some(state.borrow().get(&key).cloned().unwrap_or_else(|| vec![key as u64]));
+            let output = apply(txn, read, |key, value| {
+                state.borrow_mut().insert(key, value);
+            })
+            .expect("Sequential reads never halt");
+            output.observed
+        })
+        .collect();
+    (observed, state.into_inner())
+}
+
+/// Operations on `num_keys` keys: the fewer the keys, the more the transactions conflict.
+fn op(num_keys: Key) -> impl Strategy<Value = Op> {
+    let key = 0..num_keys;
+    prop_oneof![
+        4 => (key.clone(), 0..100u64).prop_map(|(key, amount)| Op::Add(key, amount)),
+        1 => key.clone().prop_map(Op::Clear),
+        2 => (key.clone(), 0..100u64).prop_map(|(key, item)| Op::Append(key, item)),
+        1 => (key.clone(), key).prop_map(|(from, to)| Op::Copy(from, to)),
+    ]
+}
+
+fn block() -> impl Strategy<Value = Vec<Ops>> {
+    (1..16 as Key)
+        .prop_flat_map(|num_keys| prop::collection::vec(prop::collection::vec(op(num_keys), 1..6).prop_map(Ops), 1..48))
+}
+
+proptest! {
+    #[test]
+    fn parallel_execution_matches_sequential_execution(block in block(), concurrency_level in 2..9usize) {
+        let (observed, writes) = execute_sequentially(&block);
+
+        let executor = BlockExecutor::<Ops, OpsTask, BaseState>::new(concurrency_level, None);
+        let block_output = executor.execute_block((), &block, &BaseState, None).unwrap();
+
+        let parallel_observed: Vec<_> = block_output.outputs.into_iter().map(|output| output.observed).collect();
+        prop_assert_eq!(parallel_observed, observed);
+        let parallel_writes: HashMap<_, _> =
+            block_output.writes.into_iter().map(|(key, value)| (key, (*value).clone())).collect();
+        prop_assert_eq!(parallel_writes, writes);
+    }
+}
@@ -0,0 +1,39 @@
+//! Conflicts reported by the dry runs of the batches.
+
+use parallel_executor::dry_run::{DryRunConflict, DryRunReport};
+use parallel_executor::events::ExtrinsicEvents;
+use parallel_executor::extrinsic::ExtrinsicOutput;
+use sp_weights::Weight;
+
+fn output(reads: &[&[u8]], writes: &[&[u8]]) -> ExtrinsicOutput {
+    ExtrinsicOutput {
+        result: Vec::new(),
+        writes: writes.iter().map(|key| (key.to_vec(), Some(vec![1]))).collect(),
+        events: ExtrinsicEvents::default(),
+        reads: reads.iter().map(|key| key.to_vec()).collect(),
+        offchain_writes: Vec::new(),
+        weight: Weight::zero(),
+    }
+}
+
+#[test]
+fn conflicts_point_to_the_last_writer() {
+    let outputs = vec![
+        output(&[b"a"], &[b"a", b"b"]),
+        output(&[b"c"], &[b"c"]),
+        output(&[b"b", b"a"], &[b"a"]),
+        output(&[b"a", b"c", b"d"], &[]),
+    ];
+    let conflicts = DryRunConflict::between(&outputs);
+    assert_eq!(
+        conflicts,
+        vec![
+            DryRunConflict { txn_idx: 2, dep_idx: 0, keys: vec![b"a".to_vec(), b"b".to_vec()] },
+            DryRunConflict { txn_idx: 3, dep_idx: 1, keys: vec![b"c".to_vec()] },
+            DryRunConflict { txn_idx: 3, dep_idx: 2, keys: vec![b"a".to_vec()] },
+        ]
+    );
+
+    let report = DryRunReport { results: vec![Ok(Ok(())); 4], conflicts, skipped_txns: vec![] };
+    assert_eq!(report.num_independent(), 2);
+}
@@ -0,0 +1,80 @@
+//! Keys accessed by the Ethereum transactions of Frontier based chains, predicted by the
+//! `EvmOracle`.
+
+use codec::{Decode, Encode};
+use parallel_executor::conflict_oracle::ConflictOracle;
+use parallel_executor::evm::{EvmBatchAdapter, EvmOracle, EvmTransaction};
+use parallel_executor::extrinsic::Extrinsic;
+use parallel_executor::pool_tags::PoolTagOracle;
+use sp_core::{H160, H256, U256};
+
+/// First byte of the extrinsics wrapping an Ethereum transaction in the tests.
+const TRANSACT: u8 = 0xee;
+
+/// Decodes the Ethereum transactions following [`TRANSACT`].
+struct TestAdapter;
+
+impl EvmBatchAdapter for TestAdapter {
+    fn decode_transaction(&self, xt: &Extrinsic) -> Option<EvmTransaction> {
+        match xt.encoded() {
+            [TRANSACT, mut encoded @ ..] => EvmTransaction::decode(&mut encoded).ok(),
+            _ => None,
+        }
+    }
+}
+
+fn transact(txn: &EvmTransaction) -> Extrinsic {
+    Extrinsic::new([&[TRANSACT][..], &txn.encode()].concat())
+}
+
+#[test]
+fn transfers_of_distinct_senders_do_not_conflict() {
+    let oracle = EvmOracle::new(TestAdapter);
+    let transfer = |from: u64, to: u64| EvmTransaction {
+        from: H160::from_low_u64_be(from),
+        to: Some(H160::from_low_u64_be(to)),
+        value: U256::one(),
+        ..Default::default()
+    };
+
+    let (reads, writes) = oracle.predict(&transact(&transfer(1, 2)));
+    assert!(!reads.is_exhaustive() && !writes.is_exhaustive());
+    assert_eq!(reads.keys().len(), 1);
+    assert_eq!(
+        writes.keys(),
+        [TestAdapter.account_key(&H160::from_low_u64_be(1)), TestAdapter.account_key(&H160::from_low_u64_be(2))]
+    );
+
+    let (_, other_writes) = oracle.predict(&transact(&transfer(3, 4)));
+    assert!(other_writes.keys().iter().all(|key| !writes.keys().contains(key)));
+    assert_eq!(oracle.sender(&transact(&transfer(1, 4))), oracle.sender(&transact(&transfer(1, 2))));
+    assert_ne!(oracle.sender(&transact(&transfer(3, 4))), oracle.sender(&transact(&transfer(1, 2))));
+}
+
+#[test]
+fn access_lists_are_predicted() {
+    let oracle = EvmOracle::new(TestAdapter);
+    let contract = H160::from_low_u64_be(42);
+    let call = |slot: u64| EvmTransaction {
+        from: H160::from_low_u64_be(1),
+        to: Some(contract),
+        access_list: vec![(contract, vec![H256::from_low_u64_be(slot)])],
+        ..Default::default()
+    };
+
+    // The sender, and the declared slot, without any value transferred.
+    let writes = oracle.predict_writes(&transact(&call(7)));
+    assert_eq!(writes.keys().len(), 2);
+    assert_eq!(oracle.predict_writes(&transact(&call(7))), writes);
+    assert_ne!(oracle.predict_writes(&transact(&call(8))).keys()[1], writes.keys()[1]);
+}
+
+#[test]
+fn other_extrinsics_are_delegated() {
+    let tags = PoolTagOracle::new();
+    let oracle = EvmOracle::new(TestAdapter).with_oracle(tags);
+    let xt = Extrinsic::new(vec![0, 1, 2]);
+
+    assert_eq!(oracle.predict(&xt), Default::default());
+    assert_eq!(oracle.sender(&xt), None);
+}
@@ -0,0 +1,215 @@
+//! Model-checked tests of the races between the workers sharing a scheduler: every interleaving
+//! of the threads is explored by loom, to catch the lost wake-ups and the ordering bugs that the
+//! other tests only hit by chance.
+//!
+//! Run with:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test -p parallel-executor --test loom_scheduler --release
+//! ```
+
+#![cfg(loom)]
+
+use loom::sync::atomic::{AtomicBool, Ordering};
+use loom::sync::Arc;
+use loom::thread;
+use parallel_executor::scheduler::{DependencyResult, DependencyStatus, Scheduler, SchedulerTask, TxnIndex};
+use parallel_executor::sync_wrapper::Mutex;
+
+/// Explores the interleavings with a bounded number of preemptions, which is enough to expose the
+/// races between two workers while keeping the state space tractable.
+fn model(f: impl Fn() + Sync + Send + 'static) {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound = Some(3);
+    builder.check(f);
+}
+
+/// Takes the execution tasks of the first `num_txns` transactions, as the workers would.
+fn start_executions(scheduler: &Scheduler, num_txns: TxnIndex) {
+    for txn_idx in 0..num_txns {
+        assert_eq!(scheduler.next_task(), SchedulerTask::ExecutionTask((txn_idx, 0)));
+    }
+}
+
+/// Runs the tasks of the scheduler, as the loop of a worker does, until the block is done. Every
+/// execution succeeds without writing to a new location, and every validation succeeds.
+fn run_worker(scheduler: &Scheduler, mut task: SchedulerTask) {
+    loop {
+        task = match task {
+            SchedulerTask::ExecutionTask((txn_idx, incarnation)) => {
+                scheduler.finish_execution(txn_idx, incarnation, false)
+            }
+            SchedulerTask::ValidationTask((txn_idx, incarnation), wave) => {
+                scheduler.finish_validation(txn_idx, incarnation, wave);
+                SchedulerTask::Retry
+            }
+            SchedulerTask::Retry => {
+                while scheduler.try_commit().is_some() {}
+                thread::yield_now();
+                scheduler.next_task()
+            }
+            SchedulerTask::Done => return,
+        }
+    }
+}
+
+#[test]
+fn dependency_is_never_lost_when_finishing_execution() {
+    model(|| {
+        let scheduler = Arc::new(Scheduler::new(2));
+        start_executions(&scheduler, 2);
+
+        let waiter = {
+            let scheduler = scheduler.clone();
+            thread::spawn(move || {
+                match scheduler.wait_for_dependency(1, 0) {
+                    // Woken up by the worker that picks the suspended incarnation up again.
+                    DependencyResult::Dependency => {
+                        assert_eq!(scheduler.wait_for_resolution(1), DependencyStatus::Resolved)
+                    }
+                    DependencyResult::Resolved => {}
+                    DependencyResult::ExecutionHalted => unreachable!("The execution is never halted"),
+                }
+                run_worker(&scheduler, SchedulerTask::ExecutionTask((1, 0)));
+            })
+        };
+
+        run_worker(&scheduler, SchedulerTask::ExecutionTask((0, 0)));
+        waiter.join().unwrap();
+
+        assert!(scheduler.done());
+        assert_eq!(scheduler.try_commit(), None);
+    });
+}
+
+#[test]
+fn halt_wakes_up_suspended_workers() {
+    model(|| {
+        let scheduler = Arc::new(Scheduler::new(2));
+        start_executions(&scheduler, 2);
+
+        let waiter = {
+            let scheduler = scheduler.clone();
+            thread::spawn(move || match scheduler.wait_for_dependency(1, 0) {
+                DependencyResult::Dependency => {
+                    assert_eq!(scheduler.wait_for_resolution(1), DependencyStatus::ExecutionHalted)
+                }
+                DependencyResult::ExecutionHalted => {}
+                DependencyResult::Resolved => unreachable!("Transaction 0 never finishes its execution"),
+            })
+        };
+
+        assert!(scheduler.halt());
+        waiter.join().unwrap();
+
+        assert!(scheduler.has_halted());
+        assert_eq!(scheduler.next_task(), SchedulerTask::Done);
+    });
+}
+
+#[test]
+fn skip_rest_wakes_up_the_workers_of_the_skipped_transactions() {
+    model(|| {
+        let scheduler = Arc::new(Scheduler::new(3));
+        start_executions(&scheduler, 3);
+
+        // Transaction 0 is committed, and skips the rest of the block.
+        let SchedulerTask::ValidationTask((0, 0), wave) = scheduler.finish_execution(0, 0, false) else {
+            panic!("Transaction 0 is validated right away, as the validation index passed it");
+        };
+        scheduler.finish_validation(0, 0, wave);
+        assert_eq!(scheduler.try_commit(), Some(0));
+
+        let waiter = {
+            let scheduler = scheduler.clone();
+            thread::spawn(move || match scheduler.wait_for_dependency(2, 1) {
+                DependencyResult::Dependency => {
+                    assert_eq!(scheduler.wait_for_resolution(2), DependencyStatus::ExecutionHalted)
+                }
+                DependencyResult::ExecutionHalted => {}
+                DependencyResult::Resolved => unreachable!("Transaction 1 never finishes its execution"),
+            })
+        };
+
+        assert!(scheduler.skip_rest(1));
+        waiter.join().unwrap();
+
+        // The skipped transactions are never committed, even once executed.
+        assert_eq!(scheduler.finish_execution(1, 0, false), SchedulerTask::Retry);
+        assert_eq!(scheduler.execution_limit(), 1);
+        assert_eq!(scheduler.try_commit(), None);
+        assert_eq!(scheduler.next_task(), SchedulerTask::Done);
+    });
+}
+
+#[test]
+fn transaction_aborted_by_a_failed_validation_is_never_committed() {
+    model(|| {
+        let scheduler = Arc::new(Scheduler::new(2));
+        start_executions(&scheduler, 2);
+
+        // Transaction 1 finishes first and is validated in wave 0, then transaction 0 writes to a
+        // new location, which starts wave 1 and validates transaction 1 again.
+        assert_eq!(scheduler.finish_execution(1, 0, false), SchedulerTask::Retry);
+        let SchedulerTask::ValidationTask((1, 0), stale_wave) = scheduler.next_task() else {
+            panic!("Transaction 1 is validated once executed");
+        };
+        let SchedulerTask::ValidationTask((0, 0), wave) = scheduler.finish_execution(0, 0, true) else {
+            panic!("Transaction 0 is validated right away, as the validation index passed it");
+        };
+        scheduler.finish_validation(0, 0, wave);
+        let SchedulerTask::ValidationTask((1, 0), wave) = scheduler.next_task() else {
+            panic!("Transaction 1 is validated again in the new wave");
+        };
+        assert!(wave > stale_wave);
+
+        let committed = Arc::new(AtomicBool::new(false));
+        let validator = {
+            let (scheduler, committed) = (scheduler.clone(), committed.clone());
+            thread::spawn(move || {
+                // The validation of the previous wave succeeds, and the worker commits what it can.
+                scheduler.finish_validation(1, 0, stale_wave);
+                while let Some(txn_idx) = scheduler.try_commit() {
+                    if txn_idx == 1 {
+                        committed.store(true, Ordering::SeqCst);
+                    }
+                }
+            })
+        };
+
+        // The validation of the new wave fails, as a value read by transaction 1 was marked as an
+        // estimate by the re-execution of transaction 0. The validation of the previous wave does
+        // not let the incarnation be committed in the meantime.
+        assert!(scheduler.try_abort(1, 0));
+        let task = scheduler.finish_abort(1, 0);
+        validator.join().unwrap();
+        assert!(!committed.load(Ordering::SeqCst), "Transaction 1 was committed while aborted");
+
+        // The next incarnation is executed and validated before the block is done.
+        assert_eq!(task, SchedulerTask::ExecutionTask((1, 1)));
+        run_worker(&scheduler, task);
+        assert!(scheduler.done());
+    });
+}
+
+#[test]
+fn mutex_serializes_the_workers() {
+    model(|| {
+        let counter = Arc::new(Mutex::new(0));
+        let workers: Vec<_> = (0..2)
+            .map(|_| {
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    let mut guard = counter.lock();
+                    let value = *guard;
+                    thread::yield_now();
+                    *guard = value + 1;
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(*counter.lock(), 2);
+    });
+}
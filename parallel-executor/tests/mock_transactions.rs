@@ -0,0 +1,144 @@
+//! Blocks of mock transactions executed by the block executor, checked against their baseline
+//! sequential execution.
+
+mod common;
+
+use std::time::Instant;
+
+use common::{BaselineOutput, MockIncarnation, MockState, MockTask, MockTransaction};
+use parallel_executor::executor::{BlockEnd, BlockExecutor};
+use parallel_executor::scheduler::SpeculationWindow;
+
+fn assert_matches_baseline(block: &[MockTransaction], concurrency_level: usize) {
+    let executor = BlockExecutor::<MockTransaction, MockTask, MockState>::new(concurrency_level, None);
+    let result = executor.execute_block((), block, &MockState, None);
+    BaselineOutput::generate(block).assert_output(&result);
+}
+
+/// Every transaction adds to the same key, so that each one depends on the previous one.
+fn deltas_on_one_key(len: usize) -> Vec<MockTransaction> {
+    (0..len)
+        .map(|txn_idx| MockTransaction::from_behavior(MockIncarnation::new(vec![], vec![], vec![(0, txn_idx as u64)])))
+        .collect()
+}
+
+#[test]
+fn independent_transactions() {
+    let block: Vec<_> = (0..32)
+        .map(|key| {
+            MockTransaction::from_behavior(MockIncarnation::new(vec![key], vec![(key + 100, key as u64)], vec![]))
+        })
+        .collect();
+    for concurrency_level in [1, 4] {
+        assert_matches_baseline(&block, concurrency_level);
+    }
+}
+
+#[test]
+fn dependent_deltas() {
+    for concurrency_level in [1, 4] {
+        assert_matches_baseline(&deltas_on_one_key(32), concurrency_level);
+    }
+}
+
+#[test]
+fn reads_of_own_writes() {
+    let block: Vec<_> = (0..16)
+        .map(|txn_idx| {
+            // Reads the key of the previous transaction, then overwrites and increments its own.
+            MockIncarnation::new(vec![txn_idx.max(1) - 1], vec![(txn_idx, 7)], vec![(txn_idx, 1), (txn_idx, 2)])
+        })
+        .map(MockTransaction::from_behavior)
+        .collect();
+    for concurrency_level in [1, 4] {
+        assert_matches_baseline(&block, concurrency_level);
+    }
+}
+
+#[test]
+fn behaviors_changing_between_incarnations() {
+    let block: Vec<_> = (0..16)
+        .map(|key| {
+            MockTransaction::from_behaviors(vec![
+                MockIncarnation::new(vec![0], vec![(key, 1)], vec![]),
+                MockIncarnation::new(vec![key], vec![], vec![(0, 1)]),
+            ])
+        })
+        .collect();
+    for concurrency_level in [1, 4] {
+        assert_matches_baseline(&block, concurrency_level);
+    }
+}
+
+#[test]
+fn skip_rest_ends_the_block() {
+    let mut block = deltas_on_one_key(8);
+    block.push(MockTransaction::SkipRest);
+    block.extend(deltas_on_one_key(8));
+    for concurrency_level in [1, 4] {
+        assert_matches_baseline(&block, concurrency_level);
+    }
+}
+
+#[test]
+fn abort_fails_the_block() {
+    let mut block = deltas_on_one_key(8);
+    block.push(MockTransaction::Abort);
+    block.extend(deltas_on_one_key(8));
+    for concurrency_level in [1, 4] {
+        assert_matches_baseline(&block, concurrency_level);
+    }
+}
+
+#[test]
+fn sequential_only_transaction_ends_the_block_before_it() {
+    let mut block = deltas_on_one_key(8);
+    block.push(MockTransaction::SequentialOnly);
+    block.extend(deltas_on_one_key(8));
+    for concurrency_level in [1, 4] {
+        assert_matches_baseline(&block, concurrency_level);
+        let executor = BlockExecutor::<MockTransaction, MockTask, MockState>::new(concurrency_level, None);
+        let block_output = executor.execute_block((), &block, &MockState, None).expect("The block is not aborted");
+        assert_eq!(block_output.outputs.len(), 8);
+        assert_eq!(block_output.skipped_txns.first(), Some(&8));
+        assert_eq!(block_output.maybe_end, Some(BlockEnd::SequentialOnly));
+    }
+}
+
+#[test]
+fn deadline_ends_the_block_before_the_transactions_not_attempted() {
+    for concurrency_level in [1, 4] {
+        let executor = BlockExecutor::<MockTransaction, MockTask, MockState>::new(concurrency_level, None)
+            .with_deadline(Instant::now());
+        let block_output = executor.execute_block((), &deltas_on_one_key(8), &MockState, None).unwrap();
+        // Every worker attempts one transaction at most before it notices the deadline.
+        assert!(block_output.outputs.len() <= concurrency_level);
+        assert_eq!(block_output.maybe_end, Some(BlockEnd::Deadline));
+    }
+}
+
+#[test]
+fn speculation_window_keeps_the_outputs() {
+    let mut skipping_block = deltas_on_one_key(8);
+    skipping_block.push(MockTransaction::SkipRest);
+    skipping_block.extend(deltas_on_one_key(8));
+    for block in [deltas_on_one_key(32), skipping_block] {
+        let executor = BlockExecutor::<MockTransaction, MockTask, MockState>::new(4, None)
+            .with_speculation_window(SpeculationWindow { min: 1, max: 4 });
+        let result = executor.execute_block((), &block, &MockState, None);
+        BaselineOutput::generate(&block).assert_output(&result);
+    }
+}
+
+#[test]
+fn snapshot_first_incarnations_keep_the_outputs() {
+    let independent: Vec<_> = (0..32)
+        .map(|key| MockTransaction::from_behavior(MockIncarnation::new(vec![key], vec![(key + 100, 1)], vec![])))
+        .collect();
+    for block in [independent, deltas_on_one_key(32)] {
+        let executor =
+            BlockExecutor::<MockTransaction, MockTask, MockState>::new(4, None).with_snapshot_first_incarnations();
+        let result = executor.execute_block((), &block, &MockState, None);
+        BaselineOutput::generate(&block).assert_output(&result);
+    }
+}
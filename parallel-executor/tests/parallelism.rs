@@ -0,0 +1,36 @@
+//! Parallelism records of the blocks, as stored in the auxiliary storage.
+
+use std::time::Duration;
+
+use codec::Encode;
+use parallel_executor::parallelism::{Parallelism, ParallelismRecord};
+
+#[test]
+fn records_of_the_batches_of_a_block_are_chained() {
+    let first = ParallelismRecord::new(Parallelism { num_txns: 12, critical_path: 3 }, 8, 2, Duration::from_millis(4));
+    let second = ParallelismRecord::new(Parallelism::sequential(4), 1, 0, Duration::from_millis(1));
+
+    let record = first.chain(second);
+    assert_eq!(record.parallelism, Parallelism { num_txns: 16, critical_path: 7 });
+    assert_eq!(record.concurrency_level, 8);
+    assert_eq!(record.num_aborts, 2);
+    assert_eq!(record.wall_time_micros, 5_000);
+    assert_eq!(first.estimated_speedup(), 4.0);
+    // Bounded by the number of workers.
+    let wide = ParallelismRecord::new(Parallelism { num_txns: 64, critical_path: 1 }, 8, 0, Duration::ZERO);
+    assert_eq!(wide.estimated_speedup(), 8.0);
+}
+
+#[test]
+fn records_and_bare_parallelisms_are_decoded() {
+    let record =
+        ParallelismRecord::new(Parallelism { num_txns: 100, critical_path: 10 }, 16, 300, Duration::from_secs(1));
+    assert_eq!(ParallelismRecord::decode_aux(&record.encode()), Ok(record));
+
+    // Stored before the records.
+    let parallelism = Parallelism { num_txns: 5, critical_path: 2 };
+    let decoded = ParallelismRecord::decode_aux(&parallelism.encode()).unwrap();
+    assert_eq!(decoded, ParallelismRecord { parallelism, ..Default::default() });
+
+    assert!(ParallelismRecord::decode_aux(&[1, 2, 3]).is_err());
+}
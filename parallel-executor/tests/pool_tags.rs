@@ -0,0 +1,57 @@
+//! Dependencies between the extrinsics of a batch known from the tags of the transaction pool.
+
+use std::sync::Arc;
+
+use parallel_executor::conflict_oracle::ConflictOracle;
+use parallel_executor::executor::BlockExecutor;
+use parallel_executor::extrinsic::Extrinsic;
+use parallel_executor::pool_tags::{PoolTagOracle, PoolTags};
+use parallel_executor::workload::{EmptyState, SyntheticTask, WorkloadConfig};
+
+/// Tags of the `txn_idx`-th transaction of a chain, each requiring the previous one.
+fn chain_tags(txn_idx: u32) -> PoolTags {
+    PoolTags {
+        provides: vec![txn_idx.to_le_bytes().to_vec()],
+        requires: txn_idx.checked_sub(1).map(|previous| previous.to_le_bytes().to_vec()).into_iter().collect(),
+    }
+}
+
+#[test]
+fn registered_tags_are_reported() {
+    let extrinsics = WorkloadConfig { num_txns: 2, ..Default::default() }.extrinsics();
+    let oracle = PoolTagOracle::new();
+    let registry = oracle.clone();
+    registry.register(extrinsics[0].encoded(), chain_tags(0));
+
+    assert_eq!(oracle.pool_tags(&extrinsics[0]), Some(chain_tags(0)));
+    assert_eq!(oracle.pool_tags(&extrinsics[1]), None);
+    registry.forget(extrinsics[0].encoded());
+    assert_eq!(oracle.pool_tags(&extrinsics[0]), None);
+}
+
+#[test]
+fn tag_dependencies_avoid_aborts() {
+    // Every transaction reads and writes the same key.
+    let config = WorkloadConfig {
+        num_txns: 64,
+        num_reads: 1,
+        num_writes: 1,
+        conflict_probability: 1.0,
+        num_hot_keys: 1,
+        ..Default::default()
+    };
+    let extrinsics = config.extrinsics();
+    let oracle = PoolTagOracle::new();
+    for (txn_idx, xt) in extrinsics.iter().enumerate() {
+        oracle.register(xt.encoded(), chain_tags(txn_idx as u32));
+    }
+
+    let executor = BlockExecutor::<Extrinsic, SyntheticTask, EmptyState>::new(8, None);
+    let expected = executor.execute_transactions_sequential((), &extrinsics, &EmptyState, None).unwrap();
+    let executor = executor.with_conflict_oracle(Arc::new(oracle));
+    let block_output = executor.execute_block((), &extrinsics, &EmptyState, None).unwrap();
+
+    assert_eq!(block_output.outputs, expected.outputs);
+    let stats = block_output.maybe_stats.expect("The block is executed in parallel");
+    assert_eq!(stats.scheduler.re_executions, 0, "Every transaction waits for the one it requires");
+}
@@ -0,0 +1,94 @@
+//! Proof of the parallel application of a batch of extrinsics by the
+//! `ParallelLocalCallExecutor`, checked against the proof of the sequential application.
+
+mod common;
+
+use std::cell::RefCell;
+
+use codec::{Decode, Encode};
+use common::transfer;
+use parallel_executor::extrinsic::{APPLY_EXTRINSIC_METHOD, BATCH_APPLY_EXTRINSIC_METHOD};
+use sc_client_api::{Backend as _, CallExecutor};
+use sp_api::ProofRecorder;
+use sp_blockchain::HeaderBackend;
+use sp_core::traits::CallContext;
+use sp_keyring::AccountKeyring;
+use sp_runtime::traits::{BlakeTwo256, Header as _};
+use sp_runtime::ApplyExtrinsicResult;
+use sp_state_machine::backend::{AsTrieBackend, BackendRuntimeCode};
+use sp_state_machine::OverlayedChanges;
+use sp_trie::StorageProof;
+use substrate_test_runtime_client::runtime::Block;
+
+#[test]
+fn parallel_proof_verifies_against_the_state_root() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let state_root = *client.header(genesis_hash).unwrap().unwrap().state_root();
+    let parallel_executor = common::parallel_executor(backend.clone(), 4);
+
+    // Conflicting and independent transfers, and one with a stale nonce.
+    let extrinsics = vec![
+        transfer(AccountKeyring::Alice, AccountKeyring::Bob, 69, 0),
+        transfer(AccountKeyring::Bob, AccountKeyring::Charlie, 42, 0),
+        transfer(AccountKeyring::Charlie, AccountKeyring::Dave, 7, 0),
+        transfer(AccountKeyring::Alice, AccountKeyring::Eve, 1, 1),
+        transfer(AccountKeyring::Ferdie, AccountKeyring::Alice, 3, 0),
+        transfer(AccountKeyring::Alice, AccountKeyring::Bob, 5, 1),
+    ];
+
+    let (results, parallel_proof) =
+        parallel_executor.prove_execution(genesis_hash, BATCH_APPLY_EXTRINSIC_METHOD, &extrinsics.encode()).unwrap();
+    let results = Vec::<ApplyExtrinsicResult>::decode(&mut &results[..]).unwrap();
+
+    let changes = RefCell::new(OverlayedChanges::default());
+    let recorder = ProofRecorder::<Block>::default();
+    let sequential_results: Vec<ApplyExtrinsicResult> = extrinsics
+        .iter()
+        .map(|xt| {
+            let result = parallel_executor
+                .executor
+                .contextual_call(
+                    genesis_hash,
+                    APPLY_EXTRINSIC_METHOD,
+                    &xt.encode(),
+                    &changes,
+                    &Some(recorder.clone()),
+                    CallContext::Offchain,
+                    &RefCell::default(),
+                )
+                .unwrap();
+            Decode::decode(&mut &result[..]).unwrap()
+        })
+        .collect();
+    let sequential_proof = recorder.drain_storage_proof();
+    assert_eq!(results, sequential_results);
+
+    // The runtime code is not part of the proofs.
+    let executor = substrate_test_runtime_client::new_native_or_wasm_executor();
+    let state = backend.state_at(genesis_hash).unwrap();
+    let runtime_code = BackendRuntimeCode::new(state.as_trie_backend());
+    let runtime_code = runtime_code.runtime_code().unwrap();
+    let check_proof = |proof: StorageProof| -> Vec<ApplyExtrinsicResult> {
+        let mut overlay = OverlayedChanges::default();
+        extrinsics
+            .iter()
+            .map(|xt| {
+                let result = sp_state_machine::execution_proof_check::<BlakeTwo256, _>(
+                    state_root,
+                    proof.clone(),
+                    &mut overlay,
+                    &executor,
+                    APPLY_EXTRINSIC_METHOD,
+                    &xt.encode(),
+                    &runtime_code,
+                )
+                .unwrap();
+                Decode::decode(&mut &result[..]).unwrap()
+            })
+            .collect()
+    };
+
+    assert_eq!(check_proof(parallel_proof), results);
+    assert_eq!(check_proof(sequential_proof), results);
+}
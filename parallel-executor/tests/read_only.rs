@@ -0,0 +1,34 @@
+//! Read-only runtime API calls served at once by the `ParallelLocalCallExecutor`.
+
+mod common;
+
+use codec::Encode;
+use parallel_executor::read_only::ReadOnlyCall;
+use sc_client_api::CallExecutor;
+use sp_blockchain::HeaderBackend;
+use sp_core::traits::CallContext;
+use sp_keyring::AccountKeyring;
+use substrate_test_runtime_client::runtime::AccountId;
+
+#[test]
+fn read_only_calls_return_the_results_of_the_local_executor() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let parallel_executor = common::parallel_executor(backend, 4);
+
+    let mut calls = vec![ReadOnlyCall::new("Core_version", Vec::new())];
+    for account in [AccountKeyring::Alice, AccountKeyring::Bob, AccountKeyring::Charlie, AccountKeyring::Dave] {
+        let account: AccountId = account.into();
+        calls.push(ReadOnlyCall::new("AccountNonceApi_account_nonce", account.encode()));
+    }
+    let results = parallel_executor.call_read_only(genesis_hash, &calls).unwrap();
+
+    assert_eq!(results.len(), calls.len());
+    for (call, result) in calls.iter().zip(results) {
+        let expected = parallel_executor
+            .executor
+            .call(genesis_hash, &call.method, &call.call_data, CallContext::Offchain)
+            .unwrap();
+        assert_eq!(result.unwrap(), expected, "{}", call.method);
+    }
+}
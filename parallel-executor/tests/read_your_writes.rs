@@ -0,0 +1,114 @@
+//! Reads of the values a transaction wrote itself, observed before the multi-version map and the
+//! base state.
+
+use std::sync::Arc;
+
+use parallel_executor::executor::BlockExecutor;
+use parallel_executor::scheduler::TxnIndex;
+use parallel_executor::task::{
+    ExecutionCancelled, ExecutionPanic, ExecutionStatus, ExecutorTask, Transaction, TransactionOutput, WorkerId,
+    WriteSet,
+};
+use parallel_executor::view::{LatestView, ReadResult, StateView};
+use sp_weights::Weight;
+
+const COUNTER: u32 = 0;
+
+/// Increments the counter twice, reading it back after its first write.
+#[derive(Debug)]
+struct IncrementTwice;
+
+impl Transaction for IncrementTwice {
+    type Key = u32;
+    type Value = u64;
+}
+
+#[derive(Debug)]
+struct IncrementOutput(WriteSet<IncrementTwice>);
+
+impl TransactionOutput for IncrementOutput {
+    type Txn = IncrementTwice;
+
+    fn get_writes(&self) -> WriteSet<IncrementTwice> {
+        self.0.clone()
+    }
+
+    fn weight(&self) -> Weight {
+        Weight::zero()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum IncrementError {
+    Panic(ExecutionPanic),
+    Cancelled,
+}
+
+impl From<ExecutionPanic> for IncrementError {
+    fn from(panic: ExecutionPanic) -> Self {
+        IncrementError::Panic(panic)
+    }
+}
+
+impl From<ExecutionCancelled> for IncrementError {
+    fn from(_: ExecutionCancelled) -> Self {
+        IncrementError::Cancelled
+    }
+}
+
+struct IncrementTask;
+
+impl ExecutorTask for IncrementTask {
+    type Txn = IncrementTwice;
+    type Output = IncrementOutput;
+    type Error = IncrementError;
+    type Argument = ();
+
+    fn init(_args: (), _worker_id: WorkerId) -> Self {
+        IncrementTask
+    }
+
+    fn execute_transaction<S: StateView<IncrementTwice>>(
+        &self,
+        view: &LatestView<IncrementTwice, S>,
+        _txn: &IncrementTwice,
+        _txn_idx: TxnIndex,
+    ) -> ExecutionStatus<IncrementOutput, IncrementError> {
+        let mut counter = 0;
+        for _ in 0..2 {
+            counter = match view.read(&COUNTER) {
+                ReadResult::Value(value) => *value + 1,
+                ReadResult::Exists(_) => unreachable!("The value of the counter is read"),
+                // The incarnation is discarded.
+                ReadResult::Halted => return ExecutionStatus::Success(IncrementOutput(Vec::new())),
+            };
+            view.write(COUNTER, counter);
+        }
+        ExecutionStatus::Success(IncrementOutput(vec![(COUNTER, counter)]))
+    }
+}
+
+struct ZeroState;
+
+impl StateView<IncrementTwice> for ZeroState {
+    fn get_state_value(&self, _key: &u32) -> Arc<u64> {
+        Arc::new(0)
+    }
+}
+
+fn final_counter(concurrency_level: usize) -> u64 {
+    let block: Vec<_> = (0..20).map(|_| IncrementTwice).collect();
+    let executor = BlockExecutor::<IncrementTwice, IncrementTask, ZeroState>::new(concurrency_level, None);
+    let block_output = executor.execute_block((), &block, &ZeroState, None).unwrap();
+    *block_output.writes[&COUNTER]
+}
+
+#[test]
+fn parallel_reads_observe_own_writes() {
+    assert_eq!(final_counter(4), 40);
+}
+
+#[test]
+fn sequential_reads_observe_own_writes() {
+    assert_eq!(final_counter(1), 40);
+}
@@ -0,0 +1,157 @@
+//! Batches sharded across worker processes, and the merge of their outputs.
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+
+use parallel_executor::extrinsic::Extrinsic;
+use parallel_executor::remote_executor::{
+    first_cross_shard_conflict, serve, RemoteExecutor, ShardOutput, ShardRequest, ShardResponse, MAX_MESSAGE_LEN,
+    PROTOCOL_VERSION,
+};
+use sp_weights::Weight;
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+fn output(reads: &[&[u8]], writes: &[&[u8]]) -> ShardOutput {
+    ShardOutput {
+        result: Vec::new(),
+        writes: writes.iter().map(|key| (key.to_vec(), Some(vec![1]))).collect(),
+        event_records: None,
+        event_count: 0,
+        logs: None,
+        reads: reads.iter().map(|key| key.to_vec()).collect(),
+        offchain_writes: Vec::new(),
+        weight: Weight::zero(),
+    }
+}
+
+#[test]
+fn extrinsics_of_a_partition_are_in_the_same_shard() {
+    let remote_executor = RemoteExecutor::new(vec!["127.0.0.1:1".parse().unwrap(); 3])
+        .with_partitioner(|xt| xt.encoded().first().map(|sender| vec![*sender]));
+    let block: Vec<_> = [0u8, 1, 0, 2, 1, 0].into_iter().map(|sender| Extrinsic::new(vec![sender, 0])).collect();
+
+    let shards = remote_executor.partition(&block, None);
+    assert_eq!(shards.len(), 3);
+    assert_eq!(shards.iter().map(Vec::len).sum::<usize>(), block.len());
+    let shard_of = |txn_idx: u32| shards.iter().position(|shard| shard.contains(&txn_idx)).unwrap();
+    assert_eq!(shard_of(0), shard_of(2));
+    assert_eq!(shard_of(0), shard_of(5));
+    assert_eq!(shard_of(1), shard_of(4));
+}
+
+#[test]
+fn outputs_are_kept_up_to_the_first_cross_shard_conflict() {
+    let shards = vec![vec![0, 2, 4], vec![1, 3]];
+    let outputs = vec![
+        vec![output(&[], &[b"a"]), output(&[b"a"], &[b"a"]), output(&[b"b"], &[])],
+        vec![output(&[], &[b"c"]), output(&[b"c"], &[b"b"])],
+    ];
+    // The extrinsic 4 reads the key written by the extrinsic 3, of the other shard.
+    assert_eq!(first_cross_shard_conflict(5, &shards, &outputs), 4);
+
+    // The second shard ended early.
+    let outputs = vec![outputs[0].clone(), vec![output(&[], &[b"c"])]];
+    assert_eq!(first_cross_shard_conflict(5, &shards, &outputs), 3);
+}
+
+#[test]
+fn shards_are_executed_by_the_workers() {
+    // A worker serves one session at a time.
+    let workers = (0..2)
+        .map(|_| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let worker = listener.local_addr().unwrap();
+            std::thread::spawn(move || {
+                // Every extrinsic writes itself.
+                serve(listener, &[LOCALHOST], |request: ShardRequest| {
+                    let outputs = request.extrinsics.iter().map(|xt| output(&[], &[xt])).collect();
+                    ShardResponse::Executed(outputs)
+                })
+            });
+            worker
+        })
+        .collect();
+
+    let remote_executor = RemoteExecutor::new(workers);
+    let mut sessions = remote_executor.open_sessions(vec![0; 32], Vec::new()).unwrap();
+    let extrinsics: Vec<_> = (0..4u8).map(|byte| vec![byte]).collect();
+    let shards = vec![vec![0, 2], vec![1, 3]];
+    let outputs = sessions.execute(&extrinsics, &shards).unwrap();
+
+    assert_eq!(
+        outputs,
+        vec![vec![output(&[], &[&[0]]), output(&[], &[&[2]])], vec![output(&[], &[&[1]]), output(&[], &[&[3]])]]
+    );
+}
+
+#[test]
+fn committed_writes_are_visible_in_the_next_round() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let worker = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        // Every extrinsic reads the keys of the state of the session.
+        serve(listener, &[LOCALHOST], |request: ShardRequest| {
+            let keys: Vec<&[u8]> = request.base_changes.iter().map(|(key, _)| &key[..]).collect();
+            ShardResponse::Executed(request.extrinsics.iter().map(|_| output(&keys, &[])).collect())
+        })
+    });
+
+    let remote_executor = RemoteExecutor::new(vec![worker]);
+    let mut sessions = remote_executor.open_sessions(vec![0; 32], vec![(b"a".to_vec(), None)]).unwrap();
+    let extrinsics = vec![vec![0]];
+    let shards = vec![vec![0]];
+    assert_eq!(sessions.execute(&extrinsics, &shards).unwrap(), vec![vec![output(&[b"a"], &[])]]);
+
+    sessions.remote_commit(vec![(b"b".to_vec(), Some(vec![1]))]).unwrap();
+    assert_eq!(sessions.execute(&extrinsics, &shards).unwrap(), vec![vec![output(&[b"a", b"b"], &[])]]);
+}
+
+#[test]
+fn sessions_of_other_peers_are_refused() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let worker = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        serve(listener, &[IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))], |_| ShardResponse::Executed(Vec::new()))
+    });
+
+    let remote_executor = RemoteExecutor::new(vec![worker]);
+    let mut sessions = remote_executor.open_sessions(vec![0; 32], Vec::new()).unwrap();
+    assert!(sessions.execute(&[vec![0]], &[vec![0]]).is_err());
+}
+
+#[test]
+fn truncated_message_closes_the_session() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let worker = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        serve(listener, &[LOCALHOST], |request: ShardRequest| {
+            ShardResponse::Executed(request.extrinsics.iter().map(|xt| output(&[], &[xt])).collect())
+        })
+    });
+
+    // The length prefix announces the largest message, of which only the protocol version is sent.
+    let mut stream = TcpStream::connect(worker).unwrap();
+    stream.write_all(&MAX_MESSAGE_LEN.to_le_bytes()).unwrap();
+    stream.write_all(&[PROTOCOL_VERSION]).unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+
+    // The worker serves the next session.
+    let remote_executor = RemoteExecutor::new(vec![worker]);
+    let mut sessions = remote_executor.open_sessions(vec![0; 32], Vec::new()).unwrap();
+    assert_eq!(sessions.execute(&[vec![0]], &[vec![0]]).unwrap(), vec![vec![output(&[], &[&[0]])]]);
+}
+
+#[test]
+fn shards_are_merged_back_when_cross_shard_conflicts_are_too_many() {
+    let remote_executor =
+        RemoteExecutor::new(vec!["127.0.0.1:1".parse().unwrap(); 2]).with_max_rounds(3).with_min_progress(0.5);
+
+    assert!(remote_executor.keeps_sharding(1, 100, 50));
+    // The round kept less than half of the extrinsics left.
+    assert!(!remote_executor.keeps_sharding(1, 100, 49));
+    // The first extrinsic left was not executed.
+    assert!(!remote_executor.keeps_sharding(1, 100, 0));
+    assert!(!remote_executor.keeps_sharding(3, 100, 90));
+}
@@ -0,0 +1,73 @@
+//! Schedules of failed blocks recorded by the block executor, replayed on a single thread.
+
+mod common;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use common::{MockError, MockIncarnation, MockState, MockTask, MockTransaction};
+use parallel_executor::executor::BlockExecutor;
+use parallel_executor::replay::{ReplayError, ScheduleEvent, ScheduleRecord};
+use parallel_executor::task::ExecutionStatus;
+
+/// Every transaction adds its index to the same key, then the block is aborted.
+fn failing_block(len: usize) -> Vec<MockTransaction> {
+    let mut block: Vec<_> = (0..len)
+        .map(|txn_idx| MockTransaction::from_behavior(MockIncarnation::new(vec![], vec![], vec![(0, txn_idx as u64)])))
+        .collect();
+    block.push(MockTransaction::Abort);
+    block
+}
+
+/// Executes `block` on 4 workers, recording its schedule, and returns the record written.
+fn record_failed_block(block: &[MockTransaction], dir: &Path) -> ScheduleRecord {
+    let executor = BlockExecutor::<MockTransaction, MockTask, MockState>::new(4, None).with_schedule_recording(dir);
+    let result = executor.execute_block((), block, &MockState, None);
+    assert!(matches!(result, Err(MockError::Aborted)), "The block fails");
+
+    let mut paths: Vec<_> = std::fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+    assert_eq!(paths.len(), 1, "The schedule of the failed block is written");
+    let record = ScheduleRecord::read_from(&paths.pop().unwrap()).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+    record
+}
+
+fn schedule_dir(test: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("parallel-executor-{test}-{}", std::process::id()))
+}
+
+#[test]
+fn replay_reproduces_the_final_incarnations() {
+    let block = failing_block(16);
+    let record = record_failed_block(&block, &schedule_dir("replay"));
+    assert_eq!(record.num_txns, 17);
+    assert!(record.events.iter().any(|event| matches!(event, ScheduleEvent::Validation { .. })));
+
+    let executor = BlockExecutor::<MockTransaction, MockTask, MockState>::new(4, None);
+    let statuses = executor.replay((), &block, &MockState, &record).unwrap();
+
+    // The last incarnation of every transaction was committed, and observed the sum of the lower
+    // transactions.
+    let last_statuses: HashMap<_, _> = statuses.iter().map(|((txn_idx, _), status)| (*txn_idx, status)).collect();
+    for txn_idx in 0..16 {
+        let ExecutionStatus::Success(output) = last_statuses[&txn_idx] else {
+            panic!("Transaction {txn_idx} succeeds");
+        };
+        assert_eq!(output.writes, vec![(0, (0..=txn_idx as u64).sum())]);
+    }
+    assert!(matches!(last_statuses[&16], ExecutionStatus::Abort(MockError::Aborted)));
+}
+
+#[test]
+fn replay_detects_diverging_transactions() {
+    let block = failing_block(16);
+    let record = record_failed_block(&block, &schedule_dir("diverge"));
+
+    // The transactions now read another key than the one recorded.
+    let mut other_block: Vec<_> =
+        (0..16).map(|_| MockTransaction::from_behavior(MockIncarnation::new(vec![], vec![], vec![(1, 1)]))).collect();
+    other_block.push(MockTransaction::Abort);
+    let executor = BlockExecutor::<MockTransaction, MockTask, MockState>::new(4, None);
+    let result = executor.replay((), &other_block, &MockState, &record);
+    assert!(matches!(result, Err(ReplayError::Diverged { key, .. }) if key == "1"));
+}
@@ -0,0 +1,30 @@
+//! Transactions of the pool revalidated at once by the `ParallelLocalCallExecutor`.
+
+mod common;
+
+use common::transfer;
+use sp_blockchain::HeaderBackend;
+use sp_keyring::AccountKeyring;
+use sp_runtime::transaction_validity::TransactionSource;
+
+#[test]
+fn transactions_are_revalidated_in_order() {
+    let (client, backend) = common::test_client();
+    let genesis_hash = client.info().genesis_hash;
+    let parallel_executor = common::parallel_executor(backend, 4);
+
+    let extrinsics = vec![
+        transfer(AccountKeyring::Alice, AccountKeyring::Bob, 69, 0),
+        transfer(AccountKeyring::Bob, AccountKeyring::Charlie, 42, 0),
+        transfer(AccountKeyring::Charlie, AccountKeyring::Dave, 7, 0),
+        transfer(AccountKeyring::Alice, AccountKeyring::Eve, 1, 1),
+    ];
+    let validities =
+        parallel_executor.validate_transactions(genesis_hash, TransactionSource::External, &extrinsics).unwrap();
+
+    assert_eq!(validities.len(), extrinsics.len());
+    for (xt, validity) in extrinsics.iter().zip(validities) {
+        let valid = validity.unwrap().unwrap_or_else(|err| panic!("{xt:?} is invalid: {err:?}"));
+        assert!(!valid.provides.is_empty());
+    }
+}
@@ -0,0 +1,37 @@
+//! Tasks handed out by the scheduler, as counted in its statistics.
+
+use parallel_executor::scheduler::{Scheduler, SchedulerStats, SchedulerTask};
+
+#[test]
+fn validations_following_a_re_execution_are_counted() {
+    let scheduler = Scheduler::new(2);
+    assert_eq!(scheduler.next_task(), SchedulerTask::ExecutionTask((0, 0)));
+    assert_eq!(scheduler.next_task(), SchedulerTask::ExecutionTask((1, 0)));
+
+    // Transaction 1 finishes first and is validated, then transaction 0 writes to a new location,
+    // which validates transaction 1 again.
+    assert_eq!(scheduler.finish_execution(1, 0, false), SchedulerTask::Retry);
+    assert_eq!(scheduler.next_task(), SchedulerTask::ValidationTask((1, 0), 0));
+    scheduler.finish_validation(1, 0, 0);
+    let SchedulerTask::ValidationTask((0, 0), wave) = scheduler.finish_execution(0, 0, true) else {
+        panic!("Transaction 0 is validated right away, as the validation index passed it");
+    };
+    scheduler.finish_validation(0, 0, wave);
+    assert_eq!(scheduler.next_task(), SchedulerTask::ValidationTask((1, 0), wave));
+
+    // The validation fails, and the next incarnation is validated as soon as it is executed.
+    assert!(scheduler.try_abort(1, 0));
+    assert_eq!(scheduler.finish_abort(1, 0), SchedulerTask::ExecutionTask((1, 1)));
+    let SchedulerTask::ValidationTask((1, 1), wave) = scheduler.finish_execution(1, 1, false) else {
+        panic!("Transaction 1 is validated right away, as the validation index passed it");
+    };
+    scheduler.finish_validation(1, 1, wave);
+    assert_eq!(scheduler.try_commit(), Some(0));
+    assert_eq!(scheduler.try_commit(), Some(1));
+    assert_eq!(scheduler.next_task(), SchedulerTask::Done);
+
+    assert_eq!(
+        scheduler.stats(),
+        SchedulerStats { executions: 3, re_executions: 1, validations: 4, validation_failures: 1, dependency_waits: 0 }
+    );
+}
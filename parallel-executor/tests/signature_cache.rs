@@ -0,0 +1,92 @@
+//! Signature verifications of the extrinsics cached across their incarnations.
+
+use std::cell::Cell;
+use std::sync::Arc;
+
+use parallel_executor::extrinsic::Extrinsic;
+use parallel_executor::signature_cache::{
+    verify_cached, CachedSignatures, SignatureCache, SignatureCacheExt, SignatureExtractor, SignatureScheme,
+    SignedPayload,
+};
+use sp_externalities::ExternalitiesExt;
+use sp_keyring::AccountKeyring;
+use sp_state_machine::BasicExternalities;
+
+/// Extrinsics signed by Alice, whose encoding is the message, but for the ones starting with
+/// `forged` signed with another message.
+struct SignedByAlice;
+
+impl SignatureExtractor for SignedByAlice {
+    fn signed_payload(&self, xt: &Extrinsic) -> Option<SignedPayload> {
+        let message = xt.encoded().to_vec();
+        let signed = if message.starts_with(b"forged") { b"other".to_vec() } else { message.clone() };
+        (!message.starts_with(b"unsigned")).then(|| SignedPayload {
+            scheme: SignatureScheme::Sr25519,
+            signature: AccountKeyring::Alice.sign(&signed).0.to_vec(),
+            message,
+            public: AccountKeyring::Alice.public().0.to_vec(),
+        })
+    }
+}
+
+#[test]
+fn incarnations_reuse_the_verifications() {
+    let cache = SignatureCache::new();
+    let num_verified = Cell::new(0);
+    let verify = || {
+        num_verified.set(num_verified.get() + 1);
+        true
+    };
+
+    assert!(cache.verify(0, SignatureScheme::Sr25519, b"signature", b"message", b"public", verify));
+    assert!(cache.verify(0, SignatureScheme::Sr25519, b"signature", b"message", b"public", verify));
+    assert_eq!(num_verified.get(), 1);
+    assert_eq!(cache.num_hits(), 1);
+
+    // Another extrinsic, or another payload of the same extrinsic, is verified again.
+    assert!(cache.verify(1, SignatureScheme::Sr25519, b"signature", b"message", b"public", verify));
+    assert!(cache.verify(0, SignatureScheme::Sr25519, b"signature", b"other", b"public", verify));
+    assert_eq!(num_verified.get(), 3);
+    assert_eq!(cache.num_hits(), 1);
+}
+
+#[test]
+fn invalid_signatures_are_cached() {
+    let cache = SignatureCache::new();
+    assert!(!cache.verify(0, SignatureScheme::Ed25519, b"signature", b"message", b"public", || false));
+    assert!(!cache.verify(0, SignatureScheme::Ed25519, b"signature", b"message", b"public", || true));
+    assert_eq!(cache.num_hits(), 1);
+}
+
+#[test]
+fn host_functions_go_through_the_extension() {
+    let cache = Arc::new(SignatureCache::new());
+    let mut ext = BasicExternalities::default();
+    verify_cached(&mut ext, SignatureScheme::Ecdsa, b"signature", b"message", b"public", || true);
+    verify_cached(&mut ext, SignatureScheme::Ecdsa, b"signature", b"message", b"public", || true);
+    assert_eq!(cache.num_hits(), 0);
+
+    ext.register_extension(SignatureCacheExt(CachedSignatures { cache: cache.clone(), txn_idx: 0 }))
+        .expect("The extension is registered once");
+    verify_cached(&mut ext, SignatureScheme::Ecdsa, b"signature", b"message", b"public", || true);
+    verify_cached(&mut ext, SignatureScheme::Ecdsa, b"signature", b"message", b"public", || true);
+    assert_eq!(cache.num_hits(), 1);
+}
+
+#[test]
+fn invalid_signatures_are_rejected_before_scheduling() {
+    let block: Vec<_> = [&b"transfer"[..], b"forged transfer", b"unsigned transfer"]
+        .into_iter()
+        .map(|xt| Extrinsic::new(xt.to_vec()))
+        .collect();
+    let cache = SignatureCache::new();
+    assert_eq!(cache.verify_batch(&block, &SignedByAlice), 1);
+    assert!(!cache.is_rejected(0));
+    assert!(cache.is_rejected(1));
+    assert!(!cache.is_rejected(2));
+
+    // The runtime finds the outcome of the verification in the cache.
+    let payload = SignedByAlice.signed_payload(&block[0]).expect("The extrinsic is signed");
+    assert!(cache.verify(0, payload.scheme, &payload.signature, &payload.message, &payload.public, || false));
+    assert_eq!(cache.num_hits(), 1);
+}
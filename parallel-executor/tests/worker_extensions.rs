@@ -0,0 +1,41 @@
+//! Extensions of the node made available to the workers applying a batch.
+
+use std::any::TypeId;
+use std::sync::Arc;
+
+use parallel_executor::worker_extensions::{Shareability, WorkerExtensions};
+
+sp_externalities::decl_extension! {
+    /// Extension shared by the workers.
+    struct SharedExt(Arc<u32>);
+}
+
+sp_externalities::decl_extension! {
+    /// Extension of a single worker.
+    struct WorkerExt(usize);
+}
+
+#[test]
+fn workers_have_extensions_of_their_own() {
+    let shared = Arc::new(7);
+    let extensions = WorkerExtensions::new().with_shared(SharedExt(shared.clone())).with_per_worker(WorkerExt);
+    assert_eq!(extensions.shareability(TypeId::of::<SharedExt>()), Some(Shareability::Shared));
+    assert_eq!(extensions.shareability(TypeId::of::<WorkerExt>()), Some(Shareability::PerWorker));
+
+    for worker_id in [0, 3] {
+        let mut worker_extensions = extensions.extensions(worker_id);
+        let worker_ext = worker_extensions.get_mut(TypeId::of::<WorkerExt>()).expect("The extension is registered");
+        assert_eq!(worker_ext.downcast_mut::<WorkerExt>().map(|ext| ext.0), Some(worker_id));
+        let shared_ext = worker_extensions.get_mut(TypeId::of::<SharedExt>()).expect("The extension is registered");
+        assert!(shared_ext.downcast_mut::<SharedExt>().is_some_and(|ext| Arc::ptr_eq(&ext.0, &shared)));
+    }
+}
+
+#[test]
+fn stubs_replace_the_extensions() {
+    let extensions = WorkerExtensions::new().with_per_worker(WorkerExt).with_stub(|| WorkerExt(usize::MAX));
+    assert_eq!(extensions.shareability(TypeId::of::<WorkerExt>()), Some(Shareability::Stubbed));
+    let mut worker_extensions = extensions.extensions(1);
+    let worker_ext = worker_extensions.get_mut(TypeId::of::<WorkerExt>()).expect("The extension is registered");
+    assert_eq!(worker_ext.downcast_mut::<WorkerExt>().map(|ext| ext.0), Some(usize::MAX));
+}
@@ -0,0 +1,68 @@
+//! Synthetic batches generated with a controlled contention, and their parallel execution.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use parallel_executor::executor::BlockExecutor;
+use parallel_executor::extrinsic::Extrinsic;
+use parallel_executor::workload::{hot_key, EmptyState, SyntheticTask, SyntheticTransaction, WorkloadConfig};
+
+fn hot_keys(config: &WorkloadConfig) -> Vec<Vec<u8>> {
+    (0..config.num_hot_keys).map(hot_key).collect()
+}
+
+fn accessed_keys(txn: &SyntheticTransaction) -> impl Iterator<Item = &Vec<u8>> {
+    txn.reads.iter().chain(txn.writes.iter().map(|(key, _)| key))
+}
+
+#[test]
+fn conflict_probability_dials_the_accesses_to_hot_keys() {
+    let config = WorkloadConfig { num_txns: 100, ..Default::default() };
+    let hot_keys = hot_keys(&config);
+
+    let independent = config.generate();
+    assert_eq!(independent, config.generate(), "The same config generates the same batch");
+    assert!(independent.iter().flat_map(accessed_keys).all(|key| !hot_keys.contains(key)));
+    for txn in &independent {
+        assert_eq!(txn.reads.len(), config.num_reads);
+        assert_eq!(txn.writes.len(), config.num_writes);
+        assert!(txn.writes.iter().all(|(_, value)| value.len() == config.value_size));
+    }
+
+    let conflicting = WorkloadConfig { conflict_probability: 1.0, ..config }.generate();
+    assert!(conflicting.iter().flat_map(accessed_keys).all(|key| hot_keys.contains(key)));
+
+    let mixed = WorkloadConfig { conflict_probability: 0.5, ..config }.generate();
+    let num_hot = mixed.iter().flat_map(accessed_keys).filter(|key| hot_keys.contains(key)).count();
+    let num_accesses = config.num_txns * (config.num_reads + config.num_writes);
+    assert!(num_hot > num_accesses / 4 && num_hot < num_accesses * 3 / 4);
+}
+
+#[test]
+fn parallel_execution_of_synthetic_batches_matches_sequential_execution() {
+    for conflict_probability in [0.0, 0.1, 0.5, 1.0] {
+        let config = WorkloadConfig { num_txns: 200, conflict_probability, num_hot_keys: 4, ..Default::default() };
+        let batch = config.generate();
+
+        let state = RefCell::new(HashMap::new());
+        let expected_outputs: Vec<_> = batch
+            .iter()
+            .map(|txn| {
+                let read = |key: &Vec<u8>| Some(state.borrow().get(key).cloned().flatten());
+                txn.apply(read, |key, value| {
+                    state.borrow_mut().insert(key, value);
+                })
+                .expect("Sequential reads never halt")
+            })
+            .collect();
+
+        let extrinsics: Vec<_> = batch.iter().map(SyntheticTransaction::to_extrinsic).collect();
+        let executor = BlockExecutor::<Extrinsic, SyntheticTask, EmptyState>::new(4, None);
+        let block_output = executor.execute_block((), &extrinsics, &EmptyState, None).unwrap();
+
+        assert_eq!(block_output.outputs, expected_outputs, "Conflict probability {conflict_probability}");
+        let writes: HashMap<_, _> =
+            block_output.writes.into_iter().map(|(key, value)| (key, (*value).clone())).collect();
+        assert_eq!(writes, state.into_inner(), "Conflict probability {conflict_probability}");
+    }
+}
@@ -0,0 +1,39 @@
+//! Runtime side of the batches of extrinsics applied in parallel by the node.
+//!
+//! The `ParallelLocalCallExecutor` of the node intercepts the calls to
+//! `BlockBuilder_batch_apply_extrinsic` and applies the extrinsics of the batch in parallel. The
+//! runtime still implements the method, for the executors that call it as any other, e.g. when a
+//! block is validated by a node that does not apply the batches in parallel. Both must agree: the
+//! batch is applied as the extrinsics would be one after the other with `apply_extrinsic`.
+//!
+//! Chains implement the method of their `BlockBuilder` API on top of their `Executive`:
+//!
+//! ```ignore
+//! fn batch_apply_extrinsic(extrinsics: Vec<<Block as BlockT>::Extrinsic>) -> Vec<ApplyExtrinsicResult> {
+//!     parallel_frame_executive::batch_apply_extrinsic::<Block>(extrinsics, Executive::apply_extrinsic)
+//! }
+//! ```
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use sp_runtime::traits::Block as BlockT;
+use sp_runtime::ApplyExtrinsicResult;
+
+/// Applies the `extrinsics` of a batch in order with `apply_extrinsic`, i.e.
+/// `Executive::apply_extrinsic`, and returns the result of every extrinsic.
+///
+/// Every extrinsic goes through the whole bookkeeping of `apply_extrinsic`: the index of the
+/// extrinsic is noted by `frame_system` once it is applied, its events are deposited under that
+/// index, and its weight and length are added to the ones of the block. An extrinsic that does not
+/// fit in the block anymore is rejected with `ExhaustsResources`, and the following ones are still
+/// attempted, as the block builder would with a single call each.
+pub fn batch_apply_extrinsic<Block: BlockT>(
+    extrinsics: Vec<Block::Extrinsic>,
+    apply_extrinsic: impl FnMut(Block::Extrinsic) -> ApplyExtrinsicResult,
+) -> Vec<ApplyExtrinsicResult> {
+    extrinsics.into_iter().map(apply_extrinsic).collect()
+}